@@ -209,7 +209,7 @@ struct Forwarded<'a> {
     host: Option<&'a str>,
 }
 
-fn parse_forwared(src: &str) -> IResult<&str, Forwarded> {
+fn parse_forwared(src: &str) -> IResult<&str, Forwarded<'_>> {
     let (src, _) = space0(src)?;
     let pair = map(
         tuple((
@@ -174,15 +174,26 @@ impl<T: Header> IntoResponse for TypedHeader<Option<T>> {
 
 pub struct HttpError {
     pub body: Bytes,
-    pub mime: mime::Mime,
+    /// Boxed to keep `Result<_, HttpError>` small.
+    pub mime: Box<mime::Mime>,
     pub status: StatusCode,
 }
 
+/// Shows the status and the body, which is JSON for every error built here.
+impl std::fmt::Debug for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpError")
+            .field("status", &self.status)
+            .field("body", &String::from_utf8_lossy(&self.body))
+            .finish()
+    }
+}
+
 impl HttpError {
     pub fn new_json<M: Serialize>(msg: &M, status: StatusCode) -> Self {
         HttpError {
             body: serde_json::to_vec(msg).unwrap().into(),
-            mime: mime::APPLICATION_JSON,
+            mime: Box::new(mime::APPLICATION_JSON),
             status,
         }
     }
@@ -209,7 +220,7 @@ impl<T, E: Serialize> ToHttpErrorJson for Result<T, E> {
             let body = serde_json::to_vec(&e).unwrap_or_else(serialize_err);
             HttpError {
                 body: body.into(),
-                mime: mime::APPLICATION_JSON,
+                mime: Box::new(mime::APPLICATION_JSON),
                 status,
             }
         })
@@ -221,7 +232,7 @@ impl IntoResponse for HttpError {
         let mut headers = HeaderMap::new();
         headers.insert(
             "Content-Type",
-            HeaderValue::from_str(self.mime.as_ref()).unwrap(),
+            HeaderValue::from_str(self.mime.as_ref().as_ref()).unwrap(),
         );
         (self.status, headers, self.body).into_response()
     }
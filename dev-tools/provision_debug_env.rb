@@ -1,8 +1,12 @@
 # frozen_string_literal: true
 
 require 'aws-sdk'
+require 'digest'
+require 'json'
 
 dynamo_port = 8000
+minio_port = 10_020
+media_bucket = 'media'
 aws_iam_key_id = 'EkikaAdmin'
 aws_iam_secret_key = 'EkikaAdmin'
 region = 'ap-northeast-1'
@@ -11,32 +15,217 @@ credentials = Aws::Credentials.new(aws_iam_key_id, aws_iam_secret_key)
 ddb = Aws::DynamoDB::Client.new(region: region, credentials: credentials,
                                 endpoint: format('http://localhost:%d', dynamo_port))
 
-# create user table
-begin
-  ddb.describe_table({ table_name: 'users' })
+s3 = Aws::S3::Client.new(region: region, credentials: credentials,
+                        endpoint: format('http://localhost:%d', minio_port), force_path_style: true)
+
+def ensure_table(ddb, table_def)
+  ddb.describe_table({ table_name: table_def[:table_name] })
 rescue StandardError
-  users_table_def = {
-    table_name: 'users',
-    attribute_definitions: [
-      {
-        attribute_name: 'Id',
-        attribute_type: 'S'
-      }
-    ],
-    key_schema: [
-      {
-        attribute_name: 'Id',
-        key_type: 'HASH'
-      }
-    ],
+  ddb.create_table(table_def.merge(
+                     provisioned_throughput: {
+                       read_capacity_units: 5,
+                       write_capacity_units: 5
+                     }
+                   ))
+end
+
+def string_attributes(*names)
+  names.map { |name| { attribute_name: name, attribute_type: 'S' } }
+end
+
+def key_schema(hash_key, range_key = nil)
+  schema = [{ attribute_name: hash_key, key_type: 'HASH' }]
+  schema << { attribute_name: range_key, key_type: 'RANGE' } if range_key
+  schema
+end
+
+def global_index(name, hash_key, range_key = nil)
+  {
+    index_name: name,
+    key_schema: key_schema(hash_key, range_key),
+    projection: { projection_type: 'ALL' },
     provisioned_throughput: {
       read_capacity_units: 5,
       write_capacity_units: 5
     }
   }
-  ddb.create_table(users_table_def)
 end
 
+# create user table
+ensure_table(ddb, {
+               table_name: 'users',
+               attribute_definitions: string_attributes('Id'),
+               key_schema: key_schema('Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'objects',
+               attribute_definitions: string_attributes('Id', 'InReplyTo'),
+               key_schema: key_schema('Id'),
+               global_secondary_indexes: [global_index('InReplyTo', 'InReplyTo', 'Id')]
+             })
+
+ensure_table(ddb, {
+               table_name: 'follows',
+               attribute_definitions: string_attributes('Followee', 'Follower'),
+               key_schema: key_schema('Followee', 'Follower'),
+               global_secondary_indexes: [global_index('Follower', 'Follower', 'Followee')]
+             })
+
+%w[tokens keys actors applications authorization_codes sessions deliveries].each do |table_name|
+  ensure_table(ddb, {
+                 table_name: table_name,
+                 attribute_definitions: string_attributes('Id'),
+                 key_schema: key_schema('Id')
+               })
+end
+
+ensure_table(ddb, {
+               table_name: 'conversations',
+               attribute_definitions: string_attributes('Owner', 'Id'),
+               key_schema: key_schema('Owner', 'Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'blocks',
+               attribute_definitions: string_attributes('Blocker', 'Blocked'),
+               key_schema: key_schema('Blocker', 'Blocked')
+             })
+
+ensure_table(ddb, {
+               table_name: 'mutes',
+               attribute_definitions: string_attributes('Muter', 'Muted'),
+               key_schema: key_schema('Muter', 'Muted')
+             })
+
+ensure_table(ddb, {
+               table_name: 'lists',
+               attribute_definitions: string_attributes('Owner', 'Id'),
+               key_schema: key_schema('Owner', 'Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'list_members',
+               attribute_definitions: string_attributes('List', 'Member'),
+               key_schema: key_schema('List', 'Member'),
+               global_secondary_indexes: [global_index('Member', 'Member', 'List')]
+             })
+
+ensure_table(ddb, {
+               table_name: 'notifications',
+               attribute_definitions: string_attributes('Account', 'Id', 'Group'),
+               key_schema: key_schema('Account', 'Id'),
+               global_secondary_indexes: [global_index('Group', 'Group')]
+             })
+
+ensure_table(ddb, {
+               table_name: 'notification_settings',
+               attribute_definitions: string_attributes('Account'),
+               key_schema: key_schema('Account')
+             })
+
+ensure_table(ddb, {
+               table_name: 'feeds',
+               attribute_definitions: string_attributes('Feed', 'Id'),
+               key_schema: key_schema('Feed', 'Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'large_accounts',
+               attribute_definitions: string_attributes('Actor'),
+               key_schema: key_schema('Actor')
+             })
+
+%w[domain_policies domain_allows].each do |table_name|
+  ensure_table(ddb, {
+                 table_name: table_name,
+                 attribute_definitions: string_attributes('Domain'),
+                 key_schema: key_schema('Domain')
+               })
+end
+
+ensure_table(ddb, {
+               table_name: 'reports',
+               attribute_definitions: string_attributes('Id', 'State'),
+               key_schema: key_schema('Id'),
+               global_secondary_indexes: [global_index('State', 'State')]
+             })
+
+ensure_table(ddb, {
+               table_name: 'account_policies',
+               attribute_definitions: string_attributes('Actor'),
+               key_schema: key_schema('Actor')
+             })
+
+ensure_table(ddb, {
+               table_name: 'migrations',
+               attribute_definitions: string_attributes('Account'),
+               key_schema: key_schema('Account')
+             })
+
+ensure_table(ddb, {
+               table_name: 'featured',
+               attribute_definitions: string_attributes('Actor'),
+               key_schema: key_schema('Actor')
+             })
+
+ensure_table(ddb, {
+               table_name: 'relays',
+               attribute_definitions: string_attributes('Id'),
+               key_schema: key_schema('Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'reactions',
+               attribute_definitions: string_attributes('Id', 'Object', 'Actor'),
+               key_schema: key_schema('Id'),
+               global_secondary_indexes: [global_index('Object', 'Object'), global_index('Actor', 'Actor')]
+             })
+
+ensure_table(ddb, {
+               table_name: 'boosts',
+               attribute_definitions: string_attributes('Object', 'Actor'),
+               key_schema: key_schema('Object', 'Actor')
+             })
+
+ensure_table(ddb, {
+               table_name: 'media',
+               attribute_definitions: string_attributes('Id'),
+               key_schema: key_schema('Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'media_cache',
+               attribute_definitions: string_attributes('Id'),
+               key_schema: key_schema('Id')
+             })
+
+ensure_table(ddb, {
+               table_name: 'emojis',
+               attribute_definitions: string_attributes('Id'),
+               key_schema: key_schema('Id')
+             })
+
+begin
+  s3.head_bucket({ bucket: media_bucket })
+rescue StandardError
+  s3.create_bucket({ bucket: media_bucket })
+end
+
+# media is served straight from the bucket
+s3.put_bucket_policy({
+                       bucket: media_bucket,
+                       policy: {
+                         Version: '2012-10-17',
+                         Statement: [{
+                           Effect: 'Allow',
+                           Principal: { AWS: ['*'] },
+                           Action: ['s3:GetObject'],
+                           Resource: ["arn:aws:s3:::#{media_bucket}/*"]
+                         }]
+                       }.to_json
+                     })
+
 admin_user = {
   item: {
     'Id' => 'admin',
@@ -46,10 +235,20 @@ admin_user = {
     'Icon' => [
       'https://www.namachan10777.dev/icon.webp'
     ],
-    'Kind' => 'Person'
+    'Kind' => 'Person',
+    'Role' => 'Admin'
   },
   return_consumed_capacity: 'TOTAL',
   table_name: 'users'
 }
 
 ddb.put_item(admin_user)
+
+# bearer token `debug` for the admin user
+ddb.put_item({
+               item: {
+                 'Id' => Digest::SHA256.hexdigest('debug'),
+                 'Account' => 'admin'
+               },
+               table_name: 'tokens'
+             })
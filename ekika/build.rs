@@ -1,11 +1,114 @@
-use std::{env, fs, path::Path};
+use std::{collections::HashMap, env, fs, path::Path};
+
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+const ACTIVITYSTREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// Vocabulary under its own namespace, layered on top of `vocab.yml`.
+#[derive(Deserialize)]
+struct Extension {
+    prefix: String,
+    namespace: String,
+    /// Context document which defines the terms. Without it, each term is declared inline.
+    context: Option<String>,
+    /// Properties added to types defined elsewhere, keyed by type name.
+    #[serde(default)]
+    properties: HashMap<String, Mapping>,
+    #[serde(default)]
+    types: Mapping,
+}
+
+fn properties_mut<'a>(defs: &'a mut Mapping, type_name: &str) -> &'a mut Mapping {
+    let def = defs
+        .get_mut(type_name)
+        .and_then(Value::as_mapping_mut)
+        .unwrap_or_else(|| panic!("type {type_name} not found"));
+    def.entry("properties".into())
+        .or_insert_with(|| Value::Mapping(Mapping::new()))
+        .as_mapping_mut()
+        .unwrap()
+}
+
+/// JSON-LD term definition of a property or type, if it lives in the extension's namespace.
+fn term(extension: &Extension, name: &str, def: &Value) -> Option<(String, serde_json::Value)> {
+    let def = match def {
+        Value::Tagged(tagged) => &tagged.value,
+        def => def,
+    };
+    let uri = def.get("uri")?.as_str()?;
+    let local_name = uri.strip_prefix(&extension.namespace)?;
+    let id = format!("{}:{local_name}", extension.prefix);
+    let tag = def.get("tag").and_then(Value::as_str).unwrap_or(name);
+    let ty = def.get("type").and_then(Value::as_str).unwrap_or_default();
+    let definition = if ty.starts_with("Vec<") {
+        serde_json::json!({"@id": id, "@container": "@list"})
+    } else if ty.contains("url::Url") || ty.starts_with("Remotable<") {
+        serde_json::json!({"@id": id, "@type": "@id"})
+    } else {
+        serde_json::Value::String(id)
+    };
+    Some((tag.to_string(), definition))
+}
 
 fn main() {
+    println!("cargo:rerun-if-changed=vocab.yml");
+    println!("cargo:rerun-if-changed=vocab");
     let src = fs::read_to_string("vocab.yml").unwrap();
-    let src = serde_yaml::from_str(&src).unwrap();
-    let src = activity_vocabulary_derive::gen(&src).unwrap();
+    let mut defs: Mapping = serde_yaml::from_str(&src).unwrap();
+
+    let mut paths = fs::read_dir("vocab")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yml"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    let mut contexts = vec![serde_json::Value::String(
+        ACTIVITYSTREAMS_CONTEXT.to_string(),
+    )];
+    let mut terms = serde_json::Map::new();
+    for path in paths {
+        let src = fs::read_to_string(&path).unwrap();
+        let extension: Extension =
+            serde_yaml::from_str(&src).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        let mut extension_terms = Vec::new();
+        for (type_name, properties) in &extension.properties {
+            for (name, def) in properties {
+                extension_terms.extend(term(&extension, name.as_str().unwrap(), def));
+            }
+            properties_mut(&mut defs, type_name).extend(properties.clone());
+        }
+        for (type_name, def) in &extension.types {
+            let type_name = type_name.as_str().unwrap();
+            extension_terms.extend(term(&extension, type_name, def));
+            if let Some(Value::Mapping(properties)) = def.get("properties") {
+                for (name, def) in properties {
+                    extension_terms.extend(term(&extension, name.as_str().unwrap(), def));
+                }
+            }
+            defs.insert(type_name.into(), def.clone());
+        }
+        match &extension.context {
+            Some(context) => contexts.push(serde_json::Value::String(context.clone())),
+            None => {
+                terms.insert(
+                    extension.prefix.clone(),
+                    serde_json::Value::String(extension.namespace.clone()),
+                );
+                terms.extend(extension_terms);
+            }
+        }
+    }
+    contexts.push(serde_json::Value::Object(terms));
+
+    let defs = serde_yaml::from_value(Value::Mapping(defs)).unwrap();
+    let src = activity_vocabulary_derive::gen(&defs).unwrap();
     let out_path = env::var("OUT_DIR").unwrap();
     let out_path: &Path = out_path.as_ref();
-    println!("cargo:rerun-if-changed=vocab.yml");
     fs::write(out_path.join("vocab.rs"), src.as_bytes()).unwrap();
+    fs::write(
+        out_path.join("context.json"),
+        serde_json::to_string(&contexts).unwrap(),
+    )
+    .unwrap();
 }
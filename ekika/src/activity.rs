@@ -0,0 +1,42 @@
+use serde_json::json;
+
+use crate::{ap, local::Local};
+
+/// IRI of a JSON-LD node given either as a string or as an embedded node with `id`.
+pub fn iri(value: &serde_json::Value) -> Option<url::Url> {
+    match value {
+        serde_json::Value::String(iri) => iri.parse().ok(),
+        serde_json::Value::Object(node) => node.get("id")?.as_str()?.parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn field_iri(activity: &serde_json::Value, name: &str) -> Option<url::Url> {
+    match activity.get(name)? {
+        serde_json::Value::Array(values) => values.first().and_then(iri),
+        value => iri(value),
+    }
+}
+
+pub fn kind(value: &serde_json::Value) -> Option<&str> {
+    match value.get("type")? {
+        serde_json::Value::Array(kinds) => kinds.first()?.as_str(),
+        kind => kind.as_str(),
+    }
+}
+
+/// Build an activity issued by a local actor.
+pub fn new_activity(
+    local: &Local,
+    kind: &str,
+    actor: &url::Url,
+    object: serde_json::Value,
+) -> serde_json::Value {
+    json!({
+        "@context": *ap::CONTEXT,
+        "id": local.new_activity(),
+        "type": kind,
+        "actor": actor,
+        "object": object,
+    })
+}
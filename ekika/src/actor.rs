@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use activity_vocabulary_core::{LangContainer, Or, Property, Remotable};
+use axum::response::IntoResponse;
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    ap,
+    emoji::{self, EmojiStore},
+    fetch::Remote,
+    follow,
+    local::Local,
+    migration::MigrationStore,
+    model::account::{Account, AccountKind, ProfileField},
+    object::ACTIVITY_JSON,
+    profile, report,
+    signature::{self, KeyStore, SignedRequest},
+    webfinger::AccountStore,
+};
+
+/// Collections every actor links to. Only `followers` is used for addressing; their contents
+/// are not published, so they are served empty.
+pub const COLLECTIONS: [&str; 4] = ["outbox", "following", "followers", "liked"];
+
+/// Collection `kind` of a local actor, such as `{actor}/outbox`.
+pub fn collection(actor: &url::Url, kind: &str) -> url::Url {
+    format!("{actor}/{kind}").parse().unwrap()
+}
+
+pub fn text(value: &str) -> LangContainer<Property<String>> {
+    LangContainer {
+        default: Some(Property(vec![value.to_string()])),
+        per_lang: Default::default(),
+    }
+}
+
+pub fn image(url: &url::Url) -> Or<ap::LinkSubtypes, Remotable<ap::ImageSubtypes>> {
+    let mut image: ap::Image = ap::empty();
+    image.url = Property(vec![Or::Prim(url.clone())]);
+    Or::Snd(Remotable::Inline(ap::ImageSubtypes::Image(image)))
+}
+
+fn field(field: &ProfileField) -> Or<ap::LinkSubtypes, Remotable<ap::ObjectSubtypes>> {
+    let mut value: ap::PropertyValue = ap::empty();
+    value.name = text(&field.name);
+    value.property_value = Some(profile::field_html(&field.value));
+    Or::Snd(Remotable::Inline(ap::ObjectSubtypes::PropertyValue(value)))
+}
+
+/// Document of a local account, as the generated type matching its [AccountKind].
+pub async fn render<S>(
+    state: &S,
+    local: &Local,
+    name: &str,
+    account: &Account,
+) -> Result<ap::ActorSubtypes, HttpError>
+where
+    S: MigrationStore + KeyStore + EmojiStore + Sync,
+{
+    let id = local.actor(name);
+    let texts = [account.name.as_str(), account.summary.as_str()]
+        .into_iter()
+        .chain(account.fields.iter().map(|field| field.value.as_str()))
+        .chain(account.fields.iter().map(|field| field.name.as_str()));
+    let emojis = emoji::used_in(state, texts).await?;
+    let key = signature::key_of(state, &id).await?;
+    let migration = state.get_migration(name).await?;
+    macro_rules! render {
+        ($kind:ident) => {{
+            let mut actor: ap::$kind = ap::empty();
+            actor.id = Some(id.clone());
+            actor.preferred_username = Some(name.to_string());
+            actor.name = text(&account.name);
+            actor.summary = text(&account.summary);
+            actor.icon = Property(account.icon.iter().map(image).collect());
+            actor.image = Property(account.header.iter().map(image).collect());
+            actor.attachment = Property(account.fields.iter().map(field).collect());
+            actor.tag = Property(
+                emojis
+                    .iter()
+                    .map(|custom| {
+                        Or::Snd(Remotable::Inline(ap::ObjectSubtypes::Emoji(
+                            emoji::document(custom),
+                        )))
+                    })
+                    .collect(),
+            );
+            actor.manually_approves_followers = Some(account.locked);
+            actor.discoverable = Some(account.discoverable);
+            actor.inbox = Property(vec![local.inbox(name)]);
+            actor.outbox = Property(vec![Remotable::Remote(collection(&id, "outbox"))]);
+            actor.following = Property(vec![Remotable::Remote(collection(&id, "following"))]);
+            actor.followers = Property(vec![Remotable::Remote(follow::followers_collection(&id))]);
+            actor.liked = Property(vec![Remotable::Remote(collection(&id, "liked"))]);
+            actor.featured = Some(Remotable::Remote(local.featured(name)));
+            actor.endpoints = Some(ap::Endpoints {
+                shared_inbox: Some(local.shared_inbox()),
+            });
+            actor.public_key = Some(ap::PublicKey {
+                id: Some(local.key_id(name)),
+                owner: Some(id.clone()),
+                public_key_pem: Some(key.public_key_pem.clone()),
+            });
+            if let Some(migration) = &migration {
+                actor.also_known_as = Property(migration.also_known_as.clone());
+                actor.moved_to = migration.moved_to.clone();
+            }
+            ap::ActorSubtypes::$kind(actor)
+        }};
+    }
+    Ok(match account.kind {
+        AccountKind::Person if account.bot => render!(Service),
+        AccountKind::Person => render!(Person),
+        AccountKind::Service => render!(Service),
+        AccountKind::Application => render!(Application),
+        AccountKind::Group => render!(Group),
+        AccountKind::Organization => render!(Organization),
+    })
+}
+
+pub async fn get_actor<S>(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: AccountStore<ActorInfo = Account> + MigrationStore + KeyStore + Remote + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    signature::authorize_fetch(state.as_ref(), request).await?;
+    let not_found = || HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND);
+    let account = state.query(&name).await?.ok_or_else(not_found)?;
+    if report::is_suspended(state.as_ref(), &local.actor(&name)).await? {
+        return Err(not_found());
+    }
+    let actor = render(state.as_ref(), &local, &name, &account).await?;
+    document(actor)
+}
+
+/// Built-in `Application` actor which signs requests made on behalf of the server itself.
+/// It lives outside of `/users/`, so it never collides with or shows up as an account.
+pub async fn render_instance<S>(state: &S, local: &Local) -> Result<ap::ActorSubtypes, HttpError>
+where
+    S: KeyStore + Sync,
+{
+    let id = local.instance_actor();
+    let key = signature::key_of(state, &id).await?;
+    let mut key_id = id.clone();
+    key_id.set_fragment(Some("main-key"));
+    let mut actor: ap::Application = ap::empty();
+    actor.id = Some(id.clone());
+    actor.preferred_username = Some(local.host().to_string());
+    actor.name = text(local.host());
+    actor.summary = text(&format!("Instance actor of {}", local.host()));
+    actor.manually_approves_followers = Some(false);
+    actor.discoverable = Some(false);
+    actor.inbox = Property(vec![local.instance_inbox()]);
+    actor.outbox = Property(vec![Remotable::Remote(collection(&id, "outbox"))]);
+    actor.following = Property(vec![Remotable::Remote(collection(&id, "following"))]);
+    actor.followers = Property(vec![Remotable::Remote(collection(&id, "followers"))]);
+    actor.liked = Property(vec![Remotable::Remote(collection(&id, "liked"))]);
+    actor.endpoints = Some(ap::Endpoints {
+        shared_inbox: Some(local.shared_inbox()),
+    });
+    actor.public_key = Some(ap::PublicKey {
+        id: Some(key_id),
+        owner: Some(id),
+        public_key_pem: Some(key.public_key_pem.clone()),
+    });
+    Ok(ap::ActorSubtypes::Application(actor))
+}
+
+pub async fn get_instance_actor<S>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: KeyStore + Remote + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    signature::authorize_key_fetch(state.as_ref(), request).await?;
+    document(render_instance(state.as_ref(), &local).await?)
+}
+
+#[derive(Deserialize)]
+pub struct CollectionPath {
+    /// Local account, or the instance actor when it is missing.
+    name: Option<String>,
+    collection: String,
+}
+
+/// One of the [COLLECTIONS] of a local account or the instance actor.
+pub async fn get_collection<S>(
+    axum::extract::Path(path): axum::extract::Path<CollectionPath>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: AccountStore<ActorInfo = Account> + Remote + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    signature::authorize_fetch(state.as_ref(), request).await?;
+    let not_found = || HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND);
+    if !COLLECTIONS.contains(&path.collection.as_str()) {
+        return Err(not_found());
+    }
+    let actor = match path.name {
+        Some(name) => {
+            let actor = local.actor(&name);
+            if state.query(&name).await?.is_none()
+                || report::is_suspended(state.as_ref(), &actor).await?
+            {
+                return Err(not_found());
+            }
+            actor
+        }
+        None => local.instance_actor(),
+    };
+    let mut collection: ap::OrderedCollection = ap::empty();
+    collection.id = Some(self::collection(&actor, &path.collection));
+    let collection = ap::OrderedCollectionSubtypes::OrderedCollection(collection);
+    let mut document = serde_json::to_value(collection)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    document["@context"] = ap::CONTEXT.clone();
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(ACTIVITY_JSON.clone())),
+        document.to_string(),
+    ))
+}
+
+fn document(actor: ap::ActorSubtypes) -> Result<impl IntoResponse, HttpError> {
+    let mut document = serde_json::to_value(actor)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    document["@context"] = ap::CONTEXT.clone();
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(ACTIVITY_JSON.clone())),
+        document.to_string(),
+    ))
+}
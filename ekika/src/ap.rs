@@ -0,0 +1,34 @@
+#![allow(clippy::all, unused)]
+use activity_vocabulary::Unit;
+use activity_vocabulary_core::*;
+use once_cell::sync::Lazy;
+include!(concat!(env!("OUT_DIR"), "/vocab.rs"));
+
+/// `@context` of documents we emit: ActivityStreams plus the extensions in `vocab/`.
+pub static CONTEXT: Lazy<serde_json::Value> = Lazy::new(|| {
+    serde_json::from_str(include_str!(concat!(env!("OUT_DIR"), "/context.json"))).unwrap()
+});
+
+/// IRI of the special collection which includes every actor.
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Instance of a generated type with every property unset, to be filled in field by field.
+pub fn empty<T: serde::de::DeserializeOwned>() -> T {
+    serde_json::from_value(serde_json::json!({})).unwrap()
+}
+
+/// Resolve the IRI which an audience-like property (e.g. [Object::to]) points to.
+pub fn iri_of(value: &Or<LinkSubtypes, Remotable<ObjectSubtypes>>) -> Option<url::Url> {
+    match value {
+        Or::Prim(LinkSubtypes::Link(link)) => Some(link.href.clone()),
+        Or::Prim(LinkSubtypes::Mention(mention)) => Some(mention.href.clone()),
+        Or::Prim(LinkSubtypes::Hashtag(hashtag)) => Some(hashtag.href.clone()),
+        Or::Snd(Remotable::Remote(iri)) => Some(iri.clone()),
+        Or::Snd(Remotable::Inline(object)) => Object::from(object.clone()).id,
+    }
+}
+
+/// Collect the IRIs of every entry of an audience-like property.
+pub fn iris_of(property: &Property<Or<LinkSubtypes, Remotable<ObjectSubtypes>>>) -> Vec<url::Url> {
+    property.0.iter().filter_map(iri_of).collect()
+}
@@ -0,0 +1,179 @@
+use std::{future::Future, sync::Arc};
+
+use axum::extract::{FromRequestParts, MatchedPath};
+use axum_helper::HttpError;
+use http::request::Parts;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    model::{
+        account::{Account, Role},
+        token::Token,
+    },
+    oauth,
+    webfinger::AccountStore,
+};
+
+pub trait TokenStore {
+    fn get_token(
+        &self,
+        digest: &str,
+    ) -> impl Future<Output = Result<Option<Token>, HttpError>> + Send;
+    fn put_token(&self, token: &Token) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_token(&self, digest: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub fn digest(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Token of an `Authorization: Bearer` header.
+pub fn bearer_token(headers: &http::HeaderMap) -> Option<&str> {
+    headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Whether `token` grants `scope`. Tokens without scopes grant everything.
+pub fn token_grants(token: &Token, scope: &str) -> bool {
+    token
+        .scopes
+        .as_ref()
+        .is_none_or(|scopes| oauth::grants(scopes, scope))
+}
+
+/// Token of a local account, provided that it grants `scope`.
+pub async fn token_of<S>(state: &S, token: &str, scope: &str) -> Result<Token, HttpError>
+where
+    S: TokenStore + Sync,
+{
+    let token = state
+        .get_token(&digest(token))
+        .await?
+        .ok_or_else(unauthorized)?;
+    if !token_grants(&token, scope) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("the token does not grant {scope}")}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    if token.account.is_none() {
+        return Err(unauthorized());
+    }
+    Ok(token)
+}
+
+/// Local account a token was issued to, provided that the token grants `scope`.
+pub async fn account_of<S>(state: &S, token: &str, scope: &str) -> Result<String, HttpError>
+where
+    S: TokenStore + Sync,
+{
+    token_of(state, token, scope)
+        .await?
+        .account
+        .ok_or_else(unauthorized)
+}
+
+/// Local account which sent the request, identified by its bearer token.
+/// The token must grant the scope [oauth::ROUTE_SCOPES] declares for the route.
+pub struct Authenticated {
+    pub account: String,
+}
+
+fn unauthorized() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "unauthorized"}),
+        http::StatusCode::UNAUTHORIZED,
+    )
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<Arc<S>> for Authenticated
+where
+    S: TokenStore + Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<S>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers).ok_or_else(unauthorized)?;
+        let scope = parts
+            .extensions
+            .get::<MatchedPath>()
+            .and_then(|route| oauth::route_scope(&parts.method, route.as_str()))
+            .ok_or_else(|| {
+                HttpError::new_json(
+                    &json!({"ok": false, "msg": "no scope is declared for the route"}),
+                    http::StatusCode::FORBIDDEN,
+                )
+            })?;
+        Ok(Self {
+            account: account_of(state.as_ref(), token, scope).await?,
+        })
+    }
+}
+
+async fn with_role<S>(
+    parts: &mut Parts,
+    state: &Arc<S>,
+    required: Role,
+) -> Result<String, HttpError>
+where
+    S: TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let Authenticated { account } = Authenticated::from_request_parts(parts, state).await?;
+    match state.query(&account).await? {
+        Some(Account { role, .. }) if role >= required => Ok(account),
+        _ => Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "forbidden"}),
+            http::StatusCode::FORBIDDEN,
+        )),
+    }
+}
+
+/// Local account with at least the `Admin` role.
+pub struct Admin {
+    pub account: String,
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<Arc<S>> for Admin
+where
+    S: TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<S>,
+    ) -> Result<Self, Self::Rejection> {
+        let account = with_role(parts, state, Role::Admin).await?;
+        Ok(Self { account })
+    }
+}
+
+/// Local account with the `Moderator` or `Admin` role.
+pub struct Moderator {
+    pub account: String,
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<Arc<S>> for Moderator
+where
+    S: TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<S>,
+    ) -> Result<Self, Self::Rejection> {
+        let account = with_role(parts, state, Role::Moderator).await?;
+        Ok(Self { account })
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashSet, future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    delivery::{self, DeliveryQueue},
+    fetch::Remote,
+    follow::FollowStore,
+    local::Local,
+    model::{actor::RemoteActor, block::Block},
+};
+
+pub trait BlockStore {
+    fn blocks(
+        &self,
+        blocker: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Block>, HttpError>> + Send;
+    fn get_block(
+        &self,
+        blocker: &url::Url,
+        blocked: &url::Url,
+    ) -> impl Future<Output = Result<Option<Block>, HttpError>> + Send;
+    fn put_block(&self, block: &Block) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_block(
+        &self,
+        blocker: &url::Url,
+        blocked: &url::Url,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub async fn is_blocked_between<S>(state: &S, a: &url::Url, b: &url::Url) -> Result<bool, HttpError>
+where
+    S: BlockStore + Sync,
+{
+    Ok(state.get_block(a, b).await?.is_some() || state.get_block(b, a).await?.is_some())
+}
+
+/// Names among the local accounts `names` which block none of `actors`.
+/// Activities fanned out from the shared inbox are dropped for the others.
+pub async fn unblocked_accounts<S, I>(
+    state: &S,
+    local: &Local,
+    names: I,
+    actors: &[&url::Url],
+) -> Result<Vec<String>, HttpError>
+where
+    S: BlockStore + Sync,
+    I: IntoIterator<Item = String>,
+{
+    let mut unblocked = Vec::new();
+    'names: for name in names {
+        let account = local.actor(&name);
+        for actor in actors {
+            if state.get_block(&account, actor).await?.is_some() {
+                continue 'names;
+            }
+        }
+        unblocked.push(name);
+    }
+    Ok(unblocked)
+}
+
+/// Actors whose content must be hidden from `viewer`.
+pub async fn hidden_actors<S>(state: &S, viewer: &url::Url) -> Result<HashSet<url::Url>, HttpError>
+where
+    S: BlockStore + Sync,
+{
+    Ok(state
+        .blocks(viewer)
+        .await?
+        .into_iter()
+        .map(|block| block.blocked)
+        .collect())
+}
+
+async fn sever_follows<S>(state: &S, a: &url::Url, b: &url::Url) -> Result<(), HttpError>
+where
+    S: FollowStore + Sync,
+{
+    state.delete_follow(a, b).await?;
+    state.delete_follow(b, a).await
+}
+
+pub async fn block<S>(
+    state: &S,
+    local: &Local,
+    blocker: &url::Url,
+    blocked: &url::Url,
+) -> Result<Block, HttpError>
+where
+    S: BlockStore + FollowStore + Remote + DeliveryQueue + Sync,
+{
+    if let Some(block) = state.get_block(blocker, blocked).await? {
+        return Ok(block);
+    }
+    sever_follows(state, blocker, blocked).await?;
+    let mut block = Block {
+        blocker: blocker.clone(),
+        blocked: blocked.clone(),
+        activity: None,
+        created: chrono::Utc::now(),
+    };
+    if !local.is_local(blocked) {
+        let activity = activity::new_activity(local, "Block", blocker, json!(blocked));
+        block.activity = activity::field_iri(&activity, "id");
+        delivery::enqueue_to_actor(state, blocker, activity, blocked).await?;
+    }
+    state.put_block(&block).await?;
+    Ok(block)
+}
+
+pub async fn unblock<S>(
+    state: &S,
+    local: &Local,
+    blocker: &url::Url,
+    blocked: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: BlockStore + Remote + DeliveryQueue + Sync,
+{
+    let Some(block) = state.get_block(blocker, blocked).await? else {
+        return Ok(());
+    };
+    state.delete_block(blocker, blocked).await?;
+    if !local.is_local(blocked) {
+        let original = json!({
+            "id": block.activity,
+            "type": "Block",
+            "actor": blocker,
+            "object": blocked,
+        });
+        let undo = activity::new_activity(local, "Undo", blocker, original);
+        delivery::enqueue_to_actor(state, blocker, undo, blocked).await?;
+    }
+    Ok(())
+}
+
+/// Inbound `Block` of a local account. We stop delivering to the blocker from now on.
+pub async fn handle_block<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: BlockStore + FollowStore + Sync,
+{
+    let Some(blocked) = activity::field_iri(activity, "object") else {
+        return Ok(());
+    };
+    if local.account_name(&blocked).is_none() {
+        return Ok(());
+    }
+    sever_follows(state, &signer.id, &blocked).await?;
+    state
+        .put_block(&Block {
+            blocker: signer.id.clone(),
+            blocked,
+            activity: activity::field_iri(activity, "id"),
+            created: chrono::Utc::now(),
+        })
+        .await
+}
+
+pub async fn handle_undo_block<S>(
+    state: &S,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: BlockStore + Sync,
+{
+    if let Some(blocked) = activity::field_iri(activity, "object") {
+        state.delete_block(&signer.id, &blocked).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct BlockRequest {
+    pub actor: url::Url,
+}
+
+#[derive(Serialize)]
+pub struct BlockView {
+    pub actor: url::Url,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn list_blocks<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<BlockView>>, HttpError>
+where
+    S: BlockStore + TokenStore + Send + Sync,
+{
+    let blocker = Local::try_from(&proxy_info)?.actor(&auth.account);
+    let blocks = state.blocks(&blocker).await?;
+    Ok(Json(
+        blocks
+            .into_iter()
+            .map(|block| BlockView {
+                actor: block.blocked,
+                created_at: block.created,
+            })
+            .collect(),
+    ))
+}
+
+pub async fn create_block<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<BlockRequest>,
+) -> Result<Json<BlockView>, HttpError>
+where
+    S: BlockStore + FollowStore + Remote + DeliveryQueue + TokenStore + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let blocker = local.actor(&auth.account);
+    if blocker == request.actor {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot block yourself"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    let block = block(state.as_ref(), &local, &blocker, &request.actor).await?;
+    Ok(Json(BlockView {
+        actor: block.blocked,
+        created_at: block.created,
+    }))
+}
+
+pub async fn delete_block<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<BlockRequest>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: BlockStore + Remote + DeliveryQueue + TokenStore + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let blocker = local.actor(&auth.account);
+    unblock(state.as_ref(), &local, &blocker, &request.actor).await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,158 @@
+use std::future::Future;
+
+use axum_helper::HttpError;
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    activity, ap,
+    block::BlockStore,
+    delivery::{self, DeliveryQueue},
+    fetch::Remote,
+    follow::{self, FollowStore},
+    list::ListStore,
+    local::Local,
+    model::{
+        boost::Boost, follow::FollowState, notification::NotificationKind, object::StoredObject,
+    },
+    mute::MuteStore,
+    notification::{self, NotificationStore},
+    stream::EventBus,
+    timeline::{self, TimelineStore},
+    visibility::Visibility,
+};
+
+pub trait BoostStore {
+    fn boosts_of(
+        &self,
+        object: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Boost>, HttpError>> + Send;
+    fn get_boost(
+        &self,
+        object: &url::Url,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<Option<Boost>, HttpError>> + Send;
+    fn put_boost(&self, boost: &Boost) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_boost(
+        &self,
+        object: &url::Url,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Recipients of a boost by `actor`: its followers and the author of the post.
+async fn recipients<S>(
+    state: &S,
+    actor: &url::Url,
+    object: &StoredObject,
+) -> Result<Vec<url::Url>, HttpError>
+where
+    S: FollowStore + Sync,
+{
+    let mut recipients = state
+        .followers(actor)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.follower)
+        .collect::<Vec<_>>();
+    recipients.push(object.attributed_to.clone());
+    Ok(recipients)
+}
+
+/// Boost `object` as the local actor `actor`. Only public and unlisted posts can be boosted.
+pub async fn boost<S>(
+    state: &S,
+    local: &Local,
+    actor: &url::Url,
+    object: &StoredObject,
+) -> Result<Boost, HttpError>
+where
+    S: BoostStore
+        + FollowStore
+        + TimelineStore
+        + NotificationStore
+        + MuteStore
+        + BlockStore
+        + ListStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    if let Some(boost) = state.get_boost(&object.id, actor).await? {
+        return Ok(boost);
+    }
+    if !matches!(object.visibility, Visibility::Public | Visibility::Unlisted) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "only public posts can be boosted"}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    let mut activity = activity::new_activity(local, "Announce", actor, json!(object.id));
+    activity["to"] = json!([ap::PUBLIC]);
+    activity["cc"] = json!([object.attributed_to, follow::followers_collection(actor)]);
+    activity["published"] = json!(chrono::Utc::now());
+    let boost = Boost {
+        object: object.id.clone(),
+        actor: actor.clone(),
+        activity: activity::field_iri(&activity, "id").unwrap(),
+        created: chrono::Utc::now(),
+    };
+    state.put_boost(&boost).await?;
+    timeline::try_distribute(state, local, object, Some(actor)).await;
+    let kind = NotificationKind::Reblog;
+    notification::try_notify(
+        state,
+        local,
+        &object.attributed_to,
+        kind,
+        actor,
+        Some(&object.id),
+    )
+    .await;
+    info!(
+        actor = actor.to_string(),
+        object = object.id.to_string(),
+        "boost"
+    );
+    let recipients = recipients(state, actor, object).await?;
+    delivery::deliver_activity(
+        state,
+        local,
+        actor,
+        activity,
+        recipients,
+        Visibility::Public,
+    )
+    .await?;
+    Ok(boost)
+}
+
+/// Undo the boost of `object` by the local actor `actor`, if any.
+pub async fn unboost<S>(
+    state: &S,
+    local: &Local,
+    actor: &url::Url,
+    object: &StoredObject,
+) -> Result<(), HttpError>
+where
+    S: BoostStore + FollowStore + BlockStore + Remote + DeliveryQueue + Sync,
+{
+    let Some(boost) = state.get_boost(&object.id, actor).await? else {
+        return Ok(());
+    };
+    state.delete_boost(&object.id, actor).await?;
+    let original = json!({
+        "id": boost.activity,
+        "type": "Announce",
+        "actor": actor,
+        "object": object.id,
+    });
+    let mut undo = activity::new_activity(local, "Undo", actor, original);
+    undo["to"] = json!([ap::PUBLIC]);
+    undo["cc"] = json!([object.attributed_to, follow::followers_collection(actor)]);
+    let recipients = recipients(state, actor, object).await?;
+    delivery::deliver_activity(state, local, actor, undo, recipients, Visibility::Public).await?;
+    Ok(())
+}
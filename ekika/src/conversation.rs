@@ -0,0 +1,263 @@
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ap,
+    auth::{Authenticated, TokenStore},
+    block::BlockStore,
+    delivery::{self, DeliveryQueue},
+    emoji,
+    fetch::Remote,
+    follow::{self, FollowStore},
+    list::ListStore,
+    local::Local,
+    media::{self, MediaStore},
+    model::{conversation::Conversation, object::StoredObject},
+    mute::MuteStore,
+    notification::{self, NotificationStore},
+    object::ObjectStore,
+    reaction,
+    stream::EventBus,
+    timeline::{self, TimelineStore},
+    visibility::{self, Visibility},
+};
+
+pub trait ConversationStore {
+    fn conversations(
+        &self,
+        owner: &str,
+    ) -> impl Future<Output = Result<Vec<Conversation>, HttpError>> + Send;
+    fn get_conversation(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Conversation>, HttpError>> + Send;
+    fn put_conversation(
+        &self,
+        conversation: &Conversation,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+#[derive(Serialize)]
+pub struct ConversationView {
+    pub id: String,
+    pub participants: Vec<url::Url>,
+    pub last_status: url::Url,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub unread: bool,
+}
+
+impl From<Conversation> for ConversationView {
+    fn from(conversation: Conversation) -> Self {
+        Self {
+            id: conversation.id,
+            participants: conversation.participants,
+            last_status: conversation.last_status,
+            updated_at: conversation.updated,
+            unread: conversation.unread,
+        }
+    }
+}
+
+/// `context`, or the OStatus `conversation` used by Mastodon, of an object.
+pub fn context_of(body: &serde_json::Value) -> Option<url::Url> {
+    ["context", "conversation"]
+        .iter()
+        .filter_map(|name| body.get(name)?.as_str()?.parse().ok())
+        .next()
+}
+
+/// Record a direct object in `owner`'s conversation list.
+/// Objects are grouped by their context, or by participants when they have none.
+pub async fn track<S>(
+    state: &S,
+    local: &Local,
+    owner: &str,
+    object: &StoredObject,
+    unread: bool,
+) -> Result<Conversation, HttpError>
+where
+    S: ConversationStore + Sync,
+{
+    let body: serde_json::Value = serde_json::from_str(&object.body)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut participants = object
+        .audience
+        .recipients()
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    participants.insert(object.attributed_to.clone());
+    participants.remove(&local.actor(owner));
+    let context = context_of(&body);
+    let key = match &context {
+        Some(context) => context.to_string(),
+        None => participants
+            .iter()
+            .map(url::Url::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    let conversation = Conversation {
+        owner: owner.to_string(),
+        id: format!("{:x}", Sha256::digest(key.as_bytes())),
+        context,
+        participants: participants.into_iter().collect(),
+        last_status: object.id.clone(),
+        updated: chrono::Utc::now(),
+        unread,
+    };
+    state.put_conversation(&conversation).await?;
+    Ok(conversation)
+}
+
+pub async fn list_conversations<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<ConversationView>>, HttpError>
+where
+    S: ConversationStore + TokenStore + Send + Sync,
+{
+    let mut conversations = state.conversations(&auth.account).await?;
+    conversations.sort_by_key(|conversation| std::cmp::Reverse(conversation.updated));
+    Ok(Json(conversations.into_iter().map(Into::into).collect()))
+}
+
+pub async fn mark_read<S>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<ConversationView>, HttpError>
+where
+    S: ConversationStore + TokenStore + Send + Sync,
+{
+    let mut conversation = state
+        .get_conversation(&auth.account, &id)
+        .await?
+        .ok_or_else(|| HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND))?;
+    conversation.unread = false;
+    state.put_conversation(&conversation).await?;
+    Ok(Json(conversation.into()))
+}
+
+#[derive(Deserialize)]
+pub struct DirectMessage {
+    pub to: Vec<url::Url>,
+    pub content: String,
+    #[serde(default)]
+    pub in_reply_to: Option<url::Url>,
+    /// Ids of uploaded media.
+    #[serde(default)]
+    pub media: Vec<String>,
+}
+
+pub async fn send_direct<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(message): Json<DirectMessage>,
+) -> Result<Json<ConversationView>, HttpError>
+where
+    S: ConversationStore
+        + ObjectStore
+        + MediaStore
+        + BlockStore
+        + ListStore
+        + FollowStore
+        + TimelineStore
+        + NotificationStore
+        + EventBus
+        + MuteStore
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    if message.to.is_empty() || message.to.iter().any(visibility::is_public) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "direct messages must address actors only"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    let local = Local::try_from(&proxy_info)?;
+    let author = local.actor(&auth.account);
+    let context = match &message.in_reply_to {
+        Some(parent) => {
+            let parent = reaction::visible_object(state.as_ref(), parent, &author).await?;
+            serde_json::from_str(&parent.body)
+                .ok()
+                .and_then(|body| context_of(&body))
+        }
+        None => None,
+    }
+    .unwrap_or_else(|| local.context(&uuid::Uuid::new_v4().to_string()));
+    let attachments = media::attachments_of(state.as_ref(), &auth.account, &message.media)
+        .await?
+        .iter()
+        .map(media::document)
+        .collect::<Vec<_>>();
+    let id = local.new_object();
+    let published = chrono::Utc::now();
+    let mut tags = message
+        .to
+        .iter()
+        .map(|to| json!({"type": "Mention", "href": to}))
+        .collect::<Vec<_>>();
+    tags.extend(emoji::tags(state.as_ref(), [message.content.as_str()]).await?);
+    let note = json!({
+        "id": id,
+        "type": "Note",
+        "attributedTo": author,
+        "to": message.to,
+        "content": message.content,
+        "inReplyTo": message.in_reply_to,
+        "context": context,
+        "conversation": context,
+        "published": published,
+        "tag": tags,
+        "attachment": attachments,
+    });
+    let object = StoredObject::new(note.clone(), &follow::followers_collection(&author))
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    if object.visibility != Visibility::Direct {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "direct messages must not address collections"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    state.put_object(&object).await?;
+    let create = json!({
+        "@context": *ap::CONTEXT,
+        "id": format!("{id}/activity"),
+        "type": "Create",
+        "actor": author,
+        "to": message.to,
+        "published": published,
+        "object": note,
+    });
+    let local_recipients = delivery::deliver_activity(
+        state.as_ref(),
+        &local,
+        &author,
+        create,
+        object.audience.recipients(),
+        object.visibility,
+    )
+    .await?;
+    for recipient in local_recipients {
+        if recipient != auth.account {
+            track(state.as_ref(), &local, &recipient, &object, true).await?;
+        }
+    }
+    timeline::try_distribute(state.as_ref(), &local, &object, None).await;
+    notification::notify_post(state.as_ref(), &local, &object).await;
+    let conversation = track(state.as_ref(), &local, &auth.account, &object, false).await?;
+    Ok(Json(conversation.into()))
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashSet, future::Future, sync::Arc, time::Duration};
+
+use axum_helper::HttpError;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    block::{self, BlockStore},
+    domain,
+    fetch::{self, Remote},
+    local::Local,
+    object::ACTIVITY_JSON,
+    proxy, relay, report,
+    signature::{self, KeyStore},
+    visibility::{self, Visibility},
+};
+
+const MAX_ATTEMPTS: u32 = 6;
+
+/// Deliveries waiting for the worker before enqueueing waits for room.
+pub const QUEUE_SIZE: usize = 1024;
+
+/// Delivery of one activity to one inbox. Jobs are stored until they are done,
+/// so that the ones still pending are picked up again after a restart.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeliveryJob {
+    pub id: String,
+    pub inbox: url::Url,
+    /// Local actor whose key signs the request.
+    pub signer: url::Url,
+    pub activity: String,
+}
+
+impl DeliveryJob {
+    pub fn new(inbox: url::Url, signer: url::Url, activity: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            inbox,
+            signer,
+            activity,
+        }
+    }
+}
+
+pub trait DeliveryQueue {
+    /// Store `job` and hand it to the worker.
+    fn enqueue(&self, job: DeliveryJob) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub trait DeliveryStore {
+    fn pending_deliveries(
+        &self,
+    ) -> impl Future<Output = Result<Vec<DeliveryJob>, HttpError>> + Send;
+    fn delete_delivery(&self, id: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+#[derive(Default, Debug)]
+pub struct Targets {
+    pub inboxes: HashSet<url::Url>,
+    /// Names of local accounts among the recipients. They are handled in-process.
+    pub local: HashSet<String>,
+}
+
+/// Shared inboxes fan out to every follower on the remote server,
+/// so they are only used for objects which any of those followers could see.
+pub fn uses_shared_inbox(visibility: Visibility) -> bool {
+    visibility != Visibility::Direct
+}
+
+/// Resolve the inboxes of `recipients`, skipping actors blocked by or blocking `sender`
+/// and suspended accounts. Nothing is delivered for a suspended sender.
+pub async fn targets<S, I>(
+    state: &S,
+    local: &Local,
+    sender: &url::Url,
+    recipients: I,
+    visibility: Visibility,
+) -> Result<Targets, HttpError>
+where
+    S: Remote + BlockStore + Sync,
+    I: IntoIterator<Item = url::Url>,
+{
+    let mut targets = Targets::default();
+    if report::is_suspended(state, sender).await? {
+        return Ok(targets);
+    }
+    for recipient in recipients {
+        if block::is_blocked_between(state, sender, &recipient).await? {
+            continue;
+        }
+        if report::is_suspended(state, &recipient).await? {
+            continue;
+        }
+        if !domain::federates(state, local, &recipient).await? {
+            continue;
+        }
+        if let Some(name) = local.account_name(&recipient) {
+            targets.local.insert(name);
+            continue;
+        }
+        if local.is_local(&recipient) {
+            continue;
+        }
+        let actor = match fetch::resolve_actor(state, &recipient).await {
+            Ok(actor) => actor,
+            Err(e) => {
+                warn!(
+                    recipient = recipient.to_string(),
+                    status = e.status.as_u16(),
+                    "resolve_recipient"
+                );
+                continue;
+            }
+        };
+        let inbox = match actor.shared_inbox {
+            Some(shared) if uses_shared_inbox(visibility) => shared,
+            _ => actor.inbox,
+        };
+        // Remote documents name the inbox, which must not point into our own network.
+        if !proxy::is_public_url(&inbox) {
+            warn!(inbox = inbox.to_string(), "internal_inbox");
+            continue;
+        }
+        targets.inboxes.insert(inbox);
+    }
+    Ok(targets)
+}
+
+/// Queue `activity` for every remote inbox and return the local recipients.
+/// Public activities are also pushed to the relays which accept them.
+pub async fn deliver_activity<S, I>(
+    state: &S,
+    local: &Local,
+    signer: &url::Url,
+    mut activity: serde_json::Value,
+    recipients: I,
+    visibility: Visibility,
+) -> Result<HashSet<String>, HttpError>
+where
+    S: Remote + BlockStore + DeliveryQueue + Sync,
+    I: IntoIterator<Item = url::Url>,
+{
+    visibility::strip_blind(&mut activity);
+    let mut targets = targets(state, local, signer, recipients, visibility).await?;
+    if visibility == Visibility::Public && !report::is_suspended(state, signer).await? {
+        for inbox in relay::push_inboxes(state).await? {
+            if domain::federates(state, local, &inbox).await? {
+                targets.inboxes.insert(inbox);
+            }
+        }
+    }
+    let activity = activity.to_string();
+    for inbox in targets.inboxes {
+        state
+            .enqueue(DeliveryJob::new(inbox, signer.clone(), activity.clone()))
+            .await?;
+    }
+    Ok(targets.local)
+}
+
+/// Queue `activity` for the personal inbox of a single remote actor, bypassing block checks.
+/// Used for activities which are about the relationship itself, such as `Block` or `Reject`.
+pub async fn enqueue_to_actor<S>(
+    state: &S,
+    signer: &url::Url,
+    mut activity: serde_json::Value,
+    actor: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: Remote + DeliveryQueue + Sync,
+{
+    if !domain::remote_federates(state, actor).await?
+        || report::is_suspended(state, signer).await?
+        || report::is_suspended(state, actor).await?
+    {
+        return Ok(());
+    }
+    visibility::strip_blind(&mut activity);
+    let actor = fetch::resolve_actor(state, actor).await?;
+    if !proxy::is_public_url(&actor.inbox) {
+        warn!(inbox = actor.inbox.to_string(), "internal_inbox");
+        return Ok(());
+    }
+    state
+        .enqueue(DeliveryJob::new(
+            actor.inbox,
+            signer.clone(),
+            activity.to_string(),
+        ))
+        .await
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DeliveryError {
+    #[error("signature: {0}")]
+    Signature(#[from] signature::SignatureError),
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+pub async fn deliver(
+    client: &reqwest::Client,
+    key: &crate::model::key::KeyPair,
+    job: &DeliveryJob,
+) -> Result<(), DeliveryError> {
+    let mut key_id = job.signer.clone();
+    key_id.set_fragment(Some("main-key"));
+    let mut request = client
+        .post(job.inbox.clone())
+        .header(http::header::CONTENT_TYPE, ACTIVITY_JSON.as_ref())
+        .body(job.activity.clone())
+        .build()?;
+    signature::sign(&mut request, &key_id, key)?;
+    client.execute(request).await?.error_for_status()?;
+    Ok(())
+}
+
+/// Deliver queued jobs, retrying failures with exponential backoff.
+/// A job is removed from the store once it is delivered or has run out of attempts.
+pub async fn worker<S>(
+    state: Arc<S>,
+    client: reqwest::Client,
+    mut jobs: mpsc::Receiver<DeliveryJob>,
+) where
+    S: KeyStore + DeliveryStore + Send + Sync + 'static,
+{
+    while let Some(job) = jobs.recv().await {
+        let state = state.clone();
+        let client = client.clone();
+        tokio::spawn(async move {
+            attempt(state.as_ref(), &client, &job).await;
+            if let Err(e) = state.delete_delivery(&job.id).await {
+                warn!(id = job.id, status = e.status.as_u16(), "delete_delivery");
+            }
+        });
+    }
+}
+
+async fn attempt<S>(state: &S, client: &reqwest::Client, job: &DeliveryJob)
+where
+    S: KeyStore + Sync,
+{
+    let key = match signature::key_of(state, &job.signer).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!(
+                signer = job.signer.to_string(),
+                status = e.status.as_u16(),
+                "signer_key"
+            );
+            return;
+        }
+    };
+    for attempt in 0..MAX_ATTEMPTS {
+        match deliver(client, &key, job).await {
+            Ok(()) => {
+                info!(inbox = job.inbox.to_string(), "delivered");
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    inbox = job.inbox.to_string(),
+                    attempt,
+                    error = e.to_string(),
+                    "delivery_failed"
+                );
+                if attempt + 1 < MAX_ATTEMPTS {
+                    tokio::time::sleep(Duration::from_secs(2u64.pow(attempt) * 10)).await;
+                }
+            }
+        }
+    }
+}
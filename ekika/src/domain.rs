@@ -0,0 +1,472 @@
+use std::{future::Future, sync::Arc};
+
+use axum::{response::IntoResponse, Json};
+use axum_helper::{HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{Admin, TokenStore},
+    local::Local,
+    model::{
+        account::Account,
+        domain::{DomainAllow, DomainPolicy, Severity},
+    },
+    webfinger::AccountStore,
+};
+
+#[derive(Clone, Copy, Default, Serialize, PartialEq, Eq, Debug, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FederationMode {
+    /// Federate with everyone except suspended domains.
+    #[default]
+    Open,
+    /// Federate only with explicitly allowed domains.
+    Allowlist,
+}
+
+pub trait DomainPolicyStore {
+    fn federation_mode(&self) -> FederationMode;
+    fn policies(&self) -> impl Future<Output = Result<Vec<DomainPolicy>, HttpError>> + Send;
+    fn get_policy(
+        &self,
+        domain: &str,
+    ) -> impl Future<Output = Result<Option<DomainPolicy>, HttpError>> + Send;
+    fn put_policy(
+        &self,
+        policy: &DomainPolicy,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_policy(&self, domain: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn allowed_domains(&self) -> impl Future<Output = Result<Vec<DomainAllow>, HttpError>> + Send;
+    fn get_allowed(
+        &self,
+        domain: &str,
+    ) -> impl Future<Output = Result<Option<DomainAllow>, HttpError>> + Send;
+    fn put_allowed(
+        &self,
+        allow: &DomainAllow,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_allowed(&self, domain: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Lowercased ASCII (punycode) form of a domain name.
+pub fn normalize(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    match url::Host::parse(&domain).ok()? {
+        url::Host::Domain(domain) => Some(domain),
+        host => Some(host.to_string()),
+    }
+}
+
+/// The host itself followed by each of its parent domains.
+fn candidates(host: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(host), |host| {
+        host.split_once('.').map(|(_, parent)| parent)
+    })
+}
+
+/// The most specific policy which applies to the host of `iri`.
+pub async fn policy_for<S>(state: &S, iri: &url::Url) -> Result<Option<DomainPolicy>, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    let Some(host) = iri.host_str().and_then(normalize) else {
+        return Ok(None);
+    };
+    for candidate in candidates(&host) {
+        if let Some(policy) = state.get_policy(candidate).await? {
+            return Ok(Some(policy));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the host of `iri` is on the allowlist, either itself or through a parent domain.
+pub async fn is_allowed<S>(state: &S, iri: &url::Url) -> Result<bool, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    let Some(host) = iri.host_str().and_then(normalize) else {
+        return Ok(false);
+    };
+    for candidate in candidates(&host) {
+        if state.get_allowed(candidate).await?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The single decision point for federating with the server of `iri`.
+/// Every inbound and outbound path goes through this. This server always federates with
+/// itself, as its own host is never on its allowlist.
+pub async fn federates<S>(state: &S, local: &Local, iri: &url::Url) -> Result<bool, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    if local.is_local(iri) {
+        return Ok(true);
+    }
+    remote_federates(state, iri).await
+}
+
+/// [federates] for IRIs of other servers, such as the ones which are fetched over HTTP or
+/// which sign requests. A local IRI is treated like any other host.
+pub async fn remote_federates<S>(state: &S, iri: &url::Url) -> Result<bool, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    if state.federation_mode() == FederationMode::Allowlist && !is_allowed(state, iri).await? {
+        return Ok(false);
+    }
+    Ok(!matches!(
+        policy_for(state, iri).await?,
+        Some(DomainPolicy {
+            severity: Severity::Suspend,
+            ..
+        })
+    ))
+}
+
+/// Fails with 403 unless [remote_federates] allows the server of `iri`.
+pub async fn ensure_federates<S>(state: &S, iri: &url::Url) -> Result<(), HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    if remote_federates(state, iri).await? {
+        Ok(())
+    } else {
+        Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "domain does not federate with this server"}),
+            http::StatusCode::FORBIDDEN,
+        ))
+    }
+}
+
+/// Content from silenced domains is accepted but kept off public timelines.
+pub async fn is_silenced<S>(state: &S, iri: &url::Url) -> Result<bool, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    Ok(matches!(
+        policy_for(state, iri).await?,
+        Some(DomainPolicy {
+            severity: Severity::Silence | Severity::Suspend,
+            ..
+        })
+    ))
+}
+
+pub async fn rejects_media<S>(state: &S, iri: &url::Url) -> Result<bool, HttpError>
+where
+    S: DomainPolicyStore + Sync,
+{
+    Ok(matches!(
+        policy_for(state, iri).await?,
+        Some(policy) if policy.reject_media || policy.severity == Severity::Suspend
+    ))
+}
+
+/// Mastodon's obfuscation: keep the outer quarters and the dots.
+pub fn obfuscate(domain: &str) -> String {
+    let length = domain.chars().count();
+    let visible = length / 4;
+    domain
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if i > visible && i < length - visible && c != '.' {
+                '*'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DomainPolicyEntry {
+    pub domain: String,
+    pub severity: Severity,
+    #[serde(default)]
+    pub reject_media: bool,
+    #[serde(default)]
+    pub reject_reports: bool,
+    #[serde(default)]
+    pub public_comment: Option<String>,
+    #[serde(default)]
+    pub private_comment: Option<String>,
+    #[serde(default)]
+    pub obfuscate: bool,
+}
+
+impl From<DomainPolicy> for DomainPolicyEntry {
+    fn from(policy: DomainPolicy) -> Self {
+        Self {
+            domain: policy.domain,
+            severity: policy.severity,
+            reject_media: policy.reject_media,
+            reject_reports: policy.reject_reports,
+            public_comment: policy.public_comment,
+            private_comment: policy.private_comment,
+            obfuscate: policy.obfuscate,
+        }
+    }
+}
+
+impl DomainPolicyEntry {
+    fn into_policy(self) -> Result<DomainPolicy, HttpError> {
+        let domain = normalize(&self.domain)
+            .ok_or_else(|| json!({"ok": false, "msg": format!("invalid domain: {}", self.domain)}))
+            .http_error_json(http::StatusCode::BAD_REQUEST)?;
+        Ok(DomainPolicy {
+            domain,
+            severity: self.severity,
+            reject_media: self.reject_media,
+            reject_reports: self.reject_reports,
+            public_comment: self.public_comment.filter(|c| !c.is_empty()),
+            private_comment: self.private_comment.filter(|c| !c.is_empty()),
+            obfuscate: self.obfuscate,
+            created: chrono::Utc::now(),
+        })
+    }
+}
+
+/// Row of Mastodon's domain block export.
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    #[serde(rename = "#domain")]
+    domain: String,
+    #[serde(rename = "#severity")]
+    severity: Severity,
+    #[serde(rename = "#reject_media", default)]
+    reject_media: bool,
+    #[serde(rename = "#reject_reports", default)]
+    reject_reports: bool,
+    #[serde(rename = "#public_comment", default)]
+    public_comment: Option<String>,
+    #[serde(rename = "#obfuscate", default)]
+    obfuscate: bool,
+}
+
+pub fn to_csv(policies: Vec<DomainPolicy>) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for policy in policies {
+        writer.serialize(CsvRow {
+            domain: policy.domain,
+            severity: policy.severity,
+            reject_media: policy.reject_media,
+            reject_reports: policy.reject_reports,
+            public_comment: policy.public_comment,
+            obfuscate: policy.obfuscate,
+        })?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Parse a Mastodon export. Plain lists of domains without a header are imported as suspensions.
+pub fn from_csv(src: &str) -> Result<Vec<DomainPolicyEntry>, csv::Error> {
+    if !src.trim_start().starts_with("#domain") {
+        return Ok(src
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|domain| DomainPolicyEntry {
+                domain: domain.to_string(),
+                severity: Severity::Suspend,
+                reject_media: false,
+                reject_reports: false,
+                public_comment: None,
+                private_comment: None,
+                obfuscate: false,
+            })
+            .collect());
+    }
+    csv::Reader::from_reader(src.as_bytes())
+        .deserialize::<CsvRow>()
+        .map(|row| {
+            row.map(|row| DomainPolicyEntry {
+                domain: row.domain,
+                severity: row.severity,
+                reject_media: row.reject_media,
+                reject_reports: row.reject_reports,
+                public_comment: row.public_comment,
+                private_comment: None,
+                obfuscate: row.obfuscate,
+            })
+        })
+        .collect()
+}
+
+pub async fn list_policies<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<DomainPolicyEntry>>, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut policies = state.policies().await?;
+    policies.sort_by(|a, b| a.domain.cmp(&b.domain));
+    Ok(Json(policies.into_iter().map(Into::into).collect()))
+}
+
+pub async fn put_policy<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(entry): Json<DomainPolicyEntry>,
+) -> Result<Json<DomainPolicyEntry>, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let policy = entry.into_policy()?;
+    state.put_policy(&policy).await?;
+    Ok(Json(policy.into()))
+}
+
+pub async fn delete_policy<S>(
+    _: Admin,
+    axum::extract::Path(domain): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let domain = normalize(&domain)
+        .ok_or("invalid domain")
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    state.delete_policy(&domain).await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+pub async fn export_policies<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut policies = state.policies().await?;
+    policies.sort_by(|a, b| a.domain.cmp(&b.domain));
+    let csv = to_csv(policies)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(mime::TEXT_CSV)),
+        csv,
+    ))
+}
+
+pub async fn import_policies<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    body: String,
+) -> Result<Json<serde_json::Value>, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let entries = from_csv(&body)
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    let policies = entries
+        .into_iter()
+        .map(DomainPolicyEntry::into_policy)
+        .collect::<Result<Vec<_>, _>>()?;
+    for policy in &policies {
+        state.put_policy(policy).await?;
+    }
+    Ok(Json(json!({"ok": true, "imported": policies.len()})))
+}
+
+#[derive(Serialize)]
+pub struct PublicDomainPolicy {
+    pub domain: String,
+    pub severity: Severity,
+    pub comment: Option<String>,
+}
+
+/// Moderated domains as disclosed to the public: no private comments, obfuscated names.
+pub async fn public_policies<S>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<PublicDomainPolicy>>, HttpError>
+where
+    S: DomainPolicyStore + Send + Sync,
+{
+    let mut policies = state.policies().await?;
+    policies.sort_by(|a, b| a.domain.cmp(&b.domain));
+    Ok(Json(
+        policies
+            .into_iter()
+            .filter(|policy| policy.severity != Severity::Noop || policy.reject_media)
+            .map(|policy| PublicDomainPolicy {
+                domain: if policy.obfuscate {
+                    obfuscate(&policy.domain)
+                } else {
+                    policy.domain
+                },
+                severity: policy.severity,
+                comment: policy.public_comment,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DomainAllowEntry {
+    pub domain: String,
+}
+
+pub async fn list_allowed<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<serde_json::Value>, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut allowed = state.allowed_domains().await?;
+    allowed.sort_by(|a, b| a.domain.cmp(&b.domain));
+    let domains = allowed
+        .into_iter()
+        .map(|allow| DomainAllowEntry {
+            domain: allow.domain,
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(
+        json!({"mode": state.federation_mode(), "domains": domains}),
+    ))
+}
+
+pub async fn put_allowed<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(entry): Json<DomainAllowEntry>,
+) -> Result<Json<DomainAllowEntry>, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let domain = normalize(&entry.domain)
+        .ok_or_else(|| json!({"ok": false, "msg": format!("invalid domain: {}", entry.domain)}))
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    state
+        .put_allowed(&DomainAllow {
+            domain: domain.clone(),
+            created: chrono::Utc::now(),
+        })
+        .await?;
+    Ok(Json(DomainAllowEntry { domain }))
+}
+
+pub async fn delete_allowed<S>(
+    _: Admin,
+    axum::extract::Path(domain): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: DomainPolicyStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let domain = normalize(&domain)
+        .ok_or("invalid domain")
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    state.delete_allowed(&domain).await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
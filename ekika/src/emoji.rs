@@ -0,0 +1,524 @@
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use activity_vocabulary_core::{xsd, Or, Property, Remotable};
+use axum::{extract::Multipart, response::IntoResponse, Json};
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity, actor, ap,
+    auth::{Admin, TokenStore},
+    local::Local,
+    model::{account::Account, emoji::CustomEmoji},
+    object::ACTIVITY_JSON,
+    proxy::{self, MediaCacheStore},
+    storage::{BlobStore, MediaFetcher},
+    webfinger::AccountStore,
+};
+
+/// Largest image accepted for a custom emoji.
+pub const MAX_EMOJI_SIZE: u64 = 256 * 1024;
+
+pub trait EmojiStore {
+    fn emojis(&self) -> impl Future<Output = Result<Vec<CustomEmoji>, HttpError>> + Send;
+    fn get_emoji(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<CustomEmoji>, HttpError>> + Send;
+    fn put_emoji(&self, emoji: &CustomEmoji) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_emoji(&self, id: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub fn is_shortcode(shortcode: &str) -> bool {
+    shortcode.len() >= 2
+        && shortcode
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Shortcodes written as `:shortcode:` in `text`. As in Mastodon, the colons must not touch
+/// a letter, digit or another colon, so that times such as `10:30:45` and URLs don't match.
+pub fn shortcodes_in(text: &str) -> BTreeSet<&str> {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric() && c != ':');
+    let colons = text.match_indices(':').map(|(i, _)| i).collect::<Vec<_>>();
+    colons
+        .windows(2)
+        .filter(|pair| is_boundary(text[..pair[0]].chars().next_back()))
+        .filter(|pair| is_boundary(text[pair[1] + 1..].chars().next()))
+        .map(|pair| &text[pair[0] + 1..pair[1]])
+        .filter(|shortcode| is_shortcode(shortcode))
+        .collect()
+}
+
+fn extension_of(content_type: &str) -> Option<&'static str> {
+    match content_type {
+        "image/png" => Some("png"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        _ => None,
+    }
+}
+
+/// `Emoji` object of a custom emoji, as used in `tag`.
+pub fn document(emoji: &CustomEmoji) -> ap::Emoji {
+    let mut icon: ap::Image = ap::empty();
+    icon.url = Property(vec![Or::Prim(emoji.image.clone())]);
+    icon.media_type = emoji.media_type.clone();
+    let mut document: ap::Emoji = ap::empty();
+    document.id = Some(emoji.uri.clone());
+    document.name = actor::text(&format!(":{}:", emoji.shortcode));
+    document.updated = Some(xsd::DateTime::WithOffset(emoji.updated.fixed_offset()));
+    document.icon = Property(vec![Or::Snd(Remotable::Inline(ap::ImageSubtypes::Image(
+        icon,
+    )))]);
+    document
+}
+
+/// Local emoji used in any of `texts`.
+pub async fn used_in<'a, S, I>(state: &S, texts: I) -> Result<Vec<CustomEmoji>, HttpError>
+where
+    S: EmojiStore + Sync,
+    I: IntoIterator<Item = &'a str>,
+{
+    let shortcodes = texts
+        .into_iter()
+        .flat_map(shortcodes_in)
+        .collect::<BTreeSet<_>>();
+    let mut emojis = Vec::new();
+    for shortcode in shortcodes {
+        if let Some(emoji) = state.get_emoji(shortcode).await? {
+            emojis.push(emoji);
+        }
+    }
+    Ok(emojis)
+}
+
+/// `tag` entries for the local emoji used in any of `texts`.
+pub async fn tags<'a, S, I>(state: &S, texts: I) -> Result<Vec<serde_json::Value>, HttpError>
+where
+    S: EmojiStore + Sync,
+    I: IntoIterator<Item = &'a str>,
+{
+    used_in(state, texts)
+        .await?
+        .iter()
+        .map(|emoji| {
+            serde_json::to_value(document(emoji))
+                .map_err(|e| e.to_string())
+                .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)
+        })
+        .collect()
+}
+
+fn href(value: &serde_json::Value) -> Option<url::Url> {
+    match value {
+        serde_json::Value::String(url) => url.parse().ok(),
+        serde_json::Value::Object(link) => link.get("href")?.as_str()?.parse().ok(),
+        serde_json::Value::Array(values) => values.first().and_then(href),
+        _ => None,
+    }
+}
+
+/// Cache the `Emoji` tags of a document which came from the server of `origin`.
+pub async fn ingest<S>(
+    state: &S,
+    origin: &url::Url,
+    document: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: EmojiStore + Sync,
+{
+    let Some(domain) = origin.host_str() else {
+        return Ok(());
+    };
+    let tags = match document.get("tag") {
+        Some(serde_json::Value::Array(tags)) => tags.iter().collect(),
+        Some(tag) => vec![tag],
+        None => return Ok(()),
+    };
+    for tag in tags {
+        if activity::kind(tag) != Some("Emoji") {
+            continue;
+        }
+        let Some(shortcode) = tag
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map(|name| name.trim_matches(':'))
+            .filter(|name| is_shortcode(name))
+        else {
+            continue;
+        };
+        let Some(icon) = tag.get("icon") else {
+            continue;
+        };
+        let Some(image) = icon.get("url").and_then(href) else {
+            continue;
+        };
+        let uri = activity::iri(tag).unwrap_or_else(|| image.clone());
+        if uri.origin() != origin.origin() {
+            debug!(emoji = uri.to_string(), "foreign_emoji");
+            continue;
+        }
+        let id = format!("{shortcode}@{domain}");
+        let media_type = icon
+            .get("mediaType")
+            .and_then(|media_type| media_type.as_str())
+            .map(str::to_string);
+        let existing = state.get_emoji(&id).await?;
+        if existing
+            .as_ref()
+            .is_some_and(|existing| existing.image == image && existing.uri == uri)
+        {
+            continue;
+        }
+        state
+            .put_emoji(&CustomEmoji {
+                id,
+                shortcode: shortcode.to_string(),
+                domain: Some(domain.to_string()),
+                uri,
+                image,
+                media_type,
+                category: existing.and_then(|existing| existing.category),
+                updated: chrono::Utc::now(),
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct EmojiView {
+    pub id: String,
+    pub shortcode: String,
+    pub domain: Option<String>,
+    pub url: url::Url,
+    pub category: Option<String>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<CustomEmoji> for EmojiView {
+    fn from(emoji: CustomEmoji) -> Self {
+        Self {
+            id: emoji.id,
+            shortcode: emoji.shortcode,
+            domain: emoji.domain,
+            url: emoji.image,
+            category: emoji.category,
+            updated_at: emoji.updated,
+        }
+    }
+}
+
+/// Emoji registry of the instance, for emoji pickers.
+pub async fn list_emojis<S>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<EmojiView>>, HttpError>
+where
+    S: EmojiStore + Send + Sync,
+{
+    let mut emojis = state
+        .emojis()
+        .await?
+        .into_iter()
+        .filter(|emoji| emoji.domain.is_none())
+        .collect::<Vec<_>>();
+    emojis.sort_by(|a, b| (&a.category, &a.shortcode).cmp(&(&b.category, &b.shortcode)));
+    Ok(Json(emojis.into_iter().map(Into::into).collect()))
+}
+
+fn emoji_not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "emoji not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+pub async fn get_emoji<S>(
+    axum::extract::Path(shortcode): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: EmojiStore + Send + Sync,
+{
+    let emoji = state
+        .get_emoji(&shortcode)
+        .await?
+        .filter(|emoji| emoji.domain.is_none())
+        .ok_or_else(emoji_not_found)?;
+    let mut document = serde_json::to_value(document(&emoji))
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    document["@context"] = ap::CONTEXT.clone();
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(ACTIVITY_JSON.clone())),
+        document.to_string(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RemoteEmojiQuery {
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+/// Emojis cached from remote servers. Their images are served through the media proxy.
+pub async fn list_remote_emojis<S>(
+    _: Admin,
+    axum::extract::Query(query): axum::extract::Query<RemoteEmojiQuery>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<EmojiView>>, HttpError>
+where
+    S: EmojiStore + MediaCacheStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let mut emojis = state
+        .emojis()
+        .await?
+        .into_iter()
+        .filter(|emoji| match (&emoji.domain, &query.domain) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(domain), Some(wanted)) => domain == wanted,
+        })
+        .collect::<Vec<_>>();
+    emojis.sort_by(|a, b| (&a.domain, &a.shortcode).cmp(&(&b.domain, &b.shortcode)));
+    Ok(Json(
+        emojis
+            .into_iter()
+            .map(|emoji| EmojiView {
+                url: proxy::url(state.as_ref(), &local, &emoji.image),
+                ..emoji.into()
+            })
+            .collect(),
+    ))
+}
+
+fn bad_request(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::BAD_REQUEST,
+    )
+}
+
+/// Check a new local shortcode and the image for it, returning the file extension.
+async fn check_new<S>(
+    state: &S,
+    shortcode: &str,
+    content_type: &str,
+    size: usize,
+) -> Result<&'static str, HttpError>
+where
+    S: EmojiStore + Sync,
+{
+    if !is_shortcode(shortcode) {
+        return Err(bad_request(
+            "shortcode must be at least 2 letters, digits or underscores",
+        ));
+    }
+    let extension = extension_of(content_type)
+        .ok_or_else(|| bad_request("emoji must be a PNG, GIF or WebP image"))?;
+    if size as u64 > MAX_EMOJI_SIZE {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("emoji must be at most {MAX_EMOJI_SIZE} bytes")}),
+            http::StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+    if state.get_emoji(shortcode).await?.is_some() {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "shortcode is already taken"}),
+            http::StatusCode::CONFLICT,
+        ));
+    }
+    Ok(extension)
+}
+
+async fn store_local<S>(
+    state: &S,
+    local: &Local,
+    shortcode: String,
+    content_type: String,
+    extension: &str,
+    body: Vec<u8>,
+    category: Option<String>,
+) -> Result<CustomEmoji, HttpError>
+where
+    S: EmojiStore + BlobStore + Sync,
+{
+    let key = format!("emojis/{shortcode}.{extension}");
+    let image = state.put_blob(&key, &content_type, body).await?;
+    let emoji = CustomEmoji {
+        id: shortcode.clone(),
+        uri: local.emoji(&shortcode),
+        shortcode,
+        domain: None,
+        image,
+        media_type: Some(content_type),
+        category,
+        updated: chrono::Utc::now(),
+    };
+    state.put_emoji(&emoji).await?;
+    info!(shortcode = emoji.shortcode, "emoji");
+    Ok(emoji)
+}
+
+/// Register a local emoji from a multipart form with `shortcode`, `image` and optionally `category`.
+pub async fn create_emoji<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    mut multipart: Multipart,
+) -> Result<Json<EmojiView>, HttpError>
+where
+    S: EmojiStore + BlobStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let mut shortcode = None;
+    let mut category = None;
+    let mut image = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::BAD_REQUEST)?
+    {
+        match field.name() {
+            Some("shortcode") => shortcode = Some(field.text().await.ok()),
+            Some("category") => category = field.text().await.ok(),
+            Some("image") => {
+                let content_type = field.content_type().unwrap_or_default().to_string();
+                let body = field
+                    .bytes()
+                    .await
+                    .map_err(|e| e.to_string())
+                    .http_error_json(http::StatusCode::BAD_REQUEST)?;
+                image = Some((content_type, body.to_vec()));
+            }
+            _ => (),
+        }
+    }
+    let shortcode = shortcode
+        .flatten()
+        .ok_or_else(|| bad_request("shortcode is missing"))?;
+    let (content_type, body) = image.ok_or_else(|| bad_request("image is missing"))?;
+    let state = state.as_ref();
+    let extension = check_new(state, &shortcode, &content_type, body.len()).await?;
+    let category = category.filter(|category| !category.is_empty());
+    let emoji = store_local(
+        state,
+        &local,
+        shortcode,
+        content_type,
+        extension,
+        body,
+        category,
+    )
+    .await?;
+    Ok(Json(emoji.into()))
+}
+
+#[derive(Deserialize)]
+pub struct EmojiUpdate {
+    pub category: Option<String>,
+}
+
+pub async fn update_emoji<S>(
+    _: Admin,
+    axum::extract::Path(shortcode): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(update): Json<EmojiUpdate>,
+) -> Result<Json<EmojiView>, HttpError>
+where
+    S: EmojiStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut emoji = state
+        .get_emoji(&shortcode)
+        .await?
+        .filter(|emoji| emoji.domain.is_none())
+        .ok_or_else(emoji_not_found)?;
+    emoji.category = update.category.filter(|category| !category.is_empty());
+    emoji.updated = chrono::Utc::now();
+    state.put_emoji(&emoji).await?;
+    Ok(Json(emoji.into()))
+}
+
+pub async fn delete_emoji<S>(
+    _: Admin,
+    axum::extract::Path(shortcode): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: EmojiStore + BlobStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let emoji = state
+        .get_emoji(&shortcode)
+        .await?
+        .filter(|emoji| emoji.domain.is_none())
+        .ok_or_else(emoji_not_found)?;
+    state.delete_emoji(&emoji.id).await?;
+    if let Some(extension) = emoji.media_type.as_deref().and_then(extension_of) {
+        state
+            .delete_blob(&format!("emojis/{}.{extension}", emoji.shortcode))
+            .await?;
+    }
+    info!(shortcode = emoji.shortcode, "emoji_deleted");
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct CopyRequest {
+    /// `shortcode@domain` of the remote emoji.
+    pub id: String,
+    /// Local shortcode, defaulting to the remote one.
+    #[serde(default)]
+    pub shortcode: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// Copy a cached remote emoji into the local registry.
+pub async fn copy_emoji<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<CopyRequest>,
+) -> Result<Json<EmojiView>, HttpError>
+where
+    S: EmojiStore
+        + BlobStore
+        + MediaFetcher
+        + TokenStore
+        + AccountStore<ActorInfo = Account>
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let remote = state
+        .get_emoji(&request.id)
+        .await?
+        .filter(|emoji| emoji.domain.is_some())
+        .ok_or_else(emoji_not_found)?;
+    let shortcode = request.shortcode.unwrap_or(remote.shortcode);
+    let media = state.fetch_media(&remote.image, MAX_EMOJI_SIZE).await?;
+    let content_type = media.content_type.or(remote.media_type).unwrap_or_default();
+    let extension = check_new(state, &shortcode, &content_type, media.body.len()).await?;
+    let category = request
+        .category
+        .or(remote.category)
+        .filter(|category| !category.is_empty());
+    let emoji = store_local(
+        state,
+        &local,
+        shortcode,
+        content_type,
+        extension,
+        media.body,
+        category,
+    )
+    .await?;
+    Ok(Json(emoji.into()))
+}
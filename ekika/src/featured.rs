@@ -0,0 +1,334 @@
+use std::{future::Future, sync::Arc};
+
+use activity_vocabulary_core::{Or, Property, Remotable};
+use axum::{response::IntoResponse, Json};
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    activity, ap,
+    auth::{Authenticated, TokenStore},
+    block::BlockStore,
+    delivery::{self, DeliveryQueue},
+    fetch::{self, Remote},
+    follow::{self, FollowStore},
+    local::Local,
+    model::{account::Account, actor::RemoteActor, featured::Featured, follow::FollowState},
+    object::{ObjectStore, ACTIVITY_JSON},
+    report,
+    signature::{self, SignedRequest},
+    visibility::Visibility,
+    webfinger::AccountStore,
+};
+
+/// Number of objects a local account can pin at once.
+pub const MAX_PINS: usize = 5;
+
+pub trait FeaturedStore {
+    fn get_featured(
+        &self,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<Option<Featured>, HttpError>> + Send;
+    fn put_featured(
+        &self,
+        featured: &Featured,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// IRIs pinned by `actor`, most recently pinned first.
+pub async fn pinned<S>(state: &S, actor: &url::Url) -> Result<Vec<url::Url>, HttpError>
+where
+    S: FeaturedStore + Sync,
+{
+    Ok(state
+        .get_featured(actor)
+        .await?
+        .map(|featured| featured.items)
+        .unwrap_or_default())
+}
+
+/// Items of a collection document, looking into the first page when they aren't inlined.
+async fn collection_items<S>(
+    state: &S,
+    collection: &serde_json::Value,
+) -> Result<Vec<url::Url>, HttpError>
+where
+    S: Remote + Sync,
+{
+    let items = |document: &serde_json::Value| {
+        ["orderedItems", "items"]
+            .into_iter()
+            .find_map(|name| document.get(name))
+            .map(|items| match items {
+                serde_json::Value::Array(items) => items.iter().filter_map(activity::iri).collect(),
+                item => activity::iri(item).into_iter().collect::<Vec<_>>(),
+            })
+    };
+    if let Some(items) = items(collection) {
+        return Ok(items);
+    }
+    let first = match collection.get("first") {
+        Some(page @ serde_json::Value::Object(_)) => page.clone(),
+        Some(page) => match activity::iri(page) {
+            Some(iri) => fetch::fetch_document(state, &iri).await?,
+            None => return Ok(Vec::new()),
+        },
+        None => return Ok(Vec::new()),
+    };
+    Ok(items(&first).unwrap_or_default())
+}
+
+/// Mirror the `featured` collection of a freshly fetched remote actor.
+/// Only objects from the origin of the actor are kept.
+pub async fn sync<S>(state: &S, actor: &RemoteActor) -> Result<(), HttpError>
+where
+    S: Remote + Sync,
+{
+    let Some(iri) = &actor.featured else {
+        return Ok(());
+    };
+    if iri.origin() != actor.id.origin() {
+        return Ok(());
+    }
+    let collection = fetch::fetch_document(state, iri).await?;
+    let items = collection_items(state, &collection)
+        .await?
+        .into_iter()
+        .filter(|item| item.origin() == actor.id.origin())
+        .take(MAX_PINS * 4)
+        .collect();
+    state
+        .put_featured(&Featured {
+            actor: actor.id.clone(),
+            items,
+            updated: chrono::Utc::now(),
+        })
+        .await
+}
+
+pub async fn get_featured_collection<S>(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: FeaturedStore + ObjectStore + AccountStore<ActorInfo = Account> + Remote + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    signature::authorize_fetch(state.as_ref(), request).await?;
+    let not_found = || HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND);
+    let actor = local.actor(&name);
+    if state.query(&name).await?.is_none() || report::is_suspended(state.as_ref(), &actor).await? {
+        return Err(not_found());
+    }
+    let mut items = Vec::new();
+    for iri in pinned(state.as_ref(), &actor).await? {
+        // Objects which were deleted or narrowed since they were pinned are left out.
+        match state.get_object(&iri).await? {
+            Some(object)
+                if matches!(object.visibility, Visibility::Public | Visibility::Unlisted) =>
+            {
+                items.push(Or::Snd(Remotable::Remote(object.id)));
+            }
+            _ => (),
+        }
+    }
+    let mut collection: ap::OrderedCollection = ap::empty();
+    collection.id = Some(local.featured(&name));
+    collection.total_items = Some(items.len());
+    collection.items = Property(items);
+    let collection = ap::OrderedCollectionSubtypes::OrderedCollection(collection);
+    let mut document = serde_json::to_value(collection)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    document["@context"] = ap::CONTEXT.clone();
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(ACTIVITY_JSON.clone())),
+        document.to_string(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct PinRequest {
+    pub object: url::Url,
+}
+
+#[derive(Serialize)]
+pub struct PinsView {
+    pub items: Vec<url::Url>,
+}
+
+pub async fn list_pins<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<PinsView>, HttpError>
+where
+    S: FeaturedStore + TokenStore + Send + Sync,
+{
+    let actor = Local::try_from(&proxy_info)?.actor(&auth.account);
+    let items = pinned(state.as_ref(), &actor).await?;
+    Ok(Json(PinsView { items }))
+}
+
+/// Tell the followers of `actor` that the featured collection changed.
+async fn announce_change<S>(
+    state: &S,
+    local: &Local,
+    name: &str,
+    kind: &str,
+    object: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + BlockStore + Remote + DeliveryQueue + Sync,
+{
+    let actor = local.actor(name);
+    let mut activity = activity::new_activity(local, kind, &actor, json!(object));
+    activity["target"] = json!(local.featured(name));
+    activity["to"] = json!([follow::followers_collection(&actor)]);
+    activity["cc"] = json!([ap::PUBLIC]);
+    let recipients = state
+        .followers(&actor)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.follower);
+    delivery::deliver_activity(
+        state,
+        local,
+        &actor,
+        activity,
+        recipients,
+        Visibility::Unlisted,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn pin<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<PinRequest>,
+) -> Result<Json<PinsView>, HttpError>
+where
+    S: FeaturedStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let actor = local.actor(&auth.account);
+    let object = state.get_object(&request.object).await?.ok_or_else(|| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "object not found"}),
+            http::StatusCode::NOT_FOUND,
+        )
+    })?;
+    if object.attributed_to != actor {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "only own objects can be pinned"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    if !matches!(object.visibility, Visibility::Public | Visibility::Unlisted) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "only public or unlisted objects can be pinned"}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    let mut items = pinned(state.as_ref(), &actor).await?;
+    if items.contains(&object.id) {
+        return Ok(Json(PinsView { items }));
+    }
+    if items.len() >= MAX_PINS {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("at most {MAX_PINS} objects can be pinned")}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    items.insert(0, object.id.clone());
+    state
+        .put_featured(&Featured {
+            actor: actor.clone(),
+            items: items.clone(),
+            updated: chrono::Utc::now(),
+        })
+        .await?;
+    info!(
+        actor = actor.to_string(),
+        object = object.id.to_string(),
+        "pin"
+    );
+    announce_change(state.as_ref(), &local, &auth.account, "Add", &object.id).await?;
+    Ok(Json(PinsView { items }))
+}
+
+pub async fn unpin<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<PinRequest>,
+) -> Result<Json<PinsView>, HttpError>
+where
+    S: FeaturedStore + FollowStore + BlockStore + Remote + DeliveryQueue + TokenStore + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let actor = local.actor(&auth.account);
+    let mut items = pinned(state.as_ref(), &actor).await?;
+    if !items.contains(&request.object) {
+        return Ok(Json(PinsView { items }));
+    }
+    items.retain(|item| item != &request.object);
+    state
+        .put_featured(&Featured {
+            actor: actor.clone(),
+            items: items.clone(),
+            updated: chrono::Utc::now(),
+        })
+        .await?;
+    info!(
+        actor = actor.to_string(),
+        object = request.object.to_string(),
+        "unpin"
+    );
+    announce_change(
+        state.as_ref(),
+        &local,
+        &auth.account,
+        "Remove",
+        &request.object,
+    )
+    .await?;
+    Ok(Json(PinsView { items }))
+}
+
+/// Refresh the mirrored featured collection, logging instead of failing the caller.
+pub async fn sync_or_warn<S>(state: &S, actor: &RemoteActor)
+where
+    S: Remote + Sync,
+{
+    if let Err(e) = sync(state, actor).await {
+        warn!(
+            actor = actor.id.to_string(),
+            status = e.status.as_u16(),
+            "featured_sync"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::future::Future;
+
+use axum_helper::{HttpError, ToHttpErrorJson};
+use serde_json::json;
+
+use crate::{
+    domain::{self, DomainPolicyStore},
+    emoji::{self, EmojiStore},
+    featured::{self, FeaturedStore},
+    model::{actor::RemoteActor, key::KeyPair},
+    object::ACTIVITY_JSON,
+    relay::RelayStore,
+    report::AccountPolicyStore,
+    signature,
+};
+
+const ACCEPT: &str = r#"application/activity+json, application/ld+json; profile="https://www.w3.org/ns/activitystreams""#;
+
+const ACTOR_TTL: chrono::TimeDelta = chrono::TimeDelta::days(1);
+
+/// Largest remote document read, as Mastodon does.
+pub const MAX_DOCUMENT_SIZE: u64 = 1024 * 1024;
+
+pub trait Fetcher {
+    /// GET an ActivityPub document. IRIs come from remote input, so the client must not
+    /// reach internal addresses, including through redirects.
+    fn fetch(
+        &self,
+        iri: &url::Url,
+    ) -> impl Future<Output = Result<serde_json::Value, HttpError>> + Send;
+}
+
+pub trait ActorStore {
+    fn get_actor(
+        &self,
+        id: &url::Url,
+    ) -> impl Future<Output = Result<Option<RemoteActor>, HttpError>> + Send;
+    fn put_actor(&self, actor: &RemoteActor) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Everything needed to talk to remote servers.
+/// Domain policies are enforced by the functions of this module, not by the raw [Fetcher].
+pub trait Remote:
+    Fetcher
+    + ActorStore
+    + DomainPolicyStore
+    + AccountPolicyStore
+    + RelayStore
+    + FeaturedStore
+    + EmojiStore
+{
+}
+
+impl<S> Remote for S where
+    S: Fetcher
+        + ActorStore
+        + DomainPolicyStore
+        + AccountPolicyStore
+        + RelayStore
+        + FeaturedStore
+        + EmojiStore
+{
+}
+
+/// GET an ActivityPub document, optionally signed by `signer`.
+pub async fn fetch(
+    client: &reqwest::Client,
+    iri: &url::Url,
+    signer: Option<(&url::Url, &KeyPair)>,
+) -> Result<serde_json::Value, HttpError> {
+    let bad_gateway = |e: String| json!({"ok": false, "msg": e});
+    let mut request = client
+        .get(iri.clone())
+        .header(http::header::ACCEPT, ACCEPT)
+        .build()
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?;
+    if let Some((key_id, key)) = signer {
+        signature::sign(&mut request, key_id, key)
+            .map_err(|e| e.to_string())
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    let mut response = client
+        .execute(request)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?;
+    let content_type = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok());
+    match content_type {
+        Some(mime) if mime.essence_str() == ACTIVITY_JSON.essence_str() => (),
+        Some(mime) if mime.essence_str() == "application/ld+json" => (),
+        _ => {
+            return Err(HttpError::new_json(
+                &bad_gateway("not an ActivityPub document".to_string()),
+                http::StatusCode::BAD_GATEWAY,
+            ))
+        }
+    }
+    let too_large = || {
+        HttpError::new_json(
+            &bad_gateway(format!("{iri} is larger than {MAX_DOCUMENT_SIZE} bytes")),
+            http::StatusCode::BAD_GATEWAY,
+        )
+    };
+    if response.content_length().unwrap_or(0) > MAX_DOCUMENT_SIZE {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > MAX_DOCUMENT_SIZE {
+            return Err(too_large());
+        }
+    }
+    serde_json::from_slice(&body)
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)
+}
+
+/// Fetch a remote document unless its domain is suspended.
+pub async fn fetch_document<S>(state: &S, iri: &url::Url) -> Result<serde_json::Value, HttpError>
+where
+    S: Remote + Sync,
+{
+    domain::ensure_federates(state, iri).await?;
+    state.fetch(iri).await
+}
+
+/// Look up a remote actor, refreshing the cached document when it is stale.
+pub async fn resolve_actor<S>(state: &S, id: &url::Url) -> Result<RemoteActor, HttpError>
+where
+    S: Remote + Sync,
+{
+    domain::ensure_federates(state, id).await?;
+    if let Some(actor) = state.get_actor(id).await? {
+        if chrono::Utc::now() - actor.fetched < ACTOR_TTL {
+            return Ok(actor);
+        }
+    }
+    refetch_actor(state, id).await
+}
+
+pub async fn refetch_actor<S>(state: &S, id: &url::Url) -> Result<RemoteActor, HttpError>
+where
+    S: Remote + Sync,
+{
+    let document = fetch_document(state, id).await?;
+    emoji::ingest(state, id, &document).await?;
+    let actor = RemoteActor::from_document(document)
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?;
+    if actor.id.origin() != id.origin() {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "actor id mismatch"}),
+            http::StatusCode::BAD_GATEWAY,
+        ));
+    }
+    state.put_actor(&actor).await?;
+    featured::sync_or_warn(state, &actor).await;
+    Ok(actor)
+}
@@ -0,0 +1,327 @@
+use std::future::Future;
+
+use axum_helper::HttpError;
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    activity,
+    block::{self, BlockStore},
+    delivery::{self, DeliveryQueue},
+    fetch::Remote,
+    local::Local,
+    model::{
+        account::Account,
+        actor::RemoteActor,
+        follow::{Follow, FollowState},
+        notification::NotificationKind,
+    },
+    mute::MuteStore,
+    notification::{self, NotificationStore},
+    stream::EventBus,
+    webfinger::AccountStore,
+};
+
+pub trait FollowStore {
+    fn followers(
+        &self,
+        followee: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Follow>, HttpError>> + Send;
+    fn following(
+        &self,
+        follower: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Follow>, HttpError>> + Send;
+    fn get_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> impl Future<Output = Result<Option<Follow>, HttpError>> + Send;
+    fn put_follow(&self, follow: &Follow) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub fn followers_collection(actor: &url::Url) -> url::Url {
+    format!("{actor}/followers").parse().unwrap()
+}
+
+/// Whether follows of `followee` wait for approval, as it is a locked local account.
+async fn requires_approval<S>(
+    state: &S,
+    local: &Local,
+    followee: &url::Url,
+) -> Result<bool, HttpError>
+where
+    S: AccountStore<ActorInfo = Account> + Sync,
+{
+    let Some(name) = local.account_name(followee) else {
+        return Ok(false);
+    };
+    Ok(state
+        .query(&name)
+        .await?
+        .is_some_and(|account| account.locked))
+}
+
+/// Follow `followee` as the local actor `follower`. Remote accounts have to accept the follow,
+/// so it stays pending until their `Accept` arrives. Follows of locked local accounts stay
+/// pending until they are [authorized](authorize).
+pub async fn follow<S>(
+    state: &S,
+    local: &Local,
+    follower: &url::Url,
+    followee: &url::Url,
+) -> Result<Follow, HttpError>
+where
+    S: FollowStore
+        + NotificationStore
+        + EventBus
+        + BlockStore
+        + MuteStore
+        + AccountStore<ActorInfo = Account>
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    if let Some(follow) = state.get_follow(follower, followee).await? {
+        return Ok(follow);
+    }
+    let mut follow = Follow {
+        follower: follower.clone(),
+        followee: followee.clone(),
+        state: FollowState::Accepted,
+        activity: None,
+    };
+    if !local.is_local(followee) {
+        let activity = activity::new_activity(local, "Follow", follower, json!(followee));
+        follow.state = FollowState::Pending;
+        follow.activity = activity::field_iri(&activity, "id");
+        delivery::enqueue_to_actor(state, follower, activity, followee).await?;
+        state.put_follow(&follow).await?;
+        return Ok(follow);
+    }
+    let kind = if requires_approval(state, local, followee).await? {
+        follow.state = FollowState::Pending;
+        NotificationKind::FollowRequest
+    } else {
+        NotificationKind::Follow
+    };
+    state.put_follow(&follow).await?;
+    notification::try_notify(state, local, followee, kind, follower, None).await;
+    Ok(follow)
+}
+
+pub async fn unfollow<S>(
+    state: &S,
+    local: &Local,
+    follower: &url::Url,
+    followee: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + Remote + DeliveryQueue + Sync,
+{
+    let Some(follow) = state.get_follow(follower, followee).await? else {
+        return Ok(());
+    };
+    state.delete_follow(follower, followee).await?;
+    if !local.is_local(followee) {
+        let undo = activity::new_activity(local, "Undo", follower, request_of(&follow));
+        delivery::enqueue_to_actor(state, follower, undo, followee).await?;
+    }
+    Ok(())
+}
+
+/// Inbound `Follow` of a local account. Followers blocked in either direction are rejected.
+/// The instance actor accepts follows too, as LitePub relays follow it back.
+pub async fn handle_follow<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    follow: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + AccountStore<ActorInfo = Account>
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    let Some(followee) = activity::field_iri(follow, "object") else {
+        return Ok(());
+    };
+    // Relays follow the instance actor back.
+    if !local.is_instance_actor(&followee) {
+        let Some(name) = local.account_name(&followee) else {
+            return Ok(());
+        };
+        if state.query(&name).await?.is_none() {
+            return Ok(());
+        }
+    }
+    let blocked = block::is_blocked_between(state, &followee, &signer.id).await?;
+    if blocked {
+        info!(
+            follower = signer.id.to_string(),
+            followee = followee.to_string(),
+            "reject_follow"
+        );
+    } else {
+        // Repeated follows are accepted again, as the follower may have missed the `Accept`.
+        let accepted = state
+            .get_follow(&signer.id, &followee)
+            .await?
+            .is_some_and(|follow| follow.state == FollowState::Accepted);
+        let pending = !accepted && requires_approval(state, local, &followee).await?;
+        state
+            .put_follow(&Follow {
+                follower: signer.id.clone(),
+                followee: followee.clone(),
+                state: if pending {
+                    FollowState::Pending
+                } else {
+                    FollowState::Accepted
+                },
+                activity: activity::field_iri(follow, "id"),
+            })
+            .await?;
+        if !accepted {
+            let kind = if pending {
+                NotificationKind::FollowRequest
+            } else {
+                NotificationKind::Follow
+            };
+            notification::try_notify(state, local, &followee, kind, &signer.id, None).await;
+        }
+        // The `Accept` is held back until the follow request is authorized.
+        if pending {
+            return Ok(());
+        }
+    }
+    let kind = if blocked { "Reject" } else { "Accept" };
+    let response = activity::new_activity(local, kind, &followee, follow.clone());
+    delivery::enqueue_to_actor(state, &followee, response, &signer.id).await
+}
+
+/// Approve the pending follow of the local actor `followee` by `follower`. Remote followers
+/// are sent the `Accept` which [handle_follow] held back.
+pub async fn authorize<S>(
+    state: &S,
+    local: &Local,
+    followee: &url::Url,
+    follower: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + NotificationStore + EventBus + Remote + DeliveryQueue + Sync,
+{
+    let mut follow = pending_request(state, followee, follower).await?;
+    follow.state = FollowState::Accepted;
+    state.put_follow(&follow).await?;
+    if !local.is_local(follower) {
+        let accept = activity::new_activity(local, "Accept", followee, request_of(&follow));
+        delivery::enqueue_to_actor(state, followee, accept, follower).await?;
+    }
+    Ok(())
+}
+
+/// Decline the pending follow of the local actor `followee` by `follower`.
+pub async fn reject<S>(
+    state: &S,
+    local: &Local,
+    followee: &url::Url,
+    follower: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + Remote + DeliveryQueue + Sync,
+{
+    let follow = pending_request(state, followee, follower).await?;
+    state.delete_follow(follower, followee).await?;
+    if !local.is_local(follower) {
+        let reject = activity::new_activity(local, "Reject", followee, request_of(&follow));
+        delivery::enqueue_to_actor(state, followee, reject, follower).await?;
+    }
+    Ok(())
+}
+
+async fn pending_request<S>(
+    state: &S,
+    followee: &url::Url,
+    follower: &url::Url,
+) -> Result<Follow, HttpError>
+where
+    S: FollowStore + Sync,
+{
+    match state.get_follow(follower, followee).await? {
+        Some(follow) if follow.state == FollowState::Pending => Ok(follow),
+        _ => Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "follow request not found"}),
+            http::StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+/// The `Follow` activity behind `follow`, as answered with `Accept` and `Reject`.
+fn request_of(follow: &Follow) -> serde_json::Value {
+    json!({
+        "id": follow.activity,
+        "type": "Follow",
+        "actor": follow.follower,
+        "object": follow.followee,
+    })
+}
+
+pub async fn handle_undo_follow<S>(
+    state: &S,
+    signer: &RemoteActor,
+    follow: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + Sync,
+{
+    if let Some(followee) = activity::field_iri(follow, "object") {
+        state.delete_follow(&signer.id, &followee).await?;
+    }
+    Ok(())
+}
+
+/// Inbound `Accept` of a `Follow` sent by a local account.
+pub async fn handle_accept_follow<S>(
+    state: &S,
+    signer: &RemoteActor,
+    follow: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + Sync,
+{
+    let Some(follower) = activity::field_iri(follow, "actor") else {
+        return Ok(());
+    };
+    match state.get_follow(&follower, &signer.id).await? {
+        Some(mut follow) if follow.state == FollowState::Pending => {
+            follow.state = FollowState::Accepted;
+            state.put_follow(&follow).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Inbound `Reject` of a `Follow`. Also sent when a remote account removes one of our followers.
+pub async fn handle_reject_follow<S>(
+    state: &S,
+    signer: &RemoteActor,
+    follow: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + Sync,
+{
+    if let Some(follower) = activity::field_iri(follow, "actor") {
+        state.delete_follow(&follower, &signer.id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity,
+    block::{self, BlockStore},
+    conversation::ConversationStore,
+    delivery::DeliveryQueue,
+    domain,
+    fetch::Remote,
+    follow::{self, FollowStore},
+    list::ListStore,
+    local::Local,
+    migration::{self, MigrationStore},
+    model::{account::Account, actor::RemoteActor},
+    mute::MuteStore,
+    notification::NotificationStore,
+    object::{self, ObjectStore},
+    reaction::{self, ReactionStore},
+    relay,
+    report::{self, ReportStore},
+    signature::{self, SignedRequest},
+    stream::EventBus,
+    timeline::TimelineStore,
+    webfinger::AccountStore,
+};
+
+/// Everything the inbox needs from the server state.
+pub trait InboxState:
+    AccountStore<ActorInfo = Account>
+    + ObjectStore
+    + FollowStore
+    + BlockStore
+    + ListStore
+    + ConversationStore
+    + ReportStore
+    + MigrationStore
+    + ReactionStore
+    + TimelineStore
+    + MuteStore
+    + NotificationStore
+    + EventBus
+    + Remote
+    + DeliveryQueue
+    + Send
+    + Sync
+{
+}
+
+impl<S> InboxState for S where
+    S: AccountStore<ActorInfo = Account>
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + ConversationStore
+        + ReportStore
+        + MigrationStore
+        + ReactionStore
+        + TimelineStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + Send
+        + Sync
+{
+}
+
+pub async fn shared_inbox<S: InboxState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+    body: Bytes,
+) -> Result<http::StatusCode, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: Some(&body),
+    };
+    receive(state.as_ref(), &local, request, None).await
+}
+
+pub async fn user_inbox<S: InboxState>(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+    body: Bytes,
+) -> Result<http::StatusCode, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    if state.query(&name).await?.is_none() {
+        return Err(HttpError::new_json(
+            &json!({"ok": false}),
+            http::StatusCode::NOT_FOUND,
+        ));
+    }
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: Some(&body),
+    };
+    let recipient = local.actor(&name);
+    receive(state.as_ref(), &local, request, Some(recipient)).await
+}
+
+async fn receive<S: InboxState>(
+    state: &S,
+    local: &Local,
+    request: SignedRequest<'_>,
+    recipient: Option<url::Url>,
+) -> Result<http::StatusCode, HttpError> {
+    let activity: serde_json::Value = serde_json::from_slice(request.body.unwrap_or_default())
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    // Reject suspended domains before spending a fetch on their signing key.
+    if let Some(actor) = activity::field_iri(&activity, "actor") {
+        domain::ensure_federates(state, &actor).await?;
+    }
+    let signer = signature::verify(state, request).await?;
+    if report::is_suspended(state, &signer.id).await? {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "account is suspended"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    if activity::field_iri(&activity, "actor").as_ref() != Some(&signer.id) {
+        if let Some(relay) = relay::subscribed(state, &signer.id).await? {
+            relay::handle_forwarded(state, local, &relay, &activity).await?;
+            return Ok(http::StatusCode::ACCEPTED);
+        }
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "actor does not match the signature"}),
+            http::StatusCode::UNAUTHORIZED,
+        ));
+    }
+    // Activities of the shared inbox are dropped per recipient as they fan out to
+    // timelines, conversations and notifications.
+    if let Some(recipient) = &recipient {
+        if state.get_block(recipient, &signer.id).await?.is_some() {
+            info!(
+                actor = signer.id.to_string(),
+                recipient = recipient.to_string(),
+                "blocked_delivery"
+            );
+            return Ok(http::StatusCode::ACCEPTED);
+        }
+    }
+    dispatch(state, local, &signer, &activity).await?;
+    Ok(http::StatusCode::ACCEPTED)
+}
+
+async fn dispatch<S: InboxState>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError> {
+    match activity::kind(activity) {
+        Some("Follow") => follow::handle_follow(state, local, signer, activity).await,
+        Some("Block") => block::handle_block(state, local, signer, activity).await,
+        Some("Create") => object::handle_create(state, local, signer, activity).await,
+        Some("Update") => object::handle_update(state, local, signer, activity).await,
+        Some("Delete") => object::handle_delete(state, local, signer, activity).await,
+        Some("Flag") => report::handle_flag(state, local, signer, activity).await,
+        Some("Move") => migration::handle_move(state, local, signer, activity).await,
+        Some("Announce") => relay::handle_announce(state, local, signer, activity).await,
+        Some("EmojiReact" | "Like") => reaction::handle_react(state, local, signer, activity).await,
+        Some(kind @ ("Accept" | "Reject")) => {
+            let Some(follow) = activity.get("object") else {
+                return Ok(());
+            };
+            if activity::kind(follow) != Some("Follow") {
+                debug!(kind, "unsupported_response");
+                return Ok(());
+            }
+            let accepted = kind == "Accept";
+            if activity::field_iri(follow, "actor") == Some(local.instance_actor()) {
+                relay::handle_response(state, signer, follow, accepted).await
+            } else if accepted {
+                follow::handle_accept_follow(state, signer, follow).await
+            } else {
+                follow::handle_reject_follow(state, signer, follow).await
+            }
+        }
+        Some("Undo") => {
+            let Some(undone) = activity.get("object") else {
+                return Ok(());
+            };
+            if activity::field_iri(undone, "actor").as_ref() != Some(&signer.id) {
+                return Ok(());
+            }
+            match activity::kind(undone) {
+                Some("Follow") => follow::handle_undo_follow(state, signer, undone).await,
+                Some("Block") => block::handle_undo_block(state, signer, undone).await,
+                Some("EmojiReact" | "Like") => {
+                    reaction::handle_undo_react(state, signer, undone).await
+                }
+                kind => {
+                    debug!(kind, "unsupported_undo");
+                    Ok(())
+                }
+            }
+        }
+        kind => {
+            debug!(kind, "unsupported_activity");
+            Ok(())
+        }
+    }
+}
@@ -1,5 +1,42 @@
+pub mod activity;
+pub mod actor;
+pub mod ap;
+pub mod auth;
+pub mod block;
+pub mod boost;
+pub mod conversation;
+pub mod delivery;
+pub mod domain;
+pub mod emoji;
 pub mod external;
+pub mod featured;
+pub mod fetch;
+pub mod follow;
+pub mod inbox;
+pub mod list;
+pub mod local;
+pub mod mastodon;
+pub mod media;
+pub mod migration;
 pub mod model;
+pub mod mute;
+pub mod nodeinfo;
+pub mod notification;
+pub mod oauth;
+pub mod object;
+pub mod processing;
+pub mod profile;
+pub mod proxy;
+pub mod reaction;
+pub mod relay;
+pub mod report;
+pub mod resolve;
+pub mod search;
+pub mod signature;
+pub mod storage;
+pub mod stream;
+pub mod timeline;
 pub mod types;
 pub mod util;
+pub mod visibility;
 pub mod webfinger;
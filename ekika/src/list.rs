@@ -0,0 +1,76 @@
+use std::future::Future;
+
+use axum_helper::HttpError;
+
+use crate::{
+    model::list::{List, ListMember},
+    stream::Stream,
+};
+
+/// Lists are limited like Mastodon's, so that distributing a post stays cheap.
+pub const MAX_LISTS: usize = 50;
+
+pub const MAX_MEMBERS: usize = 500;
+
+pub trait ListStore {
+    fn lists(&self, owner: &str) -> impl Future<Output = Result<Vec<List>, HttpError>> + Send;
+    fn get_list(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<List>, HttpError>> + Send;
+    fn put_list(&self, list: &List) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_list(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn list_members(
+        &self,
+        list: &str,
+    ) -> impl Future<Output = Result<Vec<ListMember>, HttpError>> + Send;
+    /// Lists of any owner `member` is on.
+    fn memberships(
+        &self,
+        member: &url::Url,
+    ) -> impl Future<Output = Result<Vec<ListMember>, HttpError>> + Send;
+    fn put_list_member(
+        &self,
+        member: &ListMember,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_list_member(
+        &self,
+        list: &str,
+        member: &url::Url,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Streams of the lists which `sharer` is on and which belong to one of `owners`,
+/// the accounts whose home feed gets what `sharer` shares.
+pub async fn streams<S>(
+    state: &S,
+    sharer: &url::Url,
+    owners: &[String],
+) -> Result<Vec<Stream>, HttpError>
+where
+    S: ListStore + Sync,
+{
+    Ok(state
+        .memberships(sharer)
+        .await?
+        .into_iter()
+        .filter(|membership| owners.contains(&membership.owner))
+        .map(|membership| Stream::List(membership.list))
+        .collect())
+}
+
+/// Delete a list along with its members.
+pub async fn delete<S>(state: &S, list: &List) -> Result<(), HttpError>
+where
+    S: ListStore + Sync,
+{
+    for member in state.list_members(&list.id).await? {
+        state.delete_list_member(&list.id, &member.member).await?;
+    }
+    state.delete_list(&list.owner, &list.id).await
+}
@@ -0,0 +1,147 @@
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde_json::json;
+
+/// Builds and recognizes IRIs of resources hosted by this server.
+#[derive(Clone, Debug)]
+pub struct Local {
+    base: url::Url,
+}
+
+/// Fails with 400 when the forwarded scheme or host do not make up a plain origin.
+impl TryFrom<&ProxyInfo> for Local {
+    type Error = HttpError;
+
+    fn try_from(proxy_info: &ProxyInfo) -> Result<Self, Self::Error> {
+        let base = format!("{}://{}/", proxy_info.proto, proxy_info.host)
+            .parse::<url::Url>()
+            .ok()
+            .filter(|base| matches!(base.scheme(), "http" | "https"))
+            .filter(|base| base.host_str().is_some() && base.path() == "/")
+            .filter(|base| base.username().is_empty() && base.password().is_none())
+            .filter(|base| base.query().is_none() && base.fragment().is_none())
+            .ok_or_else(|| {
+                HttpError::new_json(
+                    &json!({"ok": false, "msg": "invalid host"}),
+                    http::StatusCode::BAD_REQUEST,
+                )
+            })?;
+        Ok(Self { base })
+    }
+}
+
+impl Local {
+    pub fn new(base: url::Url) -> Self {
+        Self { base }
+    }
+
+    /// Host, with the port unless it is the default one. Accounts are qualified with it.
+    pub fn host(&self) -> &str {
+        &self.base[url::Position::BeforeHost..url::Position::AfterPort]
+    }
+
+    pub fn actor(&self, name: &str) -> url::Url {
+        self.base.join(&format!("users/{name}")).unwrap()
+    }
+
+    /// Page of the account in the web frontend, as WebFinger advertises it.
+    pub fn profile_page(&self, name: &str) -> url::Url {
+        self.base.join(&format!("@{name}")).unwrap()
+    }
+
+    /// Actor representing the server itself. WebFinger resolves it as `acct:<host>@<host>`.
+    pub fn instance_actor(&self) -> url::Url {
+        self.base.join("actor").unwrap()
+    }
+
+    pub fn instance_inbox(&self) -> url::Url {
+        self.base.join("actor/inbox").unwrap()
+    }
+
+    pub fn is_instance_actor(&self, iri: &url::Url) -> bool {
+        iri == &self.instance_actor()
+    }
+
+    pub fn inbox(&self, name: &str) -> url::Url {
+        self.base.join(&format!("users/{name}/inbox")).unwrap()
+    }
+
+    pub fn emoji(&self, shortcode: &str) -> url::Url {
+        self.base.join(&format!("emojis/{shortcode}")).unwrap()
+    }
+
+    pub fn media_proxy(&self, signature: &str, encoded: &str) -> url::Url {
+        self.base
+            .join(&format!("proxy/{signature}/{encoded}"))
+            .unwrap()
+    }
+
+    pub fn featured(&self, name: &str) -> url::Url {
+        self.base
+            .join(&format!("users/{name}/collections/featured"))
+            .unwrap()
+    }
+
+    pub fn shared_inbox(&self) -> url::Url {
+        self.base.join("inbox").unwrap()
+    }
+
+    pub fn key_id(&self, name: &str) -> url::Url {
+        let mut key_id = self.actor(name);
+        key_id.set_fragment(Some("main-key"));
+        key_id
+    }
+
+    pub fn object(&self, id: &str) -> url::Url {
+        self.base.join(&format!("objects/{id}")).unwrap()
+    }
+
+    pub fn tag(&self, name: &str) -> url::Url {
+        self.base.join(&format!("tags/{name}")).unwrap()
+    }
+
+    /// Placeholder for accounts without an avatar or header, where Mastodon serves it.
+    pub fn missing_image(&self, kind: &str) -> url::Url {
+        self.base
+            .join(&format!("{kind}/original/missing.png"))
+            .unwrap()
+    }
+
+    pub fn context(&self, id: &str) -> url::Url {
+        self.base.join(&format!("contexts/{id}")).unwrap()
+    }
+
+    /// Identifier of the server as an OAuth authorization server.
+    pub fn issuer(&self) -> url::Url {
+        self.base.clone()
+    }
+
+    pub fn oauth(&self, endpoint: &str) -> url::Url {
+        self.base.join(&format!("oauth/{endpoint}")).unwrap()
+    }
+
+    pub fn new_object(&self) -> url::Url {
+        self.object(&uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn new_activity(&self) -> url::Url {
+        self.base
+            .join(&format!("activities/{}", uuid::Uuid::new_v4()))
+            .unwrap()
+    }
+
+    pub fn is_local(&self, iri: &url::Url) -> bool {
+        iri.origin() == self.base.origin()
+    }
+
+    /// Name of the local account an actor IRI refers to.
+    pub fn account_name(&self, actor: &url::Url) -> Option<String> {
+        if !self.is_local(actor) {
+            return None;
+        }
+        actor
+            .path()
+            .strip_prefix("/users/")
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(ToString::to_string)
+    }
+}
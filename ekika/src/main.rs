@@ -2,22 +2,49 @@ use std::{net::SocketAddr, sync::Arc};
 
 use aws_config::BehaviorVersion;
 use aws_sdk_dynamodb::types::AttributeValue;
-use axum::{extract, response::IntoResponse, routing};
+use axum::{response::IntoResponse, routing};
 use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
 use clap::Parser;
-use ekika::model::account::Account;
+use ekika::{
+    delivery::{DeliveryJob, DeliveryStore},
+    domain::FederationMode,
+    model::{
+        account::Account,
+        actor::RemoteActor,
+        block::Block,
+        boost::Boost,
+        conversation::Conversation,
+        domain::{DomainAllow, DomainPolicy},
+        emoji::CustomEmoji,
+        featured::Featured,
+        follow::Follow,
+        key::KeyPair,
+        list::{List, ListMember},
+        media::Attachment,
+        migration::Migration,
+        mute::Mute,
+        notification::{Notification, NotificationSettings},
+        oauth::{Application, AuthorizationCode, Session},
+        object::StoredObject,
+        proxy::CachedMedia,
+        reaction::Reaction,
+        relay::Relay,
+        report::{AccountPolicy, Report, ReportState},
+        timeline::{FeedEntry, LargeAccount},
+        token::Token,
+    },
+    search::{Embedded, OpenSearch, SearchBackend, SearchDocument, SearchHit, SearchQuery},
+    storage::{Backend, FileSystem, Media, S3},
+    stream::{Envelope, Event, EventBus, LocalBus, Stream},
+    timeline::FeedRange,
+    util::{ddb, rate_limit::RateLimiter},
+};
 use once_cell::sync::Lazy;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::sync::mpsc;
 use tower_http::trace::TraceLayer;
-use tracing::info;
-
-#[allow(clippy::all, unused)]
-mod ap {
-    use activity_vocabulary::Unit;
-    use activity_vocabulary_core::*;
-    include!(concat!(env!("OUT_DIR"), "/vocab.rs"));
-}
+use tracing::{info, warn};
 
 static TEMPLATES: Lazy<handlebars::Handlebars> = Lazy::new(|| {
     let mut registry = handlebars::Handlebars::new();
@@ -57,6 +84,50 @@ async fn host_meta(proxy_info: ProxyInfo) -> Result<impl IntoResponse, HttpError
 struct State {
     ddb: aws_sdk_dynamodb::Client,
     user_table: String,
+    object_table: String,
+    follow_table: String,
+    token_table: String,
+    application_table: String,
+    authorization_code_table: String,
+    session_table: String,
+    key_table: String,
+    actor_table: String,
+    conversation_table: String,
+    block_table: String,
+    boost_table: String,
+    mute_table: String,
+    list_table: String,
+    list_member_table: String,
+    feed_table: String,
+    large_account_table: String,
+    notification_table: String,
+    notification_settings_table: String,
+    domain_policy_table: String,
+    domain_allow_table: String,
+    report_table: String,
+    account_policy_table: String,
+    migration_table: String,
+    featured_table: String,
+    relay_table: String,
+    emoji_table: String,
+    reaction_table: String,
+    media_table: String,
+    media_cache_table: String,
+    federation_mode: FederationMode,
+    /// Signs background fetches which aren't on behalf of an account.
+    instance_actor: url::Url,
+    /// Client for URLs taken from remote input, which cannot reach internal addresses.
+    media_http: reqwest::Client,
+    /// Like `media_http`, but leaves redirects to the caller.
+    page_http: reqwest::Client,
+    proxy_key: Vec<u8>,
+    search: SearchBackend,
+    lookup_limiter: RateLimiter,
+    events: LocalBus,
+    delivery_table: String,
+    deliveries: mpsc::Sender<DeliveryJob>,
+    media: Backend,
+    media_jobs: mpsc::Sender<ekika::media::ProcessingJob>,
 }
 
 impl ekika::webfinger::AccountStore for State {
@@ -82,85 +153,1579 @@ impl ekika::webfinger::AccountStore for State {
     }
 }
 
-#[derive(Parser)]
-struct Opts {
-    #[clap(short, long, env)]
-    addr: SocketAddr,
-    #[clap(short, long, env)]
-    json_log: bool,
+#[derive(Serialize)]
+struct UserItem<'a> {
+    #[serde(rename = "Id")]
+    id: &'a str,
+    #[serde(flatten)]
+    account: &'a Account,
 }
 
-fn init_logger(json: bool) {
-    use tracing_subscriber::prelude::*;
-    if json {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| "info".into()),
-            )
-            .with(tracing_subscriber::fmt::layer().json())
-            .init();
-    } else {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| "info".into()),
+impl ekika::profile::ProfileStore for State {
+    async fn put_account(&self, name: &str, account: &Account) -> Result<(), HttpError> {
+        let item = UserItem { id: name, account };
+        ddb::put(&self.ddb, &self.user_table, &item).await?;
+        ekika::search::index_local_account(self, name, account).await;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct UserRow {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(flatten)]
+    account: Account,
+}
+
+impl ekika::search::SearchIndex for State {
+    async fn index(&self, document: &SearchDocument) -> Result<(), HttpError> {
+        self.search.index(document).await
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), HttpError> {
+        self.search.remove(id).await
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, HttpError> {
+        self.search.search(query).await
+    }
+}
+
+/// Fill the embedded search index, which starts empty, from the tables.
+async fn reindex(state: &State) -> Result<(), HttpError> {
+    let objects: Vec<StoredObject> = ddb::scan(&state.ddb, &state.object_table).await?;
+    for object in &objects {
+        ekika::search::index_object(state, object).await;
+    }
+    let actors: Vec<RemoteActor> = ddb::scan(&state.ddb, &state.actor_table).await?;
+    for actor in &actors {
+        ekika::search::index_remote_actor(state, actor).await;
+    }
+    let users: Vec<UserRow> = ddb::scan(&state.ddb, &state.user_table).await?;
+    for user in &users {
+        ekika::search::index_local_account(state, &user.id, &user.account).await;
+    }
+    info!(
+        objects = objects.len(),
+        actors = actors.len(),
+        accounts = users.len(),
+        "search_reindexed"
+    );
+    Ok(())
+}
+
+/// GET `url`, refusing bodies larger than `limit` bytes.
+async fn download(
+    http: &reqwest::Client,
+    url: &url::Url,
+    accept: &str,
+    limit: u64,
+) -> Result<Media, HttpError> {
+    let bad_gateway = |e: String| json!({"ok": false, "msg": e});
+    let response = http
+        .get(url.clone())
+        .header(http::header::ACCEPT, accept)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?;
+    read_body(url, response, limit).await
+}
+
+/// Read the body of `response` to `url`, refusing bodies larger than `limit` bytes.
+async fn read_body(
+    url: &url::Url,
+    mut response: reqwest::Response,
+    limit: u64,
+) -> Result<Media, HttpError> {
+    let bad_gateway = |e: String| json!({"ok": false, "msg": e});
+    let too_large = || {
+        HttpError::new_json(
+            &bad_gateway(format!("{url} is larger than {limit} bytes")),
+            http::StatusCode::BAD_GATEWAY,
+        )
+    };
+    if response.content_length().unwrap_or(0) > limit {
+        return Err(too_large());
+    }
+    let content_type = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map(|mime| mime.essence_str().to_string());
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| bad_gateway(e.to_string()))
+        .http_error_json(http::StatusCode::BAD_GATEWAY)?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > limit {
+            return Err(too_large());
+        }
+    }
+    Ok(Media { content_type, body })
+}
+
+/// Pages linked from profiles are fetched one hop at a time, so that the profile module
+/// checks every redirect target, and only through resolvers which refuse internal addresses.
+impl ekika::profile::PageFetcher for State {
+    async fn fetch_page(
+        &self,
+        url: &url::Url,
+        limit: u64,
+    ) -> Result<ekika::profile::Page, HttpError> {
+        if !ekika::proxy::is_public_url(url) {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("{url} is not a public URL")}),
+                http::StatusCode::BAD_REQUEST,
+            ));
+        }
+        let response = self
+            .page_http
+            .get(url.clone())
+            .header(http::header::ACCEPT, "text/html")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+            .http_error_json(http::StatusCode::BAD_GATEWAY)?;
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(http::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| url.join(value).ok());
+            if let Some(location) = location {
+                return Ok(ekika::profile::Page::Redirect(location));
+            }
+        }
+        let page = read_body(url, response, limit).await?;
+        Ok(ekika::profile::Page::Html(
+            String::from_utf8_lossy(&page.body).into_owned(),
+        ))
+    }
+}
+
+/// WebFinger documents are small, so large ones are refused.
+const MAX_WEBFINGER_SIZE: u64 = 64 * 1024;
+
+impl ekika::resolve::WebfingerFetcher for State {
+    async fn fetch_webfinger(&self, url: &url::Url) -> Result<serde_json::Value, HttpError> {
+        let document = download(
+            &self.media_http,
+            url,
+            "application/jrd+json, application/json",
+            MAX_WEBFINGER_SIZE,
+        )
+        .await?;
+        serde_json::from_slice(&document.body)
+            .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+            .http_error_json(http::StatusCode::BAD_GATEWAY)
+    }
+
+    fn lookup_limiter(&self) -> &RateLimiter {
+        &self.lookup_limiter
+    }
+}
+
+impl EventBus for State {
+    fn publish(&self, stream: Stream, event: Event) {
+        self.events.publish(stream, event)
+    }
+
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Arc<Envelope>> {
+        self.events.subscribe()
+    }
+}
+
+impl ekika::storage::MediaFetcher for State {
+    async fn fetch_media(&self, url: &url::Url, limit: u64) -> Result<Media, HttpError> {
+        if !ekika::proxy::is_public_url(url) {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("{url} is not a public URL")}),
+                http::StatusCode::BAD_REQUEST,
+            ));
+        }
+        download(&self.media_http, url, "*/*", limit).await
+    }
+}
+
+impl ekika::storage::BlobStore for State {
+    async fn put_blob(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<url::Url, HttpError> {
+        self.media.put_blob(key, content_type, body).await
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, HttpError> {
+        self.media.get_blob(key).await
+    }
+
+    async fn delete_blob(&self, key: &str) -> Result<(), HttpError> {
+        self.media.delete_blob(key).await
+    }
+}
+
+impl ekika::media::MediaStore for State {
+    async fn get_media(&self, id: &str) -> Result<Option<Attachment>, HttpError> {
+        ddb::get(&self.ddb, &self.media_table, ddb::key([("Id", id)])).await
+    }
+
+    async fn put_media(&self, media: &Attachment) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.media_table, media).await
+    }
+}
+
+impl ekika::media::MediaQueue for State {
+    async fn enqueue_media(&self, job: ekika::media::ProcessingJob) -> Result<(), HttpError> {
+        self.media_jobs.try_send(job).map_err(|_| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "too many uploads are being processed"}),
+                http::StatusCode::SERVICE_UNAVAILABLE,
             )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+        })
+    }
+}
+
+impl ekika::proxy::MediaCacheStore for State {
+    fn proxy_key(&self) -> &[u8] {
+        &self.proxy_key
+    }
+
+    async fn cached_media(&self) -> Result<Vec<CachedMedia>, HttpError> {
+        ddb::scan(&self.ddb, &self.media_cache_table).await
+    }
+
+    async fn get_cached(&self, id: &str) -> Result<Option<CachedMedia>, HttpError> {
+        ddb::get(&self.ddb, &self.media_cache_table, ddb::key([("Id", id)])).await
+    }
+
+    async fn put_cached(&self, media: &CachedMedia) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.media_cache_table, media).await
+    }
+
+    async fn delete_cached(&self, id: &str) -> Result<(), HttpError> {
+        ddb::delete(&self.ddb, &self.media_cache_table, ddb::key([("Id", id)])).await
     }
 }
 
-async fn ap_get_user(
-    extract::Path(user): extract::Path<String>,
-    extract::State(state): extract::State<Arc<State>>,
+/// Files of the filesystem storage backend. S3 buckets are served by S3 itself.
+async fn serve_media(
+    axum::extract::Path(key): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<State>>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let user = state
-        .ddb
-        .get_item()
-        .table_name(state.user_table.clone())
-        .key("Id", AttributeValue::S(user))
-        .send()
+    let not_found = || HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND);
+    let Backend::FileSystem(fs) = &state.media else {
+        return Err(not_found());
+    };
+    let (content_type, body) = fs.read(&key).await?.ok_or_else(not_found)?;
+    Ok((
+        [
+            (http::header::CONTENT_TYPE, content_type),
+            (
+                http::header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable",
+            ),
+        ],
+        body,
+    ))
+}
+
+impl ekika::reaction::ReactionStore for State {
+    async fn reactions_of(&self, object: &url::Url) -> Result<Vec<Reaction>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.reaction_table,
+            Some("Object"),
+            "Object",
+            object.as_str(),
+        )
         .await
-        .map_err(|e| e.to_string())
-        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let user = user
-        .item
-        .ok_or_else(|| json!({"msg": "not found"}))
-        .http_error_json(http::StatusCode::NOT_FOUND)?;
-    #[allow(unused)]
-    let user: Account = serde_dynamo::aws_sdk_dynamodb_1::from_item(user)
-        .map_err(|e| e.to_string())
-        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    #[allow(unused)]
-    let actor = ap::Person::builder();
-    Ok("unimplemented")
+    }
+
+    async fn reactions_by(&self, actor: &url::Url) -> Result<Vec<Reaction>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.reaction_table,
+            Some("Actor"),
+            "Actor",
+            actor.as_str(),
+        )
+        .await
+    }
+
+    async fn get_reaction(&self, id: &url::Url) -> Result<Option<Reaction>, HttpError> {
+        ddb::get(
+            &self.ddb,
+            &self.reaction_table,
+            ddb::key([("Id", id.as_str())]),
+        )
+        .await
+    }
+
+    async fn put_reaction(&self, reaction: &Reaction) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.reaction_table, reaction).await
+    }
+
+    async fn delete_reaction(&self, id: &url::Url) -> Result<(), HttpError> {
+        ddb::delete(
+            &self.ddb,
+            &self.reaction_table,
+            ddb::key([("Id", id.as_str())]),
+        )
+        .await
+    }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let opts = Opts::parse();
+impl ekika::emoji::EmojiStore for State {
+    async fn emojis(&self) -> Result<Vec<CustomEmoji>, HttpError> {
+        ddb::scan(&self.ddb, &self.emoji_table).await
+    }
 
-    init_logger(opts.json_log);
+    async fn get_emoji(&self, id: &str) -> Result<Option<CustomEmoji>, HttpError> {
+        ddb::get(&self.ddb, &self.emoji_table, ddb::key([("Id", id)])).await
+    }
 
-    let sdk_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-    let ddb_config = aws_sdk_dynamodb::config::Builder::from(&sdk_config)
-        .endpoint_url("http://localhost:8000")
-        .build();
-    let ddb = aws_sdk_dynamodb::Client::from_conf(ddb_config);
+    async fn put_emoji(&self, emoji: &CustomEmoji) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.emoji_table, emoji).await
+    }
 
-    let state = Arc::new(State {
-        ddb,
-        user_table: "users".to_string(),
-    });
+    async fn delete_emoji(&self, id: &str) -> Result<(), HttpError> {
+        ddb::delete(&self.ddb, &self.emoji_table, ddb::key([("Id", id)])).await
+    }
+}
 
-    let router = axum::Router::new()
-        .route("/.well-known/host-meta", routing::get(host_meta))
-        .route(
-            "/.well-known/webfinger",
-            routing::get(ekika::webfinger::webfinger),
+impl ekika::object::ObjectStore for State {
+    async fn get_object(&self, id: &url::Url) -> Result<Option<StoredObject>, HttpError> {
+        ddb::get(
+            &self.ddb,
+            &self.object_table,
+            ddb::key([("Id", id.as_str())]),
+        )
+        .await
+    }
+
+    async fn put_object(&self, object: &StoredObject) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.object_table, object).await?;
+        ekika::search::index_object(self, object).await;
+        Ok(())
+    }
+
+    async fn delete_object(&self, id: &url::Url) -> Result<(), HttpError> {
+        ddb::delete(
+            &self.ddb,
+            &self.object_table,
+            ddb::key([("Id", id.as_str())]),
+        )
+        .await?;
+        ekika::search::remove_object(self, id).await;
+        Ok(())
+    }
+
+    async fn replies(&self, parent: &url::Url) -> Result<Vec<StoredObject>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.object_table,
+            Some("InReplyTo"),
+            "InReplyTo",
+            parent.as_str(),
+        )
+        .await
+    }
+}
+
+impl ekika::follow::FollowStore for State {
+    async fn followers(&self, followee: &url::Url) -> Result<Vec<Follow>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.follow_table,
+            None,
+            "Followee",
+            followee.as_str(),
+        )
+        .await
+    }
+
+    async fn get_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> Result<Option<Follow>, HttpError> {
+        let key = ddb::key([
+            ("Followee", followee.as_str()),
+            ("Follower", follower.as_str()),
+        ]);
+        ddb::get(&self.ddb, &self.follow_table, key).await
+    }
+
+    async fn following(&self, follower: &url::Url) -> Result<Vec<Follow>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.follow_table,
+            Some("Follower"),
+            "Follower",
+            follower.as_str(),
+        )
+        .await
+    }
+
+    async fn put_follow(&self, follow: &Follow) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.follow_table, follow).await
+    }
+
+    async fn delete_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> Result<(), HttpError> {
+        let key = ddb::key([
+            ("Followee", followee.as_str()),
+            ("Follower", follower.as_str()),
+        ]);
+        ddb::delete(&self.ddb, &self.follow_table, key).await
+    }
+}
+
+impl ekika::block::BlockStore for State {
+    async fn blocks(&self, blocker: &url::Url) -> Result<Vec<Block>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.block_table,
+            None,
+            "Blocker",
+            blocker.as_str(),
+        )
+        .await
+    }
+
+    async fn get_block(
+        &self,
+        blocker: &url::Url,
+        blocked: &url::Url,
+    ) -> Result<Option<Block>, HttpError> {
+        let key = ddb::key([("Blocker", blocker.as_str()), ("Blocked", blocked.as_str())]);
+        ddb::get(&self.ddb, &self.block_table, key).await
+    }
+
+    async fn put_block(&self, block: &Block) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.block_table, block).await
+    }
+
+    async fn delete_block(&self, blocker: &url::Url, blocked: &url::Url) -> Result<(), HttpError> {
+        let key = ddb::key([("Blocker", blocker.as_str()), ("Blocked", blocked.as_str())]);
+        ddb::delete(&self.ddb, &self.block_table, key).await
+    }
+}
+
+impl ekika::boost::BoostStore for State {
+    async fn boosts_of(&self, object: &url::Url) -> Result<Vec<Boost>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.boost_table,
+            None,
+            "Object",
+            object.as_str(),
+        )
+        .await
+    }
+
+    async fn get_boost(
+        &self,
+        object: &url::Url,
+        actor: &url::Url,
+    ) -> Result<Option<Boost>, HttpError> {
+        let key = ddb::key([("Object", object.as_str()), ("Actor", actor.as_str())]);
+        ddb::get(&self.ddb, &self.boost_table, key).await
+    }
+
+    async fn put_boost(&self, boost: &Boost) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.boost_table, boost).await
+    }
+
+    async fn delete_boost(&self, object: &url::Url, actor: &url::Url) -> Result<(), HttpError> {
+        let key = ddb::key([("Object", object.as_str()), ("Actor", actor.as_str())]);
+        ddb::delete(&self.ddb, &self.boost_table, key).await
+    }
+}
+
+impl ekika::mute::MuteStore for State {
+    async fn mutes(&self, muter: &url::Url) -> Result<Vec<Mute>, HttpError> {
+        ddb::query(&self.ddb, &self.mute_table, None, "Muter", muter.as_str()).await
+    }
+
+    async fn get_mute(
+        &self,
+        muter: &url::Url,
+        muted: &url::Url,
+    ) -> Result<Option<Mute>, HttpError> {
+        let key = ddb::key([("Muter", muter.as_str()), ("Muted", muted.as_str())]);
+        ddb::get(&self.ddb, &self.mute_table, key).await
+    }
+
+    async fn put_mute(&self, mute: &Mute) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.mute_table, mute).await
+    }
+
+    async fn delete_mute(&self, muter: &url::Url, muted: &url::Url) -> Result<(), HttpError> {
+        let key = ddb::key([("Muter", muter.as_str()), ("Muted", muted.as_str())]);
+        ddb::delete(&self.ddb, &self.mute_table, key).await
+    }
+}
+
+impl ekika::list::ListStore for State {
+    async fn lists(&self, owner: &str) -> Result<Vec<List>, HttpError> {
+        ddb::query(&self.ddb, &self.list_table, None, "Owner", owner).await
+    }
+
+    async fn get_list(&self, owner: &str, id: &str) -> Result<Option<List>, HttpError> {
+        let key = ddb::key([("Owner", owner), ("Id", id)]);
+        ddb::get(&self.ddb, &self.list_table, key).await
+    }
+
+    async fn put_list(&self, list: &List) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.list_table, list).await
+    }
+
+    async fn delete_list(&self, owner: &str, id: &str) -> Result<(), HttpError> {
+        let key = ddb::key([("Owner", owner), ("Id", id)]);
+        ddb::delete(&self.ddb, &self.list_table, key).await
+    }
+
+    async fn list_members(&self, list: &str) -> Result<Vec<ListMember>, HttpError> {
+        ddb::query(&self.ddb, &self.list_member_table, None, "List", list).await
+    }
+
+    async fn memberships(&self, member: &url::Url) -> Result<Vec<ListMember>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.list_member_table,
+            Some("Member"),
+            "Member",
+            member.as_str(),
+        )
+        .await
+    }
+
+    async fn put_list_member(&self, member: &ListMember) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.list_member_table, member).await
+    }
+
+    async fn delete_list_member(&self, list: &str, member: &url::Url) -> Result<(), HttpError> {
+        let key = ddb::key([("List", list), ("Member", member.as_str())]);
+        ddb::delete(&self.ddb, &self.list_member_table, key).await
+    }
+}
+
+impl ekika::timeline::TimelineStore for State {
+    async fn feed_entries(
+        &self,
+        feed: &str,
+        range: &FeedRange,
+        limit: usize,
+    ) -> Result<Vec<FeedEntry>, HttpError> {
+        ddb::query_range(
+            &self.ddb,
+            &self.feed_table,
+            "Feed",
+            feed,
+            "Id",
+            range.after.as_deref(),
+            range.before.as_deref(),
+            range.ascending,
+            limit,
+        )
+        .await
+    }
+
+    async fn put_feed_entry(&self, entry: &FeedEntry) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.feed_table, entry).await
+    }
+
+    async fn large_accounts(&self) -> Result<Vec<LargeAccount>, HttpError> {
+        ddb::scan(&self.ddb, &self.large_account_table).await
+    }
+
+    async fn put_large_account(&self, account: &LargeAccount) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.large_account_table, account).await
+    }
+}
+
+impl ekika::notification::NotificationStore for State {
+    async fn notifications(
+        &self,
+        account: &str,
+        range: &FeedRange,
+        limit: usize,
+    ) -> Result<Vec<Notification>, HttpError> {
+        ddb::query_range(
+            &self.ddb,
+            &self.notification_table,
+            "Account",
+            account,
+            "Id",
+            range.after.as_deref(),
+            range.before.as_deref(),
+            range.ascending,
+            limit,
+        )
+        .await
+    }
+
+    async fn grouped_notifications(&self, group: &str) -> Result<Vec<Notification>, HttpError> {
+        ddb::query(
+            &self.ddb,
+            &self.notification_table,
+            Some("Group"),
+            "Group",
+            group,
+        )
+        .await
+    }
+
+    async fn put_notification(&self, notification: &Notification) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.notification_table, notification).await
+    }
+
+    async fn delete_notification(&self, account: &str, id: &str) -> Result<(), HttpError> {
+        let key = ddb::key([("Account", account), ("Id", id)]);
+        ddb::delete(&self.ddb, &self.notification_table, key).await
+    }
+
+    async fn notification_settings(
+        &self,
+        account: &str,
+    ) -> Result<Option<NotificationSettings>, HttpError> {
+        let key = ddb::key([("Account", account)]);
+        ddb::get(&self.ddb, &self.notification_settings_table, key).await
+    }
+
+    async fn put_notification_settings(
+        &self,
+        settings: &NotificationSettings,
+    ) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.notification_settings_table, settings).await
+    }
+}
+
+impl ekika::auth::TokenStore for State {
+    async fn get_token(&self, digest: &str) -> Result<Option<Token>, HttpError> {
+        ddb::get(&self.ddb, &self.token_table, ddb::key([("Id", digest)])).await
+    }
+
+    async fn put_token(&self, token: &Token) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.token_table, token).await
+    }
+
+    async fn delete_token(&self, digest: &str) -> Result<(), HttpError> {
+        ddb::delete(&self.ddb, &self.token_table, ddb::key([("Id", digest)])).await
+    }
+}
+
+impl ekika::oauth::OAuthStore for State {
+    async fn get_application(&self, id: &str) -> Result<Option<Application>, HttpError> {
+        ddb::get(&self.ddb, &self.application_table, ddb::key([("Id", id)])).await
+    }
+
+    async fn put_application(&self, application: &Application) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.application_table, application).await
+    }
+
+    async fn put_authorization_code(&self, code: &AuthorizationCode) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.authorization_code_table, code).await
+    }
+
+    async fn get_authorization_code(
+        &self,
+        digest: &str,
+    ) -> Result<Option<AuthorizationCode>, HttpError> {
+        ddb::get(
+            &self.ddb,
+            &self.authorization_code_table,
+            ddb::key([("Id", digest)]),
+        )
+        .await
+    }
+
+    async fn take_authorization_code(
+        &self,
+        digest: &str,
+    ) -> Result<Option<AuthorizationCode>, HttpError> {
+        ddb::take(
+            &self.ddb,
+            &self.authorization_code_table,
+            ddb::key([("Id", digest)]),
+        )
+        .await
+    }
+
+    async fn get_session(&self, digest: &str) -> Result<Option<Session>, HttpError> {
+        ddb::get(&self.ddb, &self.session_table, ddb::key([("Id", digest)])).await
+    }
+
+    async fn put_session(&self, session: &Session) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.session_table, session).await
+    }
+}
+
+impl ekika::signature::KeyStore for State {
+    async fn get_key(&self, actor: &url::Url) -> Result<Option<KeyPair>, HttpError> {
+        ddb::get(
+            &self.ddb,
+            &self.key_table,
+            ddb::key([("Id", actor.as_str())]),
+        )
+        .await
+    }
+
+    async fn put_key(&self, key: &KeyPair) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.key_table, key).await
+    }
+}
+
+impl ekika::fetch::ActorStore for State {
+    async fn get_actor(&self, id: &url::Url) -> Result<Option<RemoteActor>, HttpError> {
+        ddb::get(
+            &self.ddb,
+            &self.actor_table,
+            ddb::key([("Id", id.as_str())]),
+        )
+        .await
+    }
+
+    async fn put_actor(&self, actor: &RemoteActor) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.actor_table, actor).await?;
+        ekika::search::index_remote_actor(self, actor).await;
+        Ok(())
+    }
+}
+
+impl ekika::nodeinfo::UsageStore for State {
+    async fn user_count(&self) -> Result<u64, HttpError> {
+        ddb::count(&self.ddb, &self.user_table).await
+    }
+}
+
+impl ekika::fetch::Fetcher for State {
+    async fn fetch(&self, iri: &url::Url) -> Result<serde_json::Value, HttpError> {
+        let actor = &self.instance_actor;
+        let key = ekika::signature::key_of(self, actor).await?;
+        let mut key_id = actor.clone();
+        key_id.set_fragment(Some("main-key"));
+        // IRIs come from remote documents and pasted URLs, so they may point anywhere.
+        if !ekika::proxy::is_public_url(iri) {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("{iri} is not a public URL")}),
+                http::StatusCode::BAD_REQUEST,
+            ));
+        }
+        ekika::fetch::fetch(&self.media_http, iri, Some((&key_id, &key))).await
+    }
+}
+
+impl ekika::delivery::DeliveryQueue for State {
+    async fn enqueue(&self, job: DeliveryJob) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.delivery_table, &job).await?;
+        self.deliveries
+            .send(job)
+            .await
+            .map_err(|e| e.to_string())
+            .http_error_json(http::StatusCode::SERVICE_UNAVAILABLE)
+    }
+}
+
+impl ekika::delivery::DeliveryStore for State {
+    async fn pending_deliveries(&self) -> Result<Vec<DeliveryJob>, HttpError> {
+        ddb::scan(&self.ddb, &self.delivery_table).await
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), HttpError> {
+        ddb::delete(&self.ddb, &self.delivery_table, ddb::key([("Id", id)])).await
+    }
+}
+
+impl ekika::conversation::ConversationStore for State {
+    async fn conversations(&self, owner: &str) -> Result<Vec<Conversation>, HttpError> {
+        ddb::query(&self.ddb, &self.conversation_table, None, "Owner", owner).await
+    }
+
+    async fn get_conversation(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<Option<Conversation>, HttpError> {
+        let key = ddb::key([("Owner", owner), ("Id", id)]);
+        ddb::get(&self.ddb, &self.conversation_table, key).await
+    }
+
+    async fn put_conversation(&self, conversation: &Conversation) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.conversation_table, conversation).await
+    }
+}
+
+impl ekika::domain::DomainPolicyStore for State {
+    fn federation_mode(&self) -> FederationMode {
+        self.federation_mode
+    }
+
+    async fn policies(&self) -> Result<Vec<DomainPolicy>, HttpError> {
+        ddb::scan(&self.ddb, &self.domain_policy_table).await
+    }
+
+    async fn get_policy(&self, domain: &str) -> Result<Option<DomainPolicy>, HttpError> {
+        let key = ddb::key([("Domain", domain)]);
+        ddb::get(&self.ddb, &self.domain_policy_table, key).await
+    }
+
+    async fn put_policy(&self, policy: &DomainPolicy) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.domain_policy_table, policy).await
+    }
+
+    async fn delete_policy(&self, domain: &str) -> Result<(), HttpError> {
+        let key = ddb::key([("Domain", domain)]);
+        ddb::delete(&self.ddb, &self.domain_policy_table, key).await
+    }
+
+    async fn allowed_domains(&self) -> Result<Vec<DomainAllow>, HttpError> {
+        ddb::scan(&self.ddb, &self.domain_allow_table).await
+    }
+
+    async fn get_allowed(&self, domain: &str) -> Result<Option<DomainAllow>, HttpError> {
+        let key = ddb::key([("Domain", domain)]);
+        ddb::get(&self.ddb, &self.domain_allow_table, key).await
+    }
+
+    async fn put_allowed(&self, allow: &DomainAllow) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.domain_allow_table, allow).await
+    }
+
+    async fn delete_allowed(&self, domain: &str) -> Result<(), HttpError> {
+        let key = ddb::key([("Domain", domain)]);
+        ddb::delete(&self.ddb, &self.domain_allow_table, key).await
+    }
+}
+
+impl ekika::report::ReportStore for State {
+    async fn reports(&self, state: ReportState) -> Result<Vec<Report>, HttpError> {
+        let state = match state {
+            ReportState::Open => "Open",
+            ReportState::Resolved => "Resolved",
+        };
+        ddb::query(&self.ddb, &self.report_table, Some("State"), "State", state).await
+    }
+
+    async fn get_report(&self, id: &str) -> Result<Option<Report>, HttpError> {
+        ddb::get(&self.ddb, &self.report_table, ddb::key([("Id", id)])).await
+    }
+
+    async fn put_report(&self, report: &Report) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.report_table, report).await
+    }
+}
+
+impl ekika::report::AccountPolicyStore for State {
+    async fn get_account_policy(
+        &self,
+        actor: &url::Url,
+    ) -> Result<Option<AccountPolicy>, HttpError> {
+        let key = ddb::key([("Actor", actor.as_str())]);
+        ddb::get(&self.ddb, &self.account_policy_table, key).await
+    }
+
+    async fn put_account_policy(&self, policy: &AccountPolicy) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.account_policy_table, policy).await
+    }
+}
+
+impl ekika::migration::MigrationStore for State {
+    async fn get_migration(&self, account: &str) -> Result<Option<Migration>, HttpError> {
+        let key = ddb::key([("Account", account)]);
+        ddb::get(&self.ddb, &self.migration_table, key).await
+    }
+
+    async fn put_migration(&self, migration: &Migration) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.migration_table, migration).await
+    }
+}
+
+impl ekika::featured::FeaturedStore for State {
+    async fn get_featured(&self, actor: &url::Url) -> Result<Option<Featured>, HttpError> {
+        let key = ddb::key([("Actor", actor.as_str())]);
+        ddb::get(&self.ddb, &self.featured_table, key).await
+    }
+
+    async fn put_featured(&self, featured: &Featured) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.featured_table, featured).await
+    }
+}
+
+impl ekika::relay::RelayStore for State {
+    async fn relays(&self) -> Result<Vec<Relay>, HttpError> {
+        ddb::scan(&self.ddb, &self.relay_table).await
+    }
+
+    async fn get_relay(&self, id: &str) -> Result<Option<Relay>, HttpError> {
+        ddb::get(&self.ddb, &self.relay_table, ddb::key([("Id", id)])).await
+    }
+
+    async fn put_relay(&self, relay: &Relay) -> Result<(), HttpError> {
+        ddb::put(&self.ddb, &self.relay_table, relay).await
+    }
+
+    async fn delete_relay(&self, id: &str) -> Result<(), HttpError> {
+        ddb::delete(&self.ddb, &self.relay_table, ddb::key([("Id", id)])).await
+    }
+}
+
+#[derive(Parser)]
+struct Opts {
+    #[clap(short, long, env)]
+    addr: SocketAddr,
+    #[clap(short, long, env)]
+    json_log: bool,
+    /// `allowlist` restricts federation to the domains registered through the admin API.
+    #[clap(long, env, value_enum, default_value_t)]
+    federation_mode: FederationMode,
+    /// URL the server is reachable at, such as `https://ekika.example/`.
+    /// Background fetches are signed by the instance actor of this host.
+    #[clap(long, env, default_value = "http://localhost:10000/")]
+    public_url: url::Url,
+    /// S3 compatible endpoint such as MinIO. AWS is used when it is not set.
+    #[clap(long, env)]
+    s3_endpoint: Option<url::Url>,
+    #[clap(long, env, default_value = "media")]
+    media_bucket: String,
+    /// Public URL of the media bucket, ending with a slash.
+    #[clap(long, env, default_value = "http://localhost:10020/media/")]
+    media_url: url::Url,
+    /// Store media in this directory instead of S3. The files are served under `/media/`,
+    /// so `--media-url` should point there.
+    #[clap(long, env)]
+    media_dir: Option<std::path::PathBuf>,
+    /// Longest sides in pixels of the thumbnails made for uploaded images.
+    #[clap(long, env, value_delimiter = ',', default_value = "320,640")]
+    thumbnail_sizes: Vec<u32>,
+    /// Number of images processed at the same time.
+    #[clap(long, env, default_value_t = 2)]
+    media_workers: usize,
+    /// Secret signing media proxy URLs. A random one is used when it is not set,
+    /// which changes the proxy URLs on every restart.
+    #[clap(long, env)]
+    media_proxy_key: Option<String>,
+    /// Days after which proxied remote media is fetched again.
+    #[clap(long, env, default_value_t = 30)]
+    media_cache_days: i64,
+    /// Size of the proxied remote media cache in MiB.
+    #[clap(long, env, default_value_t = 10240)]
+    media_cache_size: u64,
+    /// OpenSearch cluster such as `http://localhost:9200/`, which must accept unsigned requests.
+    /// An in-process index, rebuilt on startup, is used when it is not set.
+    #[clap(long, env)]
+    opensearch_url: Option<url::Url>,
+    #[clap(long, env, default_value = "ekika")]
+    opensearch_index: String,
+}
+
+fn init_logger(json: bool) {
+    use tracing_subscriber::prelude::*;
+    if json {
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| "info".into()),
+            )
+            .with(tracing_subscriber::fmt::layer().json())
+            .init();
+    } else {
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| "info".into()),
+            )
+            .with(tracing_subscriber::fmt::layer())
+            .init();
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = Opts::parse();
+
+    init_logger(opts.json_log);
+
+    let sdk_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let ddb_config = aws_sdk_dynamodb::config::Builder::from(&sdk_config)
+        .endpoint_url("http://localhost:8000")
+        .build();
+    let ddb = aws_sdk_dynamodb::Client::from_conf(ddb_config);
+    let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
+    if let Some(endpoint) = &opts.s3_endpoint {
+        s3_config = s3_config
+            .endpoint_url(endpoint.as_str())
+            .force_path_style(true);
+    }
+    let media = match opts.media_dir {
+        Some(root) => Backend::FileSystem(FileSystem {
+            root,
+            base: opts.media_url,
+        }),
+        None => Backend::S3(S3 {
+            client: aws_sdk_s3::Client::from_conf(s3_config.build()),
+            bucket: opts.media_bucket,
+            base: opts.media_url,
+        }),
+    };
+
+    let http = reqwest::Client::builder()
+        .user_agent(concat!("ekika/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?;
+    let media_http = reqwest::Client::builder()
+        .user_agent(concat!("ekika/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(30))
+        .dns_resolver(Arc::new(ekika::proxy::PublicResolver))
+        .redirect(ekika::proxy::redirect_policy())
+        .build()?;
+    // Inboxes come from remote actor documents, so deliveries must not reach internal hosts.
+    let delivery_http = reqwest::Client::builder()
+        .user_agent(concat!("ekika/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(30))
+        .dns_resolver(Arc::new(ekika::proxy::PublicResolver))
+        .redirect(ekika::proxy::redirect_policy())
+        .build()?;
+    let page_http = reqwest::Client::builder()
+        .user_agent(concat!("ekika/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(30))
+        .dns_resolver(Arc::new(ekika::proxy::PublicResolver))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let proxy_key = match opts.media_proxy_key {
+        Some(key) => key.into_bytes(),
+        None => rand::random::<[u8; 32]>().to_vec(),
+    };
+    let search = match opts.opensearch_url {
+        Some(base) => {
+            let index = OpenSearch {
+                client: http.clone(),
+                base,
+                index: opts.opensearch_index,
+            };
+            if let Err(e) = index.ensure_index().await {
+                warn!(status = e.status.as_u16(), "opensearch_index");
+            }
+            SearchBackend::OpenSearch(index)
+        }
+        None => SearchBackend::Embedded(Embedded::default()),
+    };
+    let embedded_search = matches!(search, SearchBackend::Embedded(_));
+    let (deliveries, delivery_jobs) = mpsc::channel(ekika::delivery::QUEUE_SIZE);
+    let (media_jobs, media_queue) = mpsc::channel(ekika::media::QUEUE_SIZE);
+
+    let state = Arc::new(State {
+        ddb,
+        user_table: "users".to_string(),
+        object_table: "objects".to_string(),
+        follow_table: "follows".to_string(),
+        token_table: "tokens".to_string(),
+        delivery_table: "deliveries".to_string(),
+        application_table: "applications".to_string(),
+        authorization_code_table: "authorization_codes".to_string(),
+        session_table: "sessions".to_string(),
+        key_table: "keys".to_string(),
+        actor_table: "actors".to_string(),
+        conversation_table: "conversations".to_string(),
+        block_table: "blocks".to_string(),
+        boost_table: "boosts".to_string(),
+        mute_table: "mutes".to_string(),
+        list_table: "lists".to_string(),
+        list_member_table: "list_members".to_string(),
+        feed_table: "feeds".to_string(),
+        large_account_table: "large_accounts".to_string(),
+        notification_table: "notifications".to_string(),
+        notification_settings_table: "notification_settings".to_string(),
+        domain_policy_table: "domain_policies".to_string(),
+        domain_allow_table: "domain_allows".to_string(),
+        report_table: "reports".to_string(),
+        account_policy_table: "account_policies".to_string(),
+        migration_table: "migrations".to_string(),
+        featured_table: "featured".to_string(),
+        relay_table: "relays".to_string(),
+        emoji_table: "emojis".to_string(),
+        reaction_table: "reactions".to_string(),
+        media_table: "media".to_string(),
+        media_cache_table: "media_cache".to_string(),
+        federation_mode: opts.federation_mode,
+        instance_actor: ekika::local::Local::new(opts.public_url).instance_actor(),
+        media_http,
+        page_http,
+        proxy_key,
+        search,
+        lookup_limiter: RateLimiter::new(
+            ekika::resolve::LOOKUPS_PER_WINDOW,
+            ekika::resolve::LOOKUP_WINDOW,
+        ),
+        events: LocalBus::new(ekika::stream::EVENT_BUFFER),
+        deliveries,
+        media,
+        media_jobs,
+    });
+    tokio::spawn(ekika::delivery::worker(
+        state.clone(),
+        delivery_http,
+        delivery_jobs,
+    ));
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let pending = match state.pending_deliveries().await {
+                Ok(pending) => pending,
+                Err(e) => {
+                    warn!(status = e.status.as_u16(), "pending_deliveries");
+                    return;
+                }
+            };
+            info!(count = pending.len(), "resume_deliveries");
+            for job in pending {
+                if state.deliveries.send(job).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    tokio::spawn(ekika::media::worker(
+        state.clone(),
+        media_queue,
+        opts.thumbnail_sizes,
+        opts.media_workers,
+    ));
+    if embedded_search {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = reindex(&state).await {
+                warn!(status = e.status.as_u16(), "search_reindex");
+            }
+        });
+    }
+    tokio::spawn(ekika::proxy::pruner(
+        state.clone(),
+        chrono::Duration::days(opts.media_cache_days),
+        opts.media_cache_size * 1024 * 1024,
+    ));
+
+    // Mastodon compatible client API.
+    let mastodon = axum::Router::new()
+        .route("/api/v1/instance", routing::get(ekika::mastodon::instance))
+        .route(
+            "/api/v1/accounts/verify_credentials",
+            routing::get(ekika::mastodon::verify_credentials::<State>),
+        )
+        .route(
+            "/api/v1/accounts/relationships",
+            routing::get(ekika::mastodon::relationships::<State>),
+        )
+        .route(
+            "/api/v1/accounts/lookup",
+            routing::get(ekika::mastodon::lookup_account::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id",
+            routing::get(ekika::mastodon::get_account::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id/statuses",
+            routing::get(ekika::mastodon::account_statuses::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id/followers",
+            routing::get(ekika::mastodon::account_followers::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id/following",
+            routing::get(ekika::mastodon::account_following::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id/follow",
+            routing::post(ekika::mastodon::follow_account::<State>),
+        )
+        .route(
+            "/api/v1/accounts/:id/unfollow",
+            routing::post(ekika::mastodon::unfollow_account::<State>),
+        )
+        .route(
+            "/api/v1/follow_requests",
+            routing::get(ekika::mastodon::follow_requests::<State>),
+        )
+        .route(
+            "/api/v1/follow_requests/:id/authorize",
+            routing::post(ekika::mastodon::authorize_follow_request::<State>),
+        )
+        .route(
+            "/api/v1/follow_requests/:id/reject",
+            routing::post(ekika::mastodon::reject_follow_request::<State>),
+        )
+        .route(
+            "/api/v1/statuses",
+            routing::post(ekika::mastodon::post_status::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id",
+            routing::get(ekika::mastodon::get_status::<State>)
+                .delete(ekika::mastodon::delete_status::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id/context",
+            routing::get(ekika::mastodon::status_context::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id/favourite",
+            routing::post(ekika::mastodon::favourite::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id/unfavourite",
+            routing::post(ekika::mastodon::unfavourite::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id/reblog",
+            routing::post(ekika::mastodon::reblog::<State>),
+        )
+        .route(
+            "/api/v1/statuses/:id/unreblog",
+            routing::post(ekika::mastodon::unreblog::<State>),
+        )
+        .route(
+            "/api/v1/favourites",
+            routing::get(ekika::mastodon::favourites::<State>),
+        )
+        .route(
+            "/api/v1/timelines/home",
+            routing::get(ekika::mastodon::home_timeline::<State>),
+        )
+        .route(
+            "/api/v1/timelines/public",
+            routing::get(ekika::mastodon::public_timeline::<State>),
+        )
+        .route(
+            "/api/v1/timelines/list/:id",
+            routing::get(ekika::mastodon::list_timeline::<State>),
+        )
+        .route(
+            "/api/v1/lists",
+            routing::get(ekika::mastodon::lists::<State>)
+                .post(ekika::mastodon::create_list::<State>),
+        )
+        .route(
+            "/api/v1/lists/:id",
+            routing::get(ekika::mastodon::get_list::<State>)
+                .put(ekika::mastodon::update_list::<State>)
+                .delete(ekika::mastodon::delete_list::<State>),
+        )
+        .route(
+            "/api/v1/lists/:id/accounts",
+            routing::get(ekika::mastodon::list_accounts::<State>)
+                .post(ekika::mastodon::add_list_accounts::<State>)
+                .delete(ekika::mastodon::remove_list_accounts::<State>),
+        )
+        .route(
+            "/api/v1/notifications",
+            routing::get(ekika::mastodon::list_notifications::<State>),
+        )
+        .route(
+            "/api/v1/notifications/clear",
+            routing::post(ekika::mastodon::clear_notifications::<State>),
+        )
+        .route(
+            "/api/v1/notifications/:id/dismiss",
+            routing::post(ekika::mastodon::dismiss_notification::<State>),
+        )
+        .route(
+            "/api/v1/media",
+            routing::post(ekika::mastodon::upload_media::<State>).layer(
+                axum::extract::DefaultBodyLimit::max(ekika::media::MAX_UPLOAD_SIZE + 1024 * 1024),
+            ),
+        )
+        .route(
+            "/api/v2/media",
+            routing::post(ekika::mastodon::upload_media_async::<State>).layer(
+                axum::extract::DefaultBodyLimit::max(ekika::media::MAX_UPLOAD_SIZE + 1024 * 1024),
+            ),
+        )
+        .route(
+            "/api/v1/media/:id",
+            routing::get(ekika::mastodon::get_media::<State>)
+                .put(ekika::mastodon::update_media::<State>),
+        )
+        .route(
+            "/api/v2/search",
+            routing::get(ekika::mastodon::search::<State>),
+        )
+        .route(
+            "/api/v1/apps",
+            routing::post(ekika::oauth::create_app::<State>),
+        )
+        .route(
+            "/api/v1/apps/verify_credentials",
+            routing::get(ekika::oauth::verify_app::<State>),
+        )
+        .layer(axum::middleware::map_response(ekika::mastodon::error_body));
+    let router = axum::Router::new()
+        .route("/.well-known/host-meta", routing::get(host_meta))
+        .route(
+            "/.well-known/oauth-authorization-server",
+            routing::get(ekika::oauth::metadata),
+        )
+        .route(
+            "/oauth/authorize",
+            routing::get(ekika::oauth::authorize::<State>).post(ekika::oauth::approve::<State>),
+        )
+        .route(
+            "/oauth/login",
+            routing::get(ekika::oauth::login_page).post(ekika::oauth::login::<State>),
+        )
+        .route("/oauth/token", routing::post(ekika::oauth::token::<State>))
+        .route(
+            "/oauth/revoke",
+            routing::post(ekika::oauth::revoke::<State>),
+        )
+        .route(
+            "/.well-known/webfinger",
+            routing::get(ekika::webfinger::webfinger),
+        )
+        .route(
+            "/users/:name",
+            routing::get(ekika::actor::get_actor::<State>),
+        )
+        .route(
+            "/objects/:id",
+            routing::get(ekika::object::get_object::<State>),
+        )
+        .route("/inbox", routing::post(ekika::inbox::shared_inbox::<State>))
+        .route(
+            "/actor",
+            routing::get(ekika::actor::get_instance_actor::<State>),
+        )
+        .route(
+            "/actor/inbox",
+            routing::post(ekika::inbox::shared_inbox::<State>),
+        )
+        .route(
+            "/actor/:collection",
+            routing::get(ekika::actor::get_collection::<State>),
+        )
+        .route(
+            "/users/:name/:collection",
+            routing::get(ekika::actor::get_collection::<State>),
+        )
+        .route(
+            "/.well-known/nodeinfo",
+            routing::get(ekika::nodeinfo::discovery),
+        )
+        .route(
+            "/nodeinfo/2.0",
+            routing::get(ekika::nodeinfo::nodeinfo::<State>),
+        )
+        .route("/media/*key", routing::get(serve_media))
+        .route(
+            "/proxy/:signature/:url",
+            routing::get(ekika::proxy::proxy::<State>),
+        )
+        .route("/api/search", routing::get(ekika::search::search::<State>))
+        .route(
+            "/api/streaming",
+            routing::get(ekika::stream::websocket::<State>),
+        )
+        .route(
+            "/api/streaming/*stream",
+            routing::get(ekika::stream::server_sent_events::<State>),
+        )
+        .route(
+            "/api/search/resolve",
+            routing::get(ekika::resolve::resolve_search::<State>),
+        )
+        .route(
+            "/api/media",
+            routing::post(ekika::media::upload::<State>).layer(
+                axum::extract::DefaultBodyLimit::max(ekika::media::MAX_UPLOAD_SIZE + 1024 * 1024),
+            ),
+        )
+        .route(
+            "/api/media/:id",
+            routing::get(ekika::media::get_media::<State>).put(ekika::media::update_media::<State>),
+        )
+        .route(
+            "/emojis/:shortcode",
+            routing::get(ekika::emoji::get_emoji::<State>),
+        )
+        .route(
+            "/api/emojis",
+            routing::get(ekika::emoji::list_emojis::<State>),
+        )
+        .route(
+            "/api/admin/emojis",
+            routing::post(ekika::emoji::create_emoji::<State>),
+        )
+        .route(
+            "/api/admin/emojis/remote",
+            routing::get(ekika::emoji::list_remote_emojis::<State>),
+        )
+        .route(
+            "/api/admin/emojis/copy",
+            routing::post(ekika::emoji::copy_emoji::<State>),
+        )
+        .route(
+            "/api/admin/emojis/:shortcode",
+            routing::patch(ekika::emoji::update_emoji::<State>)
+                .delete(ekika::emoji::delete_emoji::<State>),
+        )
+        .route(
+            "/users/:name/collections/featured",
+            routing::get(ekika::featured::get_featured_collection::<State>),
+        )
+        .route(
+            "/users/:name/inbox",
+            routing::post(ekika::inbox::user_inbox::<State>),
+        )
+        .route(
+            "/api/blocks",
+            routing::get(ekika::block::list_blocks::<State>)
+                .post(ekika::block::create_block::<State>)
+                .delete(ekika::block::delete_block::<State>),
+        )
+        .route(
+            "/api/mutes",
+            routing::get(ekika::mute::list_mutes::<State>)
+                .post(ekika::mute::create_mute::<State>)
+                .delete(ekika::mute::delete_mute::<State>),
+        )
+        .route(
+            "/api/notifications",
+            routing::get(ekika::notification::list_notifications::<State>),
+        )
+        .route(
+            "/api/notifications/read",
+            routing::post(ekika::notification::mark_read::<State>),
+        )
+        .route(
+            "/api/notifications/settings",
+            routing::get(ekika::notification::get_settings::<State>)
+                .put(ekika::notification::put_settings::<State>),
+        )
+        .route(
+            "/api/notifications/:id",
+            routing::delete(ekika::notification::dismiss_notification::<State>),
+        )
+        .route(
+            "/api/timelines/home",
+            routing::get(ekika::timeline::home_timeline::<State>),
+        )
+        .route(
+            "/api/timelines/local",
+            routing::get(ekika::timeline::local_timeline::<State>),
+        )
+        .route(
+            "/api/timelines/federated",
+            routing::get(ekika::timeline::federated_timeline::<State>),
+        )
+        .route(
+            "/api/admin/domain_blocks",
+            routing::get(ekika::domain::list_policies::<State>)
+                .post(ekika::domain::put_policy::<State>),
+        )
+        .route(
+            "/api/admin/domain_blocks/export",
+            routing::get(ekika::domain::export_policies::<State>),
+        )
+        .route(
+            "/api/admin/domain_blocks/import",
+            routing::post(ekika::domain::import_policies::<State>),
+        )
+        .route(
+            "/api/admin/domain_blocks/:domain",
+            routing::delete(ekika::domain::delete_policy::<State>),
+        )
+        .route(
+            "/api/admin/domain_allows",
+            routing::get(ekika::domain::list_allowed::<State>)
+                .post(ekika::domain::put_allowed::<State>),
+        )
+        .route(
+            "/api/admin/domain_allows/:domain",
+            routing::delete(ekika::domain::delete_allowed::<State>),
+        )
+        .route(
+            "/api/instance/domain_blocks",
+            routing::get(ekika::domain::public_policies::<State>),
+        )
+        .route(
+            "/api/admin/relays",
+            routing::get(ekika::relay::list_relays::<State>).post(ekika::relay::subscribe::<State>),
+        )
+        .route(
+            "/api/admin/relays/:id",
+            routing::patch(ekika::relay::update_relay::<State>)
+                .delete(ekika::relay::unsubscribe::<State>),
+        )
+        .route(
+            "/api/reports",
+            routing::post(ekika::report::create_report::<State>),
+        )
+        .route(
+            "/api/admin/reports",
+            routing::get(ekika::report::list_reports::<State>),
+        )
+        .route(
+            "/api/admin/reports/:id",
+            routing::get(ekika::report::get_report::<State>),
+        )
+        .route(
+            "/api/admin/reports/:id/resolve",
+            routing::post(ekika::report::resolve_report::<State>),
+        )
+        .route(
+            "/api/account/migration",
+            routing::get(ekika::migration::get_migration::<State>),
+        )
+        .route(
+            "/api/account/aliases",
+            routing::put(ekika::migration::put_aliases::<State>),
+        )
+        .route(
+            "/api/account/move",
+            routing::post(ekika::migration::move_account::<State>),
+        )
+        .route(
+            "/api/account/profile",
+            routing::get(ekika::profile::get_profile::<State>)
+                .put(ekika::profile::put_profile::<State>),
+        )
+        .route(
+            "/api/reactions",
+            routing::get(ekika::reaction::list_reactions::<State>)
+                .put(ekika::reaction::react::<State>)
+                .delete(ekika::reaction::unreact::<State>),
+        )
+        .route(
+            "/api/pins",
+            routing::get(ekika::featured::list_pins::<State>)
+                .post(ekika::featured::pin::<State>)
+                .delete(ekika::featured::unpin::<State>),
+        )
+        .route(
+            "/api/conversations",
+            routing::get(ekika::conversation::list_conversations::<State>)
+                .post(ekika::conversation::send_direct::<State>),
+        )
+        .route(
+            "/api/conversations/:id/read",
+            routing::post(ekika::conversation::mark_read::<State>),
         )
-        .route("/user/:users", routing::get(ap_get_user))
+        .merge(mastodon)
         .with_state(state)
         .layer(TraceLayer::new_for_http());
     let listener = tokio::net::TcpListener::bind(&opts.addr).await?;
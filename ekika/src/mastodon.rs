@@ -0,0 +1,2294 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{extract::Multipart, Json};
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity, ap,
+    auth::{Authenticated, TokenStore},
+    block::{self, BlockStore},
+    boost::{self, BoostStore},
+    conversation::{self, ConversationStore},
+    delivery::{self, DeliveryQueue},
+    emoji,
+    fetch::{self, Remote},
+    follow::{self, FollowStore},
+    list::{self, ListStore},
+    local::Local,
+    media::{self, MediaQueue, MediaStore, MediaUpdate, MediaView},
+    model::{
+        account::{Account, AccountKind},
+        actor::RemoteActor,
+        follow::{Follow, FollowState},
+        list::{List, ListMember},
+        media::{MediaKind, MediaState},
+        notification::NotificationKind,
+        object::StoredObject,
+    },
+    mute::{self, MuteStore},
+    notification::{self, NotificationParams, NotificationStore},
+    object::{self, ObjectStore},
+    profile,
+    reaction::{self, ReactRequest, ReactionStore, UnreactRequest},
+    resolve::{self, ResolveParams, Target, WebfingerFetcher},
+    search::{self, DocumentKind, SearchIndex, SearchParams, SearchResults},
+    storage::{self, BlobStore},
+    stream::EventBus,
+    timeline::{self, FeedRange, Filter, TimelineItem, TimelineParams, TimelineStore},
+    visibility::{self, Viewer, Visibility},
+    webfinger::AccountStore,
+};
+
+/// Characters the text of a status may have.
+pub const MAX_CHARACTERS: usize = 5000;
+
+/// Reaction a favourite is federated as.
+pub const FAVOURITE: &str = reaction::DEFAULT_REACTION;
+
+const DEFAULT_LIMIT: usize = 20;
+
+const MAX_LIMIT: usize = 40;
+
+/// Posts followed up the reply chain of a status for its context.
+const MAX_ANCESTORS: usize = 40;
+
+/// Replies of a status and of its replies in its context.
+const MAX_DESCENDANTS: usize = 60;
+
+/// Notifications removed at once when they are cleared.
+const CLEAR_BATCH: usize = 100;
+
+/// Everything the client API needs from the server state.
+pub trait ClientState:
+    AccountStore<ActorInfo = Account>
+    + ObjectStore
+    + FollowStore
+    + BlockStore
+    + ListStore
+    + MuteStore
+    + ReactionStore
+    + BoostStore
+    + ConversationStore
+    + TimelineStore
+    + NotificationStore
+    + MediaStore
+    + MediaQueue
+    + BlobStore
+    + SearchIndex
+    + WebfingerFetcher
+    + EventBus
+    + Remote
+    + DeliveryQueue
+    + TokenStore
+    + Send
+    + Sync
+{
+}
+
+impl<S> ClientState for S where
+    S: AccountStore<ActorInfo = Account>
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + MuteStore
+        + ReactionStore
+        + BoostStore
+        + ConversationStore
+        + TimelineStore
+        + NotificationStore
+        + MediaStore
+        + MediaQueue
+        + BlobStore
+        + SearchIndex
+        + WebfingerFetcher
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync
+{
+}
+
+fn not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "Record not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+fn unprocessable(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::UNPROCESSABLE_ENTITY,
+    )
+}
+
+/// Largest body of a failed response [error_body] reads.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Mastodon clients show the `error` of failed responses, so `msg` is passed on as that.
+pub async fn error_body(response: axum::response::Response) -> axum::response::Response {
+    if !(response.status().is_client_error() || response.status().is_server_error()) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    // Error bodies of this server are small; anything longer is replaced by the status.
+    let body = axum::body::to_bytes(body, MAX_ERROR_BODY)
+        .await
+        .unwrap_or_default();
+    let error = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| Some(body.get("msg")?.as_str()?.to_string()))
+        .unwrap_or_else(|| {
+            parts
+                .status
+                .canonical_reason()
+                .unwrap_or("Error")
+                .to_string()
+        });
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    parts.headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/json"),
+    );
+    axum::response::Response::from_parts(
+        parts,
+        axum::body::Body::from(json!({"error": error}).to_string()),
+    )
+}
+
+/// Accounts are identified by their base64url encoded IRI.
+pub fn account_id(actor: &url::Url) -> String {
+    URL_SAFE_NO_PAD.encode(actor.as_str())
+}
+
+/// Statuses are identified by a time ordered key and their base64url encoded IRI, so that
+/// clients which page with status ids instead of the `Link` header still get the right pages.
+pub fn status_id(key: &str, iri: &url::Url) -> String {
+    format!("{key}.{}", URL_SAFE_NO_PAD.encode(iri.as_str()))
+}
+
+/// Key comparable to feed entry ids for a point in time.
+fn time_key(time: chrono::DateTime<chrono::Utc>) -> String {
+    let millis = u64::try_from(time.timestamp_millis()).unwrap_or_default();
+    format!("{:020}", millis << 16)
+}
+
+/// IRI of an account or status id.
+pub fn iri_of(id: &str) -> Result<url::Url, HttpError> {
+    let encoded = id.split_once('.').map_or(id, |(_, encoded)| encoded);
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .ok()
+        .and_then(|iri| String::from_utf8(iri).ok())
+        .and_then(|iri| iri.parse().ok())
+        .ok_or_else(not_found)
+}
+
+/// Part of a status id which feeds are ordered by. Other ids are used as they are.
+fn page_key(id: Option<String>) -> Option<String> {
+    id.map(|id| match id.split_once('.') {
+        Some((key, _)) => key.to_string(),
+        None => id,
+    })
+}
+
+fn page_params(params: TimelineParams) -> TimelineParams {
+    TimelineParams {
+        max_id: page_key(params.max_id),
+        since_id: page_key(params.since_id),
+        min_id: page_key(params.min_id),
+        limit: params.limit,
+    }
+}
+
+fn time_of(body: &serde_json::Value, name: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    serde_json::from_value(body.get(name)?.clone()).ok()
+}
+
+/// Values of a property which may be a single value or an array.
+fn values<'a>(body: &'a serde_json::Value, name: &str) -> Vec<&'a serde_json::Value> {
+    match body.get(name) {
+        Some(serde_json::Value::Array(values)) => values.iter().collect(),
+        Some(serde_json::Value::Null) | None => Vec::new(),
+        Some(value) => vec![value],
+    }
+}
+
+fn kind_of(value: &serde_json::Value) -> Option<&str> {
+    value.get("type").and_then(|kind| kind.as_str())
+}
+
+/// First URL of a `url` property, which may be a string or a `Link`.
+fn url_of(body: &serde_json::Value) -> Option<url::Url> {
+    values(body, "url").into_iter().find_map(|url| match url {
+        serde_json::Value::String(url) => url.parse().ok(),
+        link => link.get("href")?.as_str()?.parse().ok(),
+    })
+}
+
+#[derive(Clone, Serialize)]
+pub struct FieldView {
+    pub name: String,
+    pub value: String,
+    pub verified_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SourceView {
+    pub note: String,
+    pub fields: Vec<FieldView>,
+    pub privacy: StatusVisibility,
+    pub sensitive: bool,
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CustomEmojiView {
+    pub shortcode: String,
+    pub url: url::Url,
+    pub static_url: url::Url,
+    pub visible_in_picker: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AccountView {
+    pub id: String,
+    pub username: String,
+    pub acct: String,
+    pub display_name: String,
+    pub locked: bool,
+    pub bot: bool,
+    pub discoverable: bool,
+    pub group: bool,
+    /// ekika doesn't record when accounts were created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub note: String,
+    pub url: url::Url,
+    pub uri: url::Url,
+    pub avatar: url::Url,
+    pub avatar_static: url::Url,
+    pub header: url::Url,
+    pub header_static: url::Url,
+    pub followers_count: usize,
+    pub following_count: usize,
+    pub statuses_count: usize,
+    pub last_status_at: Option<chrono::NaiveDate>,
+    pub emojis: Vec<CustomEmojiView>,
+    pub fields: Vec<FieldView>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceView>,
+}
+
+impl AccountView {
+    /// Account of a local account, with its numbers of followers and followees.
+    pub fn local(local: &Local, name: &str, account: Account, counts: (usize, usize)) -> Self {
+        let actor = local.actor(name);
+        let avatar = account
+            .icon
+            .first()
+            .cloned()
+            .unwrap_or_else(|| local.missing_image("avatars"));
+        let header = account
+            .header
+            .unwrap_or_else(|| local.missing_image("headers"));
+        Self {
+            id: account_id(&actor),
+            username: name.to_string(),
+            acct: name.to_string(),
+            display_name: account.name,
+            locked: account.locked,
+            bot: account.bot || account.kind == AccountKind::Service,
+            discoverable: account.discoverable,
+            group: account.kind == AccountKind::Group,
+            created_at: chrono::DateTime::default(),
+            note: account.summary,
+            url: actor.clone(),
+            uri: actor,
+            avatar: avatar.clone(),
+            avatar_static: avatar,
+            header: header.clone(),
+            header_static: header,
+            followers_count: counts.0,
+            following_count: counts.1,
+            statuses_count: 0,
+            last_status_at: None,
+            emojis: Vec::new(),
+            fields: account
+                .fields
+                .into_iter()
+                .map(|field| FieldView {
+                    value: profile::field_html(&field.value),
+                    name: field.name,
+                    verified_at: field.verified,
+                })
+                .collect(),
+            source: None,
+        }
+    }
+
+    /// Account of a remote actor, with what is known of it.
+    pub fn remote(
+        local: &Local,
+        iri: &url::Url,
+        actor: Option<RemoteActor>,
+        counts: (usize, usize),
+    ) -> Self {
+        let (username, display_name, note) = match actor {
+            Some(actor) => (actor.preferred_username, actor.name, actor.summary),
+            None => (None, None, None),
+        };
+        let username = username
+            .or_else(|| {
+                iri.path_segments()?
+                    .rfind(|segment| !segment.is_empty())
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| iri.host_str().unwrap_or_default().to_string());
+        let avatar = local.missing_image("avatars");
+        let header = local.missing_image("headers");
+        Self {
+            id: account_id(iri),
+            acct: format!("{username}@{}", iri.host_str().unwrap_or_default()),
+            username,
+            display_name: display_name.unwrap_or_default(),
+            locked: false,
+            bot: false,
+            discoverable: false,
+            group: false,
+            created_at: chrono::DateTime::default(),
+            note: note.unwrap_or_default(),
+            url: iri.clone(),
+            uri: iri.clone(),
+            avatar: avatar.clone(),
+            avatar_static: avatar,
+            header: header.clone(),
+            header_static: header,
+            followers_count: counts.0,
+            following_count: counts.1,
+            statuses_count: 0,
+            last_status_at: None,
+            emojis: Vec::new(),
+            fields: Vec::new(),
+            source: None,
+        }
+    }
+}
+
+/// Numbers of accepted followers and followees of an actor known to this server.
+async fn counts<S>(state: &S, actor: &url::Url) -> Result<(usize, usize), HttpError>
+where
+    S: FollowStore + Sync,
+{
+    let accepted = |follows: Vec<Follow>| {
+        follows
+            .iter()
+            .filter(|follow| follow.state == FollowState::Accepted)
+            .count()
+    };
+    Ok((
+        accepted(state.followers(actor).await?),
+        accepted(state.following(actor).await?),
+    ))
+}
+
+/// Account of a local account or of a remote actor this server has seen.
+async fn find_account<S>(
+    state: &S,
+    local: &Local,
+    actor: &url::Url,
+) -> Result<Option<AccountView>, HttpError>
+where
+    S: ClientState,
+{
+    if let Some(name) = local.account_name(actor) {
+        let Some(account) = state.query(&name).await? else {
+            return Ok(None);
+        };
+        let counts = counts(state, actor).await?;
+        return Ok(Some(AccountView::local(local, &name, account, counts)));
+    }
+    let Some(remote) = state.get_actor(actor).await? else {
+        return Ok(None);
+    };
+    let counts = counts(state, actor).await?;
+    Ok(Some(AccountView::remote(
+        local,
+        actor,
+        Some(remote),
+        counts,
+    )))
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusVisibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+impl From<Visibility> for StatusVisibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Public => Self::Public,
+            Visibility::Unlisted => Self::Unlisted,
+            Visibility::FollowersOnly => Self::Private,
+            Visibility::Direct => Self::Direct,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct AttachmentView {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Unset while the file is processed.
+    pub url: Option<url::Url>,
+    pub preview_url: Option<url::Url>,
+    pub remote_url: Option<url::Url>,
+    pub description: Option<String>,
+    pub blurhash: Option<String>,
+    pub meta: serde_json::Value,
+}
+
+fn attachment_kind(kind: MediaKind) -> &'static str {
+    match kind {
+        MediaKind::Image => "image",
+        MediaKind::Video => "video",
+        MediaKind::Audio => "audio",
+        MediaKind::Document => "unknown",
+    }
+}
+
+fn meta(width: Option<u64>, height: Option<u64>, focus: Option<[f64; 2]>) -> serde_json::Value {
+    let mut meta = json!({});
+    if let (Some(width), Some(height)) = (width, height) {
+        meta["original"] = json!({"width": width, "height": height});
+    }
+    if let Some([x, y]) = focus {
+        meta["focus"] = json!({"x": x, "y": y});
+    }
+    meta
+}
+
+impl From<MediaView> for AttachmentView {
+    fn from(media: MediaView) -> Self {
+        let preview_url = media
+            .thumbnails
+            .first()
+            .map(|thumbnail| thumbnail.url.clone())
+            .or_else(|| media.url.clone());
+        Self {
+            id: media.id,
+            kind: attachment_kind(media.kind),
+            url: media.url,
+            preview_url,
+            remote_url: None,
+            description: media.description,
+            blurhash: media.blurhash,
+            meta: meta(media.width, media.height, media.focus),
+        }
+    }
+}
+
+impl AttachmentView {
+    /// Attachment of a post, from its ActivityStreams document.
+    fn of_document(document: &serde_json::Value) -> Option<Self> {
+        let url = url_of(document)?;
+        let media_type = document
+            .get("mediaType")
+            .and_then(|media_type| media_type.as_str())
+            .unwrap_or_default();
+        let number = |name: &str| document.get(name).and_then(|value| value.as_u64());
+        let focus = document
+            .get("focalPoint")
+            .and_then(|point| serde_json::from_value(point.clone()).ok());
+        Some(Self {
+            id: account_id(&url),
+            kind: attachment_kind(MediaKind::of(media_type)),
+            url: Some(url.clone()),
+            preview_url: Some(url.clone()),
+            remote_url: Some(url),
+            description: document
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(str::to_string),
+            blurhash: document
+                .get("blurhash")
+                .and_then(|blurhash| blurhash.as_str())
+                .map(str::to_string),
+            meta: meta(number("width"), number("height"), focus),
+        })
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct MentionView {
+    pub id: String,
+    pub username: String,
+    pub acct: String,
+    pub url: url::Url,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TagView {
+    pub name: String,
+    pub url: url::Url,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StatusView {
+    pub id: String,
+    pub uri: url::Url,
+    pub url: Option<url::Url>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub account: AccountView,
+    pub content: String,
+    pub visibility: StatusVisibility,
+    pub sensitive: bool,
+    pub spoiler_text: String,
+    pub media_attachments: Vec<AttachmentView>,
+    pub mentions: Vec<MentionView>,
+    pub tags: Vec<TagView>,
+    pub emojis: Vec<CustomEmojiView>,
+    pub reblogs_count: usize,
+    pub favourites_count: usize,
+    /// Public and unlisted replies which are stored.
+    pub replies_count: usize,
+    pub in_reply_to_id: Option<String>,
+    pub in_reply_to_account_id: Option<String>,
+    pub reblog: Option<Box<StatusView>>,
+    pub poll: Option<serde_json::Value>,
+    pub card: Option<serde_json::Value>,
+    pub language: Option<String>,
+    pub favourited: bool,
+    pub reblogged: bool,
+    pub muted: bool,
+    pub bookmarked: bool,
+}
+
+#[derive(Serialize)]
+pub struct RelationshipView {
+    pub id: String,
+    pub following: bool,
+    pub showing_reblogs: bool,
+    pub notifying: bool,
+    /// Languages the viewer sees of the account, all of them when null.
+    pub languages: Option<Vec<String>>,
+    pub followed_by: bool,
+    pub blocking: bool,
+    pub blocked_by: bool,
+    pub muting: bool,
+    pub muting_notifications: bool,
+    pub requested: bool,
+    pub requested_by: bool,
+    pub domain_blocking: bool,
+    pub endorsed: bool,
+    pub note: String,
+}
+
+async fn relationship<S>(
+    state: &S,
+    viewer: &url::Url,
+    target: &url::Url,
+) -> Result<RelationshipView, HttpError>
+where
+    S: ClientState,
+{
+    let follow = state.get_follow(viewer, target).await?.map(|f| f.state);
+    let followed_by = state.get_follow(target, viewer).await?.map(|f| f.state);
+    let muting = mute::is_muted(state, viewer, target).await?;
+    Ok(RelationshipView {
+        id: account_id(target),
+        following: follow == Some(FollowState::Accepted),
+        showing_reblogs: true,
+        notifying: false,
+        languages: None,
+        followed_by: followed_by == Some(FollowState::Accepted),
+        blocking: state.get_block(viewer, target).await?.is_some(),
+        blocked_by: state.get_block(target, viewer).await?.is_some(),
+        muting,
+        muting_notifications: muting,
+        requested: follow == Some(FollowState::Pending),
+        requested_by: followed_by == Some(FollowState::Pending),
+        domain_blocking: false,
+        endorsed: false,
+        note: String::new(),
+    })
+}
+
+/// Renders internal models as Mastodon entities for `viewer`, reusing the accounts it looked up.
+struct Renderer<'a, S> {
+    state: &'a S,
+    local: &'a Local,
+    viewer: url::Url,
+    accounts: HashMap<url::Url, AccountView>,
+}
+
+impl<'a, S: ClientState> Renderer<'a, S> {
+    fn new(state: &'a S, local: &'a Local, viewer: url::Url) -> Self {
+        Self {
+            state,
+            local,
+            viewer,
+            accounts: HashMap::new(),
+        }
+    }
+
+    async fn account(&mut self, actor: &url::Url) -> Result<AccountView, HttpError> {
+        if let Some(account) = self.accounts.get(actor) {
+            return Ok(account.clone());
+        }
+        let account = match find_account(self.state, self.local, actor).await? {
+            Some(account) => account,
+            None => AccountView::remote(self.local, actor, None, (0, 0)),
+        };
+        self.accounts.insert(actor.clone(), account.clone());
+        Ok(account)
+    }
+
+    async fn status(&mut self, object: &StoredObject) -> Result<StatusView, HttpError> {
+        let body: serde_json::Value = serde_json::from_str(&object.body).unwrap_or_default();
+        let account = self.account(&object.attributed_to).await?;
+        let in_reply_to = activity::field_iri(&body, "inReplyTo");
+        let parent = match &in_reply_to {
+            Some(parent) => self.state.get_object(parent).await?,
+            None => None,
+        };
+        // Ids of posts which aren't stored can't be ordered, but still refer to them.
+        let in_reply_to_id = match &parent {
+            Some(parent) => serde_json::from_str(&parent.body)
+                .ok()
+                .and_then(|body: serde_json::Value| time_of(&body, "published")),
+            None => None,
+        }
+        .unwrap_or_default();
+        let reactions = self.state.reactions_of(&object.id).await?;
+        let boosts = self.state.boosts_of(&object.id).await?;
+        let replies_count = self
+            .state
+            .replies(&object.id)
+            .await?
+            .iter()
+            .filter(|reply| matches!(reply.visibility, Visibility::Public | Visibility::Unlisted))
+            .count();
+        let tags = values(&body, "tag");
+        let mentions = tags
+            .iter()
+            .filter(|tag| kind_of(tag) == Some("Mention"))
+            .filter_map(|tag| {
+                let href = activity::field_iri(tag, "href")?;
+                let host = href.host_str().unwrap_or_default();
+                let name = tag
+                    .get("name")
+                    .and_then(|name| name.as_str())
+                    .unwrap_or_default()
+                    .trim_start_matches('@');
+                let (username, acct) = match name.split_once('@') {
+                    Some((username, mention_host)) if mention_host == self.local.host() => {
+                        (username.to_string(), username.to_string())
+                    }
+                    Some((username, _)) => (username.to_string(), name.to_string()),
+                    None if self.local.is_local(&href) => (name.to_string(), name.to_string()),
+                    None => (name.to_string(), format!("{name}@{host}")),
+                };
+                Some(MentionView {
+                    id: account_id(&href),
+                    username,
+                    acct,
+                    url: href,
+                })
+            })
+            .collect();
+        let emojis = tags
+            .iter()
+            .filter(|tag| kind_of(tag) == Some("Emoji"))
+            .filter_map(|tag| {
+                let shortcode = tag.get("name")?.as_str()?.trim_matches(':').to_string();
+                let url: url::Url = tag.get("icon")?.get("url")?.as_str()?.parse().ok()?;
+                Some(CustomEmojiView {
+                    shortcode,
+                    static_url: url.clone(),
+                    url,
+                    visible_in_picker: false,
+                })
+            })
+            .collect();
+        let str_of = |name: &str| {
+            body.get(name)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let spoiler_text = str_of("summary");
+        let created_at = time_of(&body, "published").unwrap_or_default();
+        Ok(StatusView {
+            id: status_id(&time_key(created_at), &object.id),
+            uri: object.id.clone(),
+            url: url_of(&body).or_else(|| Some(object.id.clone())),
+            created_at,
+            edited_at: time_of(&body, "updated"),
+            account,
+            content: str_of("content"),
+            visibility: object.visibility.into(),
+            sensitive: body
+                .get("sensitive")
+                .and_then(|sensitive| sensitive.as_bool())
+                .unwrap_or_default()
+                || !spoiler_text.is_empty(),
+            spoiler_text,
+            media_attachments: values(&body, "attachment")
+                .into_iter()
+                .filter_map(AttachmentView::of_document)
+                .collect(),
+            mentions,
+            tags: search::hashtags(&body)
+                .into_iter()
+                .map(|name| TagView {
+                    url: self.local.tag(&name),
+                    name,
+                })
+                .collect(),
+            emojis,
+            reblogs_count: boosts.len(),
+            favourites_count: reactions.len(),
+            replies_count,
+            in_reply_to_id: in_reply_to
+                .as_ref()
+                .map(|iri| status_id(&time_key(in_reply_to_id), iri)),
+            in_reply_to_account_id: parent.map(|parent| account_id(&parent.attributed_to)),
+            reblog: None,
+            poll: None,
+            card: None,
+            language: None,
+            favourited: reactions
+                .iter()
+                .any(|reaction| reaction.actor == self.viewer),
+            reblogged: boosts.iter().any(|boost| boost.actor == self.viewer),
+            muted: false,
+            bookmarked: false,
+        })
+    }
+
+    /// Boost of `status` by `booster`. Boosts have no object of their own, so their id
+    /// refers to the boosted post.
+    async fn reblog(
+        &mut self,
+        key: &str,
+        booster: &url::Url,
+        created_at: chrono::DateTime<chrono::Utc>,
+        status: StatusView,
+    ) -> Result<StatusView, HttpError> {
+        Ok(StatusView {
+            id: status_id(key, &status.uri),
+            created_at,
+            edited_at: None,
+            account: self.account(booster).await?,
+            content: String::new(),
+            sensitive: false,
+            spoiler_text: String::new(),
+            media_attachments: Vec::new(),
+            mentions: Vec::new(),
+            tags: Vec::new(),
+            emojis: Vec::new(),
+            in_reply_to_id: None,
+            in_reply_to_account_id: None,
+            reblog: Some(Box::new(status.clone())),
+            ..status
+        })
+    }
+
+    /// Status of a timeline entry. Entries of deleted posts are skipped.
+    async fn item(&mut self, item: TimelineItem) -> Result<Option<StatusView>, HttpError> {
+        let Some(iri) = activity::field_iri(&item.object, "id") else {
+            return Ok(None);
+        };
+        let Some(object) = self.state.get_object(&iri).await? else {
+            return Ok(None);
+        };
+        let status = self.status(&object).await?;
+        Ok(Some(match &item.boosted_by {
+            Some(booster) => {
+                self.reblog(&item.id, booster, item.created_at, status)
+                    .await?
+            }
+            None => status,
+        }))
+    }
+
+    async fn items(&mut self, items: Vec<TimelineItem>) -> Result<Vec<StatusView>, HttpError> {
+        let mut statuses = Vec::with_capacity(items.len());
+        for item in items {
+            statuses.extend(self.item(item).await?);
+        }
+        Ok(statuses)
+    }
+}
+
+/// Actor of an account id, which must be a local account or a known remote actor.
+async fn known_actor<S>(state: &S, local: &Local, id: &str) -> Result<url::Url, HttpError>
+where
+    S: ClientState,
+{
+    let actor = iri_of(id)?;
+    find_account(state, local, &actor)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok(actor)
+}
+
+pub async fn verify_credentials<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<AccountView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let account = state.query(&auth.account).await?.ok_or_else(not_found)?;
+    let counts = counts(state, &local.actor(&auth.account)).await?;
+    let mut view = AccountView::local(&local, &auth.account, account.clone(), counts);
+    view.source = Some(SourceView {
+        note: account.summary,
+        fields: account
+            .fields
+            .into_iter()
+            .map(|field| FieldView {
+                name: field.name,
+                value: field.value,
+                verified_at: field.verified,
+            })
+            .collect(),
+        privacy: StatusVisibility::Public,
+        sensitive: false,
+        language: None,
+    });
+    Ok(Json(view))
+}
+
+pub async fn get_account<S: ClientState>(
+    _auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<AccountView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let account = find_account(state.as_ref(), &local, &iri_of(&id)?)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok(Json(account))
+}
+
+#[derive(Deserialize)]
+pub struct LookupParams {
+    pub acct: String,
+}
+
+/// Account of `user` or `user@host`. Remote accounts are looked up with WebFinger.
+pub async fn lookup_account<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<LookupParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<AccountView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let acct = params.acct.trim().trim_start_matches('@');
+    let actor = match resolve::parse(acct) {
+        Some(Target::Handle { user, host }) if host != local.host() => {
+            if !state.lookup_limiter().check(&auth.account).await {
+                return Err(HttpError::new_json(
+                    &json!({"ok": false, "msg": "too many lookups, try again later"}),
+                    http::StatusCode::TOO_MANY_REQUESTS,
+                ));
+            }
+            let iri = resolve::webfinger(state, &user, &host).await?;
+            fetch::resolve_actor(state, &iri).await?.id
+        }
+        Some(Target::Handle { user, .. }) => local.actor(&user),
+        Some(Target::Url(_)) => return Err(not_found()),
+        None => local.actor(acct),
+    };
+    let account = find_account(state, &local, &actor)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok(Json(account))
+}
+
+/// Posts and boosts of an account which reached this server, newest first.
+pub async fn account_statuses<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<StatusView>>), HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let actor = known_actor(state, &local, &id).await?;
+    let mut filter = Filter::new(state, &local, viewer.clone(), false).await?;
+    let feeds = [timeline::author_feed(&actor)];
+    let items = timeline::collect(state, &feeds, &page_params(params), &mut filter).await?;
+    let headers = timeline::link_header(
+        &proxy_info,
+        uri.path(),
+        items.iter().map(|item| item.id.as_str()),
+    );
+    let statuses = Renderer::new(state, &local, viewer).items(items).await?;
+    Ok((headers, Json(statuses)))
+}
+
+#[derive(Deserialize)]
+pub struct LimitParams {
+    pub limit: Option<usize>,
+}
+
+async fn accounts_of<S, I>(
+    state: &S,
+    local: &Local,
+    actors: I,
+    limit: Option<usize>,
+) -> Result<Vec<AccountView>, HttpError>
+where
+    S: ClientState,
+    I: IntoIterator<Item = url::Url>,
+{
+    let limit = limit.unwrap_or(MAX_LIMIT).clamp(1, MAX_LIMIT);
+    let mut accounts = Vec::new();
+    for actor in actors.into_iter().take(limit) {
+        accounts.extend(find_account(state, local, &actor).await?);
+    }
+    Ok(accounts)
+}
+
+pub async fn account_followers<S: ClientState>(
+    _auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<LimitParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<AccountView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let actor = known_actor(state, &local, &id).await?;
+    let followers = state
+        .followers(&actor)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.follower);
+    Ok(Json(
+        accounts_of(state, &local, followers, params.limit).await?,
+    ))
+}
+
+pub async fn account_following<S: ClientState>(
+    _auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<LimitParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<AccountView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let actor = known_actor(state, &local, &id).await?;
+    let following = state
+        .following(&actor)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.followee);
+    Ok(Json(
+        accounts_of(state, &local, following, params.limit).await?,
+    ))
+}
+
+pub async fn follow_account<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<RelationshipView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let target = known_actor(state, &local, &id).await?;
+    if target == viewer {
+        return Err(unprocessable("accounts cannot follow themselves"));
+    }
+    if block::is_blocked_between(state, &viewer, &target).await? {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot follow the account"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    follow::follow(state, &local, &viewer, &target).await?;
+    info!(
+        follower = viewer.to_string(),
+        followee = target.to_string(),
+        "follow"
+    );
+    Ok(Json(relationship(state, &viewer, &target).await?))
+}
+
+pub async fn unfollow_account<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<RelationshipView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let target = iri_of(&id)?;
+    follow::unfollow(state, &local, &viewer, &target).await?;
+    Ok(Json(relationship(state, &viewer, &target).await?))
+}
+
+/// Pending follow requests of the account, which is locked.
+pub async fn follow_requests<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<LimitParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<AccountView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let requests = state
+        .followers(&local.actor(&auth.account))
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Pending)
+        .map(|follow| follow.follower);
+    Ok(Json(
+        accounts_of(state, &local, requests, params.limit).await?,
+    ))
+}
+
+pub async fn authorize_follow_request<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<RelationshipView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let follower = iri_of(&id)?;
+    follow::authorize(state, &local, &viewer, &follower).await?;
+    info!(
+        follower = follower.to_string(),
+        followee = viewer.to_string(),
+        "authorize_follow"
+    );
+    Ok(Json(relationship(state, &viewer, &follower).await?))
+}
+
+pub async fn reject_follow_request<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<RelationshipView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let follower = iri_of(&id)?;
+    follow::reject(state, &local, &viewer, &follower).await?;
+    info!(
+        follower = follower.to_string(),
+        followee = viewer.to_string(),
+        "reject_follow_request"
+    );
+    Ok(Json(relationship(state, &viewer, &follower).await?))
+}
+
+/// Relationships with the accounts of `id[]`.
+pub async fn relationships<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<RelationshipView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let query = query.unwrap_or_default();
+    let mut views = Vec::new();
+    for (name, id) in url::form_urlencoded::parse(query.as_bytes()).take(MAX_LIMIT) {
+        if name != "id[]" && name != "id" {
+            continue;
+        }
+        let Ok(target) = iri_of(&id) else {
+            continue;
+        };
+        views.push(relationship(state, &viewer, &target).await?);
+    }
+    Ok(Json(views))
+}
+
+#[derive(Deserialize)]
+pub struct StatusRequest {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub media_ids: Vec<String>,
+    #[serde(default)]
+    pub in_reply_to_id: Option<String>,
+    #[serde(default)]
+    pub sensitive: bool,
+    #[serde(default)]
+    pub spoiler_text: String,
+    #[serde(default)]
+    pub visibility: StatusVisibility,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// Word without the punctuation which may follow it in a sentence.
+fn word_of(word: &str) -> &str {
+    word.trim_end_matches(['.', ',', ':', ';', '!', '?', ')', '"'])
+}
+
+fn is_hashtag(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Actors mentioned in a status as `@name` or `@name@host`, by the text of the mention.
+/// Mentions which can't be resolved are left as text.
+async fn mentioned<S>(state: &S, local: &Local, text: &str) -> BTreeMap<String, url::Url>
+where
+    S: ClientState,
+{
+    let mut mentions = BTreeMap::new();
+    for word in text.split_whitespace().map(word_of) {
+        let Some(handle) = word.strip_prefix('@') else {
+            continue;
+        };
+        if mentions.contains_key(word) {
+            continue;
+        }
+        let actor = match resolve::parse(handle) {
+            Some(Target::Handle { user, host }) if host != local.host() => {
+                match resolve::webfinger(state, &user, &host).await {
+                    Ok(iri) => fetch::resolve_actor(state, &iri)
+                        .await
+                        .map(|actor| actor.id),
+                    Err(e) => Err(e),
+                }
+            }
+            Some(Target::Handle { user, .. }) => Ok(local.actor(&user)),
+            Some(Target::Url(_)) => continue,
+            None => Ok(local.actor(handle)),
+        };
+        let actor = match actor {
+            Ok(actor) if local.account_name(&actor).is_none() => actor,
+            Ok(actor) => match state.query(&local.account_name(&actor).unwrap()).await {
+                Ok(Some(_)) => actor,
+                _ => continue,
+            },
+            Err(e) => {
+                debug!(
+                    mention = word,
+                    status = e.status.as_u16(),
+                    "unresolved_mention"
+                );
+                continue;
+            }
+        };
+        mentions.insert(word.to_string(), actor);
+    }
+    mentions
+}
+
+/// HTML of the plain text of a status with its mentions and hashtags linked,
+/// and the hashtags it has.
+fn render_text(
+    local: &Local,
+    text: &str,
+    mentions: &BTreeMap<String, url::Url>,
+) -> (String, Vec<String>) {
+    let mut hashtags = Vec::new();
+    let mut render_word = |word: &str| {
+        let core = word_of(word);
+        let rest = profile::escape(&word[core.len()..]);
+        if let Some(actor) = mentions.get(core) {
+            let user = core
+                .trim_start_matches('@')
+                .split('@')
+                .next()
+                .unwrap_or_default();
+            return format!(
+                "<span class=\"h-card\"><a href=\"{}\" class=\"u-url mention\">@<span>{}</span></a></span>{rest}",
+                profile::escape(actor.as_str()),
+                profile::escape(user),
+            );
+        }
+        match core.strip_prefix('#') {
+            Some(name) if is_hashtag(name) => {
+                hashtags.push(name.to_lowercase());
+                format!(
+                    "<a href=\"{}\" class=\"mention hashtag\" rel=\"tag\">#<span>{}</span></a>{rest}",
+                    profile::escape(local.tag(&name.to_lowercase()).as_str()),
+                    profile::escape(name),
+                )
+            }
+            _ => profile::escape(word),
+        }
+    };
+    let html = text
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| {
+            let lines = paragraph
+                .split('\n')
+                .map(|line| {
+                    line.split(' ')
+                        .map(&mut render_word)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("<br>");
+            format!("<p>{lines}</p>")
+        })
+        .collect();
+    hashtags.sort();
+    hashtags.dedup();
+    (html, hashtags)
+}
+
+/// Publish a status written in plain text, as Mastodon clients do.
+pub async fn post_status<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<StatusRequest>,
+) -> Result<Json<StatusView>, HttpError> {
+    if request.status.trim().is_empty() && request.media_ids.is_empty() {
+        return Err(unprocessable("status can't be blank"));
+    }
+    if request.status.chars().count() > MAX_CHARACTERS {
+        return Err(unprocessable(&format!(
+            "status must be at most {MAX_CHARACTERS} characters"
+        )));
+    }
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let author = local.actor(&auth.account);
+    let followers = follow::followers_collection(&author);
+    let parent = match &request.in_reply_to_id {
+        Some(id) => Some(reaction::visible_object(state, &iri_of(id)?, &author).await?),
+        None => None,
+    };
+    let context = parent
+        .as_ref()
+        .and_then(|parent| serde_json::from_str(&parent.body).ok())
+        .and_then(|body| conversation::context_of(&body))
+        .unwrap_or_else(|| local.context(&uuid::Uuid::new_v4().to_string()));
+    let attachments = media::attachments_of(state, &auth.account, &request.media_ids)
+        .await?
+        .iter()
+        .map(media::document)
+        .collect::<Vec<_>>();
+
+    let mentions = mentioned(state, &local, &request.status).await;
+    let mut addressed = mentions.values().cloned().collect::<Vec<_>>();
+    if let Some(parent) = &parent {
+        if request.visibility != StatusVisibility::Direct
+            && parent.attributed_to != author
+            && !addressed.contains(&parent.attributed_to)
+        {
+            addressed.push(parent.attributed_to.clone());
+        }
+    }
+    let public = json!(ap::PUBLIC);
+    let addressed = addressed.iter().map(|actor| json!(actor));
+    let (to, cc): (Vec<_>, Vec<_>) = match request.visibility {
+        StatusVisibility::Public => (
+            vec![public],
+            std::iter::once(json!(followers)).chain(addressed).collect(),
+        ),
+        StatusVisibility::Unlisted => (
+            vec![json!(followers)],
+            std::iter::once(public).chain(addressed).collect(),
+        ),
+        StatusVisibility::Private => (vec![json!(followers)], addressed.collect()),
+        StatusVisibility::Direct if mentions.is_empty() => {
+            return Err(unprocessable("direct statuses must mention someone"));
+        }
+        StatusVisibility::Direct => (addressed.collect(), Vec::new()),
+    };
+
+    let (content, hashtags) = render_text(&local, &request.status, &mentions);
+    let mut tags = mentions
+        .iter()
+        .map(|(name, href)| json!({"type": "Mention", "href": href, "name": name}))
+        .collect::<Vec<_>>();
+    tags.extend(hashtags.iter().map(
+        |name| json!({"type": "Hashtag", "href": local.tag(name), "name": format!("#{name}")}),
+    ));
+    let texts = [request.status.as_str(), request.spoiler_text.as_str()];
+    tags.extend(emoji::tags(state, texts).await?);
+    let id = local.new_object();
+    let published = chrono::Utc::now();
+    let mut note = json!({
+        "id": id,
+        "type": "Note",
+        "attributedTo": author,
+        "to": to,
+        "cc": cc,
+        "content": content,
+        "inReplyTo": parent.as_ref().map(|parent| &parent.id),
+        "context": context,
+        "conversation": context,
+        "published": published,
+        "sensitive": request.sensitive || !request.spoiler_text.is_empty(),
+        "tag": tags,
+        "attachment": attachments,
+    });
+    if !request.spoiler_text.is_empty() {
+        note["summary"] = json!(request.spoiler_text);
+    }
+    if let Some(language) = &request.language {
+        note["contentMap"] = json!({ language: content });
+    }
+    let object = StoredObject::new(note.clone(), &followers)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    state.put_object(&object).await?;
+    let create = json!({
+        "@context": *ap::CONTEXT,
+        "id": format!("{id}/activity"),
+        "type": "Create",
+        "actor": author,
+        "to": to,
+        "cc": cc,
+        "published": published,
+        "object": note,
+    });
+    let recipients = visibility::delivery_recipients(state, &object, &followers).await?;
+    let local_recipients = delivery::deliver_activity(
+        state,
+        &local,
+        &author,
+        create,
+        recipients,
+        object.visibility,
+    )
+    .await?;
+    if object.visibility == Visibility::Direct {
+        for recipient in local_recipients {
+            if recipient != auth.account {
+                conversation::track(state, &local, &recipient, &object, true).await?;
+            }
+        }
+        conversation::track(state, &local, &auth.account, &object, false).await?;
+    }
+    timeline::try_distribute(state, &local, &object, None).await;
+    notification::notify_post(state, &local, &object).await;
+    info!(
+        account = auth.account,
+        object = object.id.to_string(),
+        visibility = ?object.visibility,
+        "post_status"
+    );
+    let status = Renderer::new(state, &local, author).status(&object).await?;
+    Ok(Json(status))
+}
+
+pub async fn get_status<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state, &iri_of(&id)?, &viewer).await?;
+    let status = Renderer::new(state, &local, viewer).status(&object).await?;
+    Ok(Json(status))
+}
+
+/// Delete a status of the account. The deleted status is returned.
+pub async fn delete_status<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let object = state
+        .get_object(&iri_of(&id)?)
+        .await?
+        .filter(|object| object.attributed_to == viewer)
+        .ok_or_else(not_found)?;
+    let status = Renderer::new(state, &local, viewer).status(&object).await?;
+    object::retract(state, &local, &object).await?;
+    info!(
+        account = auth.account,
+        object = object.id.to_string(),
+        "delete_status"
+    );
+    Ok(Json(status))
+}
+
+#[derive(Serialize)]
+pub struct ContextView {
+    pub ancestors: Vec<StatusView>,
+    pub descendants: Vec<StatusView>,
+}
+
+/// Stored replies to `parent` which `viewer` can see, oldest first.
+async fn visible_replies<S: ClientState>(
+    state: &S,
+    parent: &url::Url,
+    viewer: &url::Url,
+) -> Result<Vec<StoredObject>, HttpError> {
+    let mut replies = Vec::new();
+    for reply in state.replies(parent).await? {
+        if visibility::can_view(state, &reply, &Viewer::Actor(viewer.clone())).await? {
+            let published = serde_json::from_str(&reply.body)
+                .ok()
+                .and_then(|body: serde_json::Value| time_of(&body, "published"));
+            replies.push((published, reply));
+        }
+    }
+    replies.sort_by(|(a_published, a), (b_published, b)| {
+        a_published.cmp(b_published).then_with(|| a.id.cmp(&b.id))
+    });
+    Ok(replies.into_iter().map(|(_, reply)| reply).collect())
+}
+
+/// Posts the status replies to, oldest first, and the replies to it, depth first, as far as
+/// they are stored and visible.
+pub async fn status_context<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<ContextView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state, &iri_of(&id)?, &viewer).await?;
+    let mut renderer = Renderer::new(state, &local, viewer.clone());
+    let mut ancestors = Vec::new();
+    let mut parent = serde_json::from_str(&object.body)
+        .ok()
+        .and_then(|body: serde_json::Value| activity::field_iri(&body, "inReplyTo"));
+    while let Some(iri) = parent.take() {
+        if ancestors.len() == MAX_ANCESTORS {
+            break;
+        }
+        let Ok(object) = reaction::visible_object(state, &iri, &viewer).await else {
+            break;
+        };
+        parent = serde_json::from_str(&object.body)
+            .ok()
+            .and_then(|body: serde_json::Value| activity::field_iri(&body, "inReplyTo"));
+        ancestors.push(renderer.status(&object).await?);
+    }
+    ancestors.reverse();
+    let mut descendants = Vec::new();
+    let mut seen = HashSet::from([object.id.clone()]);
+    let mut pending = visible_replies(state, &object.id, &viewer).await?;
+    pending.reverse();
+    while let Some(reply) = pending.pop() {
+        if descendants.len() == MAX_DESCENDANTS {
+            break;
+        }
+        // Replies of remote servers could form a loop.
+        if !seen.insert(reply.id.clone()) {
+            continue;
+        }
+        let replies = visible_replies(state, &reply.id, &viewer).await?;
+        pending.extend(replies.into_iter().rev());
+        descendants.push(renderer.status(&reply).await?);
+    }
+    Ok(Json(ContextView {
+        ancestors,
+        descendants,
+    }))
+}
+
+/// Favourite a status. It is federated as a reaction, which an existing reaction of the
+/// account already counts as.
+pub async fn favourite<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state.as_ref(), &iri_of(&id)?, &viewer).await?;
+    let reacted = state
+        .reactions_of(&object.id)
+        .await?
+        .iter()
+        .any(|reaction| reaction.actor == viewer);
+    if !reacted {
+        let request = ReactRequest {
+            object: object.id.clone(),
+            content: FAVOURITE.to_string(),
+        };
+        reaction::react(
+            auth,
+            axum::extract::State(state.clone()),
+            proxy_info,
+            Json(request),
+        )
+        .await?;
+    }
+    let status = Renderer::new(state.as_ref(), &local, viewer)
+        .status(&object)
+        .await?;
+    Ok(Json(status))
+}
+
+pub async fn unfavourite<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state.as_ref(), &iri_of(&id)?, &viewer).await?;
+    let request = UnreactRequest {
+        object: object.id.clone(),
+    };
+    reaction::unreact(
+        auth,
+        axum::extract::State(state.clone()),
+        proxy_info,
+        Json(request),
+    )
+    .await?;
+    let status = Renderer::new(state.as_ref(), &local, viewer)
+        .status(&object)
+        .await?;
+    Ok(Json(status))
+}
+
+/// Boost a status. The boost is returned with the status in `reblog`.
+pub async fn reblog<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state, &iri_of(&id)?, &viewer).await?;
+    let boost = boost::boost(state, &local, &viewer, &object).await?;
+    let mut renderer = Renderer::new(state, &local, viewer.clone());
+    let status = renderer.status(&object).await?;
+    let key = time_key(boost.created);
+    let status = renderer
+        .reblog(&key, &viewer, boost.created, status)
+        .await?;
+    Ok(Json(status))
+}
+
+pub async fn unreblog<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<StatusView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let object = reaction::visible_object(state, &iri_of(&id)?, &viewer).await?;
+    boost::unboost(state, &local, &viewer, &object).await?;
+    let status = Renderer::new(state, &local, viewer).status(&object).await?;
+    Ok(Json(status))
+}
+
+/// Statuses the account reacted to, most recent reaction first.
+pub async fn favourites<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<LimitParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<StatusView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut reactions = state.reactions_by(&viewer).await?;
+    reactions.sort_by_key(|reaction| std::cmp::Reverse(reaction.created));
+    let mut renderer = Renderer::new(state, &local, viewer.clone());
+    let mut statuses = Vec::new();
+    for reaction in reactions {
+        let Ok(object) = reaction::visible_object(state, &reaction.object, &viewer).await else {
+            continue;
+        };
+        statuses.push(renderer.status(&object).await?);
+        if statuses.len() == limit {
+            break;
+        }
+    }
+    Ok(Json(statuses))
+}
+
+pub async fn home_timeline<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<StatusView>>), HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let (headers, Json(items)) = timeline::home_timeline(
+        auth,
+        axum::extract::Query(page_params(params)),
+        axum::extract::State(state.clone()),
+        proxy_info,
+        uri,
+    )
+    .await?;
+    let statuses = Renderer::new(state.as_ref(), &local, viewer)
+        .items(items)
+        .await?;
+    Ok((headers, Json(statuses)))
+}
+
+#[derive(Deserialize)]
+pub struct PublicTimelineParams {
+    /// Only posts of local accounts.
+    #[serde(default)]
+    pub local: bool,
+    pub max_id: Option<String>,
+    pub since_id: Option<String>,
+    pub min_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn public_timeline<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<PublicTimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<StatusView>>), HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let only_local = params.local;
+    let params = axum::extract::Query(page_params(TimelineParams {
+        max_id: params.max_id,
+        since_id: params.since_id,
+        min_id: params.min_id,
+        limit: params.limit,
+    }));
+    let (mut headers, Json(items)) = if only_local {
+        timeline::local_timeline(
+            auth,
+            params,
+            axum::extract::State(state.clone()),
+            proxy_info,
+            uri.clone(),
+        )
+        .await?
+    } else {
+        timeline::federated_timeline(
+            auth,
+            params,
+            axum::extract::State(state.clone()),
+            proxy_info,
+            uri.clone(),
+        )
+        .await?
+    };
+    // Pages of the local timeline have to keep asking for it.
+    if only_local {
+        if let Some(link) = headers.get(http::header::LINK).cloned() {
+            let link = link.to_str().unwrap_or_default().replace(
+                &format!("{}?", uri.path()),
+                &format!("{}?local=true&", uri.path()),
+            );
+            if let Ok(link) = link.parse() {
+                headers.insert(http::header::LINK, link);
+            }
+        }
+    }
+    let statuses = Renderer::new(state.as_ref(), &local, viewer)
+        .items(items)
+        .await?;
+    Ok((headers, Json(statuses)))
+}
+
+#[derive(Serialize)]
+pub struct NotificationView {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Latest actor of a grouped notification.
+    pub account: AccountView,
+    /// Left out of notifications without a status, like follows, as Mastodon does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusView>,
+}
+
+fn notification_kind(kind: NotificationKind) -> &'static str {
+    match kind {
+        NotificationKind::Follow => "follow",
+        NotificationKind::FollowRequest => "follow_request",
+        NotificationKind::Mention | NotificationKind::Reply => "mention",
+        NotificationKind::Favourite => "favourite",
+        NotificationKind::Reblog => "reblog",
+        NotificationKind::Poll => "poll",
+    }
+}
+
+const NOTIFICATION_KINDS: [NotificationKind; 7] = [
+    NotificationKind::Follow,
+    NotificationKind::FollowRequest,
+    NotificationKind::Mention,
+    NotificationKind::Reply,
+    NotificationKind::Favourite,
+    NotificationKind::Reblog,
+    NotificationKind::Poll,
+];
+
+/// Notifications of the account, filtered with `types[]` and `exclude_types[]`.
+pub async fn list_notifications<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<NotificationView>>), HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let query = query.unwrap_or_default();
+    let mut params = NotificationParams {
+        max_id: None,
+        since_id: None,
+        min_id: None,
+        limit: None,
+        types: None,
+    };
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+    for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        let value = value.into_owned();
+        match name.as_ref() {
+            "max_id" => params.max_id = Some(value),
+            "since_id" => params.since_id = Some(value),
+            "min_id" => params.min_id = Some(value),
+            "limit" => params.limit = value.parse().ok(),
+            "types[]" | "types" => included.push(value),
+            "exclude_types[]" | "exclude_types" => excluded.push(value),
+            _ => (),
+        }
+    }
+    if !included.is_empty() || !excluded.is_empty() {
+        let types = NOTIFICATION_KINDS
+            .into_iter()
+            .filter(|kind| {
+                let name = notification_kind(*kind).to_string();
+                (included.is_empty() || included.contains(&name)) && !excluded.contains(&name)
+            })
+            .filter_map(|kind| Some(serde_json::to_value(kind).ok()?.as_str()?.to_string()))
+            .collect::<Vec<_>>();
+        if types.is_empty() {
+            return Ok((http::HeaderMap::new(), Json(Vec::new())));
+        }
+        params.types = Some(types.join(","));
+    }
+    let (headers, Json(notifications)) = notification::list_notifications(
+        auth,
+        axum::extract::Query(params),
+        axum::extract::State(state.clone()),
+        proxy_info,
+        uri,
+    )
+    .await?;
+    let state = state.as_ref();
+    let mut renderer = Renderer::new(state, &local, viewer.clone());
+    let mut views = Vec::new();
+    for notification in notifications {
+        let Some(actor) = notification.actors.first() else {
+            continue;
+        };
+        let status = match &notification.object {
+            Some(object) => match reaction::visible_object(state, object, &viewer).await {
+                Ok(object) => Some(renderer.status(&object).await?),
+                // Notifications about deleted posts have nothing left to show.
+                Err(_) => continue,
+            },
+            None => None,
+        };
+        views.push(NotificationView {
+            id: notification.id,
+            kind: notification_kind(notification.kind),
+            created_at: notification.updated_at,
+            account: renderer.account(actor).await?,
+            status,
+        });
+    }
+    Ok((headers, Json(views)))
+}
+
+pub async fn dismiss_notification<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    state.delete_notification(&auth.account, &id).await?;
+    Ok(Json(json!({})))
+}
+
+pub async fn clear_notifications<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    loop {
+        let notifications = state
+            .notifications(&auth.account, &FeedRange::default(), CLEAR_BATCH)
+            .await?;
+        for notification in &notifications {
+            state
+                .delete_notification(&auth.account, &notification.id)
+                .await?;
+        }
+        if notifications.len() < CLEAR_BATCH {
+            break;
+        }
+    }
+    info!(account = auth.account, "clear_notifications");
+    Ok(Json(json!({})))
+}
+
+#[derive(Serialize)]
+pub struct ListView {
+    pub id: String,
+    pub title: String,
+    pub replies_policy: &'static str,
+    pub exclusive: bool,
+}
+
+impl From<List> for ListView {
+    fn from(list: List) -> Self {
+        Self {
+            id: list.id,
+            title: list.title,
+            replies_policy: "list",
+            exclusive: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListRequest {
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListAccountsRequest {
+    #[serde(default)]
+    pub account_ids: Vec<String>,
+}
+
+fn list_title(title: &str) -> Result<String, HttpError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(unprocessable("title must not be empty"));
+    }
+    Ok(title.to_string())
+}
+
+async fn owned_list<S: ClientState>(state: &S, account: &str, id: &str) -> Result<List, HttpError> {
+    state.get_list(account, id).await?.ok_or_else(not_found)
+}
+
+pub async fn lists<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<ListView>>, HttpError> {
+    let lists = state.lists(&auth.account).await?;
+    Ok(Json(lists.into_iter().map(ListView::from).collect()))
+}
+
+pub async fn create_list<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<ListRequest>,
+) -> Result<Json<ListView>, HttpError> {
+    let title = list_title(&request.title)?;
+    if state.lists(&auth.account).await?.len() >= list::MAX_LISTS {
+        return Err(unprocessable("too many lists"));
+    }
+    let created = chrono::Utc::now();
+    let list = List {
+        owner: auth.account,
+        id: timeline::new_id(created),
+        title,
+        created,
+    };
+    state.put_list(&list).await?;
+    info!(account = list.owner, list = list.id, "create_list");
+    Ok(Json(list.into()))
+}
+
+pub async fn get_list<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<ListView>, HttpError> {
+    let list = owned_list(state.as_ref(), &auth.account, &id).await?;
+    Ok(Json(list.into()))
+}
+
+pub async fn update_list<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<ListRequest>,
+) -> Result<Json<ListView>, HttpError> {
+    let mut list = owned_list(state.as_ref(), &auth.account, &id).await?;
+    list.title = list_title(&request.title)?;
+    state.put_list(&list).await?;
+    Ok(Json(list.into()))
+}
+
+pub async fn delete_list<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    let state = state.as_ref();
+    let list = owned_list(state, &auth.account, &id).await?;
+    list::delete(state, &list).await?;
+    info!(account = list.owner, list = list.id, "delete_list");
+    Ok(Json(json!({})))
+}
+
+pub async fn list_accounts<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<LimitParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<AccountView>>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let list = owned_list(state, &auth.account, &id).await?;
+    let members = state.list_members(&list.id).await?;
+    let members = members.into_iter().map(|member| member.member);
+    Ok(Json(
+        accounts_of(state, &local, members, params.limit).await?,
+    ))
+}
+
+/// Add accounts to a list. As in Mastodon, only followed accounts can be listed.
+pub async fn add_list_accounts<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<ListAccountsRequest>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let list = owned_list(state, &auth.account, &id).await?;
+    let count = state.list_members(&list.id).await?.len();
+    if count + request.account_ids.len() > list::MAX_MEMBERS {
+        return Err(unprocessable("too many accounts in the list"));
+    }
+    let mut members = Vec::new();
+    for id in &request.account_ids {
+        let member = iri_of(id)?;
+        let followed = state
+            .get_follow(&viewer, &member)
+            .await?
+            .is_some_and(|follow| follow.state == FollowState::Accepted);
+        if !followed {
+            return Err(unprocessable("accounts must be followed to be listed"));
+        }
+        members.push(member);
+    }
+    for member in members {
+        let member = ListMember {
+            list: list.id.clone(),
+            member,
+            owner: list.owner.clone(),
+        };
+        state.put_list_member(&member).await?;
+    }
+    Ok(Json(json!({})))
+}
+
+pub async fn remove_list_accounts<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<ListAccountsRequest>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    let state = state.as_ref();
+    let list = owned_list(state, &auth.account, &id).await?;
+    for id in &request.account_ids {
+        state.delete_list_member(&list.id, &iri_of(id)?).await?;
+    }
+    Ok(Json(json!({})))
+}
+
+/// Posts and boosts of the members of a list, merged from their author feeds.
+pub async fn list_timeline<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<StatusView>>), HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let list = owned_list(state, &auth.account, &id).await?;
+    let feeds = state
+        .list_members(&list.id)
+        .await?
+        .into_iter()
+        .map(|member| timeline::author_feed(&member.member))
+        .collect::<Vec<_>>();
+    let mut filter = Filter::new(state, &local, viewer.clone(), false).await?;
+    let items = timeline::collect(state, &feeds, &page_params(params), &mut filter).await?;
+    let headers = timeline::link_header(
+        &proxy_info,
+        uri.path(),
+        items.iter().map(|item| item.id.as_str()),
+    );
+    let statuses = Renderer::new(state, &local, viewer).items(items).await?;
+    Ok((headers, Json(statuses)))
+}
+
+/// Upload through `/api/v1/media`, which answers when the file is processed.
+/// Images are processed off the request path, so clients get a `url` of `null` until then.
+pub async fn upload_media<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    multipart: Multipart,
+) -> Result<Json<AttachmentView>, HttpError> {
+    let (_, Json(media)) = media::upload(auth, axum::extract::State(state), multipart).await?;
+    Ok(Json(media.into()))
+}
+
+/// Upload through `/api/v2/media`, answered with `202 Accepted` while an image is processed.
+pub async fn upload_media_async<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    multipart: Multipart,
+) -> Result<(http::StatusCode, Json<AttachmentView>), HttpError> {
+    let (status, Json(media)) = media::upload(auth, axum::extract::State(state), multipart).await?;
+    Ok((status, Json(media.into())))
+}
+
+/// Mastodon answers `206 Partial Content` while the file is processed.
+pub async fn get_media<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<(http::StatusCode, Json<AttachmentView>), HttpError> {
+    let Json(media) =
+        media::get_media(auth, axum::extract::Path(id), axum::extract::State(state)).await?;
+    let status = match media.state {
+        MediaState::Processing => http::StatusCode::PARTIAL_CONTENT,
+        MediaState::Ready | MediaState::Failed => http::StatusCode::OK,
+    };
+    Ok((status, Json(media.into())))
+}
+
+#[derive(Deserialize)]
+pub struct MediaUpdateRequest {
+    pub description: Option<String>,
+    /// Focal point as `x,y`.
+    pub focus: Option<String>,
+}
+
+pub async fn update_media<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<MediaUpdateRequest>,
+) -> Result<Json<AttachmentView>, HttpError> {
+    let focus = match &request.focus {
+        Some(focus) => {
+            let point = focus
+                .split_once(',')
+                .and_then(|(x, y)| Some([x.trim().parse().ok()?, y.trim().parse().ok()?]));
+            Some(point.ok_or_else(|| unprocessable("focus must be x,y"))?)
+        }
+        None => None,
+    };
+    let update = MediaUpdate {
+        description: request.description,
+        focus,
+    };
+    let Json(media) = media::update_media(
+        auth,
+        axum::extract::Path(id),
+        axum::extract::State(state),
+        Json(update),
+    )
+    .await?;
+    Ok(Json(media.into()))
+}
+
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    pub q: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Look handles and URLs up on remote servers.
+    #[serde(default)]
+    pub resolve: bool,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Serialize)]
+pub struct SearchView {
+    pub accounts: Vec<AccountView>,
+    pub statuses: Vec<StatusView>,
+    pub hashtags: Vec<TagView>,
+}
+
+pub async fn search<S: ClientState>(
+    auth: Authenticated,
+    axum::extract::Query(request): axum::extract::Query<SearchRequest>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<SearchView>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let kind = match request.kind.as_deref() {
+        Some("accounts") => Some(DocumentKind::Account),
+        Some("statuses") => Some(DocumentKind::Post),
+        Some("hashtags") => Some(DocumentKind::Hashtag),
+        _ => None,
+    };
+    let state_ref = state.as_ref();
+    let results = if request.resolve && resolve::parse(&request.q).is_some() {
+        let params = ResolveParams { q: request.q };
+        match resolve::resolve_search(
+            auth,
+            axum::extract::Query(params),
+            axum::extract::State(state.clone()),
+            proxy_info,
+        )
+        .await
+        {
+            Ok(Json(results)) => results,
+            Err(e) if e.status == http::StatusCode::NOT_FOUND => SearchResults::default(),
+            Err(e) => return Err(e),
+        }
+    } else {
+        let params = SearchParams {
+            q: request.q,
+            kind,
+            limit: request.limit,
+            offset: request.offset,
+        };
+        let Json(results) = search::search(
+            auth,
+            axum::extract::Query(params),
+            axum::extract::State(state.clone()),
+            proxy_info,
+        )
+        .await?;
+        results
+    };
+    let mut renderer = Renderer::new(state_ref, &local, viewer.clone());
+    let mut view = SearchView {
+        accounts: Vec::new(),
+        statuses: Vec::new(),
+        hashtags: Vec::new(),
+    };
+    for account in results.accounts {
+        view.accounts.push(renderer.account(&account.id).await?);
+    }
+    for post in results.posts {
+        let Some(iri) = activity::field_iri(&post, "id") else {
+            continue;
+        };
+        if let Some(object) = state_ref.get_object(&iri).await? {
+            view.statuses.push(renderer.status(&object).await?);
+        }
+    }
+    view.hashtags = results
+        .hashtags
+        .into_iter()
+        .map(|hashtag| TagView {
+            url: local.tag(&hashtag.name),
+            name: hashtag.name,
+        })
+        .collect();
+    Ok(Json(view))
+}
+
+/// Description of the server which clients read before signing in.
+pub async fn instance(proxy_info: ProxyInfo) -> Result<Json<serde_json::Value>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    Ok(Json(json!({
+        "uri": local.host(),
+        "title": local.host(),
+        "short_description": "",
+        "description": "",
+        "email": "",
+        "version": format!("4.2.0 (compatible; ekika {})", env!("CARGO_PKG_VERSION")),
+        "urls": {
+            "streaming_api": format!("wss://{}", local.host()),
+        },
+        "stats": {
+            "user_count": 0,
+            "status_count": 0,
+            "domain_count": 0,
+        },
+        "thumbnail": null,
+        "languages": [],
+        "registrations": false,
+        "approval_required": false,
+        "invites_enabled": false,
+        "configuration": {
+            "statuses": {
+                "max_characters": MAX_CHARACTERS,
+                "max_media_attachments": media::MAX_ATTACHMENTS,
+            },
+            "media_attachments": {
+                "supported_mime_types": storage::content_types().collect::<Vec<_>>(),
+                "image_size_limit": media::size_limit(MediaKind::Image),
+                "video_size_limit": media::size_limit(MediaKind::Video),
+            },
+        },
+        "contact_account": null,
+        "rules": [],
+    })))
+}
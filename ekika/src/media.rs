@@ -0,0 +1,530 @@
+use std::{future::Future, sync::Arc};
+
+use activity_vocabulary_core::{Or, Property};
+use axum::{extract::Multipart, Json};
+use axum_helper::{HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{info, warn};
+
+use crate::{
+    actor, ap,
+    auth::{Authenticated, TokenStore},
+    model::media::{Attachment, FocalPoint, MediaKind, MediaState, Thumbnail},
+    processing,
+    storage::{self, BlobStore},
+};
+
+/// Number of attachments a post can have.
+pub const MAX_ATTACHMENTS: usize = 4;
+
+/// Largest upload accepted by [upload], which is the limit of videos.
+pub const MAX_UPLOAD_SIZE: usize = 100 * 1024 * 1024;
+
+/// Number of images waiting for processing before uploads are refused.
+pub const QUEUE_SIZE: usize = 64;
+
+pub trait MediaStore {
+    fn get_media(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Attachment>, HttpError>> + Send;
+    fn put_media(&self, media: &Attachment) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Uploaded image waiting to be processed by [worker].
+pub struct ProcessingJob {
+    /// Id of the [Attachment].
+    pub id: String,
+    pub body: Vec<u8>,
+}
+
+pub trait MediaQueue {
+    /// Queue a job, failing when the queue is full.
+    fn enqueue_media(
+        &self,
+        job: ProcessingJob,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub fn size_limit(kind: MediaKind) -> u64 {
+    match kind {
+        MediaKind::Image | MediaKind::Document => 16 * 1024 * 1024,
+        MediaKind::Video => MAX_UPLOAD_SIZE as u64,
+        MediaKind::Audio => 40 * 1024 * 1024,
+    }
+}
+
+fn be16(body: &[u8], offset: usize) -> Option<u64> {
+    let bytes = body.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]).into())
+}
+
+fn le16(body: &[u8], offset: usize) -> Option<u64> {
+    let bytes = body.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]).into())
+}
+
+fn le24(body: &[u8], offset: usize) -> Option<u64> {
+    let bytes = body.get(offset..offset + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]).into())
+}
+
+fn jpeg_dimensions(body: &[u8]) -> Option<(u64, u64)> {
+    let mut offset = 2;
+    loop {
+        while *body.get(offset)? == 0xff && *body.get(offset + 1)? == 0xff {
+            offset += 1;
+        }
+        if *body.get(offset)? != 0xff {
+            return None;
+        }
+        let marker = *body.get(offset + 1)?;
+        match marker {
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((be16(body, offset + 7)?, be16(body, offset + 5)?));
+            }
+            0x01 | 0xd0..=0xd9 => offset += 2,
+            _ => offset += 2 + usize::try_from(be16(body, offset + 2)?).ok()?,
+        }
+    }
+}
+
+/// Width and height of an image, read from its header.
+pub fn dimensions(media_type: &str, body: &[u8]) -> Option<(u64, u64)> {
+    match media_type {
+        "image/png" => {
+            let width = u32::from_be_bytes(body.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(body.get(20..24)?.try_into().ok()?);
+            Some((width.into(), height.into()))
+        }
+        "image/gif" => Some((le16(body, 6)?, le16(body, 8)?)),
+        "image/jpeg" => jpeg_dimensions(body),
+        "image/webp" => match body.get(12..16)? {
+            b"VP8 " => Some((le16(body, 26)? & 0x3fff, le16(body, 28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(body.get(21..25)?.try_into().ok()?);
+                Some((
+                    u64::from(bits & 0x3fff) + 1,
+                    u64::from((bits >> 14) & 0x3fff) + 1,
+                ))
+            }
+            b"VP8X" => Some((le24(body, 24)? + 1, le24(body, 27)? + 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Attachment as the generated type matching its [MediaKind].
+pub fn document(attachment: &Attachment) -> ap::DocumentSubtypes {
+    macro_rules! document {
+        ($kind:ident) => {{
+            let mut document: ap::$kind = ap::empty();
+            document.url = Property(attachment.url.iter().cloned().map(Or::Prim).collect());
+            document.media_type = Some(attachment.media_type.clone());
+            if let Some(name) = &attachment.name {
+                document.name = actor::text(name);
+            }
+            document.width = attachment.width;
+            document.height = attachment.height;
+            document.blurhash = attachment.blurhash.clone();
+            document.focal_point = attachment.focal_point.map(|point| vec![point.x, point.y]);
+            ap::DocumentSubtypes::$kind(document)
+        }};
+    }
+    match attachment.kind {
+        MediaKind::Image => document!(Image),
+        MediaKind::Video => document!(Video),
+        MediaKind::Audio => document!(Audio),
+        MediaKind::Document => document!(Document),
+    }
+}
+
+/// Attachments of `account` with the given ids, in the same order.
+/// Attachments which are not processed yet are refused.
+pub async fn attachments_of<S>(
+    state: &S,
+    account: &str,
+    ids: &[String],
+) -> Result<Vec<Attachment>, HttpError>
+where
+    S: MediaStore + Sync,
+{
+    if ids.len() > MAX_ATTACHMENTS {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("at most {MAX_ATTACHMENTS} attachments are allowed")}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    let mut attachments = Vec::with_capacity(ids.len());
+    for id in ids {
+        let attachment = state
+            .get_media(id)
+            .await?
+            .filter(|attachment| attachment.account == account)
+            .ok_or_else(media_not_found)?;
+        if attachment.state != MediaState::Ready {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("media {id} is not ready")}),
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+            ));
+        }
+        attachments.push(attachment);
+    }
+    Ok(attachments)
+}
+
+/// Focal point of an attachment, which must be an image point from -1.0 to 1.0.
+fn focal_point(kind: MediaKind, [x, y]: [f64; 2]) -> Result<FocalPoint, HttpError> {
+    if kind != MediaKind::Image || !(-1.0..=1.0).contains(&x) || !(-1.0..=1.0).contains(&y) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "focus must be an image point from -1.0 to 1.0"}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    Ok(FocalPoint { x, y })
+}
+
+/// Focus of an upload form, `x,y` as in Mastodon.
+fn parse_focus(focus: &str) -> Result<[f64; 2], HttpError> {
+    focus
+        .split_once(',')
+        .and_then(|(x, y)| Some([x.trim().parse().ok()?, y.trim().parse().ok()?]))
+        .ok_or_else(|| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "focus must be two numbers separated by a comma"}),
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        })
+}
+
+fn media_not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "media not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+/// Store a processed image and mark its attachment as ready, or as failed.
+async fn process<S>(state: &S, sizes: &[u32], job: ProcessingJob) -> Result<(), HttpError>
+where
+    S: MediaStore + BlobStore + Sync,
+{
+    let attachment = state
+        .get_media(&job.id)
+        .await?
+        .ok_or_else(media_not_found)?;
+    let media_type = attachment.media_type.clone();
+    let sizes = sizes.to_vec();
+    let processed =
+        tokio::task::spawn_blocking(move || processing::process(&media_type, job.body, &sizes))
+            .await
+            .map_err(|e| e.to_string())
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    // The description may have changed while the image was processed.
+    let mut attachment = state
+        .get_media(&job.id)
+        .await?
+        .ok_or_else(media_not_found)?;
+    let processed = match processed {
+        Ok(processed) => processed,
+        Err(e) => {
+            warn!(id = attachment.id, error = e, "media_processing_failed");
+            attachment.state = MediaState::Failed;
+            return state.put_media(&attachment).await;
+        }
+    };
+    let mut thumbnails = Vec::with_capacity(processed.thumbnails.len());
+    for thumbnail in processed.thumbnails {
+        let key = format!(
+            "media/{}/{}-{}x{}.webp",
+            attachment.account, attachment.id, thumbnail.width, thumbnail.height
+        );
+        let url = state
+            .put_blob(&key, thumbnail.media_type, thumbnail.body)
+            .await?;
+        thumbnails.push(Thumbnail {
+            key,
+            url,
+            width: thumbnail.width.into(),
+            height: thumbnail.height.into(),
+        });
+    }
+    let original = processed.original;
+    attachment.size = original.body.len() as u64;
+    attachment.width = Some(original.width.into());
+    attachment.height = Some(original.height.into());
+    attachment.url = Some(
+        state
+            .put_blob(&attachment.key, original.media_type, original.body)
+            .await?,
+    );
+    attachment.blurhash = Some(processed.blurhash);
+    // The center, unless the client chose a focus on upload or since.
+    attachment.focal_point = attachment.focal_point.or(Some(FocalPoint::default()));
+    attachment.thumbnails = thumbnails;
+    attachment.state = MediaState::Ready;
+    state.put_media(&attachment).await?;
+    info!(
+        id = attachment.id,
+        size = attachment.size,
+        "media_processed"
+    );
+    Ok(())
+}
+
+/// Process queued images, at most `workers` at a time. Thumbnails are made for each of `sizes`,
+/// the longest side in pixels.
+pub async fn worker<S>(
+    state: Arc<S>,
+    mut jobs: mpsc::Receiver<ProcessingJob>,
+    sizes: Vec<u32>,
+    workers: usize,
+) where
+    S: MediaStore + BlobStore + Send + Sync + 'static,
+{
+    let sizes: Arc<[u32]> = sizes.into();
+    let permits = Arc::new(Semaphore::new(workers.max(1)));
+    while let Some(job) = jobs.recv().await {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let state = state.clone();
+        let sizes = sizes.clone();
+        tokio::spawn(async move {
+            let id = job.id.clone();
+            if let Err(e) = process(state.as_ref(), &sizes, job).await {
+                warn!(id, status = e.status.as_u16(), "media_processing");
+            }
+            drop(permit);
+        });
+    }
+}
+
+#[derive(Serialize)]
+pub struct ThumbnailView {
+    pub url: url::Url,
+    pub width: u64,
+    pub height: u64,
+}
+
+#[derive(Serialize)]
+pub struct MediaView {
+    pub id: String,
+    pub kind: MediaKind,
+    pub media_type: String,
+    pub state: MediaState,
+    pub url: Option<url::Url>,
+    pub description: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub size: u64,
+    pub blurhash: Option<String>,
+    pub focus: Option<[f64; 2]>,
+    pub thumbnails: Vec<ThumbnailView>,
+}
+
+impl From<Attachment> for MediaView {
+    fn from(attachment: Attachment) -> Self {
+        Self {
+            id: attachment.id,
+            kind: attachment.kind,
+            media_type: attachment.media_type,
+            state: attachment.state,
+            url: attachment.url,
+            description: attachment.name,
+            width: attachment.width,
+            height: attachment.height,
+            size: attachment.size,
+            blurhash: attachment.blurhash,
+            focus: attachment.focal_point.map(|point| [point.x, point.y]),
+            thumbnails: attachment
+                .thumbnails
+                .into_iter()
+                .map(|thumbnail| ThumbnailView {
+                    url: thumbnail.url,
+                    width: thumbnail.width,
+                    height: thumbnail.height,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Upload a file from a multipart form with `file` and optionally `description`, the alt text,
+/// and `focus`, the point of an image to keep visible when it is cropped.
+/// The content type is decided from the contents, not from what the client claims.
+/// Images are stripped of their metadata off the request path, so they are answered with
+/// `202 Accepted` and stay in [MediaState::Processing] until [worker] stores them.
+pub async fn upload<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    mut multipart: Multipart,
+) -> Result<(http::StatusCode, Json<MediaView>), HttpError>
+where
+    S: MediaStore + MediaQueue + BlobStore + TokenStore + Send + Sync,
+{
+    let mut file = None;
+    let mut description = None;
+    let mut focus = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::BAD_REQUEST)?
+    {
+        match field.name() {
+            Some("file") => {
+                let body = field
+                    .bytes()
+                    .await
+                    .map_err(|e| e.to_string())
+                    .http_error_json(http::StatusCode::BAD_REQUEST)?;
+                file = Some(body.to_vec());
+            }
+            Some("description") => description = field.text().await.ok(),
+            Some("focus") => focus = field.text().await.ok(),
+            _ => (),
+        }
+    }
+    let body = file.ok_or_else(|| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "file is missing"}),
+            http::StatusCode::BAD_REQUEST,
+        )
+    })?;
+    let media_type = storage::sniff(&body).ok_or_else(|| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "unsupported file type"}),
+            http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+    })?;
+    let kind = MediaKind::of(media_type);
+    let size = body.len() as u64;
+    if size > size_limit(kind) {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("{kind:?} must be at most {} bytes", size_limit(kind))}),
+            http::StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+    let focal_point = focus
+        .as_deref()
+        .filter(|focus| !focus.is_empty())
+        .map(|focus| focal_point(kind, parse_focus(focus)?))
+        .transpose()?;
+    let (width, height) = dimensions(media_type, &body).unzip();
+    if kind == MediaKind::Image {
+        if !processing::supports(media_type) {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("{media_type} cannot be processed")}),
+                http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ));
+        }
+        if width
+            .zip(height)
+            .is_none_or(|(width, height)| processing::oversized(width, height))
+        {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("image must be at most {} pixels", processing::MAX_PIXELS)}),
+                http::StatusCode::PAYLOAD_TOO_LARGE,
+            ));
+        }
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    // Media types in `storage::sniff` all have an extension.
+    let extension = storage::extension_of(media_type).unwrap_or("bin");
+    let key = format!("media/{}/{id}.{extension}", auth.account);
+    let mut attachment = Attachment {
+        id,
+        account: auth.account,
+        kind,
+        media_type: media_type.to_string(),
+        key,
+        url: None,
+        state: MediaState::Processing,
+        name: description.filter(|description| !description.is_empty()),
+        width,
+        height,
+        size,
+        blurhash: None,
+        focal_point,
+        thumbnails: Vec::new(),
+        created: chrono::Utc::now(),
+    };
+    let status = if kind == MediaKind::Image {
+        state.put_media(&attachment).await?;
+        let job = ProcessingJob {
+            id: attachment.id.clone(),
+            body,
+        };
+        if let Err(e) = state.enqueue_media(job).await {
+            attachment.state = MediaState::Failed;
+            state.put_media(&attachment).await?;
+            return Err(e);
+        }
+        http::StatusCode::ACCEPTED
+    } else {
+        attachment.url = Some(state.put_blob(&attachment.key, media_type, body).await?);
+        attachment.state = MediaState::Ready;
+        state.put_media(&attachment).await?;
+        http::StatusCode::OK
+    };
+    info!(
+        account = attachment.account,
+        id = attachment.id,
+        media_type = attachment.media_type,
+        size,
+        "upload"
+    );
+    Ok((status, Json(attachment.into())))
+}
+
+pub async fn get_media<S>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<MediaView>, HttpError>
+where
+    S: MediaStore + TokenStore + Send + Sync,
+{
+    let attachment = state
+        .get_media(&id)
+        .await?
+        .filter(|attachment| attachment.account == auth.account)
+        .ok_or_else(media_not_found)?;
+    Ok(Json(attachment.into()))
+}
+
+#[derive(Deserialize)]
+pub struct MediaUpdate {
+    pub description: Option<String>,
+    /// Focal point of an image as `[x, y]`.
+    pub focus: Option<[f64; 2]>,
+}
+
+pub async fn update_media<S>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(update): Json<MediaUpdate>,
+) -> Result<Json<MediaView>, HttpError>
+where
+    S: MediaStore + TokenStore + Send + Sync,
+{
+    let mut attachment = state
+        .get_media(&id)
+        .await?
+        .filter(|attachment| attachment.account == auth.account)
+        .ok_or_else(media_not_found)?;
+    // Clients send only what changed, so a missing description keeps the current one.
+    if let Some(description) = update.description {
+        attachment.name = Some(description).filter(|description| !description.is_empty());
+    }
+    if let Some(focus) = update.focus {
+        attachment.focal_point = Some(focal_point(attachment.kind, focus)?);
+    }
+    state.put_media(&attachment).await?;
+    Ok(Json(attachment.into()))
+}
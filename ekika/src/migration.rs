@@ -0,0 +1,291 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    block::{self, BlockStore},
+    delivery::{self, DeliveryQueue},
+    fetch::{self, Remote},
+    follow::{self, FollowStore},
+    local::Local,
+    model::{account::Account, actor::RemoteActor, follow::FollowState, migration::Migration},
+    mute::MuteStore,
+    notification::NotificationStore,
+    stream::EventBus,
+    visibility::Visibility,
+    webfinger::AccountStore,
+};
+
+pub trait MigrationStore {
+    fn get_migration(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Option<Migration>, HttpError>> + Send;
+    fn put_migration(
+        &self,
+        migration: &Migration,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+async fn migration_of<S>(state: &S, account: &str) -> Result<Migration, HttpError>
+where
+    S: MigrationStore + Sync,
+{
+    Ok(state
+        .get_migration(account)
+        .await?
+        .unwrap_or_else(|| Migration {
+            account: account.to_string(),
+            also_known_as: Vec::new(),
+            moved_to: None,
+            moved: None,
+        }))
+}
+
+/// `alsoKnownAs` of an actor. Remote actors are refetched so that a freshly added alias is seen.
+pub async fn aliases_of<S>(
+    state: &S,
+    local: &Local,
+    actor: &url::Url,
+) -> Result<Vec<url::Url>, HttpError>
+where
+    S: MigrationStore + Remote + Sync,
+{
+    if let Some(name) = local.account_name(actor) {
+        return Ok(state
+            .get_migration(&name)
+            .await?
+            .map(|migration| migration.also_known_as)
+            .unwrap_or_default());
+    }
+    Ok(fetch::refetch_actor(state, actor).await?.also_known_as)
+}
+
+/// Make the local followers of `origin` follow `target` instead.
+/// Followers which block or are blocked by `target` only drop the old follow.
+pub async fn repoint_followers<S>(
+    state: &S,
+    local: &Local,
+    origin: &url::Url,
+    target: &url::Url,
+) -> Result<(), HttpError>
+where
+    S: FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + AccountStore<ActorInfo = Account>
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    for follow in state.followers(origin).await? {
+        if follow.state != FollowState::Accepted || local.account_name(&follow.follower).is_none() {
+            continue;
+        }
+        follow::unfollow(state, local, &follow.follower, origin).await?;
+        if follow.follower == *target
+            || block::is_blocked_between(state, &follow.follower, target).await?
+        {
+            continue;
+        }
+        follow::follow(state, local, &follow.follower, target).await?;
+    }
+    Ok(())
+}
+
+/// Inbound `Move` of a remote actor. The target must list the actor in its `alsoKnownAs`.
+pub async fn handle_move<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: MigrationStore
+        + FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + AccountStore<ActorInfo = Account>
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    if activity::field_iri(activity, "object").as_ref() != Some(&signer.id) {
+        return Ok(());
+    }
+    let Some(target) = activity::field_iri(activity, "target") else {
+        return Ok(());
+    };
+    if !aliases_of(state, local, &target)
+        .await?
+        .contains(&signer.id)
+    {
+        info!(
+            origin = signer.id.to_string(),
+            target = target.to_string(),
+            "unverified_move"
+        );
+        return Ok(());
+    }
+    state
+        .put_actor(&RemoteActor {
+            moved_to: Some(target.clone()),
+            ..signer.clone()
+        })
+        .await?;
+    info!(
+        origin = signer.id.to_string(),
+        target = target.to_string(),
+        "move"
+    );
+    repoint_followers(state, local, &signer.id, &target).await
+}
+
+#[derive(Serialize)]
+pub struct MigrationView {
+    pub also_known_as: Vec<url::Url>,
+    pub moved_to: Option<url::Url>,
+    pub moved_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<Migration> for MigrationView {
+    fn from(migration: Migration) -> Self {
+        Self {
+            also_known_as: migration.also_known_as,
+            moved_to: migration.moved_to,
+            moved_at: migration.moved,
+        }
+    }
+}
+
+pub async fn get_migration<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<MigrationView>, HttpError>
+where
+    S: MigrationStore + TokenStore + Send + Sync,
+{
+    let migration = migration_of(state.as_ref(), &auth.account).await?;
+    Ok(Json(migration.into()))
+}
+
+#[derive(Deserialize)]
+pub struct AliasesRequest {
+    pub also_known_as: Vec<url::Url>,
+}
+
+pub async fn put_aliases<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<AliasesRequest>,
+) -> Result<Json<MigrationView>, HttpError>
+where
+    S: MigrationStore + Remote + TokenStore + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let actor = local.actor(&auth.account);
+    for alias in &request.also_known_as {
+        if alias == &actor {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": "an account cannot be its own alias"}),
+                http::StatusCode::BAD_REQUEST,
+            ));
+        }
+        if !local.is_local(alias) {
+            fetch::resolve_actor(state.as_ref(), alias).await?;
+        }
+    }
+    let mut migration = migration_of(state.as_ref(), &auth.account).await?;
+    migration.also_known_as = request.also_known_as;
+    state.put_migration(&migration).await?;
+    Ok(Json(migration.into()))
+}
+
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    pub target: url::Url,
+}
+
+/// Move the account to `target`, which must already list it in `alsoKnownAs`.
+/// Followers are sent a `Move`; local ones are re-pointed right away.
+pub async fn move_account<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<MigrationView>, HttpError>
+where
+    S: MigrationStore
+        + FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + AccountStore<ActorInfo = Account>
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let origin = local.actor(&auth.account);
+    if origin == request.target {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot move to the same account"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    let state = state.as_ref();
+    if !aliases_of(state, &local, &request.target)
+        .await?
+        .contains(&origin)
+    {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "target does not list this account in alsoKnownAs"}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    let mut migration = migration_of(state, &auth.account).await?;
+    migration.moved_to = Some(request.target.clone());
+    migration.moved = Some(chrono::Utc::now());
+    state.put_migration(&migration).await?;
+
+    let followers = follow::followers_collection(&origin);
+    let mut activity = activity::new_activity(&local, "Move", &origin, json!(origin));
+    activity["target"] = json!(request.target);
+    activity["to"] = json!([followers]);
+    let recipients = state
+        .followers(&origin)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.follower);
+    delivery::deliver_activity(
+        state,
+        &local,
+        &origin,
+        activity,
+        recipients,
+        Visibility::FollowersOnly,
+    )
+    .await?;
+    repoint_followers(state, &local, &origin, &request.target).await?;
+    info!(
+        origin = origin.to_string(),
+        target = request.target.to_string(),
+        "move"
+    );
+    Ok(Json(migration.into()))
+}
@@ -3,6 +3,30 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
 pub enum AccountKind {
     Person,
+    /// Automated account such as a bot.
+    Service,
+    Application,
+    Group,
+    Organization,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+/// Key/value pair shown on a profile.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProfileField {
+    pub name: String,
+    pub value: String,
+    /// When a `rel="me"` link back to the profile was last found on the page `value` points at.
+    #[serde(default)]
+    pub verified: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
@@ -13,4 +37,19 @@ pub struct Account {
     pub name: String,
     pub summary: String,
     pub icon: Vec<url::Url>,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub header: Option<url::Url>,
+    #[serde(default)]
+    pub fields: Vec<ProfileField>,
+    /// Follow requests need to be approved.
+    #[serde(default)]
+    pub locked: bool,
+    /// The account may be listed in directories and search results.
+    #[serde(default)]
+    pub discoverable: bool,
+    /// Posts are made by an automated process. `Person` accounts are then federated as `Service`.
+    #[serde(default)]
+    pub bot: bool,
 }
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::activity;
+
+/// Cached subset of a remote actor document.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct RemoteActor {
+    pub id: url::Url,
+    pub inbox: url::Url,
+    pub shared_inbox: Option<url::Url>,
+    pub followers: Option<url::Url>,
+    pub preferred_username: Option<String>,
+    /// Display name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Bio as HTML.
+    #[serde(default)]
+    pub summary: Option<String>,
+    pub public_key_id: Option<url::Url>,
+    pub public_key_pem: Option<String>,
+    #[serde(default)]
+    pub also_known_as: Vec<url::Url>,
+    #[serde(default)]
+    pub moved_to: Option<url::Url>,
+    #[serde(default)]
+    pub featured: Option<url::Url>,
+    pub fetched: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Endpoints {
+    shared_inbox: Option<url::Url>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicKey {
+    id: url::Url,
+    public_key_pem: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActorDocument {
+    id: url::Url,
+    inbox: url::Url,
+    endpoints: Option<Endpoints>,
+    followers: Option<url::Url>,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    summary: Option<String>,
+    public_key: Option<PublicKey>,
+    also_known_as: Option<serde_json::Value>,
+    moved_to: Option<url::Url>,
+    featured: Option<url::Url>,
+}
+
+impl RemoteActor {
+    pub fn from_document(document: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let document: ActorDocument = serde_json::from_value(document)?;
+        let (public_key_id, public_key_pem) = match document.public_key {
+            Some(key) => (Some(key.id), Some(key.public_key_pem)),
+            None => (None, None),
+        };
+        let also_known_as = match document.also_known_as {
+            Some(serde_json::Value::Array(values)) => {
+                values.iter().filter_map(activity::iri).collect()
+            }
+            Some(value) => activity::iri(&value).into_iter().collect(),
+            None => Vec::new(),
+        };
+        Ok(Self {
+            id: document.id,
+            inbox: document.inbox,
+            shared_inbox: document.endpoints.and_then(|e| e.shared_inbox),
+            followers: document.followers,
+            preferred_username: document.preferred_username,
+            name: document.name,
+            summary: document.summary,
+            public_key_id,
+            public_key_pem,
+            also_known_as,
+            moved_to: document.moved_to,
+            featured: document.featured,
+            fetched: chrono::Utc::now(),
+        })
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// `blocker` blocks `blocked`. Either side may be remote:
+/// blocks received from remote actors are stored as well so that we stop delivering to them.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Block {
+    pub blocker: url::Url,
+    pub blocked: url::Url,
+    /// IRI of the `Block` activity, referenced when it is undone.
+    pub activity: Option<url::Url>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
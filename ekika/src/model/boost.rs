@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// `Announce` of a post by a local account.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Boost {
+    pub object: url::Url,
+    pub actor: url::Url,
+    /// IRI of the `Announce` activity, referenced when it is undone.
+    pub activity: url::Url,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
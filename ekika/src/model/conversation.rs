@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// A direct message thread as seen by one local account.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Conversation {
+    /// Name of the local account this entry belongs to.
+    pub owner: String,
+    pub id: String,
+    pub context: Option<url::Url>,
+    pub participants: Vec<url::Url>,
+    pub last_status: url::Url,
+    pub updated: chrono::DateTime<chrono::Utc>,
+    pub unread: bool,
+}
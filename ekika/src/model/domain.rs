@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Federate normally. Only the flags such as `reject_media` apply.
+    Noop,
+    /// Accept activities but hide them from public timelines.
+    Silence,
+    /// Reject everything in both directions.
+    Suspend,
+}
+
+/// Moderation policy for a domain and all of its subdomains.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DomainPolicy {
+    pub domain: String,
+    pub severity: Severity,
+    pub reject_media: bool,
+    pub reject_reports: bool,
+    pub public_comment: Option<String>,
+    pub private_comment: Option<String>,
+    /// Partially hide the domain name in public listings.
+    pub obfuscate: bool,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Domain which may federate while the server runs in allowlist mode.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DomainAllow {
+    pub domain: String,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct CustomEmoji {
+    /// `shortcode` for local emoji and `shortcode@domain` for remote ones.
+    pub id: String,
+    pub shortcode: String,
+    /// Host of the server a remote emoji comes from.
+    pub domain: Option<String>,
+    /// IRI of the `Emoji` object.
+    pub uri: url::Url,
+    pub image: url::Url,
+    pub media_type: Option<String>,
+    pub category: Option<String>,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
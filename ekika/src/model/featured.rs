@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Objects pinned to the profile of an actor, most recently pinned first.
+/// Remote actors' entries mirror their `featured` collection.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Featured {
+    pub actor: url::Url,
+    pub items: Vec<url::Url>,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
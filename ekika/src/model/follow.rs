@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum FollowState {
+    Pending,
+    Accepted,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Follow {
+    pub follower: url::Url,
+    pub followee: url::Url,
+    pub state: FollowState,
+    pub activity: Option<url::Url>,
+}
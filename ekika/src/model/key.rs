@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct KeyPair {
+    /// IRI of the actor owning this key.
+    pub id: url::Url,
+    pub private_key_pem: String,
+    pub public_key_pem: String,
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// List of accounts made by a local account, which streams what they share.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct List {
+    /// Name of the local account.
+    pub owner: String,
+    pub id: String,
+    pub title: String,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// `member` is on the list `list` of `owner`.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ListMember {
+    pub list: String,
+    pub member: url::Url,
+    pub owner: String,
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    Document,
+}
+
+impl MediaKind {
+    pub fn of(content_type: &str) -> Self {
+        match content_type.split('/').next() {
+            Some("image") => Self::Image,
+            Some("video") => Self::Video,
+            Some("audio") => Self::Audio,
+            _ => Self::Document,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum MediaState {
+    /// Waiting for the image processing workers. The file is not stored yet.
+    Processing,
+    #[default]
+    Ready,
+    Failed,
+}
+
+/// Point of an image to keep visible when it is cropped, from -1.0 to 1.0 on each axis.
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct FocalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Smaller WebP copy of an image.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Thumbnail {
+    pub key: String,
+    pub url: url::Url,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// File uploaded by a local account to be attached to its posts.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Attachment {
+    pub id: String,
+    pub account: String,
+    pub kind: MediaKind,
+    pub media_type: String,
+    /// Key of the file in the blob store.
+    pub key: String,
+    /// Unset until the file is processed and stored.
+    pub url: Option<url::Url>,
+    #[serde(default)]
+    pub state: MediaState,
+    /// Alt text.
+    pub name: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub size: u64,
+    #[serde(default)]
+    pub blurhash: Option<String>,
+    #[serde(default)]
+    pub focal_point: Option<FocalPoint>,
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// Aliases and move target of a local account.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Migration {
+    /// Name of the local account.
+    pub account: String,
+    /// Other actors of the same person. Moves from them to this account are accepted.
+    pub also_known_as: Vec<url::Url>,
+    pub moved_to: Option<url::Url>,
+    pub moved: Option<chrono::DateTime<chrono::Utc>>,
+}
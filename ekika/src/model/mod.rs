@@ -1 +1,23 @@
 pub mod account;
+pub mod actor;
+pub mod block;
+pub mod boost;
+pub mod conversation;
+pub mod domain;
+pub mod emoji;
+pub mod featured;
+pub mod follow;
+pub mod key;
+pub mod list;
+pub mod media;
+pub mod migration;
+pub mod mute;
+pub mod notification;
+pub mod oauth;
+pub mod object;
+pub mod proxy;
+pub mod reaction;
+pub mod relay;
+pub mod report;
+pub mod timeline;
+pub mod token;
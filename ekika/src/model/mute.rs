@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// `muter`, a local account, hides the posts and notifications of `muted`.
+/// Unlike blocks, mutes are private and not federated.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Mute {
+    pub muter: url::Url,
+    pub muted: url::Url,
+    /// The mute is ignored after this time.
+    pub expires: Option<chrono::DateTime<chrono::Utc>>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+impl Mute {
+    pub fn is_active(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+}
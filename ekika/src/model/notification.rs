@@ -0,0 +1,50 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    Follow,
+    /// A remote or local account asked to follow a locked account.
+    FollowRequest,
+    Mention,
+    Reply,
+    Favourite,
+    Reblog,
+    /// A poll the account took part in has ended.
+    Poll,
+}
+
+/// Notification of a local account. Notifications of the same [group](Notification::group)
+/// are merged into one while they are unread.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Notification {
+    pub account: String,
+    /// Time ordered id. A new one is assigned when another actor joins the group.
+    pub id: String,
+    pub kind: NotificationKind,
+    /// Actors behind the notification, most recent first. Only the latest few are kept.
+    pub actors: Vec<url::Url>,
+    /// Number of actors behind the notification, including those no longer in `actors`.
+    pub count: u64,
+    pub object: Option<url::Url>,
+    /// Key shared by the notifications which may be merged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct NotificationSettings {
+    pub account: String,
+    /// Kinds of notifications which are not created at all.
+    #[serde(default)]
+    pub disabled: BTreeSet<NotificationKind>,
+    /// Id of the newest notification the account has read.
+    #[serde(default)]
+    pub last_read: Option<String>,
+}
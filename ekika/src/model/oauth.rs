@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Client registered with `POST /api/v1/apps`.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Application {
+    /// Client id.
+    pub id: String,
+    /// SHA-256 digest of the client secret.
+    pub secret: String,
+    pub name: String,
+    pub website: Option<url::Url>,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Grant of the authorization code flow waiting to be exchanged for a token.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthorizationCode {
+    /// SHA-256 digest of the code.
+    pub id: String,
+    pub client: String,
+    pub account: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// PKCE `code_challenge`, which is always S256.
+    pub challenge: Option<String>,
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
+
+/// Sign-in of a local account on the authorization pages, kept in a cookie.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Session {
+    /// SHA-256 digest of the session cookie.
+    pub id: String,
+    pub account: String,
+    /// Value the consent page sends back, so that other sites can't submit it.
+    pub csrf: String,
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activity, ap,
+    visibility::{self, Audience, Visibility},
+};
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct StoredObject {
+    pub id: url::Url,
+    pub attributed_to: url::Url,
+    pub visibility: Visibility,
+    pub audience: Audience,
+    /// JSON-LD document of the object with `bto` and `bcc` stripped.
+    pub body: String,
+    /// Post the object replies to, by which its replies are looked up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<url::Url>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ObjectError {
+    #[error("malformed object: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("object has no id")]
+    MissingId,
+    #[error("object has no attributedTo")]
+    MissingAuthor,
+}
+
+impl StoredObject {
+    /// Classify an incoming or newly created object.
+    /// `followers` is the followers collection of the author.
+    pub fn new(mut body: serde_json::Value, followers: &url::Url) -> Result<Self, ObjectError> {
+        let object: ap::Object = serde_json::from_value(body.clone())?;
+        let id = object.id.clone().ok_or(ObjectError::MissingId)?;
+        let attributed_to = object
+            .attributed_to
+            .0
+            .first()
+            .and_then(ap::iri_of)
+            .ok_or(ObjectError::MissingAuthor)?;
+        let audience = Audience::of(&object);
+        let visibility = audience.classify(followers);
+        let in_reply_to = activity::field_iri(&body, "inReplyTo");
+        visibility::strip_blind(&mut body);
+        Ok(Self {
+            id,
+            attributed_to,
+            visibility,
+            audience,
+            body: body.to_string(),
+            in_reply_to,
+        })
+    }
+}
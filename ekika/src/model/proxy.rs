@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Copy of a remote file served by the media proxy.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct CachedMedia {
+    /// SHA-256 of [CachedMedia::url].
+    pub id: String,
+    pub url: url::Url,
+    pub domain: String,
+    /// SHA-256 of the contents, which the blob is stored under.
+    pub digest: String,
+    /// Key of the file in the blob store. Files with the same contents share it.
+    pub key: String,
+    pub content_type: String,
+    pub size: u64,
+    pub fetched: chrono::DateTime<chrono::Utc>,
+    /// Last time the file was served, updated at most once per hour.
+    pub accessed: chrono::DateTime<chrono::Utc>,
+}
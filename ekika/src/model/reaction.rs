@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Emoji reaction of an actor to an object. An actor has at most one reaction per object.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Reaction {
+    /// IRI of the `EmojiReact` or `Like` activity.
+    pub id: url::Url,
+    pub object: url::Url,
+    pub actor: url::Url,
+    /// Unicode emoji, or a custom emoji shortcode surrounded by colons.
+    pub content: String,
+    /// Id of the custom emoji in the emoji registry.
+    pub emoji: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayKind {
+    /// Subscribed with a `Follow` of `as:Public` sent to the relay's inbox.
+    Mastodon,
+    /// Subscribed with a `Follow` of the relay actor.
+    LitePub,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum RelayState {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+/// Subscription of the instance actor to a relay.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Relay {
+    pub id: String,
+    pub kind: RelayKind,
+    pub inbox: url::Url,
+    /// Relay actor. Mastodon relays are only known by their inbox until they accept.
+    pub actor: Option<url::Url>,
+    pub state: RelayState,
+    /// IRI of the `Follow` which subscribed to the relay.
+    pub activity: url::Url,
+    /// Also send local public posts to the relay.
+    pub push: bool,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
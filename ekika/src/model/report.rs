@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::domain::Severity;
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Spam,
+    Legal,
+    Violation,
+    #[default]
+    Other,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum ReportState {
+    #[default]
+    Open,
+    Resolved,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAction {
+    Dismiss,
+    DeletePosts,
+    Silence,
+    Suspend,
+}
+
+/// Report of an account, filed by a local user or received as a `Flag`.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Report {
+    pub id: String,
+    /// Local account or, for inbound `Flag`s, the remote actor which sent it.
+    pub reporter: url::Url,
+    pub target: url::Url,
+    pub objects: Vec<url::Url>,
+    pub category: Category,
+    pub comment: String,
+    /// IRI of the inbound `Flag`, or of the `Flag` we forwarded to the target's server.
+    pub activity: Option<url::Url>,
+    pub state: ReportState,
+    pub action: Option<ReportAction>,
+    /// Name of the moderator who resolved the report.
+    pub resolved_by: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub resolved: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Moderation applied to a single account, local or remote.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct AccountPolicy {
+    pub actor: url::Url,
+    pub severity: Severity,
+    /// Report which led to the policy.
+    pub report: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
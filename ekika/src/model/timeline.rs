@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Post in a timeline feed, such as the home feed of an account.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct FeedEntry {
+    pub feed: String,
+    /// Time ordered id, shared by the entries of the same delivery in every feed.
+    pub id: String,
+    pub object: url::Url,
+    pub author: url::Url,
+    /// Actor which boosted the post into the feed.
+    pub boosted_by: Option<url::Url>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Actor with too many local followers to copy its posts into every home feed.
+/// Its posts are read from its author feed instead.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct LargeAccount {
+    pub actor: url::Url,
+    pub followers: u64,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
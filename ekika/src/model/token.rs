@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Token {
+    /// SHA-256 digest of the bearer token. The token itself is never stored.
+    pub id: String,
+    /// Local account the token acts as. Tokens of the client credentials grant have none.
+    #[serde(default)]
+    pub account: Option<String>,
+    /// Client id of the OAuth application the token was issued to.
+    #[serde(default)]
+    pub client: Option<String>,
+    /// Granted OAuth scopes. Tokens provisioned by operators have none and may do anything.
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+    #[serde(default)]
+    pub created: Option<chrono::DateTime<chrono::Utc>>,
+}
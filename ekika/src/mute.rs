@@ -0,0 +1,158 @@
+use std::{collections::HashSet, future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{Authenticated, TokenStore},
+    local::Local,
+    model::mute::Mute,
+};
+
+pub trait MuteStore {
+    fn mutes(&self, muter: &url::Url) -> impl Future<Output = Result<Vec<Mute>, HttpError>> + Send;
+    fn get_mute(
+        &self,
+        muter: &url::Url,
+        muted: &url::Url,
+    ) -> impl Future<Output = Result<Option<Mute>, HttpError>> + Send;
+    fn put_mute(&self, mute: &Mute) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_mute(
+        &self,
+        muter: &url::Url,
+        muted: &url::Url,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Whether `muter` currently mutes `muted`.
+pub async fn is_muted<S>(state: &S, muter: &url::Url, muted: &url::Url) -> Result<bool, HttpError>
+where
+    S: MuteStore + Sync,
+{
+    Ok(state
+        .get_mute(muter, muted)
+        .await?
+        .is_some_and(|mute| mute.is_active(chrono::Utc::now())))
+}
+
+/// Actors `viewer` currently mutes.
+pub async fn muted_actors<S>(state: &S, viewer: &url::Url) -> Result<HashSet<url::Url>, HttpError>
+where
+    S: MuteStore + Sync,
+{
+    let now = chrono::Utc::now();
+    Ok(state
+        .mutes(viewer)
+        .await?
+        .into_iter()
+        .filter(|mute| mute.is_active(now))
+        .map(|mute| mute.muted)
+        .collect())
+}
+
+#[derive(Deserialize)]
+pub struct MuteRequest {
+    pub actor: url::Url,
+    /// Seconds until the mute expires. It never expires when unset.
+    pub duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct UnmuteRequest {
+    pub actor: url::Url,
+}
+
+#[derive(Serialize)]
+pub struct MuteView {
+    pub actor: url::Url,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<Mute> for MuteView {
+    fn from(mute: Mute) -> Self {
+        Self {
+            actor: mute.muted,
+            expires_at: mute.expires,
+            created_at: mute.created,
+        }
+    }
+}
+
+pub async fn list_mutes<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<MuteView>>, HttpError>
+where
+    S: MuteStore + TokenStore + Send + Sync,
+{
+    let muter = Local::try_from(&proxy_info)?.actor(&auth.account);
+    let now = chrono::Utc::now();
+    let mutes = state.mutes(&muter).await?;
+    Ok(Json(
+        mutes
+            .into_iter()
+            .filter(|mute| mute.is_active(now))
+            .map(MuteView::from)
+            .collect(),
+    ))
+}
+
+pub async fn create_mute<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<MuteRequest>,
+) -> Result<Json<MuteView>, HttpError>
+where
+    S: MuteStore + TokenStore + Send + Sync,
+{
+    let muter = Local::try_from(&proxy_info)?.actor(&auth.account);
+    if muter == request.actor {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot mute yourself"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    let created = chrono::Utc::now();
+    let expires = match request.duration {
+        Some(duration) => Some(
+            i64::try_from(duration)
+                .ok()
+                .and_then(chrono::TimeDelta::try_seconds)
+                .and_then(|duration| created.checked_add_signed(duration))
+                .ok_or_else(|| {
+                    HttpError::new_json(
+                        &json!({"ok": false, "msg": "duration is too long"}),
+                        http::StatusCode::BAD_REQUEST,
+                    )
+                })?,
+        ),
+        None => None,
+    };
+    let mute = Mute {
+        muter,
+        muted: request.actor,
+        expires,
+        created,
+    };
+    state.put_mute(&mute).await?;
+    Ok(Json(mute.into()))
+}
+
+pub async fn delete_mute<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<UnmuteRequest>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: MuteStore + TokenStore + Send + Sync,
+{
+    let muter = Local::try_from(&proxy_info)?.actor(&auth.account);
+    state.delete_mute(&muter, &request.actor).await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,49 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde_json::json;
+
+use crate::local::Local;
+
+pub const SCHEMA: &str = "http://nodeinfo.diaspora.software/ns/schema/2.0";
+
+pub trait UsageStore {
+    /// Number of local accounts. The instance actor has no account, so it is not counted.
+    fn user_count(&self) -> impl Future<Output = Result<u64, HttpError>> + Send;
+}
+
+/// `GET /.well-known/nodeinfo`
+pub async fn discovery(proxy_info: ProxyInfo) -> Result<Json<serde_json::Value>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    Ok(Json(json!({
+        "links": [{
+            "rel": SCHEMA,
+            "href": local.issuer().join("nodeinfo/2.0").unwrap(),
+        }],
+    })))
+}
+
+/// `GET /nodeinfo/2.0`
+pub async fn nodeinfo<S>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<serde_json::Value>, HttpError>
+where
+    S: UsageStore + Sync,
+{
+    let users = state.user_count().await?;
+    Ok(Json(json!({
+        "version": "2.0",
+        "software": {
+            "name": "ekika",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "protocols": ["activitypub"],
+        "services": {"inbound": [], "outbound": []},
+        "openRegistrations": false,
+        "usage": {
+            "users": {"total": users},
+        },
+        "metadata": {},
+    })))
+}
@@ -0,0 +1,484 @@
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    block::{self, BlockStore},
+    follow::FollowStore,
+    local::Local,
+    model::{
+        notification::{Notification, NotificationKind, NotificationSettings},
+        object::StoredObject,
+    },
+    mute::{self, MuteStore},
+    object::ObjectStore,
+    stream::{Event, EventBus, Stream},
+    timeline::{self, FeedRange, TimelineParams},
+    visibility::{self, Viewer, Visibility},
+};
+
+/// Actors kept in a grouped notification.
+const MAX_GROUP_ACTORS: usize = 8;
+
+const DEFAULT_LIMIT: usize = 20;
+
+const MAX_LIMIT: usize = 80;
+
+/// Notifications read at once while filling a page.
+const BATCH: usize = 80;
+
+/// Batches read for a single page, so that mostly filtered notifications can't stall a request.
+const MAX_BATCHES: usize = 10;
+
+pub trait NotificationStore {
+    fn notifications(
+        &self,
+        account: &str,
+        range: &FeedRange,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<Notification>, HttpError>> + Send;
+    /// Notifications of every account with the given group key.
+    fn grouped_notifications(
+        &self,
+        group: &str,
+    ) -> impl Future<Output = Result<Vec<Notification>, HttpError>> + Send;
+    fn put_notification(
+        &self,
+        notification: &Notification,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_notification(
+        &self,
+        account: &str,
+        id: &str,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn notification_settings(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Option<NotificationSettings>, HttpError>> + Send;
+    fn put_notification_settings(
+        &self,
+        settings: &NotificationSettings,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+async fn settings_of<S>(state: &S, account: &str) -> Result<NotificationSettings, HttpError>
+where
+    S: NotificationStore + Sync,
+{
+    Ok(state
+        .notification_settings(account)
+        .await?
+        .unwrap_or_else(|| NotificationSettings {
+            account: account.to_string(),
+            ..Default::default()
+        }))
+}
+
+/// Favourites and boosts of the same post are merged, and so are new followers.
+fn group_of(account: &str, kind: NotificationKind, object: Option<&url::Url>) -> Option<String> {
+    match (kind, object) {
+        (NotificationKind::Follow, _) => Some(format!("{account}:follow")),
+        (NotificationKind::Favourite, Some(object)) => {
+            Some(format!("{account}:favourite:{object}"))
+        }
+        (NotificationKind::Reblog, Some(object)) => Some(format!("{account}:reblog:{object}")),
+        _ => None,
+    }
+}
+
+/// Notify the local actor `recipient` that `actor` did something.
+/// Nothing happens for remote recipients, disabled kinds, and actors the recipient mutes
+/// or is blocked with.
+pub async fn notify<S>(
+    state: &S,
+    local: &Local,
+    recipient: &url::Url,
+    kind: NotificationKind,
+    actor: &url::Url,
+    object: Option<&url::Url>,
+) -> Result<(), HttpError>
+where
+    S: NotificationStore + BlockStore + MuteStore + EventBus + Sync,
+{
+    let Some(account) = local.account_name(recipient) else {
+        return Ok(());
+    };
+    if recipient == actor {
+        return Ok(());
+    }
+    let settings = settings_of(state, &account).await?;
+    if settings.disabled.contains(&kind) {
+        return Ok(());
+    }
+    if mute::is_muted(state, recipient, actor).await?
+        || block::is_blocked_between(state, recipient, actor).await?
+    {
+        debug!(
+            account,
+            actor = actor.to_string(),
+            ?kind,
+            "suppressed_notification"
+        );
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now();
+    let group = group_of(&account, kind, object);
+    let unread = |notification: &Notification| {
+        settings
+            .last_read
+            .as_ref()
+            .is_none_or(|last_read| &notification.id > last_read)
+    };
+    let mut existing = None;
+    if let Some(group) = &group {
+        existing = state
+            .grouped_notifications(group)
+            .await?
+            .into_iter()
+            .filter(unread)
+            .max_by(|a, b| a.id.cmp(&b.id));
+    }
+    let notification = match existing {
+        Some(mut notification) => {
+            state
+                .delete_notification(&account, &notification.id)
+                .await?;
+            if let Some(position) = notification.actors.iter().position(|a| a == actor) {
+                notification.actors.remove(position);
+            } else {
+                notification.count += 1;
+            }
+            notification.actors.insert(0, actor.clone());
+            notification.actors.truncate(MAX_GROUP_ACTORS);
+            notification.id = timeline::new_id(now);
+            notification.updated = now;
+            notification
+        }
+        None => Notification {
+            account,
+            id: timeline::new_id(now),
+            kind,
+            actors: vec![actor.clone()],
+            count: 1,
+            object: object.cloned(),
+            group,
+            created: now,
+            updated: now,
+        },
+    };
+    state.put_notification(&notification).await?;
+    let stream = Stream::User(notification.account.clone());
+    state.publish(stream, Event::Notification(notification));
+    Ok(())
+}
+
+/// Like [notify], but failures are only logged. Notifications must not fail the action.
+pub async fn try_notify<S>(
+    state: &S,
+    local: &Local,
+    recipient: &url::Url,
+    kind: NotificationKind,
+    actor: &url::Url,
+    object: Option<&url::Url>,
+) where
+    S: NotificationStore + BlockStore + MuteStore + EventBus + Sync,
+{
+    if let Err(e) = notify(state, local, recipient, kind, actor, object).await {
+        warn!(
+            recipient = recipient.to_string(),
+            ?kind,
+            status = e.status.as_u16(),
+            "notify"
+        );
+    }
+}
+
+/// Actors tagged with `Mention` in a document.
+pub fn mentions(document: &serde_json::Value) -> BTreeSet<url::Url> {
+    let tags = match document.get("tag") {
+        Some(serde_json::Value::Array(tags)) => tags.iter().collect(),
+        Some(tag) => vec![tag],
+        None => Vec::new(),
+    };
+    tags.into_iter()
+        .filter(|tag| tag.get("type").and_then(|kind| kind.as_str()) == Some("Mention"))
+        .filter_map(|tag| activity::field_iri(tag, "href"))
+        .collect()
+}
+
+/// Local accounts to notify of a new post: those mentioned, and the author of the post it
+/// replies to. Direct messages mention everyone they are addressed to.
+async fn post_recipients<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+) -> Result<Vec<(url::Url, NotificationKind)>, HttpError>
+where
+    S: ObjectStore + FollowStore + Sync,
+{
+    let Ok(body) = serde_json::from_str::<serde_json::Value>(&object.body) else {
+        return Ok(Vec::new());
+    };
+    let mut mentioned = mentions(&body);
+    if object.visibility == Visibility::Direct {
+        mentioned.extend(object.audience.recipients());
+    }
+    let mut recipients = Vec::new();
+    if let Some(parent) = activity::field_iri(&body, "inReplyTo") {
+        if let Some(parent) = state.get_object(&parent).await? {
+            if !mentioned.contains(&parent.attributed_to) {
+                recipients.push((parent.attributed_to, NotificationKind::Reply));
+            }
+        }
+    }
+    recipients.extend(
+        mentioned
+            .into_iter()
+            .map(|recipient| (recipient, NotificationKind::Mention)),
+    );
+    let mut visible = Vec::new();
+    for (recipient, kind) in recipients {
+        if local.account_name(&recipient).is_some()
+            && visibility::can_view(state, object, &Viewer::Actor(recipient.clone())).await?
+        {
+            visible.push((recipient, kind));
+        }
+    }
+    Ok(visible)
+}
+
+/// Notify local accounts of a new post which mentions them or replies to them.
+pub async fn notify_post<S>(state: &S, local: &Local, object: &StoredObject)
+where
+    S: NotificationStore + BlockStore + MuteStore + ObjectStore + FollowStore + EventBus + Sync,
+{
+    let recipients = match post_recipients(state, local, object).await {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            warn!(
+                object = object.id.to_string(),
+                status = e.status.as_u16(),
+                "notify"
+            );
+            return;
+        }
+    };
+    let author = &object.attributed_to;
+    for (recipient, kind) in recipients {
+        try_notify(state, local, &recipient, kind, author, Some(&object.id)).await;
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NotificationParams {
+    pub max_id: Option<String>,
+    pub since_id: Option<String>,
+    pub min_id: Option<String>,
+    pub limit: Option<usize>,
+    /// Comma separated kinds to include. Every kind is included when unset.
+    pub types: Option<String>,
+}
+
+impl NotificationParams {
+    fn range(&self) -> FeedRange {
+        TimelineParams {
+            max_id: self.max_id.clone(),
+            since_id: self.since_id.clone(),
+            min_id: self.min_id.clone(),
+            limit: self.limit,
+        }
+        .range()
+    }
+}
+
+#[derive(Serialize)]
+pub struct NotificationView {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: NotificationKind,
+    pub actors: Vec<url::Url>,
+    pub count: u64,
+    pub object: Option<url::Url>,
+    pub read: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl NotificationView {
+    pub fn new(notification: Notification, read: bool) -> Self {
+        Self {
+            id: notification.id,
+            kind: notification.kind,
+            actors: notification.actors,
+            count: notification.count,
+            object: notification.object,
+            read,
+            created_at: notification.created,
+            updated_at: notification.updated,
+        }
+    }
+}
+
+fn bad_types() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "unknown notification type"}),
+        http::StatusCode::BAD_REQUEST,
+    )
+}
+
+fn parse_kinds(types: &str) -> Result<BTreeSet<NotificationKind>, HttpError> {
+    types
+        .split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| serde_json::from_value(json!(kind)).map_err(|_| bad_types()))
+        .collect()
+}
+
+/// Notifications of the account, newest first. Actors the account has muted or blocked
+/// since are left out.
+pub async fn list_notifications<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<NotificationParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<NotificationView>>), HttpError>
+where
+    S: NotificationStore + BlockStore + MuteStore + TokenStore + Send + Sync,
+{
+    let state = state.as_ref();
+    let kinds = params.types.as_deref().map(parse_kinds).transpose()?;
+    let viewer = Local::try_from(&proxy_info)?.actor(&auth.account);
+    let mut hidden = block::hidden_actors(state, &viewer).await?;
+    hidden.extend(mute::muted_actors(state, &viewer).await?);
+    let settings = settings_of(state, &auth.account).await?;
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut range = params.range();
+    let mut views = Vec::new();
+    'batches: for _ in 0..MAX_BATCHES {
+        let batch = state.notifications(&auth.account, &range, BATCH).await?;
+        let exhausted = batch.len() < BATCH;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        if range.ascending {
+            range.after = Some(last.id.clone());
+        } else {
+            range.before = Some(last.id.clone());
+        }
+        for mut notification in batch {
+            if kinds
+                .as_ref()
+                .is_some_and(|kinds| !kinds.contains(&notification.kind))
+            {
+                continue;
+            }
+            notification.actors.retain(|actor| !hidden.contains(actor));
+            if notification.actors.is_empty() {
+                continue;
+            }
+            let read = settings
+                .last_read
+                .as_ref()
+                .is_some_and(|last_read| &notification.id <= last_read);
+            views.push(NotificationView::new(notification, read));
+            if views.len() == limit {
+                break 'batches;
+            }
+        }
+        if exhausted {
+            break;
+        }
+    }
+    if range.ascending {
+        views.reverse();
+    }
+    let ids = views.iter().map(|view| view.id.as_str());
+    Ok((
+        timeline::link_header(&proxy_info, uri.path(), ids),
+        Json(views),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    /// The notification and every older one are marked as read.
+    pub max_id: String,
+}
+
+/// Move the read marker of the account forward.
+pub async fn mark_read<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<MarkReadRequest>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: NotificationStore + TokenStore + Send + Sync,
+{
+    let mut settings = settings_of(state.as_ref(), &auth.account).await?;
+    if settings
+        .last_read
+        .as_ref()
+        .is_none_or(|last_read| &request.max_id > last_read)
+    {
+        settings.last_read = Some(request.max_id);
+        state.put_notification_settings(&settings).await?;
+    }
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+pub async fn dismiss_notification<S>(
+    auth: Authenticated,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: NotificationStore + TokenStore + Send + Sync,
+{
+    state.delete_notification(&auth.account, &id).await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SettingsView {
+    /// Kinds of notifications which are not created.
+    pub disabled: BTreeSet<NotificationKind>,
+}
+
+pub async fn get_settings<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<SettingsView>, HttpError>
+where
+    S: NotificationStore + TokenStore + Send + Sync,
+{
+    let settings = settings_of(state.as_ref(), &auth.account).await?;
+    Ok(Json(SettingsView {
+        disabled: settings.disabled,
+    }))
+}
+
+pub async fn put_settings<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(request): Json<SettingsView>,
+) -> Result<Json<SettingsView>, HttpError>
+where
+    S: NotificationStore + TokenStore + Send + Sync,
+{
+    let mut settings = settings_of(state.as_ref(), &auth.account).await?;
+    settings.disabled = request.disabled;
+    state.put_notification_settings(&settings).await?;
+    Ok(Json(SettingsView {
+        disabled: settings.disabled,
+    }))
+}
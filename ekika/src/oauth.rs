@@ -0,0 +1,1019 @@
+use std::{future::Future, sync::Arc};
+
+use axum::{
+    extract::{FromRequest, Query, Request},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::{
+    auth::{self, TokenStore},
+    local::Local,
+    model::{
+        oauth::{Application, AuthorizationCode, Session},
+        token::Token,
+    },
+};
+
+/// Redirect URI of clients which show the authorization code to the user instead of receiving it.
+pub const OOB: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// Scopes a client can ask for. A scope grants every scope it prefixes, e.g. `read` grants
+/// `read:statuses`.
+pub const SCOPES: &[&str] = &[
+    "read",
+    "read:accounts",
+    "read:blocks",
+    "read:favourites",
+    "read:follows",
+    "read:lists",
+    "read:mutes",
+    "read:notifications",
+    "read:search",
+    "read:statuses",
+    "write",
+    "write:accounts",
+    "write:blocks",
+    "write:favourites",
+    "write:follows",
+    "write:lists",
+    "write:media",
+    "write:mutes",
+    "write:notifications",
+    "write:reports",
+    "write:statuses",
+    "follow",
+    "push",
+    "admin:read",
+    "admin:write",
+];
+
+/// Scopes the legacy `follow` scope grants.
+const FOLLOW: &[&str] = &[
+    "read:follows",
+    "write:follows",
+    "read:blocks",
+    "write:blocks",
+    "read:mutes",
+    "write:mutes",
+];
+
+const CODE_LIFETIME_MINUTES: i64 = 10;
+
+const SESSION_LIFETIME_HOURS: i64 = 24;
+
+/// Cookie of a [Session] on the authorization pages.
+const SESSION_COOKIE: &str = "ekika_session";
+
+static TEMPLATES: Lazy<handlebars::Handlebars> = Lazy::new(|| {
+    let mut registry = handlebars::Handlebars::new();
+    registry
+        .register_template_string("authorize", include_str!("res/authorize.html"))
+        .unwrap();
+    registry
+        .register_template_string("authorized", include_str!("res/authorized.html"))
+        .unwrap();
+    registry
+        .register_template_string("login", include_str!("res/login.html"))
+        .unwrap();
+    registry
+});
+
+pub trait OAuthStore {
+    fn get_application(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Application>, HttpError>> + Send;
+    fn put_application(
+        &self,
+        application: &Application,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn put_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn get_authorization_code(
+        &self,
+        digest: &str,
+    ) -> impl Future<Output = Result<Option<AuthorizationCode>, HttpError>> + Send;
+    /// Remove a code and return it, so that a code is exchanged at most once.
+    fn take_authorization_code(
+        &self,
+        digest: &str,
+    ) -> impl Future<Output = Result<Option<AuthorizationCode>, HttpError>> + Send;
+    fn get_session(
+        &self,
+        digest: &str,
+    ) -> impl Future<Output = Result<Option<Session>, HttpError>> + Send;
+    fn put_session(&self, session: &Session) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Everything the authorization server needs from the server state.
+pub trait OAuthState: OAuthStore + TokenStore + Send + Sync {}
+
+impl<S> OAuthState for S where S: OAuthStore + TokenStore + Send + Sync {}
+
+/// Whether any of `granted` covers `required`.
+pub fn grants(granted: &[String], required: &str) -> bool {
+    granted.iter().any(|scope| {
+        scope == required
+            || required
+                .strip_prefix(scope.as_str())
+                .is_some_and(|rest| rest.starts_with(':'))
+            || scope == "follow" && FOLLOW.contains(&required)
+    })
+}
+
+/// Scope a token needs for each authenticated route, by method and route. A route without
+/// an entry refuses every token, so that a new route can't be reached before it declares one.
+pub const ROUTE_SCOPES: &[(&str, &str, &str)] = &[
+    (
+        "GET",
+        "/api/v1/accounts/verify_credentials",
+        "read:accounts",
+    ),
+    ("GET", "/api/v1/accounts/relationships", "read:follows"),
+    ("GET", "/api/v1/accounts/lookup", "read:accounts"),
+    ("GET", "/api/v1/accounts/:id", "read:accounts"),
+    ("GET", "/api/v1/accounts/:id/statuses", "read:statuses"),
+    ("GET", "/api/v1/accounts/:id/followers", "read:accounts"),
+    ("GET", "/api/v1/accounts/:id/following", "read:accounts"),
+    ("POST", "/api/v1/accounts/:id/follow", "write:follows"),
+    ("POST", "/api/v1/accounts/:id/unfollow", "write:follows"),
+    ("GET", "/api/v1/follow_requests", "read:follows"),
+    (
+        "POST",
+        "/api/v1/follow_requests/:id/authorize",
+        "write:follows",
+    ),
+    (
+        "POST",
+        "/api/v1/follow_requests/:id/reject",
+        "write:follows",
+    ),
+    ("POST", "/api/v1/statuses", "write:statuses"),
+    ("GET", "/api/v1/statuses/:id", "read:statuses"),
+    ("DELETE", "/api/v1/statuses/:id", "write:statuses"),
+    ("GET", "/api/v1/statuses/:id/context", "read:statuses"),
+    ("POST", "/api/v1/statuses/:id/favourite", "write:favourites"),
+    (
+        "POST",
+        "/api/v1/statuses/:id/unfavourite",
+        "write:favourites",
+    ),
+    ("POST", "/api/v1/statuses/:id/reblog", "write:statuses"),
+    ("POST", "/api/v1/statuses/:id/unreblog", "write:statuses"),
+    ("GET", "/api/v1/favourites", "read:favourites"),
+    ("GET", "/api/v1/timelines/home", "read:statuses"),
+    ("GET", "/api/v1/timelines/public", "read:statuses"),
+    ("GET", "/api/v1/timelines/list/:id", "read:lists"),
+    ("GET", "/api/v1/lists", "read:lists"),
+    ("POST", "/api/v1/lists", "write:lists"),
+    ("GET", "/api/v1/lists/:id", "read:lists"),
+    ("PUT", "/api/v1/lists/:id", "write:lists"),
+    ("DELETE", "/api/v1/lists/:id", "write:lists"),
+    ("GET", "/api/v1/lists/:id/accounts", "read:lists"),
+    ("POST", "/api/v1/lists/:id/accounts", "write:lists"),
+    ("DELETE", "/api/v1/lists/:id/accounts", "write:lists"),
+    ("GET", "/api/v1/notifications", "read:notifications"),
+    ("POST", "/api/v1/notifications/clear", "write:notifications"),
+    (
+        "POST",
+        "/api/v1/notifications/:id/dismiss",
+        "write:notifications",
+    ),
+    ("POST", "/api/v1/media", "write:media"),
+    ("POST", "/api/v2/media", "write:media"),
+    ("GET", "/api/v1/media/:id", "write:media"),
+    ("PUT", "/api/v1/media/:id", "write:media"),
+    ("GET", "/api/v2/search", "read:search"),
+    ("GET", "/api/search", "read:search"),
+    ("GET", "/api/search/resolve", "read:search"),
+    ("POST", "/api/media", "write:media"),
+    ("GET", "/api/media/:id", "write:media"),
+    ("PUT", "/api/media/:id", "write:media"),
+    ("POST", "/api/admin/emojis", "admin:write"),
+    ("GET", "/api/admin/emojis/remote", "admin:read"),
+    ("POST", "/api/admin/emojis/copy", "admin:write"),
+    ("PATCH", "/api/admin/emojis/:shortcode", "admin:write"),
+    ("DELETE", "/api/admin/emojis/:shortcode", "admin:write"),
+    ("GET", "/api/blocks", "read:blocks"),
+    ("POST", "/api/blocks", "write:blocks"),
+    ("DELETE", "/api/blocks", "write:blocks"),
+    ("GET", "/api/mutes", "read:mutes"),
+    ("POST", "/api/mutes", "write:mutes"),
+    ("DELETE", "/api/mutes", "write:mutes"),
+    ("GET", "/api/notifications", "read:notifications"),
+    ("POST", "/api/notifications/read", "write:notifications"),
+    ("GET", "/api/notifications/settings", "read:notifications"),
+    ("PUT", "/api/notifications/settings", "write:notifications"),
+    ("DELETE", "/api/notifications/:id", "write:notifications"),
+    ("GET", "/api/timelines/home", "read:statuses"),
+    ("GET", "/api/timelines/local", "read:statuses"),
+    ("GET", "/api/timelines/federated", "read:statuses"),
+    ("GET", "/api/admin/domain_blocks", "admin:read"),
+    ("POST", "/api/admin/domain_blocks", "admin:write"),
+    ("GET", "/api/admin/domain_blocks/export", "admin:read"),
+    ("POST", "/api/admin/domain_blocks/import", "admin:write"),
+    ("DELETE", "/api/admin/domain_blocks/:domain", "admin:write"),
+    ("GET", "/api/admin/domain_allows", "admin:read"),
+    ("POST", "/api/admin/domain_allows", "admin:write"),
+    ("DELETE", "/api/admin/domain_allows/:domain", "admin:write"),
+    ("GET", "/api/admin/relays", "admin:read"),
+    ("POST", "/api/admin/relays", "admin:write"),
+    ("PATCH", "/api/admin/relays/:id", "admin:write"),
+    ("DELETE", "/api/admin/relays/:id", "admin:write"),
+    ("POST", "/api/reports", "write:reports"),
+    ("GET", "/api/admin/reports", "admin:read"),
+    ("GET", "/api/admin/reports/:id", "admin:read"),
+    ("POST", "/api/admin/reports/:id/resolve", "admin:write"),
+    ("GET", "/api/account/migration", "read:accounts"),
+    ("PUT", "/api/account/aliases", "write:accounts"),
+    ("POST", "/api/account/move", "write:accounts"),
+    ("GET", "/api/account/profile", "read:accounts"),
+    ("PUT", "/api/account/profile", "write:accounts"),
+    ("GET", "/api/reactions", "read:favourites"),
+    ("PUT", "/api/reactions", "write:favourites"),
+    ("DELETE", "/api/reactions", "write:favourites"),
+    ("GET", "/api/pins", "read:accounts"),
+    ("POST", "/api/pins", "write:accounts"),
+    ("DELETE", "/api/pins", "write:accounts"),
+    ("GET", "/api/conversations", "read:statuses"),
+    ("POST", "/api/conversations", "write:statuses"),
+    ("POST", "/api/conversations/:id/read", "write:statuses"),
+];
+
+/// Scope [ROUTE_SCOPES] declares for a request on `route`. `HEAD` requests need the scope of
+/// `GET`.
+pub fn route_scope(method: &http::Method, route: &str) -> Option<&'static str> {
+    let method = if method == http::Method::HEAD {
+        "GET"
+    } else {
+        method.as_str()
+    };
+    ROUTE_SCOPES
+        .iter()
+        .find(|(m, r, _)| *m == method && *r == route)
+        .map(|(_, _, scope)| *scope)
+}
+
+/// Space separated scopes, `read` when there are none.
+fn parse_scopes(scopes: Option<&str>) -> Result<Vec<String>, String> {
+    let mut parsed = scopes
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if let Some(unknown) = parsed
+        .iter()
+        .find(|scope| !SCOPES.contains(&scope.as_str()))
+    {
+        return Err(format!("unknown scope {unknown}"));
+    }
+    if parsed.is_empty() {
+        parsed.push("read".to_string());
+    }
+    parsed.dedup();
+    Ok(parsed)
+}
+
+/// Scopes requested of an application, which defaults to everything it registered.
+fn requested_scopes(
+    application: &Application,
+    scopes: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let Some(scopes) = scopes.filter(|scopes| !scopes.trim().is_empty()) else {
+        return Ok(application.scopes.clone());
+    };
+    let scopes = parse_scopes(Some(scopes))?;
+    match scopes
+        .iter()
+        .find(|scope| !grants(&application.scopes, scope))
+    {
+        Some(scope) => Err(format!("{scope} was not registered for the application")),
+        None => Ok(scopes),
+    }
+}
+
+fn random_secret() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+fn bad_request(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::BAD_REQUEST,
+    )
+}
+
+fn unprocessable(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::UNPROCESSABLE_ENTITY,
+    )
+}
+
+/// Error response of RFC 6749 section 5.2.
+fn oauth_error(error: &str, description: &str) -> HttpError {
+    let status = if error == "invalid_client" {
+        http::StatusCode::UNAUTHORIZED
+    } else {
+        http::StatusCode::BAD_REQUEST
+    };
+    HttpError::new_json(
+        &json!({"error": error, "error_description": description}),
+        status,
+    )
+}
+
+fn render<T: Serialize>(name: &str, data: &T) -> Result<Html<String>, HttpError> {
+    TEMPLATES
+        .render(name, data)
+        .map(Html)
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Body of clients which send either `application/x-www-form-urlencoded` or JSON.
+pub struct FormOrJson<T>(pub T);
+
+#[async_trait::async_trait]
+impl<S, T> FromRequest<S> for FormOrJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let json = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if json {
+            Json::from_request(req, state)
+                .await
+                .map(|Json(body)| Self(body))
+                .map_err(|e| unprocessable(&e.body_text()))
+        } else {
+            Form::from_request(req, state)
+                .await
+                .map(|Form(body)| Self(body))
+                .map_err(|e| unprocessable(&e.body_text()))
+        }
+    }
+}
+
+/// Redirect URIs as one string separated by whitespace or as a list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RedirectUris {
+    List(Vec<String>),
+    One(String),
+}
+
+impl RedirectUris {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::List(uris) => uris,
+            Self::One(uris) => uris.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateApp {
+    pub client_name: String,
+    pub redirect_uris: RedirectUris,
+    pub scopes: Option<String>,
+    pub website: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AppView {
+    pub id: String,
+    pub name: String,
+    pub website: Option<url::Url>,
+    pub scopes: Vec<String>,
+    pub redirect_uri: String,
+    pub redirect_uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+impl From<Application> for AppView {
+    fn from(application: Application) -> Self {
+        Self {
+            id: application.id,
+            name: application.name,
+            website: application.website,
+            scopes: application.scopes,
+            redirect_uri: application.redirect_uris.join("\n"),
+            redirect_uris: application.redirect_uris,
+            client_id: None,
+            client_secret: None,
+        }
+    }
+}
+
+/// `POST /api/v1/apps`: register a client. Its secret is returned only this once.
+pub async fn create_app<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    FormOrJson(request): FormOrJson<CreateApp>,
+) -> Result<Json<AppView>, HttpError> {
+    let name = request.client_name.trim();
+    if name.is_empty() {
+        return Err(unprocessable("client_name is required"));
+    }
+    let redirect_uris = request.redirect_uris.into_vec();
+    if redirect_uris.is_empty() {
+        return Err(unprocessable("redirect_uris is required"));
+    }
+    if let Some(uri) = redirect_uris
+        .iter()
+        .find(|uri| uri.as_str() != OOB && url::Url::parse(uri).is_err())
+    {
+        return Err(unprocessable(&format!("{uri} is not a valid redirect URI")));
+    }
+    let website = match request.website.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(website) => Some(
+            website
+                .parse()
+                .map_err(|_| unprocessable("website is not a valid URL"))?,
+        ),
+    };
+    let scopes = parse_scopes(request.scopes.as_deref()).map_err(|msg| unprocessable(&msg))?;
+    let secret = random_secret();
+    let application = Application {
+        id: random_secret(),
+        secret: auth::digest(&secret),
+        name: name.to_string(),
+        website,
+        redirect_uris,
+        scopes,
+        created: chrono::Utc::now(),
+    };
+    state.put_application(&application).await?;
+    info!(
+        client = application.id,
+        name = application.name,
+        "application registered"
+    );
+    let client_id = application.id.clone();
+    Ok(Json(AppView {
+        client_id: Some(client_id),
+        client_secret: Some(secret),
+        ..application.into()
+    }))
+}
+
+/// `GET /api/v1/apps/verify_credentials`: application a token was issued to.
+pub async fn verify_app<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    headers: http::HeaderMap,
+) -> Result<Json<AppView>, HttpError> {
+    let unauthorized = || {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "unauthorized"}),
+            http::StatusCode::UNAUTHORIZED,
+        )
+    };
+    let token = auth::bearer_token(&headers).ok_or_else(unauthorized)?;
+    let token = state
+        .get_token(&auth::digest(token))
+        .await?
+        .ok_or_else(unauthorized)?;
+    let application = match token.client {
+        Some(client) => state.get_application(&client).await?,
+        None => None,
+    };
+    let application = application.ok_or_else(|| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "the token was not issued to an application"}),
+            http::StatusCode::NOT_FOUND,
+        )
+    })?;
+    Ok(Json(application.into()))
+}
+
+/// Parameters of an authorization request, which the consent page sends back when it is
+/// submitted.
+#[derive(Deserialize, Serialize)]
+pub struct AuthorizeParams {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    /// Value of the session, which shows that the consent page sent the request.
+    #[serde(default, skip_serializing)]
+    pub csrf: Option<String>,
+    /// `approve` or `deny`.
+    #[serde(default, skip_serializing)]
+    pub decision: Option<String>,
+}
+
+/// Check an authorization request. It is refused without redirecting, as the client may not
+/// be who it claims.
+async fn validate<S: OAuthState>(
+    state: &S,
+    params: &AuthorizeParams,
+) -> Result<(Application, Vec<String>), HttpError> {
+    let application = state
+        .get_application(&params.client_id)
+        .await?
+        .ok_or_else(|| bad_request("unknown client"))?;
+    if !application.redirect_uris.contains(&params.redirect_uri) {
+        return Err(bad_request("the redirect URI is not registered"));
+    }
+    if params.response_type != "code" {
+        return Err(bad_request("only the code response type is supported"));
+    }
+    if params.code_challenge.is_some()
+        && params.code_challenge_method.as_deref().unwrap_or("plain") != "S256"
+    {
+        return Err(bad_request(
+            "only the S256 code challenge method is supported",
+        ));
+    }
+    let scopes =
+        requested_scopes(&application, params.scope.as_deref()).map_err(|msg| bad_request(&msg))?;
+    Ok((application, scopes))
+}
+
+fn consent_page(
+    local: &Local,
+    application: &Application,
+    scopes: &[String],
+    params: &AuthorizeParams,
+    session: &Session,
+) -> Result<Html<String>, HttpError> {
+    render(
+        "authorize",
+        &json!({
+            "name": application.name,
+            "website": application.website,
+            "scopes": scopes,
+            "params": params,
+            "action": local.oauth("authorize"),
+            "account": session.account,
+            "csrf": session.csrf,
+        }),
+    )
+}
+
+/// Value of the session cookie of a request.
+fn session_cookie(headers: &http::HeaderMap) -> Option<&str> {
+    headers
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(SESSION_COOKIE)?
+                .strip_prefix('=')
+        })
+}
+
+/// Session of the account which sent a request, unless it has expired.
+async fn session<S: OAuthState>(
+    state: &S,
+    headers: &http::HeaderMap,
+) -> Result<Option<Session>, HttpError> {
+    let Some(cookie) = session_cookie(headers) else {
+        return Ok(None);
+    };
+    Ok(state
+        .get_session(&auth::digest(cookie))
+        .await?
+        .filter(|session| session.expires > chrono::Utc::now()))
+}
+
+/// `GET /oauth/authorize`: consent page of the authorization code flow. Accounts which haven't
+/// signed in are sent to the sign-in page first.
+pub async fn authorize<S: OAuthState>(
+    Query(params): Query<AuthorizeParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<Response, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let (application, scopes) = validate(state.as_ref(), &params).await?;
+    let Some(session) = session(state.as_ref(), &headers).await? else {
+        let mut next = local.oauth("authorize");
+        next.set_query(uri.query());
+        let mut login = local.oauth("login");
+        login.query_pairs_mut().append_pair("next", next.as_str());
+        return Ok(Redirect::to(login.as_str()).into_response());
+    };
+    Ok(consent_page(&local, &application, &scopes, &params, &session)?.into_response())
+}
+
+/// `POST /oauth/authorize`: grant or deny the request of the consent page for the account
+/// which signed in.
+pub async fn approve<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    headers: http::HeaderMap,
+    Form(params): Form<AuthorizeParams>,
+) -> Result<Response, HttpError> {
+    let state = state.as_ref();
+    let (application, scopes) = validate(state, &params).await?;
+    let session = session(state, &headers)
+        .await?
+        .filter(|session| params.csrf.as_deref() == Some(session.csrf.as_str()))
+        .ok_or_else(|| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "sign in again to authorize the application"}),
+                http::StatusCode::FORBIDDEN,
+            )
+        })?;
+    if params.decision.as_deref() != Some("approve") {
+        info!(client = application.id, "authorization denied");
+        return finish(&application, &params, None);
+    }
+    let account = session.account;
+    let code = random_secret();
+    state
+        .put_authorization_code(&AuthorizationCode {
+            id: auth::digest(&code),
+            client: application.id.clone(),
+            account: account.clone(),
+            redirect_uri: params.redirect_uri.clone(),
+            scopes,
+            challenge: params.code_challenge.clone(),
+            expires: chrono::Utc::now() + chrono::Duration::minutes(CODE_LIFETIME_MINUTES),
+        })
+        .await?;
+    info!(client = application.id, account, "authorization granted");
+    finish(&application, &params, Some(&code))
+}
+
+#[derive(Deserialize)]
+pub struct LoginParams {
+    /// Authorization request to return to.
+    pub next: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    pub next: String,
+    pub token: String,
+}
+
+/// Authorization request to return to after signing in, which must be one of this server.
+fn next_page(local: &Local, next: &str) -> Result<url::Url, HttpError> {
+    let authorize = local.oauth("authorize");
+    url::Url::parse(next)
+        .ok()
+        .filter(|next| next.origin() == authorize.origin() && next.path() == authorize.path())
+        .ok_or_else(|| bad_request("next must be an authorization request"))
+}
+
+fn login_form(
+    local: &Local,
+    next: &url::Url,
+    error: Option<&str>,
+) -> Result<Html<String>, HttpError> {
+    render(
+        "login",
+        &json!({"action": local.oauth("login"), "next": next, "error": error}),
+    )
+}
+
+/// `GET /oauth/login`: sign-in page of the authorization pages.
+pub async fn login_page(
+    Query(params): Query<LoginParams>,
+    proxy_info: ProxyInfo,
+) -> Result<Html<String>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let next = next_page(&local, &params.next)?;
+    login_form(&local, &next, None)
+}
+
+/// `POST /oauth/login`: start a session and return to the authorization request. Since
+/// accounts have no passwords, an account signs in with an access token which has no scope
+/// restriction, as operators provision them. The token is only entered on this page, never
+/// on the consent page of a client.
+pub async fn login<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let next = next_page(&local, &form.next)?;
+    let account = state
+        .get_token(&auth::digest(form.token.trim()))
+        .await?
+        .filter(|token| token.scopes.is_none())
+        .and_then(|token| token.account);
+    let Some(account) = account else {
+        let page = login_form(&local, &next, Some("The access token is not valid."))?;
+        return Ok((http::StatusCode::UNAUTHORIZED, page).into_response());
+    };
+    let secret = random_secret();
+    state
+        .put_session(&Session {
+            id: auth::digest(&secret),
+            account: account.clone(),
+            csrf: random_secret(),
+            expires: chrono::Utc::now() + chrono::Duration::hours(SESSION_LIFETIME_HOURS),
+        })
+        .await?;
+    info!(account, "signed in");
+    let cookie = format!(
+        "{SESSION_COOKIE}={secret}; Path={}; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        local.oauth("").path(),
+        SESSION_LIFETIME_HOURS * 60 * 60,
+    );
+    Ok((
+        [(http::header::SET_COOKIE, cookie)],
+        Redirect::to(next.as_str()),
+    )
+        .into_response())
+}
+
+/// Pass the outcome of an authorization request to the client.
+fn finish(
+    application: &Application,
+    params: &AuthorizeParams,
+    code: Option<&str>,
+) -> Result<Response, HttpError> {
+    if params.redirect_uri == OOB {
+        let page = render(
+            "authorized",
+            &json!({"name": application.name, "code": code}),
+        )?;
+        return Ok(page.into_response());
+    }
+    let mut uri = url::Url::parse(&params.redirect_uri)
+        .map_err(|_| bad_request("the redirect URI is not valid"))?;
+    {
+        let mut query = uri.query_pairs_mut();
+        match code {
+            Some(code) => query.append_pair("code", code),
+            None => query.append_pair("error", "access_denied"),
+        };
+        if let Some(state) = &params.state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(Redirect::to(uri.as_str()).into_response())
+}
+
+/// Client id and secret of `client_secret_basic`, or else of `client_secret_post`.
+fn client_credentials(
+    headers: &http::HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<(String, Option<String>), HttpError> {
+    let basic = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "));
+    if let Some(basic) = basic {
+        let malformed = || oauth_error("invalid_client", "malformed basic authentication");
+        let decoded = STANDARD
+            .decode(basic.trim())
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .ok_or_else(malformed)?;
+        let (id, secret) = decoded.split_once(':').ok_or_else(malformed)?;
+        return Ok((id.to_string(), Some(secret.to_string())));
+    }
+    let client_id = client_id
+        .ok_or_else(|| oauth_error("invalid_client", "client authentication is required"))?;
+    Ok((client_id.to_string(), client_secret.map(str::to_string)))
+}
+
+/// Application which sent a request to the token or revocation endpoint, and whether it
+/// authenticated with its secret.
+async fn authenticate_client<S: OAuthState>(
+    state: &S,
+    headers: &http::HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<(Application, bool), HttpError> {
+    let (client_id, secret) = client_credentials(headers, client_id, client_secret)?;
+    let application = state
+        .get_application(&client_id)
+        .await?
+        .ok_or_else(|| oauth_error("invalid_client", "unknown client"))?;
+    match secret {
+        Some(secret) if auth::digest(&secret) != application.secret => {
+            Err(oauth_error("invalid_client", "the client secret is wrong"))
+        }
+        Some(_) => Ok((application, true)),
+        None => Ok((application, false)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub code_verifier: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TokenView {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub scope: String,
+    pub created_at: i64,
+}
+
+/// `POST /oauth/token`: issue a token for an authorization code, with PKCE for public clients,
+/// or for the client itself with the client credentials grant. Only the digest of the token
+/// is stored.
+pub async fn token<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    headers: http::HeaderMap,
+    FormOrJson(request): FormOrJson<TokenRequest>,
+) -> Result<impl IntoResponse, HttpError> {
+    let state = state.as_ref();
+    let (application, authenticated) = authenticate_client(
+        state,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    let (account, scopes) = match request.grant_type.as_str() {
+        "authorization_code" => {
+            let code = request
+                .code
+                .as_deref()
+                .ok_or_else(|| oauth_error("invalid_request", "code is required"))?;
+            let digest = auth::digest(code);
+            let invalid = || oauth_error("invalid_grant", "the code is not valid");
+            let grant = state
+                .get_authorization_code(&digest)
+                .await?
+                .filter(|grant| grant.client == application.id)
+                .filter(|grant| grant.expires > chrono::Utc::now())
+                .ok_or_else(invalid)?;
+            if request.redirect_uri.as_deref() != Some(grant.redirect_uri.as_str()) {
+                return Err(oauth_error(
+                    "invalid_grant",
+                    "the redirect URI does not match the authorization request",
+                ));
+            }
+            match &grant.challenge {
+                Some(challenge) => {
+                    let verifier = request.code_verifier.as_deref().ok_or_else(|| {
+                        oauth_error("invalid_request", "code_verifier is required")
+                    })?;
+                    if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != *challenge {
+                        return Err(oauth_error(
+                            "invalid_grant",
+                            "the code verifier does not match the challenge",
+                        ));
+                    }
+                }
+                None if !authenticated => {
+                    return Err(oauth_error(
+                        "invalid_client",
+                        "the client secret is required without PKCE",
+                    ));
+                }
+                None => {}
+            }
+            // The code is used up only by a request which may exchange it, and only once.
+            state
+                .take_authorization_code(&digest)
+                .await?
+                .ok_or_else(invalid)?;
+            (Some(grant.account), grant.scopes)
+        }
+        "client_credentials" => {
+            if !authenticated {
+                return Err(oauth_error(
+                    "invalid_client",
+                    "the client secret is required",
+                ));
+            }
+            let scopes = requested_scopes(&application, request.scope.as_deref())
+                .map_err(|msg| oauth_error("invalid_scope", &msg))?;
+            (None, scopes)
+        }
+        _ => {
+            return Err(oauth_error(
+                "unsupported_grant_type",
+                "only authorization_code and client_credentials are supported",
+            ));
+        }
+    };
+    let access_token = random_secret();
+    let created = chrono::Utc::now();
+    state
+        .put_token(&Token {
+            id: auth::digest(&access_token),
+            account,
+            client: Some(application.id.clone()),
+            scopes: Some(scopes.clone()),
+            created: Some(created),
+        })
+        .await?;
+    info!(
+        client = application.id,
+        grant = request.grant_type,
+        "token issued"
+    );
+    Ok((
+        [(http::header::CACHE_CONTROL, "no-store")],
+        Json(TokenView {
+            access_token,
+            token_type: "Bearer",
+            scope: scopes.join(" "),
+            created_at: created.timestamp(),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeRequest {
+    pub token: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// `POST /oauth/revoke`: revoke a token of the client, as in RFC 7009. Unknown tokens and
+/// tokens of other clients are ignored.
+pub async fn revoke<S: OAuthState>(
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    headers: http::HeaderMap,
+    FormOrJson(request): FormOrJson<RevokeRequest>,
+) -> Result<Json<serde_json::Value>, HttpError> {
+    let state = state.as_ref();
+    let (application, authenticated) = authenticate_client(
+        state,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    if !authenticated {
+        return Err(oauth_error(
+            "invalid_client",
+            "the client secret is required",
+        ));
+    }
+    let digest = auth::digest(&request.token);
+    let token = state.get_token(&digest).await?;
+    if token.is_some_and(|token| token.client.as_deref() == Some(application.id.as_str())) {
+        state.delete_token(&digest).await?;
+        info!(client = application.id, "token revoked");
+    }
+    Ok(Json(json!({})))
+}
+
+/// `GET /.well-known/oauth-authorization-server`: metadata of RFC 8414.
+pub async fn metadata(proxy_info: ProxyInfo) -> Result<Json<serde_json::Value>, HttpError> {
+    let local = Local::try_from(&proxy_info)?;
+    let registration = local.issuer().join("api/v1/apps").unwrap();
+    Ok(Json(json!({
+        "issuer": local.issuer(),
+        "authorization_endpoint": local.oauth("authorize"),
+        "token_endpoint": local.oauth("token"),
+        "revocation_endpoint": local.oauth("revoke"),
+        "registration_endpoint": registration,
+        "app_registration_endpoint": registration,
+        "scopes_supported": SCOPES,
+        "response_types_supported": ["code"],
+        "response_modes_supported": ["query"],
+        "grant_types_supported": ["authorization_code", "client_credentials"],
+        "token_endpoint_auth_methods_supported": [
+            "client_secret_basic",
+            "client_secret_post",
+            "none",
+        ],
+        "revocation_endpoint_auth_methods_supported": [
+            "client_secret_basic",
+            "client_secret_post",
+        ],
+        "code_challenge_methods_supported": ["S256"],
+    })))
+}
@@ -0,0 +1,341 @@
+use std::{future::Future, sync::Arc};
+
+use axum::response::IntoResponse;
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity,
+    block::BlockStore,
+    conversation::{self, ConversationStore},
+    delivery::{self, DeliveryQueue},
+    emoji,
+    fetch::{self, Remote},
+    follow::{self, FollowStore},
+    list::ListStore,
+    local::Local,
+    model::{actor::RemoteActor, notification::NotificationKind, object::StoredObject},
+    mute::MuteStore,
+    notification::{self, NotificationStore},
+    report,
+    signature::{self, SignedRequest},
+    stream::{Event, EventBus},
+    timeline::{self, TimelineStore},
+    visibility::{self, Viewer, Visibility},
+};
+
+pub static ACTIVITY_JSON: Lazy<mime::Mime> =
+    Lazy::new(|| "application/activity+json".parse().unwrap());
+
+pub trait ObjectStore {
+    fn get_object(
+        &self,
+        id: &url::Url,
+    ) -> impl Future<Output = Result<Option<StoredObject>, HttpError>> + Send;
+    fn put_object(
+        &self,
+        object: &StoredObject,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_object(&self, id: &url::Url) -> impl Future<Output = Result<(), HttpError>> + Send;
+    /// Stored objects replying to `parent`, in no particular order.
+    fn replies(
+        &self,
+        parent: &url::Url,
+    ) -> impl Future<Output = Result<Vec<StoredObject>, HttpError>> + Send;
+}
+
+pub async fn get_object<S>(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+) -> Result<impl IntoResponse, HttpError>
+where
+    S: ObjectStore + FollowStore + BlockStore + Remote + Sync,
+{
+    let iri = Local::try_from(&proxy_info)?.object(&id);
+    let not_found = || HttpError::new_json(&json!({"ok": false}), http::StatusCode::NOT_FOUND);
+    let object = state.get_object(&iri).await?.ok_or_else(not_found)?;
+    if report::is_suspended(state.as_ref(), &object.attributed_to).await? {
+        return Err(not_found());
+    }
+    // Authorized fetch: a signed GET identifies the actor asking for the object.
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    let viewer = match signature::authorize_fetch(state.as_ref(), request).await? {
+        Some(signer) => {
+            if state
+                .get_block(&object.attributed_to, &signer.id)
+                .await?
+                .is_some()
+            {
+                return Err(not_found());
+            }
+            Viewer::Actor(signer.id)
+        }
+        None => Viewer::Anonymous,
+    };
+    // Hidden objects are reported as missing so that their existence doesn't leak.
+    if !visibility::can_view(state.as_ref(), &object, &viewer).await? {
+        return Err(not_found());
+    }
+    Ok((
+        axum_helper::TypedHeader(axum_helper::headers::ContentType(ACTIVITY_JSON.clone())),
+        object.body,
+    ))
+}
+
+/// Fetch a remote object from its origin and store it.
+/// Used for objects which reach us through a third party, so nothing but the origin is trusted.
+pub async fn fetch_object<S>(state: &S, iri: &url::Url) -> Result<StoredObject, HttpError>
+where
+    S: ObjectStore + Remote + Sync,
+{
+    let bad_gateway = |msg: String| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": msg}),
+            http::StatusCode::BAD_GATEWAY,
+        )
+    };
+    let body = fetch::fetch_document(state, iri).await?;
+    let author = activity::field_iri(&body, "attributedTo")
+        .ok_or_else(|| bad_gateway("object has no attributedTo".to_string()))?;
+    if report::is_suspended(state, &author).await? {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "account is suspended"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    let actor = fetch::resolve_actor(state, &author).await?;
+    let followers = actor
+        .followers
+        .unwrap_or_else(|| follow::followers_collection(&actor.id));
+    emoji::ingest(state, iri, &body).await?;
+    let object = StoredObject::new(body, &followers).map_err(|e| bad_gateway(e.to_string()))?;
+    if object.id.origin() != iri.origin() || object.attributed_to.origin() != iri.origin() {
+        return Err(bad_gateway("object is not from its origin".to_string()));
+    }
+    state.put_object(&object).await?;
+    Ok(object)
+}
+
+/// Inbound `Create`. The object is stored, added to timelines, and direct messages are
+/// added to the conversations of local recipients which don't block the author.
+pub async fn handle_create<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    create: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + BlockStore
+        + ListStore
+        + MuteStore
+        + ConversationStore
+        + FollowStore
+        + NotificationStore
+        + TimelineStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    let body = match create.get("object") {
+        Some(body @ serde_json::Value::Object(_)) => body.clone(),
+        Some(object) => match activity::iri(object) {
+            Some(iri) => fetch::fetch_document(state, &iri).await?,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let followers = signer
+        .followers
+        .clone()
+        .unwrap_or_else(|| follow::followers_collection(&signer.id));
+    emoji::ingest(state, &signer.id, &body).await?;
+    let object = StoredObject::new(body, &followers)
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    if object.attributed_to != signer.id || object.id.origin() != signer.id.origin() {
+        info!(
+            actor = signer.id.to_string(),
+            object = object.id.to_string(),
+            "spoofed_create"
+        );
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "object is not attributed to the actor"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    state.put_object(&object).await?;
+    if object.visibility == Visibility::Direct {
+        for recipient in object.audience.recipients() {
+            let Some(name) = local.account_name(&recipient) else {
+                continue;
+            };
+            if state.get_block(&recipient, &signer.id).await?.is_some() {
+                continue;
+            }
+            conversation::track(state, local, &name, &object, true).await?;
+        }
+    }
+    timeline::try_distribute(state, local, &object, None).await;
+    notification::notify_post(state, local, &object).await;
+    Ok(())
+}
+
+fn is_closed_poll(body: &serde_json::Value) -> bool {
+    activity::kind(body) == Some("Question")
+        && body.get("closed").is_some_and(|closed| !closed.is_null())
+}
+
+/// Inbound `Update` of an object we have, such as an edited post.
+/// When it closes a poll, the local accounts the poll was addressed to are notified,
+/// as votes are not tracked.
+pub async fn handle_update<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    update: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + BlockStore
+        + ListStore
+        + MuteStore
+        + NotificationStore
+        + FollowStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    let Some(body @ serde_json::Value::Object(_)) = update.get("object") else {
+        return Ok(());
+    };
+    let Some(iri) = activity::iri(body) else {
+        return Ok(());
+    };
+    let Some(previous) = state.get_object(&iri).await? else {
+        debug!(object = iri.to_string(), "unknown_updated_object");
+        return Ok(());
+    };
+    let followers = signer
+        .followers
+        .clone()
+        .unwrap_or_else(|| follow::followers_collection(&signer.id));
+    emoji::ingest(state, &signer.id, body).await?;
+    let object = StoredObject::new(body.clone(), &followers)
+        .map_err(|e| json!({"ok": false, "msg": e.to_string()}))
+        .http_error_json(http::StatusCode::BAD_REQUEST)?;
+    if object.attributed_to != signer.id
+        || previous.attributed_to != signer.id
+        || object.id.origin() != signer.id.origin()
+    {
+        info!(
+            actor = signer.id.to_string(),
+            object = object.id.to_string(),
+            "spoofed_update"
+        );
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "object is not attributed to the actor"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    state.put_object(&object).await?;
+    timeline::try_announce(
+        state,
+        local,
+        &object,
+        Event::StatusUpdate(object.id.clone()),
+    )
+    .await;
+    let was_closed = serde_json::from_str(&previous.body).is_ok_and(|body| is_closed_poll(&body));
+    if is_closed_poll(body) && !was_closed {
+        let mut recipients = object.audience.recipients();
+        recipients.extend(notification::mentions(body));
+        for recipient in recipients {
+            let kind = NotificationKind::Poll;
+            notification::try_notify(state, local, &recipient, kind, &signer.id, Some(&object.id))
+                .await;
+        }
+    }
+    Ok(())
+}
+
+/// Inbound `Delete` of an object by its author. Its timeline entries stay behind and are
+/// skipped when read.
+pub async fn handle_delete<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    delete: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ObjectStore + FollowStore + BlockStore + ListStore + EventBus + Sync,
+{
+    let Some(iri) = activity::field_iri(delete, "object") else {
+        return Ok(());
+    };
+    let Some(object) = state.get_object(&iri).await? else {
+        debug!(object = iri.to_string(), "unknown_deleted_object");
+        return Ok(());
+    };
+    if object.attributed_to != signer.id {
+        info!(
+            actor = signer.id.to_string(),
+            object = object.id.to_string(),
+            "spoofed_delete"
+        );
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "object is not attributed to the actor"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    state.delete_object(&object.id).await?;
+    let event = Event::Delete(object.id.clone());
+    timeline::try_announce(state, local, &object, event).await;
+    Ok(())
+}
+
+/// Delete an object and tell timeline readers. The `Delete` is federated for local objects.
+pub async fn retract<S>(state: &S, local: &Local, object: &StoredObject) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    state.delete_object(&object.id).await?;
+    timeline::try_announce(state, local, object, Event::Delete(object.id.clone())).await;
+    if !local.is_local(&object.attributed_to) {
+        return Ok(());
+    }
+    let followers = follow::followers_collection(&object.attributed_to);
+    let recipients = visibility::delivery_recipients(state, object, &followers).await?;
+    let tombstone = json!({"id": object.id, "type": "Tombstone"});
+    let mut delete = activity::new_activity(local, "Delete", &object.attributed_to, tombstone);
+    delete["to"] = json!(object.audience.to);
+    delete["cc"] = json!(object.audience.cc);
+    delivery::deliver_activity(
+        state,
+        local,
+        &object.attributed_to,
+        delete,
+        recipients,
+        object.visibility,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+
+/// Images with more pixels than this are rejected, before and while decoding them.
+pub const MAX_PIXELS: u64 = 40_000_000;
+
+/// Longest side of an image, as a limit of the decoder.
+const MAX_SIDE: u32 = 16384;
+
+/// Number of BlurHash components along the x and y axes.
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+/// Largest side of the copy of an image the BlurHash is computed from.
+const BLURHASH_SAMPLE: u32 = 64;
+
+/// Quality of re-encoded JPEG images.
+const JPEG_QUALITY: u8 = 90;
+
+/// Encoded image with its dimensions.
+pub struct Encoded {
+    pub media_type: &'static str,
+    pub body: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Result of [process].
+pub struct Processed {
+    pub original: Encoded,
+    /// WebP previews, one per requested size that is smaller than the image.
+    pub thumbnails: Vec<Encoded>,
+    pub blurhash: String,
+}
+
+/// Whether [process] can handle images of `media_type`.
+pub fn supports(media_type: &str) -> bool {
+    format_of(media_type).is_some()
+}
+
+fn format_of(media_type: &str) -> Option<ImageFormat> {
+    match media_type {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/gif" => Some(ImageFormat::Gif),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Whether an image of `width` by `height` is too large to process.
+pub fn oversized(width: u64, height: u64) -> bool {
+    width.saturating_mul(height) > MAX_PIXELS || width > MAX_SIDE.into() || height > MAX_SIDE.into()
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Encoded, String> {
+    let mut body = Vec::new();
+    let media_type = match format {
+        ImageFormat::Jpeg => {
+            let encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut body, JPEG_QUALITY);
+            image
+                .to_rgb8()
+                .write_with_encoder(encoder)
+                .map_err(|e| e.to_string())?;
+            "image/jpeg"
+        }
+        ImageFormat::Png => {
+            image
+                .write_to(&mut Cursor::new(&mut body), ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+            "image/png"
+        }
+        _ => {
+            let encoder = image::codecs::webp::WebPEncoder::new_lossless(&mut body);
+            image
+                .to_rgba8()
+                .write_with_encoder(encoder)
+                .map_err(|e| e.to_string())?;
+            "image/webp"
+        }
+    };
+    Ok(Encoded {
+        media_type,
+        body,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// GIF application extensions which only say how often an animation loops.
+const LOOP_EXTENSIONS: &[&[u8]] = &[b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+
+/// Sub-blocks of a GIF block starting at `offset`, as the offset after their terminator.
+fn gif_sub_blocks(body: &[u8], mut offset: usize) -> Result<usize, String> {
+    loop {
+        let size = usize::from(*body.get(offset).ok_or("truncated GIF")?);
+        offset += 1 + size;
+        if size == 0 {
+            return Ok(offset);
+        }
+    }
+}
+
+/// Copy of a GIF without comment extensions and application extensions, such as XMP,
+/// other than the loop count of animations. Frames are copied byte for byte.
+pub fn strip_gif(body: &[u8]) -> Result<Vec<u8>, String> {
+    let color_table = |flags: u8| {
+        if flags & 0x80 == 0 {
+            0
+        } else {
+            3 << ((flags & 0x07) + 1)
+        }
+    };
+    let flags = *body.get(10).ok_or("truncated GIF")?;
+    let mut offset = 13 + color_table(flags);
+    let mut stripped = body.get(..offset).ok_or("truncated GIF")?.to_vec();
+    loop {
+        let start = offset;
+        match *body.get(offset).ok_or("truncated GIF")? {
+            // Trailer.
+            0x3b => {
+                stripped.push(0x3b);
+                return Ok(stripped);
+            }
+            // Image descriptor, its local color table and its LZW coded data.
+            0x2c => {
+                let flags = *body.get(offset + 9).ok_or("truncated GIF")?;
+                offset = gif_sub_blocks(body, offset + 10 + color_table(flags) + 1)?;
+            }
+            0x21 => {
+                let label = *body.get(offset + 1).ok_or("truncated GIF")?;
+                offset = gif_sub_blocks(body, offset + 2)?;
+                let keep = match label {
+                    0xfe => false,
+                    0xff => body
+                        .get(start + 3..start + 14)
+                        .is_some_and(|identifier| LOOP_EXTENSIONS.contains(&identifier)),
+                    _ => true,
+                };
+                if !keep {
+                    continue;
+                }
+            }
+            block => return Err(format!("unknown GIF block {block:#x}")),
+        }
+        stripped.extend_from_slice(body.get(start..offset).ok_or("truncated GIF")?);
+    }
+}
+
+/// Copy of a WebP file without its `EXIF` and `XMP ` chunks. The image data, which may be
+/// lossy or animated, is copied byte for byte.
+pub fn strip_webp(body: &[u8]) -> Result<Vec<u8>, String> {
+    if body.get(..4) != Some(b"RIFF") || body.get(8..12) != Some(b"WEBP") {
+        return Err("not a WebP file".to_string());
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset < body.len() {
+        let header = body.get(offset..offset + 8).ok_or("truncated WebP")?;
+        let size = u32::from_le_bytes(header[4..8].try_into().unwrap_or_default()) as usize;
+        // Chunks are padded to an even size.
+        let end = offset + 8 + size + size % 2;
+        let chunk = body
+            .get(offset..end.min(body.len()))
+            .ok_or("truncated WebP")?;
+        match &header[..4] {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let mut chunk = chunk.to_vec();
+                // Flags of the chunks which are dropped.
+                *chunk.get_mut(8).ok_or("truncated WebP")? &= !(0x08 | 0x04);
+                chunks.extend(chunk);
+            }
+            _ => chunks.extend_from_slice(chunk),
+        }
+        offset = end;
+    }
+    let size = u32::try_from(chunks.len() + 4).map_err(|e| e.to_string())?;
+    let mut stripped = Vec::with_capacity(chunks.len() + 12);
+    stripped.extend_from_slice(b"RIFF");
+    stripped.extend_from_slice(&size.to_le_bytes());
+    stripped.extend_from_slice(b"WEBP");
+    stripped.extend(chunks);
+    Ok(stripped)
+}
+
+/// Decode an uploaded image, rotate it upright and re-encode it without its metadata.
+/// GIF and WebP files are only stripped of their metadata, so that animations survive and
+/// lossy WebP images aren't blown up by the lossless encoder. WebP images are shown as their
+/// pixels are stored, since their EXIF orientation goes with the rest of the metadata.
+pub fn process(media_type: &str, body: Vec<u8>, sizes: &[u32]) -> Result<Processed, String> {
+    let format = format_of(media_type).ok_or("unsupported image type")?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SIDE);
+    limits.max_image_height = Some(MAX_SIDE);
+    let mut reader = ImageReader::with_format(Cursor::new(body.as_slice()), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+    if oversized(width.into(), height.into()) {
+        return Err(format!("image must be at most {MAX_PIXELS} pixels"));
+    }
+    let orientation = decoder.orientation().map_err(|e| e.to_string())?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    if format != ImageFormat::WebP {
+        image.apply_orientation(orientation);
+    }
+
+    let original = match format {
+        ImageFormat::Gif => Encoded {
+            media_type: "image/gif",
+            width: image.width(),
+            height: image.height(),
+            body: strip_gif(&body)?,
+        },
+        ImageFormat::WebP => Encoded {
+            media_type: "image/webp",
+            width: image.width(),
+            height: image.height(),
+            body: strip_webp(&body)?,
+        },
+        format => encode(&image, format)?,
+    };
+    let mut thumbnails = Vec::new();
+    for &size in sizes {
+        if size < image.width().max(image.height()) {
+            thumbnails.push(encode(&image.thumbnail(size, size), ImageFormat::WebP)?);
+        }
+    }
+    let sample = image.thumbnail(BLURHASH_SAMPLE, BLURHASH_SAMPLE);
+    Ok(Processed {
+        original,
+        thumbnails,
+        blurhash: blurhash(&sample, BLURHASH_COMPONENTS.0, BLURHASH_COMPONENTS.1),
+    })
+}
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+fn base83(hash: &mut String, value: u32, length: u32) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        hash.push(BASE83[digit as usize].into());
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = f64::from(value) / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn sign_pow(value: f64, exp: f64) -> f64 {
+    value.abs().powf(exp).copysign(value)
+}
+
+/// [BlurHash](https://blurha.sh/) of an image with `x` by `y` components.
+pub fn blurhash(image: &DynamicImage, x: u32, y: u32) -> String {
+    let image = image.to_rgb8();
+    let (width, height) = image.dimensions();
+    let pixels: Vec<[f64; 3]> = image
+        .pixels()
+        .map(|pixel| pixel.0.map(srgb_to_linear))
+        .collect();
+    let mut factors = Vec::with_capacity((x * y) as usize);
+    for j in 0..y {
+        for i in 0..x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for py in 0..height {
+                for px in 0..width {
+                    let basis = normalisation
+                        * (std::f64::consts::PI * f64::from(i) * f64::from(px) / f64::from(width))
+                            .cos()
+                        * (std::f64::consts::PI * f64::from(j) * f64::from(py) / f64::from(height))
+                            .cos();
+                    let pixel = pixels[(py * width + px) as usize];
+                    for c in 0..3 {
+                        factor[c] += basis * pixel[c];
+                    }
+                }
+            }
+            let scale = 1.0 / f64::from(width * height).max(1.0);
+            factors.push(factor.map(|v| v * scale));
+        }
+    }
+
+    let mut hash = String::new();
+    base83(&mut hash, (x - 1) + (y - 1) * 9, 1);
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let max_value = if ac.is_empty() {
+        base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let actual_max = ac.iter().flatten().fold(0.0f64, |max, v| max.max(v.abs()));
+        let quantised = (actual_max * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        base83(&mut hash, quantised, 1);
+        f64::from(quantised + 1) / 166.0
+    };
+    let [r, g, b] = dc.map(linear_to_srgb);
+    base83(&mut hash, (r << 16) + (g << 8) + b, 4);
+    for factor in ac {
+        let [r, g, b] = factor.map(|v| {
+            (sign_pow(v / max_value, 0.5) * 9.0 + 9.5)
+                .floor()
+                .clamp(0.0, 18.0) as u32
+        });
+        base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
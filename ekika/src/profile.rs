@@ -0,0 +1,412 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError, ToHttpErrorJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity, actor, ap,
+    auth::{Authenticated, TokenStore},
+    block::BlockStore,
+    delivery::{self, DeliveryQueue},
+    fetch::Remote,
+    follow::{self, FollowStore},
+    local::Local,
+    migration::MigrationStore,
+    model::{
+        account::{Account, ProfileField},
+        follow::FollowState,
+    },
+    proxy,
+    signature::KeyStore,
+    visibility::Visibility,
+    webfinger::AccountStore,
+};
+
+/// Number of profile fields an account can have.
+pub const MAX_FIELDS: usize = 4;
+
+pub trait ProfileStore {
+    fn put_account(
+        &self,
+        name: &str,
+        account: &Account,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Largest page read when looking for `rel="me"` links.
+pub const MAX_PAGE_SIZE: u64 = 1024 * 1024;
+
+/// Number of redirects followed from a field link.
+pub const MAX_REDIRECTS: usize = 5;
+
+/// Response to a single GET of a page linked from a profile.
+#[derive(Clone, Debug)]
+pub enum Page {
+    Html(String),
+    Redirect(url::Url),
+}
+
+/// HTTP client for web pages linked from profiles. It does not follow redirects, and
+/// fails for bodies larger than `limit` bytes.
+pub trait PageFetcher {
+    fn fetch_page(
+        &self,
+        url: &url::Url,
+        limit: u64,
+    ) -> impl Future<Output = Result<Page, HttpError>> + Send;
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Web page a field value points at, if any.
+pub fn link_of(value: &str) -> Option<url::Url> {
+    url::Url::parse(value.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// HTML of a field value as federated in `PropertyValue`. Links are marked `rel="me"`.
+pub fn field_html(value: &str) -> String {
+    match link_of(value) {
+        Some(url) => {
+            let href = escape(url.as_str());
+            format!(
+                r#"<a href="{href}" rel="me nofollow noopener noreferrer" target="_blank">{href}</a>"#
+            )
+        }
+        None => escape(value),
+    }
+}
+
+/// Attributes of the start tags named `a` or `link` in an HTML document.
+fn link_tags(html: &str) -> Vec<Vec<(String, String)>> {
+    let mut tags = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let name = rest[..name_len].to_ascii_lowercase();
+        rest = &rest[name_len..];
+        if name != "a" && name != "link" {
+            continue;
+        }
+        let mut attributes = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+            if rest.is_empty() || rest.starts_with('>') {
+                break;
+            }
+            let name_len = rest
+                .find(|c: char| c.is_ascii_whitespace() || matches!(c, '=' | '>' | '/'))
+                .unwrap_or(rest.len())
+                .max(1);
+            let name = rest[..name_len].to_ascii_lowercase();
+            rest = rest[name_len..].trim_start();
+            let Some(after_eq) = rest.strip_prefix('=') else {
+                attributes.push((name, String::new()));
+                continue;
+            };
+            rest = after_eq.trim_start();
+            let value = match rest.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = rest[1..].find(quote).map_or(rest.len(), |end| end + 1);
+                    let value = &rest[1..end];
+                    rest = rest.get(end + 1..).unwrap_or("");
+                    value
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                        .unwrap_or(rest.len());
+                    let value = &rest[..end];
+                    rest = &rest[end..];
+                    value
+                }
+            };
+            attributes.push((name, unescape(value)));
+        }
+        tags.push(attributes);
+    }
+    tags
+}
+
+/// Targets of the `rel="me"` links of a page, resolved against the page itself.
+pub fn me_links(page: &url::Url, html: &str) -> Vec<url::Url> {
+    link_tags(html)
+        .into_iter()
+        .filter(|attributes| {
+            attributes.iter().any(|(name, value)| {
+                name == "rel"
+                    && value
+                        .split_ascii_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("me"))
+            })
+        })
+        .filter_map(|attributes| {
+            let (_, href) = attributes.into_iter().find(|(name, _)| name == "href")?;
+            page.join(href.trim()).ok()
+        })
+        .collect()
+}
+
+/// Whether the page a field links to links back to one of `profiles` with `rel="me"`.
+/// Redirects are followed up to [MAX_REDIRECTS] times, and only to public addresses.
+async fn links_back<S>(state: &S, page: &url::Url, profiles: &[url::Url]) -> bool
+where
+    S: PageFetcher + Sync,
+{
+    let mut url = page.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        if !proxy::is_public_url(&url) {
+            debug!(page = url.to_string(), "rel_me_internal");
+            return false;
+        }
+        match state.fetch_page(&url, MAX_PAGE_SIZE).await {
+            Ok(Page::Redirect(next)) => url = next,
+            Ok(Page::Html(html)) => {
+                return html.len() as u64 <= MAX_PAGE_SIZE
+                    && me_links(&url, &html)
+                        .iter()
+                        .any(|link| profiles.contains(link))
+            }
+            Err(e) => {
+                debug!(
+                    page = url.to_string(),
+                    status = e.status.as_u16(),
+                    "rel_me_fetch"
+                );
+                return false;
+            }
+        }
+    }
+    debug!(page = page.to_string(), "rel_me_redirects");
+    false
+}
+
+/// Check the link fields of an account, which pages may link to through any of `profiles`,
+/// such as its actor IRI or profile page. A field which was verified before and still
+/// links back keeps its original timestamp.
+pub async fn verify_fields<S>(
+    state: &S,
+    profiles: &[url::Url],
+    previous: &[ProfileField],
+    fields: Vec<ProfileField>,
+) -> Vec<ProfileField>
+where
+    S: PageFetcher + Sync,
+{
+    let mut verified_fields = Vec::with_capacity(fields.len());
+    for field in fields {
+        let verified = match link_of(&field.value) {
+            Some(page) if links_back(state, &page, profiles).await => previous
+                .iter()
+                .find(|old| old.value == field.value)
+                .and_then(|old| old.verified)
+                .or_else(|| Some(chrono::Utc::now())),
+            _ => None,
+        };
+        verified_fields.push(ProfileField { verified, ..field });
+    }
+    verified_fields
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FieldView {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_deserializing)]
+    pub verified_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileView {
+    pub name: String,
+    pub summary: String,
+    pub icon: Vec<url::Url>,
+    pub header: Option<url::Url>,
+    pub fields: Vec<FieldView>,
+    pub locked: bool,
+    pub discoverable: bool,
+    pub bot: bool,
+}
+
+impl From<Account> for ProfileView {
+    fn from(account: Account) -> Self {
+        Self {
+            name: account.name,
+            summary: account.summary,
+            icon: account.icon,
+            header: account.header,
+            fields: account
+                .fields
+                .into_iter()
+                .map(|field| FieldView {
+                    name: field.name,
+                    value: field.value,
+                    verified_at: field.verified,
+                })
+                .collect(),
+            locked: account.locked,
+            discoverable: account.discoverable,
+            bot: account.bot,
+        }
+    }
+}
+
+/// Replacement of the editable parts of a profile.
+#[derive(Deserialize)]
+pub struct ProfileRequest {
+    pub name: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub icon: Vec<url::Url>,
+    #[serde(default)]
+    pub header: Option<url::Url>,
+    #[serde(default)]
+    pub fields: Vec<FieldView>,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub discoverable: bool,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+fn account_not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "account not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+pub async fn get_profile<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<ProfileView>, HttpError>
+where
+    S: AccountStore<ActorInfo = Account> + TokenStore + Send + Sync,
+{
+    let account = state
+        .query(&auth.account)
+        .await?
+        .ok_or_else(account_not_found)?;
+    Ok(Json(account.into()))
+}
+
+/// Send the new actor document to the followers of `name`.
+async fn announce_update<S>(
+    state: &S,
+    local: &Local,
+    name: &str,
+    account: &Account,
+) -> Result<(), HttpError>
+where
+    S: MigrationStore + KeyStore + FollowStore + BlockStore + Remote + DeliveryQueue + Sync,
+{
+    let id = local.actor(name);
+    let document = actor::render(state, local, name, account).await?;
+    let document = serde_json::to_value(document)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut activity = activity::new_activity(local, "Update", &id, document);
+    activity["to"] = json!([ap::PUBLIC]);
+    activity["cc"] = json!([follow::followers_collection(&id)]);
+    let recipients = state
+        .followers(&id)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.follower);
+    delivery::deliver_activity(
+        state,
+        local,
+        &id,
+        activity,
+        recipients,
+        Visibility::Unlisted,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn put_profile<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<ProfileRequest>,
+) -> Result<Json<ProfileView>, HttpError>
+where
+    S: AccountStore<ActorInfo = Account>
+        + ProfileStore
+        + PageFetcher
+        + MigrationStore
+        + KeyStore
+        + FollowStore
+        + BlockStore
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    if request.fields.len() > MAX_FIELDS {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": format!("at most {MAX_FIELDS} fields are allowed")}),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let account = state
+        .query(&auth.account)
+        .await?
+        .ok_or_else(account_not_found)?;
+    let fields = request
+        .fields
+        .into_iter()
+        .map(|field| ProfileField {
+            name: field.name,
+            value: field.value,
+            verified: None,
+        })
+        .collect();
+    // Mastodon links to the profile page, other software to the actor.
+    let profiles = [
+        local.actor(&auth.account),
+        local.profile_page(&auth.account),
+    ];
+    let fields = verify_fields(state, &profiles, &account.fields, fields).await;
+    let account = Account {
+        name: request.name,
+        summary: request.summary,
+        icon: request.icon,
+        header: request.header,
+        fields,
+        locked: request.locked,
+        discoverable: request.discoverable,
+        bot: request.bot,
+        ..account
+    };
+    state.put_account(&auth.account, &account).await?;
+    info!(account = auth.account, "profile_update");
+    announce_update(state, &local, &auth.account, &account).await?;
+    Ok(Json(account.into()))
+}
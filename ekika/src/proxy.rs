@@ -0,0 +1,346 @@
+use std::{
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::response::{IntoResponse, Response};
+use axum_helper::HttpError;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    domain::{self, DomainPolicyStore},
+    local::Local,
+    model::proxy::CachedMedia,
+    storage::{self, BlobStore, MediaFetcher},
+};
+
+/// Largest remote file the proxy downloads.
+pub const MAX_PROXY_SIZE: u64 = 40 * 1024 * 1024;
+
+/// How long clients may cache proxied files.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often the pruner runs.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum time between two updates of [CachedMedia::accessed].
+fn access_resolution() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub trait MediaCacheStore {
+    /// Secret which signs proxied URLs, so that the proxy is not open to anyone.
+    fn proxy_key(&self) -> &[u8];
+    fn cached_media(&self) -> impl Future<Output = Result<Vec<CachedMedia>, HttpError>> + Send;
+    fn get_cached(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<CachedMedia>, HttpError>> + Send;
+    fn put_cached(&self, media: &CachedMedia)
+        -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_cached(&self, id: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+fn sha256(body: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(body))
+}
+
+fn mac(key: &[u8], encoded: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(encoded.as_bytes());
+    mac
+}
+
+/// URL of `remote` through the media proxy. Local files are not proxied.
+pub fn url<S>(state: &S, local: &Local, remote: &url::Url) -> url::Url
+where
+    S: MediaCacheStore,
+{
+    if local.is_local(remote) {
+        return remote.clone();
+    }
+    let encoded = URL_SAFE_NO_PAD.encode(remote.as_str());
+    let signature =
+        URL_SAFE_NO_PAD.encode(mac(state.proxy_key(), &encoded).finalize().into_bytes());
+    local.media_proxy(&signature, &encoded)
+}
+
+/// Remote URL of a proxy path, if the signature matches.
+fn verify(key: &[u8], signature: &str, encoded: &str) -> Option<url::Url> {
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    mac(key, encoded).verify_slice(&signature).ok()?;
+    let remote = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded).ok()?).ok()?;
+    remote.parse().ok()
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || a >= 240
+        // Shared address space and benchmarking networks.
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_v4(ip);
+    }
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link local and documentation addresses.
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && second == 0x0db8))
+}
+
+/// Whether the server may connect to `ip` on behalf of remote input.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+/// Whether `url` is a web URL which does not point at an internal address by itself.
+/// Host names are checked when they are resolved by [PublicResolver].
+pub fn is_public_url(url: &url::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host() {
+        Some(url::Host::Ipv4(ip)) => is_public_v4(ip),
+        Some(url::Host::Ipv6(ip)) => is_public_v6(ip),
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => false,
+    }
+}
+
+/// DNS resolver which drops internal addresses, for clients fetching URLs from remote input.
+pub struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(format!("{host} has no public address").into());
+            }
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Redirect policy to use along with [PublicResolver].
+pub fn redirect_policy() -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= 5 {
+            attempt.error("too many redirects")
+        } else if !is_public_url(attempt.url()) {
+            attempt.error("redirect to an internal address")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+fn not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "media not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+/// Download `remote` and store it under the hash of its contents.
+async fn fetch<S>(
+    state: &S,
+    id: String,
+    remote: url::Url,
+) -> Result<(CachedMedia, Vec<u8>), HttpError>
+where
+    S: MediaCacheStore + BlobStore + MediaFetcher + Sync,
+{
+    if !is_public_url(&remote) {
+        return Err(not_found());
+    }
+    let media = state.fetch_media(&remote, MAX_PROXY_SIZE).await?;
+    // Only media which browsers render inertly is proxied, whatever the server claims.
+    let content_type = storage::sniff(&media.body)
+        .filter(|content_type| {
+            ["image/", "video/", "audio/"]
+                .iter()
+                .any(|prefix| content_type.starts_with(prefix))
+        })
+        .ok_or_else(|| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "unsupported media type"}),
+                http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            )
+        })?;
+    let digest = sha256(&media.body);
+    // Media types in `storage::sniff` all have an extension.
+    let extension = storage::extension_of(content_type).unwrap_or("bin");
+    let key = format!("proxy/{digest}.{extension}");
+    let size = media.body.len() as u64;
+    state
+        .put_blob(&key, content_type, media.body.clone())
+        .await?;
+    let now = chrono::Utc::now();
+    let cached = CachedMedia {
+        id,
+        domain: remote.host_str().unwrap_or_default().to_string(),
+        url: remote,
+        digest,
+        key,
+        content_type: content_type.to_string(),
+        size,
+        fetched: now,
+        accessed: now,
+    };
+    state.put_cached(&cached).await?;
+    info!(url = cached.url.to_string(), size, "media_cache_stored");
+    Ok((cached, media.body))
+}
+
+/// Serve a remote file from the cache, fetching it on the first request.
+pub async fn proxy<S>(
+    axum::extract::Path((signature, encoded)): axum::extract::Path<(String, String)>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    headers: http::HeaderMap,
+) -> Result<Response, HttpError>
+where
+    S: MediaCacheStore + BlobStore + MediaFetcher + DomainPolicyStore + Send + Sync,
+{
+    let state = state.as_ref();
+    let remote = verify(state.proxy_key(), &signature, &encoded).ok_or_else(not_found)?;
+    if domain::rejects_media(state, &remote).await? {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "media from this domain is rejected"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    let id = sha256(remote.as_str().as_bytes());
+    let cached = state.get_cached(&id).await?;
+    let body = match &cached {
+        Some(cached) => state.get_blob(&cached.key).await?,
+        None => None,
+    };
+    let (mut cached, body) = match cached.zip(body) {
+        Some(hit) => hit,
+        None => fetch(state, id, remote).await?,
+    };
+    let now = chrono::Utc::now();
+    if now - cached.accessed > access_resolution() {
+        cached.accessed = now;
+        state.put_cached(&cached).await?;
+    }
+
+    let etag = format!("\"{}\"", cached.digest);
+    let mut response_headers = http::HeaderMap::new();
+    let mut insert = |name, value: &str| {
+        if let Ok(value) = http::HeaderValue::from_str(value) {
+            response_headers.insert(name, value);
+        }
+    };
+    insert(http::header::CONTENT_TYPE, &cached.content_type);
+    insert(
+        http::header::CACHE_CONTROL,
+        &format!("public, max-age={}, immutable", MAX_AGE.as_secs()),
+    );
+    insert(http::header::ETAG, &etag);
+    insert(http::header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    insert(
+        http::header::CONTENT_SECURITY_POLICY,
+        "default-src 'none'; sandbox",
+    );
+    let not_modified = headers
+        .get(http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+    if not_modified {
+        return Ok((http::StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    Ok((response_headers, body).into_response())
+}
+
+/// Remove cached files older than `max_age` or from domains whose media is rejected,
+/// then the least recently used ones until the cache fits in `max_size` bytes.
+pub async fn prune<S>(state: &S, max_age: chrono::Duration, max_size: u64) -> Result<(), HttpError>
+where
+    S: MediaCacheStore + BlobStore + DomainPolicyStore + Sync,
+{
+    let now = chrono::Utc::now();
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for cached in state.cached_media().await? {
+        if now - cached.fetched > max_age || domain::rejects_media(state, &cached.url).await? {
+            removed.push(cached);
+        } else {
+            kept.push(cached);
+        }
+    }
+    kept.sort_by_key(|cached| std::cmp::Reverse(cached.accessed));
+    let mut total = 0;
+    let mut index = kept.len();
+    for (i, cached) in kept.iter().enumerate() {
+        total += cached.size;
+        if total > max_size {
+            index = i;
+            break;
+        }
+    }
+    removed.extend(kept.split_off(index));
+
+    let mut freed = 0;
+    for cached in &removed {
+        state.delete_cached(&cached.id).await?;
+        // The blob may be shared with other URLs serving the same contents.
+        if !kept.iter().any(|other| other.key == cached.key) {
+            state.delete_blob(&cached.key).await?;
+        }
+        freed += cached.size;
+    }
+    if !removed.is_empty() {
+        info!(removed = removed.len(), freed, "media_cache_pruned");
+    }
+    Ok(())
+}
+
+/// Prune the media cache every hour.
+pub async fn pruner<S>(state: Arc<S>, max_age: chrono::Duration, max_size: u64)
+where
+    S: MediaCacheStore + BlobStore + DomainPolicyStore + Send + Sync + 'static,
+{
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = prune(state.as_ref(), max_age, max_size).await {
+            warn!(status = e.status.as_u16(), "media_cache_prune");
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    block::{self, BlockStore},
+    delivery::{self, DeliveryQueue},
+    emoji::{self, EmojiStore},
+    fetch::Remote,
+    follow::FollowStore,
+    local::Local,
+    model::{
+        actor::RemoteActor, notification::NotificationKind, object::StoredObject,
+        reaction::Reaction,
+    },
+    mute::MuteStore,
+    notification::{self, NotificationStore},
+    object::ObjectStore,
+    stream::EventBus,
+    visibility::{self, Viewer, Visibility},
+};
+
+/// Reaction a `Like` without content stands for, as Misskey shows it.
+pub const DEFAULT_REACTION: &str = "⭐";
+
+pub trait ReactionStore {
+    fn reactions_of(
+        &self,
+        object: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Reaction>, HttpError>> + Send;
+    /// Reactions made by `actor`, in no particular order.
+    fn reactions_by(
+        &self,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<Vec<Reaction>, HttpError>> + Send;
+    fn get_reaction(
+        &self,
+        id: &url::Url,
+    ) -> impl Future<Output = Result<Option<Reaction>, HttpError>> + Send;
+    fn put_reaction(
+        &self,
+        reaction: &Reaction,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_reaction(&self, id: &url::Url) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Emoji a reaction is made with.
+enum ReactionEmoji<'a> {
+    Unicode(&'a str),
+    Custom(&'a str),
+}
+
+fn parse(content: &str) -> Option<ReactionEmoji<'_>> {
+    let content = content.trim();
+    if let Some(shortcode) = content
+        .strip_prefix(':')
+        .and_then(|content| content.strip_suffix(':'))
+    {
+        // Misskey writes reactions with remote emoji as `:shortcode@host:`.
+        let shortcode = shortcode.split('@').next().unwrap_or_default();
+        return emoji::is_shortcode(shortcode).then_some(ReactionEmoji::Custom(shortcode));
+    }
+    let plausible = !content.is_empty()
+        && content.len() <= 64
+        && !content
+            .chars()
+            .any(|c| c.is_ascii_alphanumeric() || c.is_whitespace());
+    plausible.then_some(ReactionEmoji::Unicode(content))
+}
+
+/// Existing reaction of `actor` to `object`.
+async fn reaction_of<S>(
+    state: &S,
+    object: &url::Url,
+    actor: &url::Url,
+) -> Result<Option<Reaction>, HttpError>
+where
+    S: ReactionStore + Sync,
+{
+    Ok(state
+        .reactions_of(object)
+        .await?
+        .into_iter()
+        .find(|reaction| &reaction.actor == actor))
+}
+
+/// Inbound `EmojiReact`, or `Like` with `content` or `_misskey_reaction` as Misskey sends them.
+/// A `Like` without either is stored as [DEFAULT_REACTION].
+pub async fn handle_react<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ReactionStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    let content = ["_misskey_reaction", "content"]
+        .into_iter()
+        .find_map(|name| activity.get(name).and_then(|content| content.as_str()))
+        .or_else(|| (activity::kind(activity) == Some("Like")).then_some(DEFAULT_REACTION));
+    let (Some(id), Some(object)) = (
+        activity::field_iri(activity, "id"),
+        activity::field_iri(activity, "object"),
+    ) else {
+        return Ok(());
+    };
+    if id.origin() != signer.id.origin() {
+        return Ok(());
+    }
+    let Some(object) = state.get_object(&object).await? else {
+        debug!(object = object.to_string(), "unknown_reacted_object");
+        return Ok(());
+    };
+    if !visibility::can_view(state, &object, &Viewer::Actor(signer.id.clone())).await?
+        || block::is_blocked_between(state, &object.attributed_to, &signer.id).await?
+    {
+        return Ok(());
+    }
+    let Some(content) = content else {
+        debug!(actor = signer.id.to_string(), "reaction_without_content");
+        return Ok(());
+    };
+    let (content, emoji) = match parse(content) {
+        Some(ReactionEmoji::Unicode(content)) => (content.to_string(), None),
+        Some(ReactionEmoji::Custom(shortcode)) => {
+            emoji::ingest(state, &signer.id, activity).await?;
+            let id = format!("{shortcode}@{}", signer.id.host_str().unwrap_or_default());
+            let emoji = state.get_emoji(&id).await?.map(|emoji| emoji.id);
+            (format!(":{shortcode}:"), emoji)
+        }
+        None => {
+            debug!(actor = signer.id.to_string(), content, "malformed_reaction");
+            return Ok(());
+        }
+    };
+    if let Some(previous) = reaction_of(state, &object.id, &signer.id).await? {
+        state.delete_reaction(&previous.id).await?;
+    }
+    state
+        .put_reaction(&Reaction {
+            id,
+            object: object.id.clone(),
+            actor: signer.id.clone(),
+            content,
+            emoji,
+            created: chrono::Utc::now(),
+        })
+        .await?;
+    let kind = NotificationKind::Favourite;
+    notification::try_notify(
+        state,
+        local,
+        &object.attributed_to,
+        kind,
+        &signer.id,
+        Some(&object.id),
+    )
+    .await;
+    Ok(())
+}
+
+/// Inbound `Undo` of an `EmojiReact` or `Like`.
+pub async fn handle_undo_react<S>(
+    state: &S,
+    signer: &RemoteActor,
+    undone: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ReactionStore + Sync,
+{
+    let reaction = match activity::field_iri(undone, "id") {
+        Some(id) => state.get_reaction(&id).await?,
+        None => None,
+    };
+    let reaction = match (reaction, activity::field_iri(undone, "object")) {
+        (Some(reaction), _) => Some(reaction),
+        (None, Some(object)) => reaction_of(state, &object, &signer.id).await?,
+        (None, None) => None,
+    };
+    match reaction {
+        Some(reaction) if reaction.actor == signer.id => state.delete_reaction(&reaction.id).await,
+        _ => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReactionCount {
+    pub content: String,
+    pub count: usize,
+    /// Image of a custom emoji.
+    pub url: Option<url::Url>,
+    /// Whether the viewer reacted with this emoji.
+    pub me: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ReactionQuery {
+    pub object: url::Url,
+}
+
+pub async fn visible_object<S>(
+    state: &S,
+    object: &url::Url,
+    viewer: &url::Url,
+) -> Result<StoredObject, HttpError>
+where
+    S: ObjectStore + FollowStore + Sync,
+{
+    let not_found = || {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "object not found"}),
+            http::StatusCode::NOT_FOUND,
+        )
+    };
+    let object = state.get_object(object).await?.ok_or_else(not_found)?;
+    if !visibility::can_view(state, &object, &Viewer::Actor(viewer.clone())).await? {
+        return Err(not_found());
+    }
+    Ok(object)
+}
+
+pub async fn list_reactions<S>(
+    auth: Authenticated,
+    axum::extract::Query(query): axum::extract::Query<ReactionQuery>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<ReactionCount>>, HttpError>
+where
+    S: ReactionStore + ObjectStore + FollowStore + EmojiStore + TokenStore + Send + Sync,
+{
+    let viewer = Local::try_from(&proxy_info)?.actor(&auth.account);
+    let object = visible_object(state.as_ref(), &query.object, &viewer).await?;
+    let mut counts = BTreeMap::<(String, Option<String>), ReactionCount>::new();
+    for reaction in state.reactions_of(&object.id).await? {
+        let count = counts
+            .entry((reaction.content.clone(), reaction.emoji.clone()))
+            .or_insert_with(|| ReactionCount {
+                content: reaction.content.clone(),
+                count: 0,
+                url: None,
+                me: false,
+            });
+        count.count += 1;
+        count.me |= reaction.actor == viewer;
+    }
+    for ((_, emoji), count) in counts.iter_mut() {
+        if let Some(emoji) = emoji {
+            count.url = state.get_emoji(emoji).await?.map(|emoji| emoji.image);
+        }
+    }
+    let mut counts = counts.into_values().collect::<Vec<_>>();
+    counts.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.content.cmp(&b.content))
+    });
+    Ok(Json(counts))
+}
+
+#[derive(Deserialize)]
+pub struct ReactRequest {
+    pub object: url::Url,
+    pub content: String,
+}
+
+/// Send `Undo` of a reaction of a local account.
+async fn undo<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+    reaction: &Reaction,
+) -> Result<(), HttpError>
+where
+    S: ReactionStore + BlockStore + Remote + DeliveryQueue + Sync,
+{
+    state.delete_reaction(&reaction.id).await?;
+    let original = json!({
+        "id": reaction.id,
+        "type": "Like",
+        "actor": reaction.actor,
+        "object": reaction.object,
+        "content": reaction.content,
+        "_misskey_reaction": reaction.content,
+    });
+    let mut activity = activity::new_activity(local, "Undo", &reaction.actor, original);
+    activity["to"] = json!([object.attributed_to]);
+    delivery::deliver_activity(
+        state,
+        local,
+        &reaction.actor,
+        activity,
+        [object.attributed_to.clone()],
+        Visibility::Direct,
+    )
+    .await?;
+    Ok(())
+}
+
+/// React to an object, replacing the previous reaction of the account to it.
+/// Reactions are federated as `Like` with `content` and `_misskey_reaction`, like Misskey does.
+pub async fn react<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<ReactRequest>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: ReactionStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let actor = local.actor(&auth.account);
+    let object = visible_object(state, &request.object, &actor).await?;
+    if block::is_blocked_between(state, &object.attributed_to, &actor).await? {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot react to the object"}),
+            http::StatusCode::FORBIDDEN,
+        ));
+    }
+    let bad_emoji = || {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "reaction must be an emoji"}),
+            http::StatusCode::BAD_REQUEST,
+        )
+    };
+    let (content, emoji) = match parse(&request.content).ok_or_else(bad_emoji)? {
+        ReactionEmoji::Unicode(content) => (content.to_string(), None),
+        ReactionEmoji::Custom(shortcode) => {
+            let emoji = state
+                .get_emoji(shortcode)
+                .await?
+                .filter(|emoji| emoji.domain.is_none())
+                .ok_or_else(bad_emoji)?;
+            (format!(":{shortcode}:"), Some(emoji))
+        }
+    };
+    if let Some(previous) = reaction_of(state, &object.id, &actor).await? {
+        if previous.content == content {
+            return Ok(http::StatusCode::NO_CONTENT);
+        }
+        undo(state, &local, &object, &previous).await?;
+    }
+    let mut activity = activity::new_activity(&local, "Like", &actor, json!(object.id));
+    activity["content"] = json!(content);
+    activity["_misskey_reaction"] = json!(content);
+    activity["to"] = json!([object.attributed_to]);
+    if let Some(emoji) = &emoji {
+        activity["tag"] = json!([emoji::document(emoji)]);
+    }
+    let reaction = Reaction {
+        id: activity::field_iri(&activity, "id").unwrap(),
+        object: object.id.clone(),
+        actor: actor.clone(),
+        content,
+        emoji: emoji.map(|emoji| emoji.id),
+        created: chrono::Utc::now(),
+    };
+    state.put_reaction(&reaction).await?;
+    let kind = NotificationKind::Favourite;
+    notification::try_notify(
+        state,
+        &local,
+        &object.attributed_to,
+        kind,
+        &actor,
+        Some(&object.id),
+    )
+    .await;
+    info!(
+        actor = actor.to_string(),
+        object = object.id.to_string(),
+        content = reaction.content,
+        "reaction"
+    );
+    delivery::deliver_activity(
+        state,
+        &local,
+        &actor,
+        activity,
+        [object.attributed_to.clone()],
+        Visibility::Direct,
+    )
+    .await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct UnreactRequest {
+    pub object: url::Url,
+}
+
+pub async fn unreact<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<UnreactRequest>,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: ReactionStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let actor = local.actor(&auth.account);
+    let object = visible_object(state, &request.object, &actor).await?;
+    if let Some(reaction) = reaction_of(state, &object.id, &actor).await? {
+        undo(state, &local, &object, &reaction).await?;
+    }
+    Ok(http::StatusCode::NO_CONTENT)
+}
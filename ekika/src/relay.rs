@@ -0,0 +1,334 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::{
+    activity, ap,
+    auth::{Admin, TokenStore},
+    block::BlockStore,
+    delivery::{DeliveryJob, DeliveryQueue},
+    domain,
+    fetch::{self, Remote},
+    follow::FollowStore,
+    list::ListStore,
+    local::Local,
+    model::{
+        account::Account,
+        actor::RemoteActor,
+        relay::{Relay, RelayKind, RelayState},
+    },
+    mute::MuteStore,
+    notification::NotificationStore,
+    object::{self, ObjectStore},
+    stream::EventBus,
+    timeline::{self, TimelineStore},
+    webfinger::AccountStore,
+};
+
+pub trait RelayStore {
+    fn relays(&self) -> impl Future<Output = Result<Vec<Relay>, HttpError>> + Send;
+    fn get_relay(&self, id: &str) -> impl Future<Output = Result<Option<Relay>, HttpError>> + Send;
+    fn put_relay(&self, relay: &Relay) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn delete_relay(&self, id: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Accepted relay whose actor is `actor`.
+pub async fn subscribed<S>(state: &S, actor: &url::Url) -> Result<Option<Relay>, HttpError>
+where
+    S: RelayStore + Sync,
+{
+    Ok(state
+        .relays()
+        .await?
+        .into_iter()
+        .find(|relay| relay.state == RelayState::Accepted && relay.actor.as_ref() == Some(actor)))
+}
+
+/// Inboxes of the relays which local public posts are pushed to.
+pub async fn push_inboxes<S>(state: &S) -> Result<Vec<url::Url>, HttpError>
+where
+    S: RelayStore + Sync,
+{
+    Ok(state
+        .relays()
+        .await?
+        .into_iter()
+        .filter(|relay| relay.push && relay.state == RelayState::Accepted)
+        .map(|relay| relay.inbox)
+        .collect())
+}
+
+/// Inbound `Accept` or `Reject` of a subscription sent by the instance actor.
+/// Mastodon relays answer from an actor we haven't seen before, so the `Follow` is matched by IRI.
+pub async fn handle_response<S>(
+    state: &S,
+    signer: &RemoteActor,
+    follow: &serde_json::Value,
+    accepted: bool,
+) -> Result<(), HttpError>
+where
+    S: RelayStore + Sync,
+{
+    let follow_id = activity::field_iri(follow, "id");
+    let relay = state.relays().await?.into_iter().find(|relay| {
+        follow_id.as_ref() == Some(&relay.activity)
+            || relay.actor.as_ref() == Some(&signer.id)
+            || (relay.actor.is_none() && relay.inbox.origin() == signer.id.origin())
+    });
+    let Some(mut relay) = relay else {
+        debug!(actor = signer.id.to_string(), "unknown_relay");
+        return Ok(());
+    };
+    relay.actor = Some(signer.id.clone());
+    relay.state = if accepted {
+        RelayState::Accepted
+    } else {
+        RelayState::Rejected
+    };
+    info!(inbox = relay.inbox.to_string(), state = ?relay.state, "relay");
+    state.put_relay(&relay).await
+}
+
+/// Store the object an activity from a relay refers to.
+/// Relays may not alter what they pass on, so the object is always fetched from its origin.
+async fn ingest<S>(state: &S, local: &Local, activity: &serde_json::Value) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + TimelineStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    let Some(iri) = activity::field_iri(activity, "object") else {
+        return Ok(());
+    };
+    match object::fetch_object(state, &iri).await {
+        Ok(object) => timeline::try_distribute(state, local, &object, None).await,
+        Err(e) => warn!(
+            object = iri.to_string(),
+            status = e.status.as_u16(),
+            "relayed_object"
+        ),
+    }
+    Ok(())
+}
+
+/// Inbound `Announce`. LitePub relays share posts by announcing them;
+/// from any other actor it is a boost.
+pub async fn handle_announce<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + TimelineStore
+        + Remote
+        + Sync,
+{
+    if subscribed(state, &signer.id).await?.is_none() {
+        return timeline::handle_announce(state, local, signer, activity).await;
+    }
+    ingest(state, local, activity).await
+}
+
+/// Activity of another actor forwarded by a relay. Mastodon relays pass on `Create`s as they are.
+pub async fn handle_forwarded<S>(
+    state: &S,
+    local: &Local,
+    relay: &Relay,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + TimelineStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    match activity::kind(activity) {
+        Some("Create" | "Announce") => ingest(state, local, activity).await,
+        kind => {
+            debug!(kind, relay = relay.inbox.to_string(), "unsupported_relayed");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RelayRequest {
+    /// Inbox of a Mastodon relay, or the actor of a LitePub relay.
+    pub url: url::Url,
+    pub kind: RelayKind,
+    #[serde(default)]
+    pub push: bool,
+}
+
+#[derive(Serialize)]
+pub struct RelayView {
+    pub id: String,
+    pub kind: RelayKind,
+    pub inbox: url::Url,
+    pub actor: Option<url::Url>,
+    pub state: RelayState,
+    pub push: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<Relay> for RelayView {
+    fn from(relay: Relay) -> Self {
+        Self {
+            id: relay.id,
+            kind: relay.kind,
+            inbox: relay.inbox,
+            actor: relay.actor,
+            state: relay.state,
+            push: relay.push,
+            created_at: relay.created,
+        }
+    }
+}
+
+pub async fn list_relays<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+) -> Result<Json<Vec<RelayView>>, HttpError>
+where
+    S: RelayStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut relays = state.relays().await?;
+    relays.sort_by_key(|relay| relay.created);
+    Ok(Json(relays.into_iter().map(Into::into).collect()))
+}
+
+pub async fn subscribe<S>(
+    _: Admin,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<RelayRequest>,
+) -> Result<Json<RelayView>, HttpError>
+where
+    S: RelayStore
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + AccountStore<ActorInfo = Account>
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let instance = local.instance_actor();
+    let (inbox, actor) = match request.kind {
+        RelayKind::Mastodon => (request.url, None),
+        RelayKind::LitePub => {
+            let actor = fetch::resolve_actor(state.as_ref(), &request.url).await?;
+            (actor.inbox, Some(actor.id))
+        }
+    };
+    domain::ensure_federates(state.as_ref(), &inbox).await?;
+    if state
+        .relays()
+        .await?
+        .iter()
+        .any(|relay| relay.inbox == inbox)
+    {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "already subscribed to the relay"}),
+            http::StatusCode::CONFLICT,
+        ));
+    }
+    let object = match &actor {
+        Some(actor) => json!(actor),
+        None => json!(ap::PUBLIC),
+    };
+    let follow = activity::new_activity(&local, "Follow", &instance, object);
+    let relay = Relay {
+        id: uuid::Uuid::new_v4().to_string(),
+        kind: request.kind,
+        inbox: inbox.clone(),
+        actor,
+        state: RelayState::Pending,
+        activity: activity::field_iri(&follow, "id").unwrap(),
+        push: request.push,
+        created: chrono::Utc::now(),
+    };
+    state.put_relay(&relay).await?;
+    state
+        .enqueue(DeliveryJob::new(inbox, instance, follow.to_string()))
+        .await?;
+    Ok(Json(relay.into()))
+}
+
+#[derive(Deserialize)]
+pub struct RelayUpdate {
+    pub push: bool,
+}
+
+pub async fn update_relay<S>(
+    _: Admin,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    Json(update): Json<RelayUpdate>,
+) -> Result<Json<RelayView>, HttpError>
+where
+    S: RelayStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let mut relay = state.get_relay(&id).await?.ok_or_else(relay_not_found)?;
+    relay.push = update.push;
+    state.put_relay(&relay).await?;
+    Ok(Json(relay.into()))
+}
+
+fn relay_not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "relay not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+pub async fn unsubscribe<S>(
+    _: Admin,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<http::StatusCode, HttpError>
+where
+    S: RelayStore + DeliveryQueue + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let relay = state.get_relay(&id).await?.ok_or_else(relay_not_found)?;
+    state.delete_relay(&id).await?;
+    let instance = local.instance_actor();
+    let object = match &relay.actor {
+        Some(actor) if relay.kind == RelayKind::LitePub => json!(actor),
+        _ => json!(ap::PUBLIC),
+    };
+    let original = json!({
+        "id": relay.activity,
+        "type": "Follow",
+        "actor": instance,
+        "object": object,
+    });
+    let undo = activity::new_activity(&local, "Undo", &instance, original);
+    state
+        .enqueue(DeliveryJob::new(relay.inbox, instance, undo.to_string()))
+        .await?;
+    Ok(http::StatusCode::NO_CONTENT)
+}
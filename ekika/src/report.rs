@@ -0,0 +1,386 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    activity,
+    auth::{Authenticated, Moderator, TokenStore},
+    block::BlockStore,
+    delivery::{self, DeliveryQueue},
+    domain,
+    fetch::Remote,
+    follow::FollowStore,
+    list::ListStore,
+    local::Local,
+    model::{
+        account::Account,
+        actor::RemoteActor,
+        domain::Severity,
+        report::{AccountPolicy, Category, Report, ReportAction, ReportState},
+    },
+    object::{self, ObjectStore},
+    stream::EventBus,
+    webfinger::AccountStore,
+};
+
+pub trait ReportStore {
+    fn reports(
+        &self,
+        state: ReportState,
+    ) -> impl Future<Output = Result<Vec<Report>, HttpError>> + Send;
+    fn get_report(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Report>, HttpError>> + Send;
+    fn put_report(&self, report: &Report) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub trait AccountPolicyStore {
+    fn get_account_policy(
+        &self,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<Option<AccountPolicy>, HttpError>> + Send;
+    fn put_account_policy(
+        &self,
+        policy: &AccountPolicy,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Suspended accounts neither send nor receive anything through this server.
+pub async fn is_suspended<S>(state: &S, actor: &url::Url) -> Result<bool, HttpError>
+where
+    S: AccountPolicyStore + Sync,
+{
+    Ok(matches!(
+        state.get_account_policy(actor).await?,
+        Some(AccountPolicy {
+            severity: Severity::Suspend,
+            ..
+        })
+    ))
+}
+
+/// Silenced accounts are kept off public timelines, like silenced domains.
+pub async fn is_silenced<S>(state: &S, actor: &url::Url) -> Result<bool, HttpError>
+where
+    S: AccountPolicyStore + Sync,
+{
+    Ok(matches!(
+        state.get_account_policy(actor).await?,
+        Some(AccountPolicy {
+            severity: Severity::Silence | Severity::Suspend,
+            ..
+        })
+    ))
+}
+
+/// Inbound `Flag` about local accounts or objects. Its `object` lists the reported
+/// accounts and objects; the first local account, or the author of the first local object,
+/// becomes the target.
+pub async fn handle_flag<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: ReportStore + ObjectStore + Remote + Sync,
+{
+    if matches!(domain::policy_for(state, &signer.id).await?, Some(policy) if policy.reject_reports)
+    {
+        info!(actor = signer.id.to_string(), "rejected_report");
+        return Ok(());
+    }
+    let flagged = match activity.get("object") {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(activity::iri).collect(),
+        Some(value) => activity::iri(value).into_iter().collect(),
+        None => Vec::new(),
+    };
+    let (accounts, objects): (Vec<_>, Vec<_>) = flagged
+        .into_iter()
+        .filter(|iri| local.is_local(iri))
+        .partition(|iri| local.account_name(iri).is_some());
+    let target = match accounts.into_iter().next() {
+        Some(target) => target,
+        None => match objects.first() {
+            Some(object) => match state.get_object(object).await? {
+                Some(object) => object.attributed_to,
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        },
+    };
+    let report = Report {
+        id: uuid::Uuid::new_v4().to_string(),
+        reporter: signer.id.clone(),
+        target,
+        objects,
+        category: Category::Other,
+        comment: activity
+            .get("content")
+            .and_then(|content| content.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        activity: activity::field_iri(activity, "id"),
+        state: ReportState::Open,
+        action: None,
+        resolved_by: None,
+        created: chrono::Utc::now(),
+        resolved: None,
+    };
+    info!(
+        id = report.id,
+        target = report.target.to_string(),
+        "report_received"
+    );
+    state.put_report(&report).await
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub target: url::Url,
+    #[serde(default)]
+    pub objects: Vec<url::Url>,
+    #[serde(default)]
+    pub category: Category,
+    #[serde(default)]
+    pub comment: String,
+    /// Also send the report to the target's server.
+    #[serde(default)]
+    pub forward: bool,
+}
+
+#[derive(Serialize)]
+pub struct ReportView {
+    pub id: String,
+    pub reporter: url::Url,
+    pub target: url::Url,
+    pub objects: Vec<url::Url>,
+    pub category: Category,
+    pub comment: String,
+    pub forwarded: bool,
+    pub action: Option<ReportAction>,
+    pub resolved_by: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ReportView {
+    fn new(report: Report, local: &Local) -> Self {
+        Self {
+            forwarded: local.is_local(&report.reporter) && report.activity.is_some(),
+            id: report.id,
+            reporter: report.reporter,
+            target: report.target,
+            objects: report.objects,
+            category: report.category,
+            comment: report.comment,
+            action: report.action,
+            resolved_by: report.resolved_by,
+            created_at: report.created,
+            resolved_at: report.resolved,
+        }
+    }
+}
+
+pub async fn create_report<S>(
+    auth: Authenticated,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<ReportRequest>,
+) -> Result<Json<ReportView>, HttpError>
+where
+    S: ReportStore + ObjectStore + Remote + DeliveryQueue + TokenStore + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let reporter = local.actor(&auth.account);
+    if reporter == request.target {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "cannot report yourself"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    for object in &request.objects {
+        let attributed = state
+            .get_object(object)
+            .await?
+            .is_some_and(|object| object.attributed_to == request.target);
+        if !attributed {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("{object} is not by the reported account")}),
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+            ));
+        }
+    }
+    let mut report = Report {
+        id: uuid::Uuid::new_v4().to_string(),
+        reporter,
+        target: request.target,
+        objects: request.objects,
+        category: request.category,
+        comment: request.comment,
+        activity: None,
+        state: ReportState::Open,
+        action: None,
+        resolved_by: None,
+        created: chrono::Utc::now(),
+        resolved: None,
+    };
+    // Forwarded reports come from the instance actor so that the reporter stays anonymous.
+    if request.forward && !local.is_local(&report.target) {
+        let instance = local.instance_actor();
+        let mut flagged = vec![report.target.clone()];
+        flagged.extend(report.objects.iter().cloned());
+        let mut flag = activity::new_activity(&local, "Flag", &instance, json!(flagged));
+        flag["content"] = json!(report.comment);
+        report.activity = activity::field_iri(&flag, "id");
+        delivery::enqueue_to_actor(state.as_ref(), &instance, flag, &report.target).await?;
+    }
+    state.put_report(&report).await?;
+    Ok(Json(ReportView::new(report, &local)))
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    #[serde(default)]
+    pub resolved: bool,
+}
+
+pub async fn list_reports<S>(
+    _: Moderator,
+    axum::extract::Query(query): axum::extract::Query<ReportQuery>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<Vec<ReportView>>, HttpError>
+where
+    S: ReportStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let report_state = if query.resolved {
+        ReportState::Resolved
+    } else {
+        ReportState::Open
+    };
+    let mut reports = state.reports(report_state).await?;
+    reports.sort_by_key(|report| report.created);
+    Ok(Json(
+        reports
+            .into_iter()
+            .map(|report| ReportView::new(report, &local))
+            .collect(),
+    ))
+}
+
+fn report_not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "report not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+pub async fn get_report<S>(
+    _: Moderator,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<ReportView>, HttpError>
+where
+    S: ReportStore + TokenStore + AccountStore<ActorInfo = Account> + Send + Sync,
+{
+    let report = state.get_report(&id).await?.ok_or_else(report_not_found)?;
+    Ok(Json(ReportView::new(
+        report,
+        &Local::try_from(&proxy_info)?,
+    )))
+}
+
+/// Remove the reported objects. Local ones are also deleted on the servers they were delivered to.
+async fn delete_objects<S>(state: &S, local: &Local, report: &Report) -> Result<(), HttpError>
+where
+    S: ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + Sync,
+{
+    for id in &report.objects {
+        if let Some(object) = state.get_object(id).await? {
+            object::retract(state, local, &object).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    pub action: ReportAction,
+}
+
+pub async fn resolve_report<S>(
+    moderator: Moderator,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    Json(request): Json<ResolveRequest>,
+) -> Result<Json<ReportView>, HttpError>
+where
+    S: ReportStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + EventBus
+        + Remote
+        + DeliveryQueue
+        + TokenStore
+        + AccountStore<ActorInfo = Account>
+        + Send
+        + Sync,
+{
+    let local = Local::try_from(&proxy_info)?;
+    let mut report = state.get_report(&id).await?.ok_or_else(report_not_found)?;
+    if report.state == ReportState::Resolved {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "report is already resolved"}),
+            http::StatusCode::CONFLICT,
+        ));
+    }
+    let severity = match request.action {
+        ReportAction::Dismiss => None,
+        ReportAction::DeletePosts => {
+            delete_objects(state.as_ref(), &local, &report).await?;
+            None
+        }
+        ReportAction::Silence => Some(Severity::Silence),
+        ReportAction::Suspend => Some(Severity::Suspend),
+    };
+    if let Some(severity) = severity {
+        state
+            .put_account_policy(&AccountPolicy {
+                actor: report.target.clone(),
+                severity,
+                report: Some(report.id.clone()),
+                created: chrono::Utc::now(),
+            })
+            .await?;
+    }
+    report.state = ReportState::Resolved;
+    report.action = Some(request.action);
+    report.resolved_by = Some(moderator.account);
+    report.resolved = Some(chrono::Utc::now());
+    state.put_report(&report).await?;
+    info!(
+        id = report.id,
+        action = ?request.action,
+        target = report.target.to_string(),
+        "report_resolved"
+    );
+    Ok(Json(ReportView::new(report, &local)))
+}
@@ -0,0 +1,218 @@
+use std::{future::Future, sync::Arc};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    domain,
+    fetch::{self, Remote},
+    follow::FollowStore,
+    local::Local,
+    model::account::Account,
+    object::{self, ObjectStore},
+    search::{AccountResult, SearchResults},
+    util::rate_limit::RateLimiter,
+    visibility::{self, Viewer},
+    webfinger::AccountStore,
+};
+
+/// Lookups an account may make per [LOOKUP_WINDOW].
+pub const LOOKUPS_PER_WINDOW: u32 = 30;
+
+pub const LOOKUP_WINDOW: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+const ACTOR_TYPES: &[&str] = &["Person", "Service", "Group", "Organization", "Application"];
+
+/// Client for WebFinger lookups on remote servers.
+pub trait WebfingerFetcher {
+    /// GET a JRD document. The client must not reach internal addresses.
+    fn fetch_webfinger(
+        &self,
+        url: &url::Url,
+    ) -> impl Future<Output = Result<serde_json::Value, HttpError>> + Send;
+    /// Limit of the lookups of each account.
+    fn lookup_limiter(&self) -> &RateLimiter;
+}
+
+/// What a search box input refers to.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Target {
+    Handle { user: String, host: String },
+    Url(url::Url),
+}
+
+/// Recognize `@user@host`, `user@host`, `acct:user@host` and web URLs.
+pub fn parse(query: &str) -> Option<Target> {
+    let query = query.trim();
+    if let Ok(url) = url::Url::parse(query) {
+        if matches!(url.scheme(), "http" | "https") {
+            return Some(Target::Url(url));
+        }
+    }
+    let handle = query.strip_prefix("acct:").unwrap_or(query);
+    let handle = handle.strip_prefix('@').unwrap_or(handle);
+    let (user, host) = handle.split_once('@')?;
+    let valid_user = !user.is_empty()
+        && user
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    let valid_host = !host.is_empty()
+        && url::Url::parse(&format!("https://{host}/"))
+            .ok()
+            .and_then(|url| url.host_str().map(|parsed| parsed == host.to_lowercase()))
+            .unwrap_or(false);
+    (valid_user && valid_host).then(|| Target::Handle {
+        user: user.to_string(),
+        host: host.to_lowercase(),
+    })
+}
+
+fn not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "nothing was found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+/// Actor IRI announced by the WebFinger document of `user@host`.
+pub async fn webfinger<S>(state: &S, user: &str, host: &str) -> Result<url::Url, HttpError>
+where
+    S: WebfingerFetcher + Sync,
+{
+    let mut url: url::Url = format!("https://{host}/.well-known/webfinger")
+        .parse()
+        .map_err(|_| not_found())?;
+    url.query_pairs_mut()
+        .append_pair("resource", &format!("acct:{user}@{host}"));
+    let document = state.fetch_webfinger(&url).await?;
+    let links = document
+        .get("links")
+        .and_then(|links| links.as_array())
+        .ok_or_else(not_found)?;
+    links
+        .iter()
+        .filter(|link| link.get("rel").and_then(|rel| rel.as_str()) == Some("self"))
+        .filter(|link| {
+            link.get("type")
+                .and_then(|kind| kind.as_str())
+                .is_some_and(|kind| {
+                    kind == "application/activity+json" || kind.starts_with("application/ld+json")
+                })
+        })
+        .find_map(|link| link.get("href")?.as_str()?.parse().ok())
+        .ok_or_else(not_found)
+}
+
+/// Import what `target` refers to and return it like a search result.
+async fn resolve<S>(
+    state: &S,
+    local: &Local,
+    viewer: &url::Url,
+    target: Target,
+) -> Result<SearchResults, HttpError>
+where
+    S: WebfingerFetcher
+        + AccountStore<ActorInfo = Account>
+        + ObjectStore
+        + FollowStore
+        + Remote
+        + Sync,
+{
+    let mut results = SearchResults::default();
+    let iri = match target {
+        Target::Handle { user, host } if host == local.host() => {
+            let account = state.query(&user).await?.ok_or_else(not_found)?;
+            results
+                .accounts
+                .push(AccountResult::local(local, &user, account));
+            return Ok(results);
+        }
+        Target::Handle { user, host } => {
+            let iri = webfinger(state, &user, &host).await?;
+            let actor = fetch::refetch_actor(state, &iri).await?;
+            results.accounts.push(actor.into());
+            return Ok(results);
+        }
+        Target::Url(url) => url,
+    };
+
+    let object = if local.is_local(&iri) {
+        if let Some(name) = local.account_name(&iri) {
+            let account = state.query(&name).await?.ok_or_else(not_found)?;
+            results
+                .accounts
+                .push(AccountResult::local(local, &name, account));
+            return Ok(results);
+        }
+        state.get_object(&iri).await?.ok_or_else(not_found)?
+    } else {
+        // Fetched like any other remote document, through the client which refuses
+        // internal addresses, so that a pasted URL cannot probe the internal network.
+        let document = fetch::fetch_document(state, &iri).await?;
+        // The URL may be a permalink which serves a document with another id.
+        let id = activity::iri(&document).ok_or_else(not_found)?;
+        domain::ensure_federates(state, &id).await?;
+        if activity::kind(&document).is_some_and(|kind| ACTOR_TYPES.contains(&kind)) {
+            let actor = fetch::refetch_actor(state, &id).await?;
+            results.accounts.push(actor.into());
+            return Ok(results);
+        }
+        match state.get_object(&id).await? {
+            Some(object) => object,
+            None => object::fetch_object(state, &id).await?,
+        }
+    };
+    if !visibility::can_view(state, &object, &Viewer::Actor(viewer.clone())).await? {
+        return Err(not_found());
+    }
+    if let Ok(body) = serde_json::from_str(&object.body) {
+        results.posts.push(body);
+    }
+    Ok(results)
+}
+
+#[derive(Deserialize)]
+pub struct ResolveParams {
+    pub q: String,
+}
+
+/// Look up a handle with WebFinger, or fetch a URL as ActivityPub, and import the result.
+pub async fn resolve_search<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<ResolveParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<SearchResults>, HttpError>
+where
+    S: WebfingerFetcher
+        + AccountStore<ActorInfo = Account>
+        + ObjectStore
+        + FollowStore
+        + Remote
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let target = parse(&params.q).ok_or_else(|| {
+        HttpError::new_json(
+            &json!({"ok": false, "msg": "query is neither a handle nor a URL"}),
+            http::StatusCode::BAD_REQUEST,
+        )
+    })?;
+    let state = state.as_ref();
+    if !state.lookup_limiter().check(&auth.account).await {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "too many lookups, try again later"}),
+            http::StatusCode::TOO_MANY_REQUESTS,
+        ));
+    }
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    info!(account = auth.account, query = params.q, "resolve_search");
+    Ok(Json(resolve(state, &local, &viewer, target).await?))
+}
@@ -0,0 +1,771 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    auth::{Authenticated, TokenStore},
+    fetch::ActorStore,
+    follow::FollowStore,
+    local::Local,
+    model::{account::Account, actor::RemoteActor, follow::FollowState, object::StoredObject},
+    object::ObjectStore,
+    visibility::{self, Viewer, Visibility},
+    webfinger::AccountStore,
+};
+
+/// Largest page of search results.
+pub const MAX_RESULTS: usize = 40;
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentKind {
+    Post,
+    Account,
+    Hashtag,
+}
+
+/// Entry of the search index.
+///
+/// Ids are the IRI of posts and remote actors, `@name` for local accounts and `#name`
+/// (in lowercase) for hashtags.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SearchDocument {
+    pub id: String,
+    pub kind: DocumentKind,
+    pub text: String,
+    /// Author of a post.
+    pub author: Option<url::Url>,
+    /// Visibility of a post. Accounts and hashtags have none and are visible to everyone.
+    pub visibility: Option<Visibility>,
+    /// Actors a post is addressed to, including its author.
+    #[serde(default)]
+    pub readers: Vec<url::Url>,
+    pub published: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub struct SearchQuery {
+    pub text: String,
+    pub kind: Option<DocumentKind>,
+    pub viewer: Option<url::Url>,
+    /// Actors the viewer follows, whose followers-only posts it can see.
+    pub following: Vec<url::Url>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct SearchHit {
+    pub id: String,
+    pub kind: DocumentKind,
+}
+
+pub trait SearchIndex {
+    fn index(
+        &self,
+        document: &SearchDocument,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn remove(&self, id: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn search(
+        &self,
+        query: &SearchQuery,
+    ) -> impl Future<Output = Result<Vec<SearchHit>, HttpError>> + Send;
+}
+
+/// Text of an HTML fragment with the tags removed and the entities decoded.
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        rest = rest[start..]
+            .find('>')
+            .map_or("", |end| &rest[start + end + 1..]);
+    }
+    text.push_str(rest);
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Whether `c` belongs to a script written without spaces between words, i.e. Chinese
+/// characters and kana.
+fn unspaced(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30ff}'
+            | '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{ff66}'..='\u{ff9f}'
+    )
+}
+
+/// Lowercase words of `text`, the unit of matching of the embedded index. Runs of Chinese
+/// characters and kana are split into overlapping pairs of characters, so that a word within
+/// a sentence can be found without knowing where the words of the sentence end.
+pub fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+    {
+        let chars = word.chars().collect::<Vec<_>>();
+        for run in chars.chunk_by(|a, b| unspaced(*a) == unspaced(*b)) {
+            if !unspaced(run[0]) {
+                tokens.push(run.iter().collect::<String>().to_lowercase());
+            } else if run.len() == 1 {
+                tokens.push(run[0].to_string());
+            } else {
+                tokens.extend(run.windows(2).map(|pair| pair.iter().collect()));
+            }
+        }
+    }
+    tokens
+}
+
+fn strings(value: Option<&serde_json::Value>) -> Vec<&str> {
+    match value {
+        Some(serde_json::Value::String(text)) => vec![text],
+        Some(serde_json::Value::Object(map)) => map.values().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Names of the `Hashtag` tags of a document, without `#` and in lowercase.
+pub fn hashtags(document: &serde_json::Value) -> Vec<String> {
+    let tags = match document.get("tag") {
+        Some(serde_json::Value::Array(tags)) => tags.iter().collect(),
+        Some(tag) => vec![tag],
+        None => Vec::new(),
+    };
+    tags.into_iter()
+        .filter(|tag| tag.get("type").and_then(|kind| kind.as_str()) == Some("Hashtag"))
+        .filter_map(|tag| tag.get("name")?.as_str())
+        .map(|name| name.trim_start_matches('#').to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Document of a stored post.
+pub fn post_document(object: &StoredObject) -> Option<SearchDocument> {
+    let body: serde_json::Value = serde_json::from_str(&object.body).ok()?;
+    let mut text = Vec::new();
+    for field in ["name", "summary", "content"] {
+        text.extend(strings(body.get(field)).into_iter().map(plain_text));
+    }
+    text.extend(strings(body.get("contentMap")).into_iter().map(plain_text));
+    let published = body
+        .get("published")
+        .and_then(|published| published.as_str())
+        .and_then(|published| chrono::DateTime::parse_from_rfc3339(published).ok())
+        .map(|published| published.to_utc());
+    let mut readers = object.audience.recipients().into_iter().collect::<Vec<_>>();
+    readers.push(object.attributed_to.clone());
+    Some(SearchDocument {
+        id: object.id.to_string(),
+        kind: DocumentKind::Post,
+        text: text.join("\n"),
+        author: Some(object.attributed_to.clone()),
+        visibility: Some(object.visibility),
+        readers,
+        published,
+    })
+}
+
+fn account_document(
+    id: String,
+    username: Option<&str>,
+    name: Option<&str>,
+    summary: Option<&str>,
+) -> SearchDocument {
+    let text = [username, name]
+        .into_iter()
+        .flatten()
+        .map(ToString::to_string)
+        .chain(summary.map(plain_text))
+        .collect::<Vec<_>>();
+    SearchDocument {
+        id,
+        kind: DocumentKind::Account,
+        text: text.join("\n"),
+        author: None,
+        visibility: None,
+        readers: Vec::new(),
+        published: None,
+    }
+}
+
+/// Document of a local account. The bio is only indexed for discoverable accounts.
+pub fn local_account_document(name: &str, account: &Account) -> SearchDocument {
+    account_document(
+        format!("@{name}"),
+        Some(name),
+        Some(&account.name),
+        Some(account.summary.as_str()).filter(|_| account.discoverable),
+    )
+}
+
+pub fn remote_actor_document(actor: &RemoteActor) -> SearchDocument {
+    account_document(
+        actor.id.to_string(),
+        actor.preferred_username.as_deref(),
+        actor.name.as_deref(),
+        actor.summary.as_deref(),
+    )
+}
+
+fn hashtag_document(name: &str) -> SearchDocument {
+    SearchDocument {
+        id: format!("#{name}"),
+        kind: DocumentKind::Hashtag,
+        text: name.to_string(),
+        author: None,
+        visibility: None,
+        readers: Vec::new(),
+        published: None,
+    }
+}
+
+fn warn_on_error(id: &str, result: Result<(), HttpError>) {
+    if let Err(e) = result {
+        warn!(id, status = e.status.as_u16(), "search_index");
+    }
+}
+
+/// Index a stored post and, for public posts, its hashtags.
+/// Failures are logged since the post itself is already stored.
+pub async fn index_object<S>(state: &S, object: &StoredObject)
+where
+    S: SearchIndex + Sync,
+{
+    let Some(document) = post_document(object) else {
+        return;
+    };
+    warn_on_error(&document.id, state.index(&document).await);
+    if object.visibility != Visibility::Public {
+        return;
+    }
+    let Ok(body) = serde_json::from_str(&object.body) else {
+        return;
+    };
+    for name in hashtags(&body) {
+        let document = hashtag_document(&name);
+        warn_on_error(&document.id, state.index(&document).await);
+    }
+}
+
+pub async fn remove_object<S>(state: &S, id: &url::Url)
+where
+    S: SearchIndex + Sync,
+{
+    warn_on_error(id.as_str(), state.remove(id.as_str()).await);
+}
+
+pub async fn index_local_account<S>(state: &S, name: &str, account: &Account)
+where
+    S: SearchIndex + Sync,
+{
+    let document = local_account_document(name, account);
+    warn_on_error(&document.id, state.index(&document).await);
+}
+
+pub async fn index_remote_actor<S>(state: &S, actor: &RemoteActor)
+where
+    S: SearchIndex + Sync,
+{
+    let document = remote_actor_document(actor);
+    warn_on_error(&document.id, state.index(&document).await);
+}
+
+/// Whether the viewer of `query` may find `document`.
+/// Only public posts are found by everyone; unlisted ones are treated like followers-only
+/// posts since they are meant to stay off discovery features.
+fn visible(document: &SearchDocument, query: &SearchQuery) -> bool {
+    let visibility = match document.visibility {
+        None | Some(Visibility::Public) => return true,
+        Some(visibility) => visibility,
+    };
+    let Some(viewer) = &query.viewer else {
+        return false;
+    };
+    document.readers.contains(viewer)
+        || (matches!(visibility, Visibility::Unlisted | Visibility::FollowersOnly)
+            && document
+                .author
+                .as_ref()
+                .is_some_and(|author| query.following.contains(author)))
+}
+
+#[derive(Default)]
+struct EmbeddedState {
+    documents: HashMap<String, (SearchDocument, Vec<String>)>,
+    /// Documents containing each token.
+    postings: BTreeMap<String, HashSet<String>>,
+}
+
+impl EmbeddedState {
+    fn remove(&mut self, id: &str) {
+        let Some((_, tokens)) = self.documents.remove(id) else {
+            return;
+        };
+        for token in tokens {
+            if let Some(ids) = self.postings.get_mut(&token) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Documents with a token starting with `prefix`.
+    fn matching(&self, prefix: &str) -> HashSet<&str> {
+        self.postings
+            .range(prefix.to_string()..)
+            .take_while(|(token, _)| token.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+/// In-process index for single-node deployments and tests. It is empty after a restart.
+/// Every query word must match the start of a word of the document.
+#[derive(Default)]
+pub struct Embedded {
+    state: RwLock<EmbeddedState>,
+}
+
+impl SearchIndex for Embedded {
+    async fn index(&self, document: &SearchDocument) -> Result<(), HttpError> {
+        let mut tokens = tokens(&document.text);
+        tokens.sort();
+        tokens.dedup();
+        let mut state = self.state.write().unwrap();
+        state.remove(&document.id);
+        for token in &tokens {
+            state
+                .postings
+                .entry(token.clone())
+                .or_default()
+                .insert(document.id.clone());
+        }
+        state
+            .documents
+            .insert(document.id.clone(), (document.clone(), tokens));
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), HttpError> {
+        self.state.write().unwrap().remove(id);
+        Ok(())
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, HttpError> {
+        let words = tokens(&query.text);
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let state = self.state.read().unwrap();
+        let mut candidates = state.matching(&words[0]);
+        for word in &words[1..] {
+            let matching = state.matching(word);
+            candidates.retain(|id| matching.contains(id));
+        }
+        let mut hits = candidates
+            .into_iter()
+            .filter_map(|id| state.documents.get(id))
+            .filter(|(document, _)| query.kind.is_none_or(|kind| kind == document.kind))
+            .filter(|(document, _)| visible(document, query))
+            .map(|(document, tokens)| {
+                let exact = words.iter().filter(|word| tokens.contains(word)).count();
+                (exact, document)
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by(|(a_exact, a), (b_exact, b)| {
+            b_exact
+                .cmp(a_exact)
+                .then_with(|| b.published.cmp(&a.published))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        Ok(hits
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit)
+            .map(|(_, document)| SearchHit {
+                id: document.id.clone(),
+                kind: document.kind,
+            })
+            .collect())
+    }
+}
+
+/// Index of an OpenSearch cluster, used through its REST API. Requests are not signed, so
+/// the cluster must accept them unauthenticated, such as one in a private network or behind
+/// a proxy which signs them. Amazon OpenSearch Service domains requiring SigV4 are not
+/// supported directly.
+pub struct OpenSearch {
+    pub client: reqwest::Client,
+    /// URL of the cluster, ending with a slash.
+    pub base: url::Url,
+    pub index: String,
+}
+
+fn search_error(e: impl ToString) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": e.to_string()}),
+        http::StatusCode::BAD_GATEWAY,
+    )
+}
+
+impl OpenSearch {
+    fn url(&self, path: &str) -> Result<url::Url, HttpError> {
+        self.base
+            .join(&format!("{}/{path}", self.index))
+            .map_err(search_error)
+    }
+
+    fn document_url(&self, id: &str) -> Result<url::Url, HttpError> {
+        let mut url = self.url("_doc/")?;
+        url.path_segments_mut()
+            .map_err(|_| search_error("invalid OpenSearch URL"))?
+            .pop()
+            .push(id);
+        Ok(url)
+    }
+
+    /// Create the index with its mappings unless it exists.
+    pub async fn ensure_index(&self) -> Result<(), HttpError> {
+        let url = self.base.join(&self.index).map_err(search_error)?;
+        let exists = self
+            .client
+            .head(url.clone())
+            .send()
+            .await
+            .map_err(search_error)?
+            .status()
+            .is_success();
+        if exists {
+            return Ok(());
+        }
+        let mappings = json!({
+            "mappings": {
+                "properties": {
+                    "kind": {"type": "keyword"},
+                    "text": {"type": "text"},
+                    "author": {"type": "keyword"},
+                    "visibility": {"type": "keyword"},
+                    "readers": {"type": "keyword"},
+                    "published": {"type": "date"},
+                }
+            }
+        });
+        self.client
+            .put(url)
+            .json(&mappings)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(search_error)?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Hits,
+}
+
+#[derive(Deserialize)]
+struct Hits {
+    hits: Vec<Hit>,
+}
+
+#[derive(Deserialize)]
+struct Hit {
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(rename = "_source")]
+    source: HitSource,
+}
+
+#[derive(Deserialize)]
+struct HitSource {
+    kind: DocumentKind,
+}
+
+/// Filter of [visible] in the query DSL.
+fn visibility_filter(query: &SearchQuery) -> serde_json::Value {
+    let mut should = vec![
+        json!({"bool": {"must_not": {"exists": {"field": "visibility"}}}}),
+        json!({"term": {"visibility": "Public"}}),
+    ];
+    if let Some(viewer) = &query.viewer {
+        should.push(json!({"term": {"readers": viewer}}));
+        if !query.following.is_empty() {
+            should.push(json!({"bool": {"filter": [
+                {"terms": {"visibility": ["Unlisted", "FollowersOnly"]}},
+                {"terms": {"author": query.following}},
+            ]}}));
+        }
+    }
+    json!({"bool": {"should": should, "minimum_should_match": 1}})
+}
+
+impl SearchIndex for OpenSearch {
+    async fn index(&self, document: &SearchDocument) -> Result<(), HttpError> {
+        self.client
+            .put(self.document_url(&document.id)?)
+            .json(document)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(search_error)?;
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), HttpError> {
+        let response = self
+            .client
+            .delete(self.document_url(id)?)
+            .send()
+            .await
+            .map_err(search_error)?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            response.error_for_status().map_err(search_error)?;
+        }
+        Ok(())
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, HttpError> {
+        let mut filter = vec![visibility_filter(query)];
+        if let Some(kind) = query.kind {
+            filter.push(json!({"term": {"kind": kind}}));
+        }
+        let body = json!({
+            "from": query.offset,
+            "size": query.limit,
+            "_source": ["kind"],
+            "query": {"bool": {
+                "must": [{"simple_query_string": {
+                    "query": query.text,
+                    "fields": ["text"],
+                    "default_operator": "and",
+                }}],
+                "filter": filter,
+            }},
+            "sort": ["_score", {"published": {"order": "desc", "missing": "_last"}}],
+        });
+        let response: SearchResponse = self
+            .client
+            .post(self.url("_search")?)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(search_error)?
+            .json()
+            .await
+            .map_err(search_error)?;
+        Ok(response
+            .hits
+            .hits
+            .into_iter()
+            .map(|hit| SearchHit {
+                id: hit.id,
+                kind: hit.source.kind,
+            })
+            .collect())
+    }
+}
+
+/// Search backend chosen at startup.
+pub enum SearchBackend {
+    OpenSearch(OpenSearch),
+    Embedded(Embedded),
+}
+
+impl SearchIndex for SearchBackend {
+    async fn index(&self, document: &SearchDocument) -> Result<(), HttpError> {
+        match self {
+            SearchBackend::OpenSearch(index) => index.index(document).await,
+            SearchBackend::Embedded(index) => index.index(document).await,
+        }
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), HttpError> {
+        match self {
+            SearchBackend::OpenSearch(index) => index.remove(id).await,
+            SearchBackend::Embedded(index) => index.remove(id).await,
+        }
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, HttpError> {
+        match self {
+            SearchBackend::OpenSearch(index) => index.search(query).await,
+            SearchBackend::Embedded(index) => index.search(query).await,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    #[serde(rename = "type")]
+    pub kind: Option<DocumentKind>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Serialize)]
+pub struct AccountResult {
+    pub id: url::Url,
+    pub username: Option<String>,
+    pub name: Option<String>,
+    pub summary: Option<String>,
+}
+
+impl AccountResult {
+    pub fn local(local: &Local, name: &str, account: Account) -> Self {
+        Self {
+            id: local.actor(name),
+            username: Some(name.to_string()),
+            name: Some(account.name),
+            summary: Some(account.summary),
+        }
+    }
+}
+
+impl From<RemoteActor> for AccountResult {
+    fn from(actor: RemoteActor) -> Self {
+        Self {
+            id: actor.id,
+            username: actor.preferred_username,
+            name: actor.name,
+            summary: actor.summary,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HashtagResult {
+    pub name: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct SearchResults {
+    pub accounts: Vec<AccountResult>,
+    /// Documents of the posts.
+    pub posts: Vec<serde_json::Value>,
+    pub hashtags: Vec<HashtagResult>,
+}
+
+/// Resolve hits into results, dropping the ones which are gone or not visible anymore.
+async fn results<S>(
+    state: &S,
+    local: &Local,
+    viewer: &url::Url,
+    hits: Vec<SearchHit>,
+) -> Result<SearchResults, HttpError>
+where
+    S: ObjectStore + ActorStore + AccountStore<ActorInfo = Account> + FollowStore + Sync,
+{
+    let mut results = SearchResults::default();
+    for hit in hits {
+        match hit.kind {
+            DocumentKind::Post => {
+                let Ok(id) = hit.id.parse() else {
+                    continue;
+                };
+                let Some(object) = state.get_object(&id).await? else {
+                    continue;
+                };
+                if !visibility::can_view(state, &object, &Viewer::Actor(viewer.clone())).await? {
+                    continue;
+                }
+                if let Ok(body) = serde_json::from_str(&object.body) {
+                    results.posts.push(body);
+                }
+            }
+            DocumentKind::Account => match hit.id.strip_prefix('@') {
+                Some(name) => {
+                    let Some(account) = state.query(name).await? else {
+                        continue;
+                    };
+                    results
+                        .accounts
+                        .push(AccountResult::local(local, name, account));
+                }
+                None => {
+                    let Ok(id) = hit.id.parse() else {
+                        continue;
+                    };
+                    let Some(actor) = state.get_actor(&id).await? else {
+                        continue;
+                    };
+                    results.accounts.push(actor.into());
+                }
+            },
+            DocumentKind::Hashtag => results.hashtags.push(HashtagResult {
+                name: hit.id.trim_start_matches('#').to_string(),
+            }),
+        }
+    }
+    Ok(results)
+}
+
+pub async fn search<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<SearchParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+) -> Result<Json<SearchResults>, HttpError>
+where
+    S: SearchIndex
+        + ObjectStore
+        + ActorStore
+        + AccountStore<ActorInfo = Account>
+        + FollowStore
+        + TokenStore
+        + Send
+        + Sync,
+{
+    if params.q.trim().is_empty() {
+        return Err(HttpError::new_json(
+            &json!({"ok": false, "msg": "query is empty"}),
+            http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    let local = Local::try_from(&proxy_info)?;
+    let state = state.as_ref();
+    let viewer = local.actor(&auth.account);
+    let following = state
+        .following(&viewer)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.followee)
+        .collect();
+    let query = SearchQuery {
+        text: params.q,
+        kind: params.kind,
+        viewer: Some(viewer.clone()),
+        following,
+        limit: params.limit.unwrap_or(20).clamp(1, MAX_RESULTS),
+        offset: params.offset,
+    };
+    let hits = state.search(&query).await?;
+    Ok(Json(results(state, &local, &viewer, hits).await?))
+}
@@ -0,0 +1,335 @@
+use std::{collections::HashMap, future::Future};
+
+use axum_helper::{HttpError, ToHttpErrorJson};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rsa::{
+    pkcs1::DecodeRsaPublicKey,
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+    signature::{SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::{self, FederationMode},
+    fetch::{self, Remote},
+    model::{actor::RemoteActor, key::KeyPair},
+};
+
+const CLOCK_SKEW: chrono::TimeDelta = chrono::TimeDelta::hours(12);
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignatureError {
+    #[error("rsa: {0}")]
+    Rsa(#[from] rsa::Error),
+    #[error("pkcs8: {0}")]
+    Pkcs8(#[from] rsa::pkcs8::Error),
+    #[error("spki: {0}")]
+    Spki(#[from] rsa::pkcs8::spki::Error),
+    #[error("invalid header value")]
+    InvalidHeader,
+}
+
+pub trait KeyStore {
+    fn get_key(
+        &self,
+        actor: &url::Url,
+    ) -> impl Future<Output = Result<Option<KeyPair>, HttpError>> + Send;
+    fn put_key(&self, key: &KeyPair) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+pub fn generate(actor: &url::Url) -> Result<KeyPair, SignatureError> {
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?;
+    let public_key_pem = private_key
+        .to_public_key()
+        .to_public_key_pem(LineEnding::LF)?;
+    let private_key_pem = private_key.to_pkcs8_pem(LineEnding::LF)?.to_string();
+    Ok(KeyPair {
+        id: actor.clone(),
+        private_key_pem,
+        public_key_pem,
+    })
+}
+
+/// Key pair of a local actor, generated and stored on first use.
+pub async fn key_of<S>(state: &S, actor: &url::Url) -> Result<KeyPair, HttpError>
+where
+    S: KeyStore + Sync,
+{
+    if let Some(key) = state.get_key(actor).await? {
+        return Ok(key);
+    }
+    let owner = actor.clone();
+    let key = tokio::task::spawn_blocking(move || generate(&owner))
+        .await
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.put_key(&key).await?;
+    Ok(key)
+}
+
+pub fn digest_header(body: &[u8]) -> String {
+    format!("SHA-256={}", STANDARD.encode(Sha256::digest(body)))
+}
+
+/// Sign a request with draft-cavage HTTP Signatures (`rsa-sha256`).
+/// `Date`, `Host` and (for requests with a body) `Digest` are set before signing.
+pub fn sign(
+    request: &mut reqwest::Request,
+    key_id: &url::Url,
+    key: &KeyPair,
+) -> Result<(), SignatureError> {
+    let headers = request.headers_mut();
+    let date = chrono::Utc::now()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    headers.insert(
+        http::header::DATE,
+        date.parse().map_err(|_| SignatureError::InvalidHeader)?,
+    );
+    let host = request.url().host_str().unwrap_or_default().to_string();
+    let host = match request.url().port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    };
+    let body = request.body().and_then(|body| body.as_bytes());
+    let digest = body.map(digest_header);
+    let target = format!(
+        "{} {}",
+        request.method().as_str().to_lowercase(),
+        &request.url()[url::Position::BeforePath..url::Position::AfterQuery]
+    );
+    let mut signed = vec![("(request-target)", target), ("host", host.clone())];
+    signed.push(("date", date));
+    if let Some(digest) = &digest {
+        signed.push(("digest", digest.clone()));
+    }
+    let signing_string = signed
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let private_key = RsaPrivateKey::from_pkcs8_pem(&key.private_key_pem)?;
+    let signature = SigningKey::<Sha256>::new(private_key).sign(signing_string.as_bytes());
+    let header = format!(
+        r#"keyId="{key_id}",algorithm="rsa-sha256",headers="{}",signature="{}""#,
+        signed
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" "),
+        STANDARD.encode(signature.to_bytes())
+    );
+    let headers = request.headers_mut();
+    headers.insert(
+        http::header::HOST,
+        host.parse().map_err(|_| SignatureError::InvalidHeader)?,
+    );
+    if let Some(digest) = digest {
+        headers.insert(
+            "Digest",
+            digest.parse().map_err(|_| SignatureError::InvalidHeader)?,
+        );
+    }
+    headers.insert(
+        "Signature",
+        header.parse().map_err(|_| SignatureError::InvalidHeader)?,
+    );
+    Ok(())
+}
+
+pub struct SignedRequest<'a> {
+    pub method: &'a http::Method,
+    pub uri: &'a http::Uri,
+    pub headers: &'a http::HeaderMap,
+    pub body: Option<&'a [u8]>,
+}
+
+struct SignatureHeader {
+    key_id: url::Url,
+    headers: Vec<String>,
+    signature: Vec<u8>,
+}
+
+fn parse_signature_header(value: &str) -> Option<SignatureHeader> {
+    let fields = value
+        .split(',')
+        .map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            Some((name.trim(), value.trim().trim_matches('"')))
+        })
+        .collect::<Option<HashMap<_, _>>>()?;
+    Some(SignatureHeader {
+        key_id: fields.get("keyId")?.parse().ok()?,
+        headers: fields
+            .get("headers")
+            .unwrap_or(&"date")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect(),
+        signature: STANDARD.decode(fields.get("signature")?).ok()?,
+    })
+}
+
+fn unauthorized(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::UNAUTHORIZED,
+    )
+}
+
+fn signing_string(request: &SignedRequest, names: &[String]) -> Option<String> {
+    names
+        .iter()
+        .map(|name| {
+            if name == "(request-target)" {
+                let path = request
+                    .uri
+                    .path_and_query()
+                    .map(|path| path.as_str())
+                    .unwrap_or("/");
+                Some(format!(
+                    "(request-target): {} {path}",
+                    request.method.as_str().to_lowercase()
+                ))
+            } else {
+                let values = request
+                    .headers
+                    .get_all(name.as_str())
+                    .iter()
+                    .map(|value| value.to_str().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                if values.is_empty() {
+                    return None;
+                }
+                Some(format!("{name}: {}", values.join(", ")))
+            }
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|lines| lines.join("\n"))
+}
+
+fn verify_with(actor: &RemoteActor, header: &SignatureHeader, signing_string: &str) -> bool {
+    if actor.public_key_id.as_ref() != Some(&header.key_id) {
+        return false;
+    }
+    let Some(pem) = &actor.public_key_pem else {
+        return false;
+    };
+    let Ok(public_key) =
+        RsaPublicKey::from_public_key_pem(pem).or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+    else {
+        return false;
+    };
+    let Ok(signature) = Signature::try_from(header.signature.as_slice()) else {
+        return false;
+    };
+    VerifyingKey::<Sha256>::new(public_key)
+        .verify(signing_string.as_bytes(), &signature)
+        .is_ok()
+}
+
+/// Verify the HTTP signature of an incoming request and return the signing actor.
+pub async fn verify<S>(state: &S, request: SignedRequest<'_>) -> Result<RemoteActor, HttpError>
+where
+    S: Remote + Sync,
+{
+    let header = request
+        .headers
+        .get("Signature")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_signature_header)
+        .ok_or_else(|| unauthorized("missing or malformed signature"))?;
+    if !header.headers.iter().any(|name| name == "(request-target)")
+        || !header.headers.iter().any(|name| name == "date")
+    {
+        return Err(unauthorized("(request-target) and date must be signed"));
+    }
+    let date = request
+        .headers
+        .get(http::header::DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok())
+        .ok_or_else(|| unauthorized("missing or malformed date"))?;
+    if (chrono::Utc::now() - date.to_utc()).abs() > CLOCK_SKEW {
+        return Err(unauthorized("date out of range"));
+    }
+    if let Some(body) = request.body {
+        if !header.headers.iter().any(|name| name == "digest") {
+            return Err(unauthorized("digest must be signed"));
+        }
+        let expected = digest_header(body);
+        let matched = request
+            .headers
+            .get("Digest")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(',').any(|digest| digest.trim() == expected))
+            .unwrap_or(false);
+        if !matched {
+            return Err(unauthorized("digest mismatch"));
+        }
+    }
+    let signing_string = signing_string(&request, &header.headers)
+        .ok_or_else(|| unauthorized("signed header is missing"))?;
+    let mut owner = header.key_id.clone();
+    owner.set_fragment(None);
+    let actor = fetch::resolve_actor(state, &owner).await?;
+    if verify_with(&actor, &header, &signing_string) {
+        return Ok(actor);
+    }
+    // The key may have been rotated since the actor was cached.
+    let actor = fetch::refetch_actor(state, &owner).await?;
+    if verify_with(&actor, &header, &signing_string) {
+        Ok(actor)
+    } else {
+        Err(unauthorized("signature mismatch"))
+    }
+}
+
+/// Signer of a GET for a local document, if it is signed. Every ActivityPub and WebFinger GET
+/// goes through here: in allowlist mode such fetches must be signed by an allowed server, so
+/// that only they can read local content. The instance actor is the one exception, see
+/// [authorize_key_fetch].
+pub async fn authorize_fetch<S>(
+    state: &S,
+    request: SignedRequest<'_>,
+) -> Result<Option<RemoteActor>, HttpError>
+where
+    S: Remote + Sync,
+{
+    if !request.headers.contains_key("Signature") {
+        if state.federation_mode() == FederationMode::Allowlist {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": "signature required"}),
+                http::StatusCode::FORBIDDEN,
+            ));
+        }
+        return Ok(None);
+    }
+    let signer = verify(state, request).await?;
+    domain::ensure_federates(state, &signer.id).await?;
+    Ok(Some(signer))
+}
+
+/// Policy for GETs of the instance actor, which carries the key of our own signed fetches.
+/// Remote servers fetch it unsigned, or signed with a key they can only resolve after this
+/// fetch succeeded, so unsigned fetches are always served and a signature is not verified.
+/// In allowlist mode a signature still names its server, which must be allowed.
+pub async fn authorize_key_fetch<S>(state: &S, request: SignedRequest<'_>) -> Result<(), HttpError>
+where
+    S: Remote + Sync,
+{
+    let header = request
+        .headers
+        .get("Signature")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_signature_header);
+    if let Some(header) = header {
+        domain::ensure_federates(state, &header.key_id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::{future::Future, path::PathBuf};
+
+use aws_sdk_s3::primitives::ByteStream;
+use axum_helper::{HttpError, ToHttpErrorJson};
+use serde_json::json;
+
+/// Object storage for files served to clients, such as media and custom emoji.
+pub trait BlobStore {
+    /// Store `body` under `key` and return the public URL of it.
+    fn put_blob(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> impl Future<Output = Result<url::Url, HttpError>> + Send;
+    fn get_blob(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, HttpError>> + Send;
+    fn delete_blob(&self, key: &str) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// File downloaded from a remote server.
+pub struct Media {
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Plain HTTP client for remote media.
+pub trait MediaFetcher {
+    /// Download `url`, failing when it is larger than `limit` bytes.
+    fn fetch_media(
+        &self,
+        url: &url::Url,
+        limit: u64,
+    ) -> impl Future<Output = Result<Media, HttpError>> + Send;
+}
+
+fn public_url(base: &url::Url, key: &str) -> Result<url::Url, HttpError> {
+    base.join(key)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Bucket of S3 or a compatible service such as MinIO, served publicly from `base`.
+pub struct S3 {
+    pub client: aws_sdk_s3::Client,
+    pub bucket: String,
+    pub base: url::Url,
+}
+
+impl BlobStore for S3 {
+    async fn put_blob(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<url::Url, HttpError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        public_url(&self.base, key)
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, HttpError> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => {
+                return Err(format!("{e:?}"))
+                    .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+        let body = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| e.to_string())
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Some(body.into_bytes().to_vec()))
+    }
+
+    async fn delete_blob(&self, key: &str) -> Result<(), HttpError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(())
+    }
+}
+
+/// Directory on the local disk, for single-node deployments and tests.
+/// The server itself serves the files from `base` with [FileSystem::read].
+pub struct FileSystem {
+    pub root: PathBuf,
+    pub base: url::Url,
+}
+
+impl FileSystem {
+    /// Path of `key`, unless it could point outside of the root.
+    fn path(&self, key: &str) -> Result<PathBuf, HttpError> {
+        let valid = !key.is_empty()
+            && key
+                .split('/')
+                .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+            && !key.contains('\\');
+        if !valid {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": "invalid key"}),
+                http::StatusCode::BAD_REQUEST,
+            ));
+        }
+        Ok(self.root.join(key))
+    }
+
+    /// Contents of `key` with the content type guessed from its extension.
+    pub async fn read(&self, key: &str) -> Result<Option<(&'static str, Vec<u8>)>, HttpError> {
+        let path = self.path(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(body) => Ok(Some((content_type_of(key), body))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()).http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+impl BlobStore for FileSystem {
+    async fn put_blob(
+        &self,
+        key: &str,
+        _content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<url::Url, HttpError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())
+                .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        tokio::fs::write(&path, body)
+            .await
+            .map_err(|e| e.to_string())
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        public_url(&self.base, key)
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, HttpError> {
+        Ok(self.read(key).await?.map(|(_, body)| body))
+    }
+
+    async fn delete_blob(&self, key: &str) -> Result<(), HttpError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()).http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+/// Storage backend chosen at startup.
+pub enum Backend {
+    S3(S3),
+    FileSystem(FileSystem),
+}
+
+impl BlobStore for Backend {
+    async fn put_blob(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<url::Url, HttpError> {
+        match self {
+            Backend::S3(s3) => s3.put_blob(key, content_type, body).await,
+            Backend::FileSystem(fs) => fs.put_blob(key, content_type, body).await,
+        }
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, HttpError> {
+        match self {
+            Backend::S3(s3) => s3.get_blob(key).await,
+            Backend::FileSystem(fs) => fs.get_blob(key).await,
+        }
+    }
+
+    async fn delete_blob(&self, key: &str) -> Result<(), HttpError> {
+        match self {
+            Backend::S3(s3) => s3.delete_blob(key).await,
+            Backend::FileSystem(fs) => fs.delete_blob(key).await,
+        }
+    }
+}
+
+/// File extensions of the content types stored by the server.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/avif", "avif"),
+    ("video/mp4", "mp4"),
+    ("video/quicktime", "mov"),
+    ("video/webm", "webm"),
+    ("audio/mpeg", "mp3"),
+    ("audio/ogg", "ogg"),
+    ("audio/wav", "wav"),
+    ("audio/flac", "flac"),
+    ("application/pdf", "pdf"),
+];
+
+/// Content types the server stores, in the order of [EXTENSIONS].
+pub fn content_types() -> impl Iterator<Item = &'static str> {
+    EXTENSIONS.iter().map(|(content_type, _)| *content_type)
+}
+
+pub fn extension_of(content_type: &str) -> Option<&'static str> {
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == content_type)
+        .map(|(_, extension)| *extension)
+}
+
+pub fn content_type_of(key: &str) -> &'static str {
+    let extension = key.rsplit_once('.').map(|(_, extension)| extension);
+    EXTENSIONS
+        .iter()
+        .find(|(_, known)| Some(*known) == extension)
+        .map_or("application/octet-stream", |(content_type, _)| content_type)
+}
+
+/// Content type of a file judging from its first bytes, for the types in [extension_of].
+pub fn sniff(body: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| body.get(offset..offset + magic.len()) == Some(magic);
+    if at(0, b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if at(0, b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        Some("image/gif")
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        Some("image/webp")
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        Some("audio/wav")
+    } else if at(4, b"ftypavif") {
+        Some("image/avif")
+    } else if at(4, b"ftypqt") {
+        Some("video/quicktime")
+    } else if at(4, b"ftyp") {
+        Some("video/mp4")
+    } else if at(0, b"\x1a\x45\xdf\xa3") {
+        Some("video/webm")
+    } else if at(0, b"ID3") || at(0, b"\xff\xfb") || at(0, b"\xff\xf3") || at(0, b"\xff\xf2") {
+        Some("audio/mpeg")
+    } else if at(0, b"OggS") {
+        Some("audio/ogg")
+    } else if at(0, b"fLaC") {
+        Some("audio/flac")
+    } else if at(0, b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
@@ -0,0 +1,571 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse,
+    },
+};
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{
+    auth::{self, TokenStore},
+    block::BlockStore,
+    fetch::Remote,
+    follow::FollowStore,
+    list::ListStore,
+    local::Local,
+    model::{notification::Notification, timeline::FeedEntry, token::Token},
+    mute::MuteStore,
+    notification::NotificationView,
+    object::ObjectStore,
+    timeline::{self, Filter},
+    visibility::{self, Viewer},
+};
+
+/// Events buffered for each subscriber. Subscribers which fall further behind are disconnected.
+pub const EVENT_BUFFER: usize = 1024;
+
+/// Interval of WebSocket pings and SSE comments. Connections silent for two intervals are closed.
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// WebSocket close code asking the client to reconnect later.
+const TRY_AGAIN_LATER: u16 = 1013;
+
+/// Where an event is published.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, Hash, Debug)]
+pub enum Stream {
+    /// Home timeline and notifications of a local account.
+    User(String),
+    Public,
+    PublicLocal,
+    Hashtag(String),
+    /// What members of a list share, by the id of the list.
+    List(String),
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum Event {
+    /// Entry added to a timeline.
+    Update(FeedEntry),
+    Notification(Notification),
+    /// Post which was deleted.
+    Delete(url::Url),
+    /// Post which was edited.
+    StatusUpdate(url::Url),
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Envelope {
+    pub stream: Stream,
+    pub event: Event,
+}
+
+/// Publish/subscribe of events within the server. Each subscriber sees every event, in order.
+pub trait EventBus {
+    fn publish(&self, stream: Stream, event: Event);
+    fn subscribe(&self) -> broadcast::Receiver<Arc<Envelope>>;
+}
+
+/// [EventBus] of a single process.
+pub struct LocalBus {
+    sender: broadcast::Sender<Arc<Envelope>>,
+}
+
+impl LocalBus {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity).0,
+        }
+    }
+}
+
+impl EventBus for LocalBus {
+    fn publish(&self, stream: Stream, event: Event) {
+        // Sending only fails when nobody is listening.
+        let _ = self.sender.send(Arc::new(Envelope { stream, event }));
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Arc<Envelope>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Stream a timeline entry is published on, by the name of its feed.
+pub fn stream_of_feed(feed: &str) -> Option<Stream> {
+    match feed {
+        timeline::LOCAL_FEED => Some(Stream::PublicLocal),
+        timeline::FEDERATED_FEED => Some(Stream::Public),
+        feed => feed
+            .strip_prefix("home:")
+            .map(|name| Stream::User(name.to_string())),
+    }
+}
+
+/// What a client subscribes to.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Channel {
+    User,
+    /// Notifications only.
+    UserNotification,
+    Public,
+    PublicLocal,
+    Hashtag(String),
+    List(String),
+}
+
+fn bad_channel(msg: &str) -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": msg}),
+        http::StatusCode::BAD_REQUEST,
+    )
+}
+
+impl Channel {
+    /// Parse Mastodon style stream names. Whether a list may be read is checked on subscription.
+    pub fn parse(name: &str, tag: Option<&str>, list: Option<&str>) -> Result<Self, HttpError> {
+        match name {
+            "user" => Ok(Self::User),
+            "user:notification" => Ok(Self::UserNotification),
+            "public" => Ok(Self::Public),
+            "public:local" => Ok(Self::PublicLocal),
+            "hashtag" => tag
+                .map(|tag| tag.trim_start_matches('#').to_lowercase())
+                .filter(|tag| !tag.is_empty())
+                .map(Self::Hashtag)
+                .ok_or_else(|| bad_channel("hashtag streams need a tag")),
+            "list" => list
+                .filter(|list| !list.is_empty())
+                .map(|list| Self::List(list.to_string()))
+                .ok_or_else(|| bad_channel("list streams need a list")),
+            _ => Err(bad_channel("unknown stream")),
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        match self {
+            Self::User => vec!["user".to_string()],
+            Self::UserNotification => vec!["user:notification".to_string()],
+            Self::Public => vec!["public".to_string()],
+            Self::PublicLocal => vec!["public:local".to_string()],
+            Self::Hashtag(tag) => vec!["hashtag".to_string(), tag.clone()],
+            Self::List(list) => vec!["list".to_string(), list.clone()],
+        }
+    }
+
+    fn is_public(&self) -> bool {
+        matches!(self, Self::Public | Self::PublicLocal | Self::Hashtag(_))
+    }
+
+    /// OAuth scope a token needs to subscribe, as in Mastodon.
+    fn scope(&self) -> &'static str {
+        match self {
+            Self::UserNotification => "read:notifications",
+            _ => "read:statuses",
+        }
+    }
+
+    fn receives(&self, account: &str, envelope: &Envelope) -> bool {
+        match (self, &envelope.stream, &envelope.event) {
+            (Self::User, Stream::User(name), _) => name == account,
+            (Self::UserNotification, Stream::User(name), Event::Notification(_)) => name == account,
+            (Self::Public, Stream::Public, _) | (Self::PublicLocal, Stream::PublicLocal, _) => true,
+            (Self::Hashtag(tag), Stream::Hashtag(published), _) => tag == published,
+            (Self::List(list), Stream::List(published), _) => list == published,
+            _ => false,
+        }
+    }
+}
+
+/// Event as sent to clients, in the shape of Mastodon's streaming API.
+#[derive(Serialize)]
+pub struct StreamMessage {
+    pub stream: Vec<String>,
+    pub event: &'static str,
+    /// JSON encoded payload, or the id of a deleted post.
+    pub payload: String,
+}
+
+/// State of a client connection: its subscriptions and what it may see.
+struct Connection<S> {
+    state: Arc<S>,
+    local: Local,
+    token: Token,
+    account: String,
+    viewer: url::Url,
+    channels: Vec<Channel>,
+    home: Filter,
+    public: Filter,
+    refreshed: tokio::time::Instant,
+}
+
+impl<S> Connection<S>
+where
+    S: BlockStore + MuteStore + ObjectStore + FollowStore + ListStore + Remote + Send + Sync,
+{
+    async fn new(state: Arc<S>, local: &Local, token: Token) -> Result<Self, HttpError> {
+        let account = token.account.clone().unwrap_or_default();
+        let viewer = local.actor(&account);
+        let home = Filter::new(state.as_ref(), local, viewer.clone(), false).await?;
+        let public = Filter::new(state.as_ref(), local, viewer.clone(), true).await?;
+        Ok(Self {
+            state,
+            local: local.clone(),
+            token,
+            account,
+            viewer,
+            channels: Vec::new(),
+            home,
+            public,
+            refreshed: tokio::time::Instant::now(),
+        })
+    }
+
+    /// Pick up blocks and mutes made since the filters were built, once per [HEARTBEAT].
+    async fn refresh(&mut self) -> Result<(), HttpError> {
+        if self.refreshed.elapsed() < HEARTBEAT {
+            return Ok(());
+        }
+        let (state, local) = (self.state.as_ref(), &self.local);
+        self.home = Filter::new(state, local, self.viewer.clone(), false).await?;
+        self.public = Filter::new(state, local, self.viewer.clone(), true).await?;
+        self.refreshed = tokio::time::Instant::now();
+        Ok(())
+    }
+
+    async fn subscribe(&mut self, channel: Channel) -> Result<(), HttpError> {
+        let scope = channel.scope();
+        if !auth::token_grants(&self.token, scope) {
+            return Err(HttpError::new_json(
+                &json!({"ok": false, "msg": format!("the token does not grant {scope}")}),
+                http::StatusCode::FORBIDDEN,
+            ));
+        }
+        if let Channel::List(list) = &channel {
+            if self.state.get_list(&self.account, list).await?.is_none() {
+                return Err(HttpError::new_json(
+                    &json!({"ok": false, "msg": "list not found"}),
+                    http::StatusCode::NOT_FOUND,
+                ));
+            }
+        }
+        if !self.channels.contains(&channel) {
+            self.channels.push(channel);
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, channel: &Channel) {
+        self.channels.retain(|subscribed| subscribed != channel);
+    }
+
+    async fn command(&mut self, command: Command) -> Result<(), HttpError> {
+        let channel = Channel::parse(
+            &command.stream,
+            command.tag.as_deref(),
+            command.list.as_deref(),
+        )?;
+        match command.kind.as_str() {
+            "subscribe" => self.subscribe(channel).await,
+            "unsubscribe" => {
+                self.unsubscribe(&channel);
+                Ok(())
+            }
+            _ => Err(bad_channel("unknown command")),
+        }
+    }
+
+    async fn render(&mut self, envelope: &Envelope) -> Result<Option<StreamMessage>, HttpError> {
+        self.refresh().await?;
+        let Some(channel) = self
+            .channels
+            .iter()
+            .find(|channel| channel.receives(&self.account, envelope))
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        let state = self.state.as_ref();
+        let filter = if channel.is_public() {
+            &mut self.public
+        } else {
+            &mut self.home
+        };
+        let (event, payload) = match &envelope.event {
+            Event::Update(entry) => {
+                let Some(item) = filter.item(state, entry.clone()).await? else {
+                    return Ok(None);
+                };
+                ("update", json!(item).to_string())
+            }
+            // The `user` stream carries notifications too, but only for tokens which may read them.
+            Event::Notification(_) if !auth::token_grants(&self.token, "read:notifications") => {
+                return Ok(None);
+            }
+            Event::Notification(notification) => {
+                let view = NotificationView::new(notification.clone(), false);
+                ("notification", json!(view).to_string())
+            }
+            Event::Delete(id) => ("delete", id.to_string()),
+            Event::StatusUpdate(id) => {
+                let Some(object) = state.get_object(id).await? else {
+                    return Ok(None);
+                };
+                let viewer = Viewer::Actor(self.viewer.clone());
+                if !filter.shows_actor(state, &object.attributed_to).await?
+                    || !visibility::can_view(state, &object, &viewer).await?
+                {
+                    return Ok(None);
+                }
+                ("status.update", object.body)
+            }
+        };
+        Ok(Some(StreamMessage {
+            stream: channel.names(),
+            event,
+            payload,
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StreamParams {
+    pub stream: Option<String>,
+    pub tag: Option<String>,
+    pub list: Option<String>,
+    /// For clients which can't set headers, such as browsers' `EventSource`.
+    pub access_token: Option<String>,
+}
+
+/// Command of a WebSocket client.
+#[derive(Deserialize)]
+struct Command {
+    #[serde(rename = "type")]
+    kind: String,
+    stream: String,
+    tag: Option<String>,
+    list: Option<String>,
+}
+
+/// Token of the request, which must grant `scope`.
+async fn authenticate<S>(
+    state: &S,
+    headers: &http::HeaderMap,
+    params: &StreamParams,
+    scope: &str,
+) -> Result<Token, HttpError>
+where
+    S: TokenStore + Sync,
+{
+    // Browsers pass the token of a WebSocket as its subprotocol.
+    let protocol = headers
+        .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok());
+    let token = auth::bearer_token(headers)
+        .or(params.access_token.as_deref())
+        .or(protocol)
+        .ok_or_else(|| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "unauthorized"}),
+                http::StatusCode::UNAUTHORIZED,
+            )
+        })?;
+    auth::token_of(state, token.trim(), scope).await
+}
+
+async fn send(socket: &mut WebSocket, message: &StreamMessage) -> bool {
+    socket
+        .send(Message::Text(json!(message).to_string()))
+        .await
+        .is_ok()
+}
+
+async fn serve_socket<S>(mut connection: Connection<S>, mut socket: WebSocket)
+where
+    S: BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + ListStore
+        + Remote
+        + EventBus
+        + Send
+        + Sync,
+{
+    let mut events = connection.state.subscribe();
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    let mut last_seen = tokio::time::Instant::now();
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > HEARTBEAT * 2 {
+                    debug!(account = connection.account, "stream_timeout");
+                    break;
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                let Some(Ok(message)) = incoming else {
+                    break;
+                };
+                last_seen = tokio::time::Instant::now();
+                let text = match message {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                let result = match serde_json::from_str::<Command>(&text) {
+                    Ok(command) => connection.command(command).await,
+                    Err(_) => Err(bad_channel("malformed command")),
+                };
+                if let Err(e) = result {
+                    let error = String::from_utf8_lossy(&e.body).into_owned();
+                    if socket.send(Message::Text(error)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            event = events.recv() => {
+                let envelope = match event {
+                    Ok(envelope) => envelope,
+                    Err(RecvError::Lagged(skipped)) => {
+                        info!(account = connection.account, skipped, "stream_lagged");
+                        let close = CloseFrame {
+                            code: TRY_AGAIN_LATER,
+                            reason: "client is too slow".into(),
+                        };
+                        let _ = socket.send(Message::Close(Some(close))).await;
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                match connection.render(&envelope).await {
+                    Ok(Some(message)) => {
+                        if !send(&mut socket, &message).await {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!(status = e.status.as_u16(), "stream_render"),
+                }
+            }
+        }
+    }
+}
+
+/// WebSocket streaming. The connection subscribes to `stream` if given, and to more
+/// streams with `{"type": "subscribe", "stream": .., "tag": ..}` messages.
+/// Clients which fall more than [EVENT_BUFFER] events behind are disconnected,
+/// so that they refetch timelines instead of silently missing events.
+pub async fn websocket<S>(
+    ws: WebSocketUpgrade,
+    axum::extract::Query(params): axum::extract::Query<StreamParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    headers: http::HeaderMap,
+) -> Result<axum::response::Response, HttpError>
+where
+    S: BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + ListStore
+        + Remote
+        + EventBus
+        + TokenStore
+        + Send
+        + Sync
+        + 'static,
+{
+    let channel = params
+        .stream
+        .as_deref()
+        .map(|name| Channel::parse(name, params.tag.as_deref(), params.list.as_deref()))
+        .transpose()?;
+    let scope = channel.as_ref().map_or("read:statuses", Channel::scope);
+    let token = authenticate(state.as_ref(), &headers, &params, scope).await?;
+    let local = Local::try_from(&proxy_info)?;
+    let mut connection = Connection::new(state, &local, token).await?;
+    if let Some(channel) = channel {
+        connection.subscribe(channel).await?;
+    }
+    let ws = match headers
+        .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(protocol) => ws.protocols([protocol.trim().to_string()]),
+        None => ws,
+    };
+    Ok(ws
+        .on_upgrade(move |socket| serve_socket(connection, socket))
+        .into_response())
+}
+
+/// Server-sent events of a single stream, for clients without WebSocket support.
+pub async fn server_sent_events<S>(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<StreamParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    headers: http::HeaderMap,
+) -> Result<axum::response::Response, HttpError>
+where
+    S: BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + ListStore
+        + Remote
+        + EventBus
+        + TokenStore
+        + Send
+        + Sync
+        + 'static,
+{
+    // `public/local` and `user/notification` in the path stand for `public:local` and so on.
+    let channel = Channel::parse(
+        &name.replace('/', ":"),
+        params.tag.as_deref(),
+        params.list.as_deref(),
+    )?;
+    let token = authenticate(state.as_ref(), &headers, &params, channel.scope()).await?;
+    let events = state.subscribe();
+    let local = Local::try_from(&proxy_info)?;
+    let mut connection = Connection::new(state, &local, token).await?;
+    connection.subscribe(channel).await?;
+    let stream = futures_util::stream::unfold(
+        (connection, events),
+        |(mut connection, mut events)| async move {
+            loop {
+                let envelope = match events.recv().await {
+                    Ok(envelope) => envelope,
+                    Err(RecvError::Lagged(skipped)) => {
+                        info!(account = connection.account, skipped, "stream_lagged");
+                        return None;
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                match connection.render(&envelope).await {
+                    Ok(Some(message)) => {
+                        let event = sse::Event::default()
+                            .event(message.event)
+                            .data(message.payload);
+                        return Some((Ok::<_, Infallible>(event), (connection, events)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!(status = e.status.as_u16(), "stream_render"),
+                }
+            }
+        },
+    );
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::new().interval(HEARTBEAT))
+        .into_response())
+}
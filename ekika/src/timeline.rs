@@ -0,0 +1,627 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+};
+
+use axum::Json;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    activity,
+    auth::{Authenticated, TokenStore},
+    block::{self, BlockStore},
+    domain,
+    fetch::Remote,
+    follow::FollowStore,
+    list::{self, ListStore},
+    local::Local,
+    model::{
+        actor::RemoteActor,
+        follow::FollowState,
+        notification::NotificationKind,
+        object::StoredObject,
+        timeline::{FeedEntry, LargeAccount},
+    },
+    mute::{self, MuteStore},
+    notification::{self, NotificationStore},
+    object::{self, ObjectStore},
+    report, search,
+    stream::{self, Event, EventBus, Stream},
+    visibility::{self, Viewer, Visibility},
+};
+
+/// Public posts of local accounts.
+pub const LOCAL_FEED: &str = "local";
+
+/// Public posts of every known actor.
+pub const FEDERATED_FEED: &str = "federated";
+
+/// Authors with more local followers than this are read on demand
+/// instead of being copied into the home feed of every follower.
+pub const FANOUT_LIMIT: usize = 1000;
+
+const DEFAULT_LIMIT: usize = 20;
+
+const MAX_LIMIT: usize = 40;
+
+/// Entries read from each feed at once while filling a page.
+const BATCH: usize = 40;
+
+/// Batches read for a single page, so that a mostly hidden feed can't stall a request.
+const MAX_BATCHES: usize = 10;
+
+pub fn home_feed(name: &str) -> String {
+    format!("home:{name}")
+}
+
+/// Everything an actor posted or boosted, for readers of accounts with too many followers to fan out.
+pub fn author_feed(actor: &url::Url) -> String {
+    format!("author:{actor}")
+}
+
+/// Entries between two ids, exclusive, newest first unless `ascending`.
+#[derive(Clone, Default, Debug)]
+pub struct FeedRange {
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub ascending: bool,
+}
+
+pub trait TimelineStore {
+    fn feed_entries(
+        &self,
+        feed: &str,
+        range: &FeedRange,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<FeedEntry>, HttpError>> + Send;
+    fn put_feed_entry(
+        &self,
+        entry: &FeedEntry,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+    fn large_accounts(&self) -> impl Future<Output = Result<Vec<LargeAccount>, HttpError>> + Send;
+    fn put_large_account(
+        &self,
+        account: &LargeAccount,
+    ) -> impl Future<Output = Result<(), HttpError>> + Send;
+}
+
+/// Time ordered id: milliseconds since the epoch followed by 16 random bits, zero padded.
+pub fn new_id(time: chrono::DateTime<chrono::Utc>) -> String {
+    let millis = u64::try_from(time.timestamp_millis()).unwrap_or_default();
+    format!("{:020}", millis << 16 | u64::from(rand::random::<u16>()))
+}
+
+/// Add a post, or a boost of it by `boosted_by`, to the feeds it belongs in.
+/// Home feeds of local followers are written now, unless the sharer has more than
+/// [FANOUT_LIMIT] of them; its author feed is then merged into their home timelines on read.
+/// Accounts blocking the sharer or the author get nothing.
+pub async fn distribute<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+    boosted_by: Option<&url::Url>,
+) -> Result<(), HttpError>
+where
+    S: TimelineStore + FollowStore + BlockStore + ListStore + EventBus + Sync,
+{
+    let created = chrono::Utc::now();
+    let id = new_id(created);
+    let sharer = boosted_by.unwrap_or(&object.attributed_to);
+    let entry = |feed: String| FeedEntry {
+        feed,
+        id: id.clone(),
+        object: object.id.clone(),
+        author: object.attributed_to.clone(),
+        boosted_by: boosted_by.cloned(),
+        created,
+    };
+
+    let mut feeds = vec![author_feed(sharer)];
+    if boosted_by.is_none() && object.visibility == Visibility::Public {
+        if local.is_local(&object.attributed_to) {
+            feeds.push(LOCAL_FEED.to_string());
+        }
+        feeds.push(FEDERATED_FEED.to_string());
+    }
+
+    let (homes, followers) = homes(state, local, object, sharer).await?;
+    if let Some(followers) = followers {
+        info!(actor = sharer.to_string(), followers, "fanout_on_read");
+        state
+            .put_large_account(&LargeAccount {
+                actor: sharer.clone(),
+                followers: followers as u64,
+                updated: created,
+            })
+            .await?;
+    }
+    let lists = list::streams(state, sharer, &homes).await?;
+    feeds.extend(homes.iter().map(|name| home_feed(name)));
+
+    for feed in feeds {
+        let entry = entry(feed);
+        state.put_feed_entry(&entry).await?;
+        if let Some(stream) = stream::stream_of_feed(&entry.feed) {
+            state.publish(stream, Event::Update(entry));
+        }
+    }
+    if boosted_by.is_none() && object.visibility == Visibility::Public {
+        for tag in hashtags(object) {
+            let entry = entry(FEDERATED_FEED.to_string());
+            state.publish(Stream::Hashtag(tag), Event::Update(entry));
+        }
+    }
+    for stream in lists {
+        state.publish(stream, Event::Update(entry(author_feed(sharer))));
+    }
+    Ok(())
+}
+
+/// Like [distribute], but failures are only logged. Timelines must not fail deliveries.
+pub async fn try_distribute<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+    boosted_by: Option<&url::Url>,
+) where
+    S: TimelineStore + FollowStore + BlockStore + ListStore + EventBus + Sync,
+{
+    if let Err(e) = distribute(state, local, object, boosted_by).await {
+        warn!(
+            object = object.id.to_string(),
+            status = e.status.as_u16(),
+            "distribute"
+        );
+    }
+}
+
+fn hashtags(object: &StoredObject) -> BTreeSet<String> {
+    serde_json::from_str(&object.body)
+        .map(|body| search::hashtags(&body))
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// Local accounts whose home feed gets `object` when `sharer` shares it, except followers
+/// of sharers with more than [FANOUT_LIMIT] of them. Their number is returned instead.
+async fn homes<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+    sharer: &url::Url,
+) -> Result<(Vec<String>, Option<usize>), HttpError>
+where
+    S: FollowStore + BlockStore + Sync,
+{
+    let mut homes = HashSet::new();
+    let mut large = None;
+    homes.extend(local.account_name(sharer));
+    if object.visibility == Visibility::Direct {
+        homes.extend(
+            object
+                .audience
+                .recipients()
+                .iter()
+                .filter_map(|recipient| local.account_name(recipient)),
+        );
+    } else {
+        let followers = state
+            .followers(sharer)
+            .await?
+            .into_iter()
+            .filter(|follow| follow.state == FollowState::Accepted)
+            .filter_map(|follow| local.account_name(&follow.follower))
+            .collect::<Vec<_>>();
+        if followers.len() > FANOUT_LIMIT {
+            large = Some(followers.len());
+        } else {
+            homes.extend(followers);
+        }
+    }
+    let mut blocked = vec![sharer];
+    if sharer != &object.attributed_to {
+        blocked.push(&object.attributed_to);
+    }
+    let homes = block::unblocked_accounts(state, local, homes, &blocked).await?;
+    Ok((homes, large))
+}
+
+/// Publish `event` about `object`, such as its edit or deletion, on the streams
+/// [distribute] published the object on, so that it reaches the same readers.
+pub async fn announce<S>(
+    state: &S,
+    local: &Local,
+    object: &StoredObject,
+    event: Event,
+) -> Result<(), HttpError>
+where
+    S: FollowStore + BlockStore + ListStore + EventBus + Sync,
+{
+    let mut streams = Vec::new();
+    if object.visibility == Visibility::Public {
+        if local.is_local(&object.attributed_to) {
+            streams.push(Stream::PublicLocal);
+        }
+        streams.push(Stream::Public);
+        streams.extend(hashtags(object).into_iter().map(Stream::Hashtag));
+    }
+    let (homes, _) = homes(state, local, object, &object.attributed_to).await?;
+    streams.extend(list::streams(state, &object.attributed_to, &homes).await?);
+    streams.extend(homes.into_iter().map(Stream::User));
+    for stream in streams {
+        state.publish(stream, event.clone());
+    }
+    Ok(())
+}
+
+/// Like [announce], but failures are only logged.
+pub async fn try_announce<S>(state: &S, local: &Local, object: &StoredObject, event: Event)
+where
+    S: FollowStore + BlockStore + ListStore + EventBus + Sync,
+{
+    if let Err(e) = announce(state, local, object, event).await {
+        warn!(
+            object = object.id.to_string(),
+            status = e.status.as_u16(),
+            "announce"
+        );
+    }
+}
+
+/// Inbound `Announce` of a post. Boosts by actors local accounts follow go to their home
+/// timelines, and boosts of local posts notify the author.
+/// The post is fetched from its origin unless we already have it.
+pub async fn handle_announce<S>(
+    state: &S,
+    local: &Local,
+    signer: &RemoteActor,
+    activity: &serde_json::Value,
+) -> Result<(), HttpError>
+where
+    S: TimelineStore
+        + ObjectStore
+        + FollowStore
+        + BlockStore
+        + ListStore
+        + MuteStore
+        + NotificationStore
+        + EventBus
+        + Remote
+        + Sync,
+{
+    let Some(iri) = activity::field_iri(activity, "object") else {
+        return Ok(());
+    };
+    let followed =
+        state.followers(&signer.id).await?.iter().any(|follow| {
+            follow.state == FollowState::Accepted && local.is_local(&follow.follower)
+        });
+    if !followed && !local.is_local(&iri) {
+        return Ok(());
+    }
+    let object = match state.get_object(&iri).await? {
+        Some(object) => object,
+        None if local.is_local(&iri) => return Ok(()),
+        None => object::fetch_object(state, &iri).await?,
+    };
+    // Only public posts can be boosted.
+    if !matches!(object.visibility, Visibility::Public | Visibility::Unlisted) {
+        return Ok(());
+    }
+    let kind = NotificationKind::Reblog;
+    notification::try_notify(
+        state,
+        local,
+        &object.attributed_to,
+        kind,
+        &signer.id,
+        Some(&iri),
+    )
+    .await;
+    if !followed {
+        return Ok(());
+    }
+    distribute(state, local, &object, Some(&signer.id)).await
+}
+
+#[derive(Deserialize)]
+pub struct TimelineParams {
+    /// Entries older than this id.
+    pub max_id: Option<String>,
+    /// Entries newer than this id, newest first.
+    pub since_id: Option<String>,
+    /// Entries immediately newer than this id.
+    pub min_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl TimelineParams {
+    pub fn range(&self) -> FeedRange {
+        FeedRange {
+            after: self.min_id.clone().or_else(|| self.since_id.clone()),
+            before: self.max_id.clone(),
+            ascending: self.min_id.is_some(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TimelineItem {
+    pub id: String,
+    pub object: serde_json::Value,
+    pub boosted_by: Option<url::Url>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Decides which entries `viewer` gets to see.
+pub struct Filter {
+    local: Local,
+    viewer: url::Url,
+    /// Actors blocked or muted by the viewer.
+    hidden: HashSet<url::Url>,
+    /// Whether the timeline lists posts the viewer didn't opt into, so silences apply.
+    public: bool,
+    /// Whether each actor seen so far may be shown.
+    actors: HashMap<url::Url, bool>,
+}
+
+impl Filter {
+    pub async fn new<S>(
+        state: &S,
+        local: &Local,
+        viewer: url::Url,
+        public: bool,
+    ) -> Result<Self, HttpError>
+    where
+        S: BlockStore + MuteStore + Sync,
+    {
+        let mut hidden = block::hidden_actors(state, &viewer).await?;
+        hidden.extend(mute::muted_actors(state, &viewer).await?);
+        Ok(Self {
+            local: local.clone(),
+            viewer,
+            hidden,
+            public,
+            actors: HashMap::new(),
+        })
+    }
+
+    pub async fn shows_actor<S>(&mut self, state: &S, actor: &url::Url) -> Result<bool, HttpError>
+    where
+        S: BlockStore + Remote + Sync,
+    {
+        if let Some(shown) = self.actors.get(actor) {
+            return Ok(*shown);
+        }
+        let shown = !self.hidden.contains(actor)
+            && state.get_block(actor, &self.viewer).await?.is_none()
+            && domain::federates(state, &self.local, actor).await?
+            && !report::is_suspended(state, actor).await?
+            && !(self.public
+                && (domain::is_silenced(state, actor).await?
+                    || report::is_silenced(state, actor).await?));
+        self.actors.insert(actor.clone(), shown);
+        Ok(shown)
+    }
+
+    pub async fn item<S>(
+        &mut self,
+        state: &S,
+        entry: FeedEntry,
+    ) -> Result<Option<TimelineItem>, HttpError>
+    where
+        S: BlockStore + ObjectStore + FollowStore + Remote + Sync,
+    {
+        if !self.shows_actor(state, &entry.author).await? {
+            return Ok(None);
+        }
+        if let Some(booster) = &entry.boosted_by {
+            if !self.shows_actor(state, booster).await? {
+                return Ok(None);
+            }
+        }
+        // Deleted posts leave their entries behind.
+        let Some(object) = state.get_object(&entry.object).await? else {
+            return Ok(None);
+        };
+        if !visibility::can_view(state, &object, &Viewer::Actor(self.viewer.clone())).await? {
+            return Ok(None);
+        }
+        let Ok(body) = serde_json::from_str(&object.body) else {
+            return Ok(None);
+        };
+        Ok(Some(TimelineItem {
+            id: entry.id,
+            object: body,
+            boosted_by: entry.boosted_by,
+            created_at: entry.created,
+        }))
+    }
+}
+
+/// Merge `feeds` and fill a page with the entries `filter` lets through, newest first.
+pub async fn collect<S>(
+    state: &S,
+    feeds: &[String],
+    params: &TimelineParams,
+    filter: &mut Filter,
+) -> Result<Vec<TimelineItem>, HttpError>
+where
+    S: TimelineStore + BlockStore + ObjectStore + FollowStore + Remote + Sync,
+{
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut range = params.range();
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+    'batches: for _ in 0..MAX_BATCHES {
+        let mut batch = Vec::new();
+        let mut exhausted = true;
+        for feed in feeds {
+            let entries = state.feed_entries(feed, &range, BATCH).await?;
+            exhausted &= entries.len() < BATCH;
+            batch.extend(entries);
+        }
+        if range.ascending {
+            batch.sort_by(|a, b| a.id.cmp(&b.id));
+        } else {
+            batch.sort_by(|a, b| b.id.cmp(&a.id));
+        }
+        batch.dedup_by(|a, b| a.id == b.id);
+        // Beyond the first batch, entries of a feed which filled its batch may be missing.
+        exhausted &= batch.len() <= BATCH;
+        batch.truncate(BATCH);
+        let Some(last) = batch.last() else {
+            break;
+        };
+        if range.ascending {
+            range.after = Some(last.id.clone());
+        } else {
+            range.before = Some(last.id.clone());
+        }
+        for entry in batch {
+            // A post reaches a feed once, but may also come through an author feed.
+            if !seen.insert((entry.object.clone(), entry.boosted_by.clone())) {
+                continue;
+            }
+            if let Some(item) = filter.item(state, entry).await? {
+                items.push(item);
+                if items.len() == limit {
+                    break 'batches;
+                }
+            }
+        }
+        if exhausted {
+            break;
+        }
+    }
+    if range.ascending {
+        items.reverse();
+    }
+    Ok(items)
+}
+
+/// Mastodon style `Link` header pointing to the previous and next pages.
+/// `ids` are those of the page, newest first.
+pub fn link_header<'a>(
+    proxy_info: &ProxyInfo,
+    path: &str,
+    ids: impl IntoIterator<Item = &'a str>,
+) -> http::HeaderMap {
+    let mut headers = http::HeaderMap::new();
+    let mut ids = ids.into_iter();
+    let Some(first) = ids.next() else {
+        return headers;
+    };
+    let last = ids.last().unwrap_or(first);
+    let base = format!("{}://{}{path}", proxy_info.proto, proxy_info.host);
+    let link =
+        format!("<{base}?max_id={last}>; rel=\"next\", <{base}?min_id={first}>; rel=\"prev\"");
+    if let Ok(link) = link.parse() {
+        headers.insert(http::header::LINK, link);
+    }
+    headers
+}
+
+/// Posts of the accounts the viewer follows, its own posts and direct messages to it.
+pub async fn home_timeline<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<TimelineItem>>), HttpError>
+where
+    S: TimelineStore
+        + BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + Remote
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let state = state.as_ref();
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let following = state
+        .following(&viewer)
+        .await?
+        .into_iter()
+        .filter(|follow| follow.state == FollowState::Accepted)
+        .map(|follow| follow.followee)
+        .collect::<HashSet<_>>();
+    let mut feeds = vec![home_feed(&auth.account)];
+    feeds.extend(
+        state
+            .large_accounts()
+            .await?
+            .iter()
+            .filter(|account| following.contains(&account.actor))
+            .map(|account| author_feed(&account.actor)),
+    );
+    let mut filter = Filter::new(state, &local, viewer, false).await?;
+    let items = collect(state, &feeds, &params, &mut filter).await?;
+    let ids = items.iter().map(|item| item.id.as_str());
+    Ok((link_header(&proxy_info, uri.path(), ids), Json(items)))
+}
+
+/// Public posts of local accounts.
+pub async fn local_timeline<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<TimelineItem>>), HttpError>
+where
+    S: TimelineStore
+        + BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + Remote
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let state = state.as_ref();
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let mut filter = Filter::new(state, &local, viewer, true).await?;
+    let items = collect(state, &[LOCAL_FEED.to_string()], &params, &mut filter).await?;
+    let ids = items.iter().map(|item| item.id.as_str());
+    Ok((link_header(&proxy_info, uri.path(), ids), Json(items)))
+}
+
+/// Public posts of every actor known to this server.
+pub async fn federated_timeline<S>(
+    auth: Authenticated,
+    axum::extract::Query(params): axum::extract::Query<TimelineParams>,
+    axum::extract::State(state): axum::extract::State<Arc<S>>,
+    proxy_info: ProxyInfo,
+    uri: http::Uri,
+) -> Result<(http::HeaderMap, Json<Vec<TimelineItem>>), HttpError>
+where
+    S: TimelineStore
+        + BlockStore
+        + MuteStore
+        + ObjectStore
+        + FollowStore
+        + Remote
+        + TokenStore
+        + Send
+        + Sync,
+{
+    let state = state.as_ref();
+    let local = Local::try_from(&proxy_info)?;
+    let viewer = local.actor(&auth.account);
+    let mut filter = Filter::new(state, &local, viewer, true).await?;
+    let items = collect(state, &[FEDERATED_FEED.to_string()], &params, &mut filter).await?;
+    let ids = items.iter().map(|item| item.id.as_str());
+    Ok((link_header(&proxy_info, uri.path(), ids), Json(items)))
+}
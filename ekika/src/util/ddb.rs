@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_helper::{HttpError, ToHttpErrorJson};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub fn key<'a, I>(pairs: I) -> HashMap<String, AttributeValue>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    pairs
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), AttributeValue::S(v.to_string())))
+        .collect()
+}
+
+pub async fn get<T: DeserializeOwned>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    key: HashMap<String, AttributeValue>,
+) -> Result<Option<T>, HttpError> {
+    let item = ddb
+        .get_item()
+        .table_name(table)
+        .set_key(Some(key))
+        .send()
+        .await
+        .map_err(|e| format!("{e:?}"))
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(item) = item.item else {
+        return Ok(None);
+    };
+    let item = serde_dynamo::aws_sdk_dynamodb_1::from_item(item)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Some(item))
+}
+
+pub async fn put<T: Serialize>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    item: &T,
+) -> Result<(), HttpError> {
+    let item = serde_dynamo::aws_sdk_dynamodb_1::to_item(item)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    ddb.put_item()
+        .table_name(table)
+        .set_item(Some(item))
+        .send()
+        .await
+        .map_err(|e| format!("{e:?}"))
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+pub async fn delete(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    key: HashMap<String, AttributeValue>,
+) -> Result<(), HttpError> {
+    ddb.delete_item()
+        .table_name(table)
+        .set_key(Some(key))
+        .send()
+        .await
+        .map_err(|e| format!("{e:?}"))
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+/// Delete an item and return what it was, so that only one of concurrent callers gets it.
+pub async fn take<T: DeserializeOwned>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    key: HashMap<String, AttributeValue>,
+) -> Result<Option<T>, HttpError> {
+    let output = ddb
+        .delete_item()
+        .table_name(table)
+        .set_key(Some(key))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+        .send()
+        .await
+        .map_err(|e| format!("{e:?}"))
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(item) = output.attributes else {
+        return Ok(None);
+    };
+    let item = serde_dynamo::aws_sdk_dynamodb_1::from_item(item)
+        .map_err(|e| e.to_string())
+        .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Some(item))
+}
+
+/// Fetch every item whose partition key `name` equals `value`, following pagination.
+pub async fn query<T: DeserializeOwned>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    index: Option<&str>,
+    name: &str,
+    value: &str,
+) -> Result<Vec<T>, HttpError> {
+    let mut items = Vec::new();
+    let mut start = None;
+    loop {
+        let output = ddb
+            .query()
+            .table_name(table)
+            .set_index_name(index.map(ToString::to_string))
+            .key_condition_expression("#k = :v")
+            .expression_attribute_names("#k", name)
+            .expression_attribute_values(":v", AttributeValue::S(value.to_string()))
+            .set_exclusive_start_key(start)
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        for item in output.items.unwrap_or_default() {
+            items.push(
+                serde_dynamo::aws_sdk_dynamodb_1::from_item(item)
+                    .map_err(|e| e.to_string())
+                    .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        start = output.last_evaluated_key;
+        if start.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+/// Fetch up to `limit` items whose partition key `name` equals `value` and whose sort key
+/// `sort` is strictly between `after` and `before`, in ascending or descending order.
+#[allow(clippy::too_many_arguments)]
+pub async fn query_range<T: DeserializeOwned>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+    name: &str,
+    value: &str,
+    sort: &str,
+    after: Option<&str>,
+    before: Option<&str>,
+    ascending: bool,
+    limit: usize,
+) -> Result<Vec<T>, HttpError> {
+    let condition = match (after, before) {
+        // BETWEEN is inclusive, so the bounds themselves are dropped below.
+        (Some(_), Some(_)) => "#k = :v AND #s BETWEEN :a AND :b",
+        (Some(_), None) => "#k = :v AND #s > :a",
+        (None, Some(_)) => "#k = :v AND #s < :b",
+        (None, None) => "#k = :v",
+    };
+    let mut request = ddb
+        .query()
+        .table_name(table)
+        .key_condition_expression(condition)
+        .expression_attribute_names("#k", name)
+        .expression_attribute_values(":v", AttributeValue::S(value.to_string()))
+        .scan_index_forward(ascending);
+    if after.is_some() || before.is_some() {
+        request = request.expression_attribute_names("#s", sort);
+    }
+    if let Some(after) = after {
+        request = request.expression_attribute_values(":a", AttributeValue::S(after.to_string()));
+    }
+    if let Some(before) = before {
+        request = request.expression_attribute_values(":b", AttributeValue::S(before.to_string()));
+    }
+    let mut items = Vec::new();
+    let mut start = None;
+    loop {
+        let output = request
+            .clone()
+            .set_exclusive_start_key(start)
+            .limit(i32::try_from(limit + 2).unwrap_or(i32::MAX))
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        for item in output.items.unwrap_or_default() {
+            let key = match item.get(sort) {
+                Some(AttributeValue::S(key)) => Some(key.as_str()),
+                _ => None,
+            };
+            if key.is_some() && (key == after || key == before) {
+                continue;
+            }
+            items.push(
+                serde_dynamo::aws_sdk_dynamodb_1::from_item(item)
+                    .map_err(|e| e.to_string())
+                    .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        start = output.last_evaluated_key;
+        if items.len() >= limit || start.is_none() {
+            items.truncate(limit);
+            return Ok(items);
+        }
+    }
+}
+
+/// Fetch every item of a table. Only for small tables such as instance settings.
+pub async fn scan<T: DeserializeOwned>(
+    ddb: &aws_sdk_dynamodb::Client,
+    table: &str,
+) -> Result<Vec<T>, HttpError> {
+    let mut items = Vec::new();
+    let mut start = None;
+    loop {
+        let output = ddb
+            .scan()
+            .table_name(table)
+            .set_exclusive_start_key(start)
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        for item in output.items.unwrap_or_default() {
+            items.push(
+                serde_dynamo::aws_sdk_dynamodb_1::from_item(item)
+                    .map_err(|e| e.to_string())
+                    .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        start = output.last_evaluated_key;
+        if start.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+/// Number of items in a table, following pagination.
+pub async fn count(ddb: &aws_sdk_dynamodb::Client, table: &str) -> Result<u64, HttpError> {
+    let mut count = 0;
+    let mut start = None;
+    loop {
+        let output = ddb
+            .scan()
+            .table_name(table)
+            .select(aws_sdk_dynamodb::types::Select::Count)
+            .set_exclusive_start_key(start)
+            .send()
+            .await
+            .map_err(|e| format!("{e:?}"))
+            .http_error_json(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        count += output.count as u64;
+        start = output.last_evaluated_key;
+        if start.is_none() {
+            return Ok(count);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub mod ddb;
+pub mod rate_limit;
+
 use tracing::info;
 
 #[cfg(not(unix))]
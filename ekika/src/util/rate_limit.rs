@@ -0,0 +1,35 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Fixed window counter of requests per key, such as an account name.
+pub struct RateLimiter {
+    limit: u32,
+    counts: moka::future::Cache<String, Arc<AtomicU32>>,
+}
+
+impl RateLimiter {
+    /// Allow `limit` requests per key in each `window`, which starts at the first request.
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            counts: moka::future::Cache::builder()
+                .time_to_live(window)
+                .max_capacity(100_000)
+                .build(),
+        }
+    }
+
+    /// Count a request of `key`, returning whether it is within the limit.
+    pub async fn check(&self, key: &str) -> bool {
+        let count = self
+            .counts
+            .get_with(key.to_string(), async { Arc::new(AtomicU32::new(0)) })
+            .await;
+        count.fetch_add(1, Ordering::Relaxed) < self.limit
+    }
+}
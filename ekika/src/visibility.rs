@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use axum_helper::HttpError;
+use serde::{Deserialize, Serialize};
+
+use crate::{ap, follow::FollowStore, model::follow::FollowState, model::object::StoredObject};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Debug, Hash)]
+pub enum Visibility {
+    /// Addressed to `as:Public` in `to`. Listed on public timelines.
+    Public,
+    /// Addressed to `as:Public` only in `cc`. Readable by anyone but not listed.
+    Unlisted,
+    /// Addressed to the followers collection of the author.
+    FollowersOnly,
+    /// Addressed only to explicitly listed actors.
+    Direct,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Audience {
+    pub to: Vec<url::Url>,
+    pub cc: Vec<url::Url>,
+    pub bto: Vec<url::Url>,
+    pub bcc: Vec<url::Url>,
+}
+
+pub enum Viewer {
+    Anonymous,
+    Actor(url::Url),
+}
+
+pub fn is_public(iri: &url::Url) -> bool {
+    matches!(iri.as_str(), ap::PUBLIC | "as:Public")
+}
+
+impl Audience {
+    pub fn of(object: &ap::Object) -> Self {
+        Self {
+            to: ap::iris_of(&object.to),
+            cc: ap::iris_of(&object.cc),
+            bto: ap::iris_of(&object.bto),
+            bcc: ap::iris_of(&object.bcc),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &url::Url> {
+        self.to
+            .iter()
+            .chain(&self.cc)
+            .chain(&self.bto)
+            .chain(&self.bcc)
+    }
+
+    pub fn classify(&self, followers: &url::Url) -> Visibility {
+        if self.to.iter().any(is_public) {
+            Visibility::Public
+        } else if self.cc.iter().any(is_public) {
+            Visibility::Unlisted
+        } else if self.iter().any(|iri| iri == followers) {
+            Visibility::FollowersOnly
+        } else {
+            Visibility::Direct
+        }
+    }
+
+    pub fn addresses(&self, actor: &url::Url) -> bool {
+        self.iter().any(|iri| iri == actor)
+    }
+
+    /// Every addressed IRI except `as:Public`. Collections are not expanded.
+    pub fn recipients(&self) -> HashSet<url::Url> {
+        self.iter().filter(|iri| !is_public(iri)).cloned().collect()
+    }
+}
+
+/// Remove `bto` and `bcc` from an object (and from the object embedded in an activity)
+/// so that blind recipients are never disclosed.
+pub fn strip_blind(value: &mut serde_json::Value) {
+    if let serde_json::Value::Object(map) = value {
+        map.remove("bto");
+        map.remove("bcc");
+        if let Some(object) = map.get_mut("object") {
+            strip_blind(object);
+        }
+    }
+}
+
+pub async fn can_view<S>(
+    state: &S,
+    object: &StoredObject,
+    viewer: &Viewer,
+) -> Result<bool, HttpError>
+where
+    S: FollowStore + Sync,
+{
+    let actor = match (object.visibility, viewer) {
+        (Visibility::Public | Visibility::Unlisted, _) => return Ok(true),
+        (_, Viewer::Anonymous) => return Ok(false),
+        (_, Viewer::Actor(actor)) => actor,
+    };
+    if actor == &object.attributed_to || object.audience.addresses(actor) {
+        return Ok(true);
+    }
+    if object.visibility != Visibility::FollowersOnly {
+        return Ok(false);
+    }
+    let follow = state.get_follow(actor, &object.attributed_to).await?;
+    Ok(matches!(follow, Some(follow) if follow.state == FollowState::Accepted))
+}
+
+/// Actors which should receive the object, with the author's followers collection expanded.
+pub async fn delivery_recipients<S>(
+    state: &S,
+    object: &StoredObject,
+    followers: &url::Url,
+) -> Result<HashSet<url::Url>, HttpError>
+where
+    S: FollowStore + Sync,
+{
+    let mut recipients = object.audience.recipients();
+    if recipients.remove(followers) {
+        recipients.extend(
+            state
+                .followers(&object.attributed_to)
+                .await?
+                .into_iter()
+                .filter(|follow| follow.state == FollowState::Accepted)
+                .map(|follow| follow.follower),
+        );
+    }
+    recipients.remove(&object.attributed_to);
+    Ok(recipients)
+}
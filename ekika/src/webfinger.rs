@@ -8,14 +8,19 @@ use serde_json::json;
 use std::future::Future;
 use tracing::debug;
 
-use crate::model::account::Account;
+use crate::{
+    fetch::Remote,
+    local::Local,
+    model::account::Account,
+    signature::{self, SignedRequest},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct WebfingerQuery {
     resource: url::Url,
 }
 
-#[derive(Serialize, Deserialize, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Hash, PartialEq, Eq, Debug)]
 #[serde(untagged)]
 pub enum WebfingerLinks {
     Href {
@@ -30,7 +35,7 @@ pub enum WebfingerLinks {
     },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct WebfingerResponse {
     pub subject: url::Url,
     pub aliases: HashSet<url::Url>,
@@ -49,10 +54,20 @@ pub async fn webfinger<S>(
     axum::extract::Query(query): axum::extract::Query<WebfingerQuery>,
     axum::extract::State(state): axum::extract::State<Arc<S>>,
     proxy_info: ProxyInfo,
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
 ) -> Result<Json<WebfingerResponse>, HttpError>
 where
-    S: AccountStore<ActorInfo = Account>,
+    S: AccountStore<ActorInfo = Account> + Remote + Sync,
 {
+    let request = SignedRequest {
+        method: &method,
+        uri: &uri,
+        headers: &headers,
+        body: None,
+    };
+    signature::authorize_fetch(state.as_ref(), request).await?;
     debug!(resource = query.resource.to_string(), "query");
     if query.resource.scheme() != "acct" {
         return Err(HttpError::new_json(
@@ -60,22 +75,23 @@ where
             http::StatusCode::BAD_REQUEST,
         ));
     }
+    let local = Local::try_from(&proxy_info)?;
     let account = query
         .resource
         .path()
-        .strip_suffix(&format!("@{}", proxy_info.host))
+        .strip_suffix(&format!("@{}", local.host()))
         .ok_or("not_found")
         .http_error_json(http::StatusCode::NOT_FOUND)?;
     debug!(account = account, "account");
-    if state.query(account).await?.is_some() {
-        let frontend_profile: url::Url =
-            format!("{}://{}/@{account}", proxy_info.proto, proxy_info.host)
-                .parse()
-                .unwrap();
-        let api_endtpoint: url::Url =
-            format!("{}://{}/users/{account}", proxy_info.proto, proxy_info.host)
-                .parse()
-                .unwrap();
+    // The instance actor is `acct:<host>@<host>`.
+    let found = if account == local.host() {
+        Some((local.issuer(), local.instance_actor()))
+    } else if state.query(account).await?.is_some() {
+        Some((local.profile_page(account), local.actor(account)))
+    } else {
+        None
+    };
+    if let Some((frontend_profile, api_endtpoint)) = found {
         Ok(Json(WebfingerResponse {
             subject: query.resource,
             aliases: maplit::hashset![frontend_profile.clone(), api_endtpoint.clone(),],
@@ -0,0 +1,72 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use axum_helper::HttpError;
+use common::{proxy_info, Memory};
+use ekika::actor::{self, CollectionPath};
+use serde_json::json;
+
+async fn collection(
+    store: &Arc<Memory>,
+    path: serde_json::Value,
+) -> Result<serde_json::Value, HttpError> {
+    let path: CollectionPath = serde_json::from_value(path).unwrap();
+    let response = actor::get_collection(
+        axum::extract::Path(path),
+        State(store.clone()),
+        proxy_info(),
+        http::Method::GET,
+        "/".parse().unwrap(),
+        http::HeaderMap::new(),
+    )
+    .await?
+    .into_response();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    Ok(serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn collections_are_served_empty() {
+    let store = Memory::new();
+    store.account("alice");
+    for kind in actor::COLLECTIONS {
+        let document = collection(&store, json!({"name": "alice", "collection": kind}))
+            .await
+            .unwrap();
+        assert_eq!(document["type"], "OrderedCollection");
+        assert_eq!(
+            document["id"],
+            format!("https://ekika.example/users/alice/{kind}")
+        );
+    }
+    let document = collection(&store, json!({"collection": "outbox"}))
+        .await
+        .unwrap();
+    assert_eq!(document["id"], "https://ekika.example/actor/outbox");
+}
+
+#[tokio::test]
+async fn unknown_collections_are_not_found() {
+    let store = Memory::new();
+    store.account("alice");
+    let path = json!({"name": "alice", "collection": "inbox"});
+    let error = collection(&store, path).await.unwrap_err();
+    assert_eq!(error.status, http::StatusCode::NOT_FOUND);
+    let path = json!({"name": "bob", "collection": "outbox"});
+    let error = collection(&store, path).await.unwrap_err();
+    assert_eq!(error.status, http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unsigned_fetches_are_refused_in_allowlist_mode() {
+    let store = Memory::new();
+    store.allowlist(&["allowed.example"]);
+    store.account("alice");
+    let path = json!({"name": "alice", "collection": "outbox"});
+    let error = collection(&store, path).await.unwrap_err();
+    assert_eq!(error.status, http::StatusCode::FORBIDDEN);
+}
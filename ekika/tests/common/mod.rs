@@ -0,0 +1,779 @@
+//! In-memory stores shared by the integration tests. Each test binary uses part of them.
+#![allow(dead_code)]
+
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use axum_helper::{headers::ProxyInfo, HttpError};
+use ekika::{
+    auth::TokenStore,
+    block::BlockStore,
+    delivery::{DeliveryJob, DeliveryQueue},
+    domain::{DomainPolicyStore, FederationMode},
+    emoji::EmojiStore,
+    featured::FeaturedStore,
+    fetch::{ActorStore, Fetcher},
+    follow::FollowStore,
+    list::ListStore,
+    local::Local,
+    media::MediaStore,
+    migration::MigrationStore,
+    model::{
+        account::{Account, AccountKind},
+        actor::RemoteActor,
+        block::Block,
+        domain::{DomainAllow, DomainPolicy, Severity},
+        emoji::CustomEmoji,
+        featured::Featured,
+        follow::{Follow, FollowState},
+        list::{List, ListMember},
+        media::Attachment,
+        migration::Migration,
+        mute::Mute,
+        notification::{Notification, NotificationSettings},
+        oauth::{Application, AuthorizationCode, Session},
+        relay::Relay,
+        report::AccountPolicy,
+        token::Token,
+    },
+    mute::MuteStore,
+    notification::NotificationStore,
+    oauth::OAuthStore,
+    relay::RelayStore,
+    report::AccountPolicyStore,
+    storage::{BlobStore, FileSystem},
+    stream::{Envelope, Event, EventBus, LocalBus, Stream},
+    timeline::FeedRange,
+    webfinger::AccountStore,
+};
+use serde_json::json;
+
+/// Everything a test stores, with files in a temporary directory removed on drop.
+pub struct Memory {
+    pub tokens: Mutex<HashMap<String, Token>>,
+    pub lists: Mutex<Vec<List>>,
+    pub members: Mutex<Vec<ListMember>>,
+    pub applications: Mutex<HashMap<String, Application>>,
+    pub codes: Mutex<HashMap<String, AuthorizationCode>>,
+    pub sessions: Mutex<HashMap<String, Session>>,
+    pub media: Mutex<HashMap<String, Attachment>>,
+    pub files: FileSystem,
+    pub mode: Mutex<FederationMode>,
+    pub policies: Mutex<HashMap<String, DomainPolicy>>,
+    pub allowed: Mutex<HashMap<String, DomainAllow>>,
+    pub account_policies: Mutex<HashMap<url::Url, AccountPolicy>>,
+    /// Documents served to [Fetcher::fetch], which records every IRI in `fetched`.
+    pub documents: Mutex<HashMap<url::Url, serde_json::Value>>,
+    pub fetched: Mutex<Vec<url::Url>>,
+    pub actors: Mutex<HashMap<url::Url, RemoteActor>>,
+    pub relays: Mutex<Vec<Relay>>,
+    pub featured: Mutex<HashMap<url::Url, Featured>>,
+    pub emojis: Mutex<HashMap<String, CustomEmoji>>,
+    pub blocks: Mutex<Vec<Block>>,
+    pub deliveries: Mutex<Vec<DeliveryJob>>,
+    pub accounts: Mutex<HashMap<String, Account>>,
+    pub follows: Mutex<Vec<Follow>>,
+    pub mutes: Mutex<Vec<Mute>>,
+    pub notifications: Mutex<Vec<Notification>>,
+    pub events: LocalBus,
+    pub migrations: Mutex<HashMap<String, Migration>>,
+}
+
+impl Memory {
+    pub fn new() -> Arc<Self> {
+        let root = std::env::temp_dir().join(format!("ekika-test-{}", uuid::Uuid::new_v4()));
+        Arc::new(Self {
+            tokens: Mutex::default(),
+            lists: Mutex::default(),
+            members: Mutex::default(),
+            applications: Mutex::default(),
+            codes: Mutex::default(),
+            sessions: Mutex::default(),
+            media: Mutex::default(),
+            files: FileSystem {
+                root,
+                base: "http://localhost:10000/files/".parse().unwrap(),
+            },
+            mode: Mutex::default(),
+            policies: Mutex::default(),
+            allowed: Mutex::default(),
+            account_policies: Mutex::default(),
+            documents: Mutex::default(),
+            fetched: Mutex::default(),
+            actors: Mutex::default(),
+            relays: Mutex::default(),
+            featured: Mutex::default(),
+            emojis: Mutex::default(),
+            blocks: Mutex::default(),
+            deliveries: Mutex::default(),
+            accounts: Mutex::default(),
+            follows: Mutex::default(),
+            mutes: Mutex::default(),
+            notifications: Mutex::default(),
+            events: LocalBus::new(16),
+            migrations: Mutex::default(),
+        })
+    }
+
+    /// Allow only `domains` to federate.
+    pub fn allowlist(&self, domains: &[&str]) {
+        *self.mode.lock().unwrap() = FederationMode::Allowlist;
+        let mut allowed = self.allowed.lock().unwrap();
+        for domain in domains {
+            allowed.insert(
+                domain.to_string(),
+                DomainAllow {
+                    domain: domain.to_string(),
+                    created: chrono::Utc::now(),
+                },
+            );
+        }
+    }
+
+    /// Suspend `domain` and its subdomains.
+    pub fn suspend(&self, domain: &str) {
+        self.policies.lock().unwrap().insert(
+            domain.to_string(),
+            DomainPolicy {
+                domain: domain.to_string(),
+                severity: Severity::Suspend,
+                reject_media: false,
+                reject_reports: false,
+                public_comment: None,
+                private_comment: None,
+                obfuscate: false,
+                created: chrono::Utc::now(),
+            },
+        );
+    }
+
+    /// Cache `actor` with an inbox under its IRI and no shared inbox.
+    pub fn remote_actor(&self, actor: &str) -> RemoteActor {
+        let id = url(actor);
+        let actor = RemoteActor {
+            inbox: url(&format!("{actor}/inbox")),
+            shared_inbox: None,
+            followers: Some(url(&format!("{actor}/followers"))),
+            preferred_username: id
+                .path_segments()
+                .and_then(|mut s| s.next_back())
+                .map(str::to_string),
+            name: None,
+            summary: None,
+            public_key_id: None,
+            public_key_pem: None,
+            also_known_as: Vec::new(),
+            moved_to: None,
+            featured: None,
+            fetched: chrono::Utc::now(),
+            id,
+        };
+        self.actors
+            .lock()
+            .unwrap()
+            .insert(actor.id.clone(), actor.clone());
+        actor
+    }
+
+    pub fn block(&self, blocker: &str, blocked: &str) {
+        self.blocks.lock().unwrap().push(Block {
+            blocker: url(blocker),
+            blocked: url(blocked),
+            activity: None,
+            created: chrono::Utc::now(),
+        });
+    }
+
+    /// Register a local `Person` account called `name`.
+    pub fn account(&self, name: &str) -> Account {
+        let account = Account {
+            kind: AccountKind::Person,
+            preferred_user_name: name.to_string(),
+            name: name.to_string(),
+            summary: String::new(),
+            icon: Vec::new(),
+            role: Default::default(),
+            header: None,
+            fields: Vec::new(),
+            locked: false,
+            discoverable: false,
+            bot: false,
+        };
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), account.clone());
+        account
+    }
+
+    pub fn follow(&self, follower: &str, followee: &str, state: FollowState) {
+        self.follows.lock().unwrap().push(Follow {
+            follower: url(follower),
+            followee: url(followee),
+            state,
+            activity: None,
+        });
+    }
+
+    pub fn delivered_activities(&self) -> Vec<serde_json::Value> {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries
+            .iter()
+            .map(|job| serde_json::from_str(&job.activity).unwrap())
+            .collect()
+    }
+
+    pub fn delivered_inboxes(&self) -> Vec<url::Url> {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries.iter().map(|job| job.inbox.clone()).collect()
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.files.root);
+    }
+}
+
+pub fn url(s: &str) -> url::Url {
+    s.parse().unwrap()
+}
+
+pub fn local() -> Local {
+    Local::new(url("https://ekika.example/"))
+}
+
+/// Request headers of a plain HTTPS request for [local].
+pub fn proxy_info() -> ProxyInfo {
+    ProxyInfo {
+        host_proxied: false,
+        proto_proxied: false,
+        host: "ekika.example".to_string(),
+        proto: "https".to_string(),
+    }
+}
+
+fn not_found() -> HttpError {
+    HttpError::new_json(
+        &json!({"ok": false, "msg": "not found"}),
+        http::StatusCode::NOT_FOUND,
+    )
+}
+
+impl TokenStore for Memory {
+    async fn get_token(&self, digest: &str) -> Result<Option<Token>, HttpError> {
+        Ok(self.tokens.lock().unwrap().get(digest).cloned())
+    }
+
+    async fn put_token(&self, token: &Token) -> Result<(), HttpError> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token.id.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn delete_token(&self, digest: &str) -> Result<(), HttpError> {
+        self.tokens.lock().unwrap().remove(digest);
+        Ok(())
+    }
+}
+
+impl ListStore for Memory {
+    async fn lists(&self, owner: &str) -> Result<Vec<List>, HttpError> {
+        let lists = self.lists.lock().unwrap();
+        Ok(lists
+            .iter()
+            .filter(|list| list.owner == owner)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_list(&self, owner: &str, id: &str) -> Result<Option<List>, HttpError> {
+        let lists = self.lists.lock().unwrap();
+        Ok(lists
+            .iter()
+            .find(|list| list.owner == owner && list.id == id)
+            .cloned())
+    }
+
+    async fn put_list(&self, list: &List) -> Result<(), HttpError> {
+        self.lists.lock().unwrap().push(list.clone());
+        Ok(())
+    }
+
+    async fn delete_list(&self, owner: &str, id: &str) -> Result<(), HttpError> {
+        let mut lists = self.lists.lock().unwrap();
+        lists.retain(|list| list.owner != owner || list.id != id);
+        Ok(())
+    }
+
+    async fn list_members(&self, list: &str) -> Result<Vec<ListMember>, HttpError> {
+        let members = self.members.lock().unwrap();
+        Ok(members
+            .iter()
+            .filter(|member| member.list == list)
+            .cloned()
+            .collect())
+    }
+
+    async fn memberships(&self, member: &url::Url) -> Result<Vec<ListMember>, HttpError> {
+        let members = self.members.lock().unwrap();
+        Ok(members
+            .iter()
+            .filter(|membership| &membership.member == member)
+            .cloned()
+            .collect())
+    }
+
+    async fn put_list_member(&self, member: &ListMember) -> Result<(), HttpError> {
+        self.members.lock().unwrap().push(member.clone());
+        Ok(())
+    }
+
+    async fn delete_list_member(&self, list: &str, member: &url::Url) -> Result<(), HttpError> {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|membership| membership.list != list || &membership.member != member);
+        Ok(())
+    }
+}
+
+impl OAuthStore for Memory {
+    async fn get_application(&self, id: &str) -> Result<Option<Application>, HttpError> {
+        Ok(self.applications.lock().unwrap().get(id).cloned())
+    }
+
+    async fn put_application(&self, application: &Application) -> Result<(), HttpError> {
+        self.applications
+            .lock()
+            .unwrap()
+            .insert(application.id.clone(), application.clone());
+        Ok(())
+    }
+
+    async fn put_authorization_code(&self, code: &AuthorizationCode) -> Result<(), HttpError> {
+        self.codes
+            .lock()
+            .unwrap()
+            .insert(code.id.clone(), code.clone());
+        Ok(())
+    }
+
+    async fn get_authorization_code(
+        &self,
+        digest: &str,
+    ) -> Result<Option<AuthorizationCode>, HttpError> {
+        Ok(self.codes.lock().unwrap().get(digest).cloned())
+    }
+
+    async fn take_authorization_code(
+        &self,
+        digest: &str,
+    ) -> Result<Option<AuthorizationCode>, HttpError> {
+        Ok(self.codes.lock().unwrap().remove(digest))
+    }
+
+    async fn get_session(&self, digest: &str) -> Result<Option<Session>, HttpError> {
+        Ok(self.sessions.lock().unwrap().get(digest).cloned())
+    }
+
+    async fn put_session(&self, session: &Session) -> Result<(), HttpError> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+}
+
+impl MediaStore for Memory {
+    async fn get_media(&self, id: &str) -> Result<Option<Attachment>, HttpError> {
+        Ok(self.media.lock().unwrap().get(id).cloned())
+    }
+
+    async fn put_media(&self, media: &Attachment) -> Result<(), HttpError> {
+        let mut stored = self.media.lock().unwrap();
+        stored.insert(media.id.clone(), media.clone());
+        Ok(())
+    }
+}
+
+impl BlobStore for Memory {
+    async fn put_blob(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<url::Url, HttpError> {
+        self.files.put_blob(key, content_type, body).await
+    }
+
+    async fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, HttpError> {
+        self.files.get_blob(key).await
+    }
+
+    async fn delete_blob(&self, key: &str) -> Result<(), HttpError> {
+        self.files.delete_blob(key).await
+    }
+}
+
+impl DomainPolicyStore for Memory {
+    fn federation_mode(&self) -> FederationMode {
+        *self.mode.lock().unwrap()
+    }
+
+    async fn policies(&self) -> Result<Vec<DomainPolicy>, HttpError> {
+        Ok(self.policies.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn get_policy(&self, domain: &str) -> Result<Option<DomainPolicy>, HttpError> {
+        Ok(self.policies.lock().unwrap().get(domain).cloned())
+    }
+
+    async fn put_policy(&self, policy: &DomainPolicy) -> Result<(), HttpError> {
+        self.policies
+            .lock()
+            .unwrap()
+            .insert(policy.domain.clone(), policy.clone());
+        Ok(())
+    }
+
+    async fn delete_policy(&self, domain: &str) -> Result<(), HttpError> {
+        self.policies.lock().unwrap().remove(domain);
+        Ok(())
+    }
+
+    async fn allowed_domains(&self) -> Result<Vec<DomainAllow>, HttpError> {
+        Ok(self.allowed.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn get_allowed(&self, domain: &str) -> Result<Option<DomainAllow>, HttpError> {
+        Ok(self.allowed.lock().unwrap().get(domain).cloned())
+    }
+
+    async fn put_allowed(&self, allow: &DomainAllow) -> Result<(), HttpError> {
+        self.allowed
+            .lock()
+            .unwrap()
+            .insert(allow.domain.clone(), allow.clone());
+        Ok(())
+    }
+
+    async fn delete_allowed(&self, domain: &str) -> Result<(), HttpError> {
+        self.allowed.lock().unwrap().remove(domain);
+        Ok(())
+    }
+}
+
+impl AccountPolicyStore for Memory {
+    async fn get_account_policy(
+        &self,
+        actor: &url::Url,
+    ) -> Result<Option<AccountPolicy>, HttpError> {
+        Ok(self.account_policies.lock().unwrap().get(actor).cloned())
+    }
+
+    async fn put_account_policy(&self, policy: &AccountPolicy) -> Result<(), HttpError> {
+        self.account_policies
+            .lock()
+            .unwrap()
+            .insert(policy.actor.clone(), policy.clone());
+        Ok(())
+    }
+}
+
+impl Fetcher for Memory {
+    async fn fetch(&self, iri: &url::Url) -> Result<serde_json::Value, HttpError> {
+        self.fetched.lock().unwrap().push(iri.clone());
+        self.documents
+            .lock()
+            .unwrap()
+            .get(iri)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+}
+
+impl ActorStore for Memory {
+    async fn get_actor(&self, id: &url::Url) -> Result<Option<RemoteActor>, HttpError> {
+        Ok(self.actors.lock().unwrap().get(id).cloned())
+    }
+
+    async fn put_actor(&self, actor: &RemoteActor) -> Result<(), HttpError> {
+        self.actors
+            .lock()
+            .unwrap()
+            .insert(actor.id.clone(), actor.clone());
+        Ok(())
+    }
+}
+
+impl RelayStore for Memory {
+    async fn relays(&self) -> Result<Vec<Relay>, HttpError> {
+        Ok(self.relays.lock().unwrap().clone())
+    }
+
+    async fn get_relay(&self, id: &str) -> Result<Option<Relay>, HttpError> {
+        let relays = self.relays.lock().unwrap();
+        Ok(relays.iter().find(|relay| relay.id == id).cloned())
+    }
+
+    async fn put_relay(&self, relay: &Relay) -> Result<(), HttpError> {
+        let mut relays = self.relays.lock().unwrap();
+        relays.retain(|stored| stored.id != relay.id);
+        relays.push(relay.clone());
+        Ok(())
+    }
+
+    async fn delete_relay(&self, id: &str) -> Result<(), HttpError> {
+        self.relays.lock().unwrap().retain(|relay| relay.id != id);
+        Ok(())
+    }
+}
+
+impl FeaturedStore for Memory {
+    async fn get_featured(&self, actor: &url::Url) -> Result<Option<Featured>, HttpError> {
+        Ok(self.featured.lock().unwrap().get(actor).cloned())
+    }
+
+    async fn put_featured(&self, featured: &Featured) -> Result<(), HttpError> {
+        self.featured
+            .lock()
+            .unwrap()
+            .insert(featured.actor.clone(), featured.clone());
+        Ok(())
+    }
+}
+
+impl EmojiStore for Memory {
+    async fn emojis(&self) -> Result<Vec<CustomEmoji>, HttpError> {
+        Ok(self.emojis.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn get_emoji(&self, id: &str) -> Result<Option<CustomEmoji>, HttpError> {
+        Ok(self.emojis.lock().unwrap().get(id).cloned())
+    }
+
+    async fn put_emoji(&self, emoji: &CustomEmoji) -> Result<(), HttpError> {
+        self.emojis
+            .lock()
+            .unwrap()
+            .insert(emoji.id.clone(), emoji.clone());
+        Ok(())
+    }
+
+    async fn delete_emoji(&self, id: &str) -> Result<(), HttpError> {
+        self.emojis.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
+
+impl BlockStore for Memory {
+    async fn blocks(&self, blocker: &url::Url) -> Result<Vec<Block>, HttpError> {
+        let blocks = self.blocks.lock().unwrap();
+        Ok(blocks
+            .iter()
+            .filter(|block| &block.blocker == blocker)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_block(
+        &self,
+        blocker: &url::Url,
+        blocked: &url::Url,
+    ) -> Result<Option<Block>, HttpError> {
+        let blocks = self.blocks.lock().unwrap();
+        Ok(blocks
+            .iter()
+            .find(|block| &block.blocker == blocker && &block.blocked == blocked)
+            .cloned())
+    }
+
+    async fn put_block(&self, block: &Block) -> Result<(), HttpError> {
+        self.blocks.lock().unwrap().push(block.clone());
+        Ok(())
+    }
+
+    async fn delete_block(&self, blocker: &url::Url, blocked: &url::Url) -> Result<(), HttpError> {
+        let mut blocks = self.blocks.lock().unwrap();
+        blocks.retain(|block| &block.blocker != blocker || &block.blocked != blocked);
+        Ok(())
+    }
+}
+
+impl DeliveryQueue for Memory {
+    async fn enqueue(&self, job: DeliveryJob) -> Result<(), HttpError> {
+        self.deliveries.lock().unwrap().push(job);
+        Ok(())
+    }
+}
+
+impl AccountStore for Memory {
+    type ActorInfo = Account;
+
+    async fn query(&self, name: &str) -> Result<Option<Account>, HttpError> {
+        Ok(self.accounts.lock().unwrap().get(name).cloned())
+    }
+}
+
+impl FollowStore for Memory {
+    async fn followers(&self, followee: &url::Url) -> Result<Vec<Follow>, HttpError> {
+        let follows = self.follows.lock().unwrap();
+        Ok(follows
+            .iter()
+            .filter(|follow| &follow.followee == followee)
+            .cloned()
+            .collect())
+    }
+
+    async fn following(&self, follower: &url::Url) -> Result<Vec<Follow>, HttpError> {
+        let follows = self.follows.lock().unwrap();
+        Ok(follows
+            .iter()
+            .filter(|follow| &follow.follower == follower)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> Result<Option<Follow>, HttpError> {
+        let follows = self.follows.lock().unwrap();
+        Ok(follows
+            .iter()
+            .find(|follow| &follow.follower == follower && &follow.followee == followee)
+            .cloned())
+    }
+
+    async fn put_follow(&self, follow: &Follow) -> Result<(), HttpError> {
+        let mut follows = self.follows.lock().unwrap();
+        follows.retain(|f| f.follower != follow.follower || f.followee != follow.followee);
+        follows.push(follow.clone());
+        Ok(())
+    }
+
+    async fn delete_follow(
+        &self,
+        follower: &url::Url,
+        followee: &url::Url,
+    ) -> Result<(), HttpError> {
+        let mut follows = self.follows.lock().unwrap();
+        follows.retain(|follow| &follow.follower != follower || &follow.followee != followee);
+        Ok(())
+    }
+}
+
+impl MuteStore for Memory {
+    async fn mutes(&self, muter: &url::Url) -> Result<Vec<Mute>, HttpError> {
+        let mutes = self.mutes.lock().unwrap();
+        Ok(mutes
+            .iter()
+            .filter(|mute| &mute.muter == muter)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_mute(
+        &self,
+        muter: &url::Url,
+        muted: &url::Url,
+    ) -> Result<Option<Mute>, HttpError> {
+        let mutes = self.mutes.lock().unwrap();
+        Ok(mutes
+            .iter()
+            .find(|mute| &mute.muter == muter && &mute.muted == muted)
+            .cloned())
+    }
+
+    async fn put_mute(&self, mute: &Mute) -> Result<(), HttpError> {
+        self.mutes.lock().unwrap().push(mute.clone());
+        Ok(())
+    }
+
+    async fn delete_mute(&self, muter: &url::Url, muted: &url::Url) -> Result<(), HttpError> {
+        let mut mutes = self.mutes.lock().unwrap();
+        mutes.retain(|mute| &mute.muter != muter || &mute.muted != muted);
+        Ok(())
+    }
+}
+
+/// Ranges are ignored, as tests only look at a handful of notifications.
+impl NotificationStore for Memory {
+    async fn notifications(
+        &self,
+        account: &str,
+        _range: &FeedRange,
+        limit: usize,
+    ) -> Result<Vec<Notification>, HttpError> {
+        let notifications = self.notifications.lock().unwrap();
+        Ok(notifications
+            .iter()
+            .rev()
+            .filter(|notification| notification.account == account)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn grouped_notifications(&self, group: &str) -> Result<Vec<Notification>, HttpError> {
+        let notifications = self.notifications.lock().unwrap();
+        Ok(notifications
+            .iter()
+            .filter(|notification| notification.group.as_deref() == Some(group))
+            .cloned()
+            .collect())
+    }
+
+    async fn put_notification(&self, notification: &Notification) -> Result<(), HttpError> {
+        let mut notifications = self.notifications.lock().unwrap();
+        notifications.retain(|n| n.account != notification.account || n.id != notification.id);
+        notifications.push(notification.clone());
+        Ok(())
+    }
+
+    async fn delete_notification(&self, account: &str, id: &str) -> Result<(), HttpError> {
+        let mut notifications = self.notifications.lock().unwrap();
+        notifications.retain(|n| n.account != account || n.id != id);
+        Ok(())
+    }
+
+    async fn notification_settings(
+        &self,
+        _account: &str,
+    ) -> Result<Option<NotificationSettings>, HttpError> {
+        Ok(None)
+    }
+
+    async fn put_notification_settings(
+        &self,
+        _settings: &NotificationSettings,
+    ) -> Result<(), HttpError> {
+        Ok(())
+    }
+}
+
+impl EventBus for Memory {
+    fn publish(&self, stream: Stream, event: Event) {
+        self.events.publish(stream, event)
+    }
+
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Arc<Envelope>> {
+        self.events.subscribe()
+    }
+}
+
+impl MigrationStore for Memory {
+    async fn get_migration(&self, account: &str) -> Result<Option<Migration>, HttpError> {
+        Ok(self.migrations.lock().unwrap().get(account).cloned())
+    }
+
+    async fn put_migration(&self, migration: &Migration) -> Result<(), HttpError> {
+        self.migrations
+            .lock()
+            .unwrap()
+            .insert(migration.account.clone(), migration.clone());
+        Ok(())
+    }
+}
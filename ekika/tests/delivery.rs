@@ -0,0 +1,54 @@
+mod common;
+
+use common::{local, url, Memory};
+use ekika::{delivery, visibility::Visibility};
+
+const ALICE: &str = "https://ekika.example/users/alice";
+const BOB: &str = "https://ekika.example/users/bob";
+const CAROL: &str = "https://allowed.example/users/carol";
+const DAVE: &str = "https://other.example/users/dave";
+
+async fn targets(store: &Memory, recipients: &[&str]) -> delivery::Targets {
+    let recipients = recipients.iter().map(|recipient| url(recipient));
+    delivery::targets(store, &local(), &url(ALICE), recipients, Visibility::Direct)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn local_recipients_are_delivered_in_allowlist_mode() {
+    let store = Memory::new();
+    store.allowlist(&["allowed.example"]);
+    store.remote_actor(CAROL);
+    store.remote_actor(DAVE);
+    let targets = targets(&store, &[BOB, CAROL, DAVE]).await;
+    assert_eq!(targets.local.into_iter().collect::<Vec<_>>(), ["bob"]);
+    assert_eq!(
+        targets.inboxes.into_iter().collect::<Vec<_>>(),
+        [url(&format!("{CAROL}/inbox"))]
+    );
+}
+
+#[tokio::test]
+async fn blocked_recipients_are_skipped() {
+    let store = Memory::new();
+    store.remote_actor(CAROL);
+    store.block(BOB, ALICE);
+    store.block(ALICE, CAROL);
+    let targets = targets(&store, &[BOB, CAROL]).await;
+    assert!(targets.local.is_empty());
+    assert!(targets.inboxes.is_empty());
+}
+
+#[tokio::test]
+async fn internal_inboxes_are_dropped() {
+    const EVE: &str = "https://localhost/users/eve";
+    let store = Memory::new();
+    store.remote_actor(CAROL);
+    store.remote_actor(EVE);
+    let targets = targets(&store, &[CAROL, EVE]).await;
+    assert_eq!(
+        targets.inboxes.into_iter().collect::<Vec<_>>(),
+        [url(&format!("{CAROL}/inbox"))]
+    );
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{local, url, Memory};
+use ekika::domain;
+
+const LOCAL: &str = "https://ekika.example/users/alice";
+const ALLOWED: &str = "https://allowed.example/users/carol";
+const ALLOWED_SUBDOMAIN: &str = "https://social.allowed.example/users/carol";
+const OTHER: &str = "https://other.example/users/dave";
+const SUSPENDED: &str = "https://spam.suspended.example/users/eve";
+
+async fn federates(store: &Memory, iri: &str) -> bool {
+    domain::federates(store, &local(), &url(iri)).await.unwrap()
+}
+
+#[tokio::test]
+async fn open_mode_federates_with_all_but_suspended_domains() {
+    let store = Memory::new();
+    store.suspend("suspended.example");
+    assert!(federates(&store, LOCAL).await);
+    assert!(federates(&store, ALLOWED).await);
+    assert!(federates(&store, OTHER).await);
+    assert!(!federates(&store, SUSPENDED).await);
+    let error = domain::ensure_federates(store.as_ref(), &url(SUSPENDED))
+        .await
+        .unwrap_err();
+    assert_eq!(error.status, http::StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn allowlist_mode_federates_with_allowed_domains_and_itself() {
+    let store = Memory::new();
+    store.allowlist(&["allowed.example", "suspended.example"]);
+    store.suspend("suspended.example");
+    assert!(federates(&store, LOCAL).await);
+    assert!(federates(&store, ALLOWED).await);
+    assert!(federates(&store, ALLOWED_SUBDOMAIN).await);
+    assert!(!federates(&store, OTHER).await);
+    // Suspensions still apply to allowed domains.
+    assert!(!federates(&store, SUSPENDED).await);
+    // Remote-only checks don't know the local host, which is not on the allowlist.
+    assert!(!domain::remote_federates(store.as_ref(), &url(LOCAL))
+        .await
+        .unwrap());
+    let error = domain::ensure_federates(store.as_ref(), &url(OTHER))
+        .await
+        .unwrap_err();
+    assert_eq!(error.status, http::StatusCode::FORBIDDEN);
+}
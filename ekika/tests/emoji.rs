@@ -0,0 +1,28 @@
+use ekika::emoji::shortcodes_in;
+
+fn shortcodes(text: &str) -> Vec<&str> {
+    shortcodes_in(text).into_iter().collect()
+}
+
+#[test]
+fn shortcodes_are_found_between_words() {
+    assert_eq!(shortcodes(":blobcat:"), ["blobcat"]);
+    assert_eq!(
+        shortcodes("hi :blobcat: and :blob_fox:!"),
+        ["blob_fox", "blobcat"]
+    );
+    assert_eq!(shortcodes("(:blobcat:)\n:ok:"), ["blobcat", "ok"]);
+}
+
+#[test]
+fn colons_inside_words_are_not_shortcodes() {
+    assert!(shortcodes("10:30:45").is_empty());
+    assert!(shortcodes("https://a:b@c:").is_empty());
+    assert!(shortcodes("a:blobcat:b").is_empty());
+    assert!(shortcodes(":blobcat::blobfox:").is_empty());
+}
+
+#[test]
+fn invalid_shortcodes_are_ignored() {
+    assert!(shortcodes(":a: :blob cat: :blob-cat:").is_empty());
+}
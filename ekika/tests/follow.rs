@@ -0,0 +1,126 @@
+mod common;
+
+use common::{local, url, Memory};
+use ekika::{
+    follow::{self, FollowStore},
+    model::follow::FollowState,
+};
+use serde_json::json;
+
+const ALICE: &str = "https://ekika.example/users/alice";
+const BOB: &str = "https://ekika.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+
+fn locked(store: &Memory, name: &str) {
+    let mut account = store.account(name);
+    account.locked = true;
+    store
+        .accounts
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), account);
+}
+
+async fn follow_state(store: &Memory, follower: &str, followee: &str) -> Option<FollowState> {
+    let follow = store
+        .get_follow(&url(follower), &url(followee))
+        .await
+        .unwrap();
+    follow.map(|follow| follow.state)
+}
+
+fn follow_activity() -> serde_json::Value {
+    json!({
+        "id": "https://remote.example/follows/1",
+        "type": "Follow",
+        "actor": CAROL,
+        "object": ALICE,
+    })
+}
+
+#[tokio::test]
+async fn remote_follows_of_unlocked_accounts_are_accepted() {
+    let store = Memory::new();
+    store.account("alice");
+    let carol = store.remote_actor(CAROL);
+    follow::handle_follow(store.as_ref(), &local(), &carol, &follow_activity())
+        .await
+        .unwrap();
+    assert_eq!(
+        follow_state(&store, CAROL, ALICE).await,
+        Some(FollowState::Accepted)
+    );
+    assert_eq!(store.delivered_inboxes(), [carol.inbox]);
+}
+
+#[tokio::test]
+async fn remote_follows_of_locked_accounts_wait_for_approval() {
+    let store = Memory::new();
+    locked(&store, "alice");
+    let carol = store.remote_actor(CAROL);
+    follow::handle_follow(store.as_ref(), &local(), &carol, &follow_activity())
+        .await
+        .unwrap();
+    assert_eq!(
+        follow_state(&store, CAROL, ALICE).await,
+        Some(FollowState::Pending)
+    );
+    assert!(store.delivered_inboxes().is_empty());
+
+    follow::authorize(store.as_ref(), &local(), &url(ALICE), &carol.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        follow_state(&store, CAROL, ALICE).await,
+        Some(FollowState::Accepted)
+    );
+    let delivered = store.delivered_activities();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0]["type"], "Accept");
+    assert_eq!(
+        delivered[0]["object"]["id"],
+        "https://remote.example/follows/1"
+    );
+}
+
+#[tokio::test]
+async fn rejected_follow_requests_are_dropped() {
+    let store = Memory::new();
+    locked(&store, "alice");
+    let carol = store.remote_actor(CAROL);
+    follow::handle_follow(store.as_ref(), &local(), &carol, &follow_activity())
+        .await
+        .unwrap();
+    follow::reject(store.as_ref(), &local(), &url(ALICE), &carol.id)
+        .await
+        .unwrap();
+    assert_eq!(follow_state(&store, CAROL, ALICE).await, None);
+    assert_eq!(store.delivered_activities()[0]["type"], "Reject");
+
+    let error = follow::authorize(store.as_ref(), &local(), &url(ALICE), &carol.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status, http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn local_follows_of_locked_accounts_wait_for_approval() {
+    let store = Memory::new();
+    locked(&store, "alice");
+    store.account("bob");
+    follow::follow(store.as_ref(), &local(), &url(BOB), &url(ALICE))
+        .await
+        .unwrap();
+    assert_eq!(
+        follow_state(&store, BOB, ALICE).await,
+        Some(FollowState::Pending)
+    );
+    follow::authorize(store.as_ref(), &local(), &url(ALICE), &url(BOB))
+        .await
+        .unwrap();
+    assert_eq!(
+        follow_state(&store, BOB, ALICE).await,
+        Some(FollowState::Accepted)
+    );
+    assert!(store.delivered_inboxes().is_empty());
+}
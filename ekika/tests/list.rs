@@ -0,0 +1,89 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::Memory;
+use ekika::{
+    list::{self, ListStore},
+    model::list::{List, ListMember},
+    stream::{Channel, Stream},
+};
+
+fn carol() -> url::Url {
+    "https://remote.example/users/carol".parse().unwrap()
+}
+
+async fn lists() -> Arc<Memory> {
+    let lists = Memory::new();
+    for (owner, id) in [("alice", "1"), ("bob", "2")] {
+        let list = List {
+            owner: owner.to_string(),
+            id: id.to_string(),
+            title: "Friends".to_string(),
+            created: chrono::Utc::now(),
+        };
+        lists.put_list(&list).await.unwrap();
+        let member = ListMember {
+            list: list.id,
+            member: carol(),
+            owner: list.owner,
+        };
+        lists.put_list_member(&member).await.unwrap();
+    }
+    lists
+}
+
+#[tokio::test]
+async fn posts_reach_lists_of_recipients() {
+    let lists = lists().await;
+    let streams = list::streams(
+        lists.as_ref(),
+        &carol(),
+        &["alice".to_string(), "bob".to_string()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        streams,
+        vec![Stream::List("1".to_string()), Stream::List("2".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn posts_skip_lists_of_others() {
+    // Bob is left out of the recipients when blocking carol or when the post is not addressed to bob.
+    let lists = lists().await;
+    let streams = list::streams(lists.as_ref(), &carol(), &["alice".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(streams, vec![Stream::List("1".to_string())]);
+}
+
+#[tokio::test]
+async fn posts_of_non_members_reach_no_list() {
+    let lists = lists().await;
+    let dave = "https://remote.example/users/dave".parse().unwrap();
+    let streams = list::streams(lists.as_ref(), &dave, &["alice".to_string()])
+        .await
+        .unwrap();
+    assert!(streams.is_empty());
+}
+
+#[tokio::test]
+async fn deleting_a_list_removes_its_members() {
+    let lists = lists().await;
+    let list = lists.get_list("alice", "1").await.unwrap().unwrap();
+    list::delete(lists.as_ref(), &list).await.unwrap();
+    assert!(lists.get_list("alice", "1").await.unwrap().is_none());
+    assert!(lists.list_members("1").await.unwrap().is_empty());
+    assert_eq!(lists.memberships(&carol()).await.unwrap().len(), 1);
+}
+
+#[test]
+fn list_channel_needs_a_list() {
+    assert_eq!(
+        Channel::parse("list", None, Some("1")).unwrap(),
+        Channel::List("1".to_string())
+    );
+    assert!(Channel::parse("list", None, None).is_err());
+}
@@ -0,0 +1,452 @@
+use axum::response::IntoResponse;
+use axum_helper::{headers::ProxyInfo, HttpError};
+use ekika::{
+    local::Local,
+    mastodon::{
+        self, AccountView, AttachmentView, CustomEmojiView, MentionView, NotificationView,
+        RelationshipView, SourceView, StatusView, StatusVisibility, TagView,
+    },
+    media::{MediaView, ThumbnailView},
+    model::{
+        account::{Account, AccountKind, ProfileField},
+        actor::RemoteActor,
+        media::{MediaKind, MediaState},
+    },
+};
+use serde_json::{json, Value};
+
+/// Fields of a Mastodon response which are checked under a prefix, such as `account.` for
+/// the author of a status.
+struct Entity {
+    /// Fields which may be null even though the fixture has a value.
+    nullable: &'static [&'static str],
+    /// Fields of the fixture this server leaves out.
+    unsupported: &'static [&'static str],
+}
+
+const ACCOUNT: Entity = Entity {
+    nullable: &["last_status_at", "fields[].verified_at"],
+    unsupported: &["noindex", "roles", "role", "source.follow_requests_count"],
+};
+
+const STATUS: Entity = Entity {
+    nullable: &[
+        "url",
+        "edited_at",
+        "in_reply_to_id",
+        "in_reply_to_account_id",
+        "reblog",
+        "poll",
+        "card",
+        "language",
+        "media_attachments[].url",
+        "media_attachments[].preview_url",
+        "media_attachments[].remote_url",
+        "media_attachments[].description",
+        "media_attachments[].blurhash",
+    ],
+    unsupported: &[
+        "pinned",
+        "filtered",
+        "application",
+        "media_attachments[].preview_remote_url",
+        "media_attachments[].text_url",
+        "media_attachments[].meta.original.size",
+        "media_attachments[].meta.original.aspect",
+    ],
+};
+
+const NONE: Entity = Entity {
+    nullable: &[],
+    unsupported: &[],
+};
+
+/// Polls and featured hashtags aren't implemented, and the length of a status counts URLs
+/// as they are.
+const INSTANCE: Entity = Entity {
+    nullable: &["thumbnail"],
+    unsupported: &[
+        "configuration.accounts",
+        "configuration.polls",
+        "configuration.statuses.characters_reserved_per_url",
+        "configuration.media_attachments.image_matrix_limit",
+        "configuration.media_attachments.video_frame_rate_limit",
+        "configuration.media_attachments.video_matrix_limit",
+    ],
+};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{name}.json", env!("CARGO_MANIFEST_DIR"));
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+struct Contract<'a> {
+    entities: &'a [(&'a str, Entity)],
+    errors: Vec<String>,
+}
+
+impl Contract<'_> {
+    fn is(&self, path: &str, fields: fn(&Entity) -> &'static [&'static str]) -> bool {
+        self.entities.iter().any(|(prefix, entity)| {
+            fields(entity)
+                .iter()
+                .any(|field| path == format!("{prefix}{field}"))
+        })
+    }
+
+    /// Compares `ours` with the value Mastodon returns at `path`.
+    fn check(&mut self, path: &str, theirs: &Value, ours: &Value) {
+        match (theirs, ours) {
+            // Mastodon leaves the type of values it returns as null open.
+            (Value::Null, _) => {}
+            (_, Value::Null) if self.is(path, |entity| entity.nullable) => {}
+            (Value::Object(theirs), Value::Object(ours)) => {
+                let field = |name: &str| match path {
+                    "" => name.to_string(),
+                    path => format!("{path}.{name}"),
+                };
+                for (name, value) in theirs {
+                    let field = field(name);
+                    match ours.get(name) {
+                        Some(ours) => self.check(&field, value, ours),
+                        None if self.is(&field, |entity| entity.unsupported) => {}
+                        None => self.errors.push(format!("{field} is missing")),
+                    }
+                }
+                for name in ours.keys().filter(|name| !theirs.contains_key(*name)) {
+                    self.errors
+                        .push(format!("{} isn't returned by Mastodon", field(name)));
+                }
+            }
+            (Value::Array(theirs), Value::Array(ours)) => {
+                let path = format!("{path}[]");
+                if let Some(theirs) = theirs.first() {
+                    for ours in ours {
+                        self.check(&path, theirs, ours);
+                    }
+                }
+            }
+            _ if kind_of(theirs) == kind_of(ours) => {}
+            _ => self.errors.push(format!(
+                "{path} is {} instead of {}",
+                kind_of(ours),
+                kind_of(theirs)
+            )),
+        }
+    }
+}
+
+/// Checks that `ours` has the fields of the fixture `name`, with values of the same types,
+/// and nothing else.
+fn assert_contract(name: &str, entities: &[(&str, Entity)], ours: &impl serde::Serialize) {
+    let ours = serde_json::to_value(ours).unwrap();
+    let mut contract = Contract {
+        entities,
+        errors: Vec::new(),
+    };
+    contract.check("", &fixture(name), &ours);
+    assert!(
+        contract.errors.is_empty(),
+        "{name}:\n{}",
+        contract.errors.join("\n")
+    );
+}
+
+fn url(s: &str) -> url::Url {
+    s.parse().unwrap()
+}
+
+fn local() -> Local {
+    Local::new(url("https://ekika.example/"))
+}
+
+fn proxy_info() -> ProxyInfo {
+    ProxyInfo {
+        host_proxied: false,
+        proto_proxied: false,
+        host: "ekika.example".to_string(),
+        proto: "https".to_string(),
+    }
+}
+
+fn alice() -> AccountView {
+    let account = Account {
+        kind: AccountKind::Person,
+        preferred_user_name: "alice".to_string(),
+        name: "Alice".to_string(),
+        summary: "<p>Down the rabbit hole.</p>".to_string(),
+        icon: Vec::new(),
+        role: Default::default(),
+        header: None,
+        fields: vec![ProfileField {
+            name: "Website".to_string(),
+            value: "https://alice.example".to_string(),
+            verified: Some(chrono::Utc::now()),
+        }],
+        locked: false,
+        discoverable: true,
+        bot: false,
+    };
+    let mut view = AccountView::local(&local(), "alice", account, (12, 3));
+    view.emojis = vec![CustomEmojiView {
+        shortcode: "rabbit".to_string(),
+        url: url("https://ekika.example/emojis/rabbit.png"),
+        static_url: url("https://ekika.example/emojis/rabbit.png"),
+        visible_in_picker: true,
+    }];
+    view
+}
+
+fn bob() -> AccountView {
+    let iri = url("https://social.example/users/bob");
+    let actor = RemoteActor {
+        id: iri.clone(),
+        inbox: url("https://social.example/users/bob/inbox"),
+        shared_inbox: None,
+        followers: None,
+        preferred_username: Some("bob".to_string()),
+        name: None,
+        summary: None,
+        public_key_id: None,
+        public_key_pem: None,
+        also_known_as: Vec::new(),
+        moved_to: None,
+        featured: None,
+        fetched: chrono::Utc::now(),
+    };
+    AccountView::remote(&local(), &iri, Some(actor), (1, 1))
+}
+
+fn status() -> StatusView {
+    let media = MediaView {
+        id: "media".to_string(),
+        kind: MediaKind::Image,
+        media_type: "image/png".to_string(),
+        state: MediaState::Ready,
+        url: Some(url("https://ekika.example/media/cat.png")),
+        description: Some("A grinning cat".to_string()),
+        width: Some(640),
+        height: Some(480),
+        size: 1024,
+        blurhash: Some("UFD+@Wxu00IU~qRjIUxu00ofRjRj-;WBRjof".to_string()),
+        focus: Some([0.0, 0.5]),
+        thumbnails: vec![ThumbnailView {
+            url: url("https://ekika.example/media/cat.320.png"),
+            width: 320,
+            height: 240,
+        }],
+    };
+    let author = alice();
+    let iri = url("https://ekika.example/objects/2");
+    StatusView {
+        id: mastodon::status_id("00000000000000000002", &iri),
+        uri: iri.clone(),
+        url: Some(iri),
+        created_at: chrono::Utc::now(),
+        edited_at: None,
+        content: "<p>Curiouser and curiouser!</p>".to_string(),
+        visibility: StatusVisibility::Public,
+        sensitive: false,
+        spoiler_text: String::new(),
+        media_attachments: vec![AttachmentView::from(media)],
+        mentions: vec![MentionView {
+            id: bob().id,
+            username: "bob".to_string(),
+            acct: "bob@social.example".to_string(),
+            url: url("https://social.example/users/bob"),
+        }],
+        tags: vec![TagView {
+            name: "wonderland".to_string(),
+            url: url("https://ekika.example/tags/wonderland"),
+        }],
+        emojis: author.emojis.clone(),
+        account: author,
+        reblogs_count: 1,
+        favourites_count: 2,
+        replies_count: 0,
+        in_reply_to_id: Some(mastodon::status_id(
+            "00000000000000000001",
+            &url("https://social.example/objects/1"),
+        )),
+        in_reply_to_account_id: Some(bob().id),
+        reblog: None,
+        poll: None,
+        card: None,
+        language: None,
+        favourited: false,
+        reblogged: false,
+        muted: false,
+        bookmarked: false,
+    }
+}
+
+#[test]
+fn accounts_match_mastodon() {
+    assert_contract("account", &[("", ACCOUNT)], &alice());
+    assert_contract("account", &[("", ACCOUNT)], &bob());
+    assert_eq!(bob().acct, "bob@social.example");
+    assert_eq!(alice().acct, "alice");
+
+    // `source` is only returned to the account itself.
+    let account = serde_json::to_value(alice()).unwrap();
+    assert!(account.get("source").is_none());
+    assert!(account["last_status_at"].is_null());
+    let mut credentials = alice();
+    credentials.source = Some(SourceView {
+        note: "Down the rabbit hole.".to_string(),
+        fields: Vec::new(),
+        privacy: StatusVisibility::Public,
+        sensitive: false,
+        language: None,
+    });
+    assert_contract("credential_account", &[("", ACCOUNT)], &credentials);
+}
+
+#[test]
+fn statuses_match_mastodon() {
+    let status = status();
+    assert_contract("status", &[("", STATUS), ("account.", ACCOUNT)], &status);
+
+    let mut boost = status.clone();
+    boost.reblog = Some(Box::new(status));
+    boost.in_reply_to_id = None;
+    boost.in_reply_to_account_id = None;
+    let value = serde_json::to_value(&boost).unwrap();
+    assert_eq!(value["visibility"], "public");
+    assert!(value["in_reply_to_id"].is_null());
+    assert!(value["reblog"]["reblog"].is_null());
+    assert!(value["edited_at"].is_null());
+}
+
+#[test]
+fn notifications_match_mastodon() {
+    let follow = NotificationView {
+        id: "1".to_string(),
+        kind: "follow",
+        created_at: chrono::Utc::now(),
+        account: bob(),
+        status: None,
+    };
+    assert_contract("notification_follow", &[("account.", ACCOUNT)], &follow);
+
+    let favourite = NotificationView {
+        kind: "favourite",
+        status: Some(status()),
+        ..follow
+    };
+    assert_contract(
+        "notification_favourite",
+        &[
+            ("account.", ACCOUNT),
+            ("status.", STATUS),
+            ("status.account.", ACCOUNT),
+        ],
+        &favourite,
+    );
+}
+
+#[test]
+fn relationships_match_mastodon() {
+    let relationship = RelationshipView {
+        id: alice().id,
+        following: true,
+        showing_reblogs: true,
+        notifying: false,
+        languages: None,
+        followed_by: false,
+        blocking: false,
+        blocked_by: false,
+        muting: false,
+        muting_notifications: false,
+        requested: false,
+        requested_by: false,
+        domain_blocking: false,
+        endorsed: false,
+        note: String::new(),
+    };
+    assert_contract("relationship", &[("", NONE)], &relationship);
+}
+
+#[tokio::test]
+async fn instance_matches_mastodon() {
+    let instance = mastodon::instance(proxy_info()).await.unwrap().0;
+    assert_contract("instance", &[("", INSTANCE)], &instance);
+    assert_eq!(instance["uri"], "ekika.example");
+    assert!(instance["version"].as_str().unwrap().starts_with("4.2.0 "));
+}
+
+#[test]
+fn ids_are_base64url_iris() {
+    let actor = url("https://social.example/users/bob?x=1");
+    let id = mastodon::account_id(&actor);
+    assert!(id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    assert_eq!(mastodon::iri_of(&id).ok(), Some(actor));
+
+    // Status ids order like their time keys, whatever the IRI.
+    let iri = url("https://ekika.example/objects/2");
+    let id = mastodon::status_id("00000000000000000002", &iri);
+    let (key, encoded) = id.split_once('.').unwrap();
+    assert_eq!(key, "00000000000000000002");
+    assert!(!encoded.contains(['+', '/', '=', '.']));
+    assert_eq!(mastodon::iri_of(&id).ok(), Some(iri));
+    let later = mastodon::status_id("00000000000000000010", &url("https://a.example/"));
+    assert!(id < later);
+
+    assert!(mastodon::iri_of("not base64!").is_err());
+    assert!(mastodon::iri_of(&mastodon::account_id(&url("https://a.example/"))[1..]).is_err());
+}
+
+async fn error_of(response: axum::response::Response) -> (http::StatusCode, Value) {
+    let response = mastodon::error_body(response).await;
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "application/json"
+    );
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn errors_match_mastodon() {
+    let error = HttpError::new_json(
+        &json!({"ok": false, "msg": "Record not found"}),
+        http::StatusCode::NOT_FOUND,
+    );
+    let (status, body) = error_of(error.into_response()).await;
+    assert_eq!(status, http::StatusCode::NOT_FOUND);
+    assert_eq!(body, fixture("error"));
+
+    let (status, body) =
+        error_of((http::StatusCode::BAD_REQUEST, "not json").into_response()).await;
+    assert_eq!(status, http::StatusCode::BAD_REQUEST);
+    assert_eq!(body, json!({"error": "Bad Request"}));
+
+    // Bodies too long to read are replaced by the status.
+    let long = json!({"msg": "x".repeat(100 * 1024)}).to_string();
+    let (status, body) =
+        error_of((http::StatusCode::INTERNAL_SERVER_ERROR, long).into_response()).await;
+    assert_eq!(status, http::StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body, json!({"error": "Internal Server Error"}));
+
+    let response = mastodon::error_body((http::StatusCode::OK, "fine").into_response()).await;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"fine");
+}
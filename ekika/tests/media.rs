@@ -0,0 +1,237 @@
+mod common;
+
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+use axum::Json;
+use axum_helper::HttpError;
+use common::Memory;
+use ekika::{
+    auth::Authenticated,
+    media::{self, MediaStore, MediaUpdate, ProcessingJob},
+    model::media::{Attachment, MediaKind, MediaState},
+    storage::BlobStore,
+};
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut body = Vec::new();
+    image::RgbImage::new(width, height)
+        .write_to(&mut Cursor::new(&mut body), image::ImageFormat::Png)
+        .unwrap();
+    body
+}
+
+fn attachment(id: &str, state: MediaState) -> Attachment {
+    Attachment {
+        id: id.to_string(),
+        account: "alice".to_string(),
+        kind: MediaKind::Image,
+        media_type: "image/png".to_string(),
+        key: format!("media/alice/{id}.png"),
+        url: None,
+        state,
+        name: Some("A cat".to_string()),
+        width: None,
+        height: None,
+        size: 0,
+        blurhash: None,
+        focal_point: None,
+        thumbnails: Vec::new(),
+        created: chrono::Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn files_round_trip() {
+    let store = Memory::new();
+    let url = store
+        .put_blob("media/alice/1.png", "image/png", png(4, 4))
+        .await
+        .unwrap();
+    assert_eq!(
+        url.as_str(),
+        "http://localhost:10000/files/media/alice/1.png"
+    );
+    let (content_type, body) = store
+        .files
+        .read("media/alice/1.png")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(content_type, "image/png");
+    assert_eq!(body, png(4, 4));
+
+    store
+        .put_blob("media/alice/1.png", "image/png", png(8, 8))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_blob("media/alice/1.png").await.unwrap(),
+        Some(png(8, 8))
+    );
+
+    store.delete_blob("media/alice/1.png").await.unwrap();
+    assert_eq!(store.get_blob("media/alice/1.png").await.unwrap(), None);
+    // Deleting is idempotent.
+    store.delete_blob("media/alice/1.png").await.unwrap();
+}
+
+#[tokio::test]
+async fn files_stay_under_the_root() {
+    let store = Memory::new();
+    for key in [
+        "../escape.png",
+        "media/../../escape.png",
+        "",
+        "media//1.png",
+        "a\\b",
+    ] {
+        let result = store.put_blob(key, "image/png", png(1, 1)).await;
+        assert_eq!(
+            result.err().map(|e| e.status),
+            Some(http::StatusCode::BAD_REQUEST)
+        );
+    }
+}
+
+#[tokio::test]
+async fn processed_upload_is_stored() {
+    let store = Memory::new();
+    store
+        .put_media(&attachment("1", MediaState::Processing))
+        .await
+        .unwrap();
+    let (jobs, receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(media::worker(store.clone(), receiver, vec![16], 1));
+    let job = ProcessingJob {
+        id: "1".to_string(),
+        body: png(64, 48),
+    };
+    jobs.send(job).await.unwrap();
+
+    let mut processed = None;
+    for _ in 0..100 {
+        let attachment = store.get_media("1").await.unwrap().unwrap();
+        if attachment.state != MediaState::Processing {
+            processed = Some(attachment);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let processed = processed.expect("the image is processed");
+    assert_eq!(processed.state, MediaState::Ready);
+    assert_eq!((processed.width, processed.height), (Some(64), Some(48)));
+    assert_eq!(processed.name.as_deref(), Some("A cat"));
+    let stored = store.get_blob(&processed.key).await.unwrap().unwrap();
+    assert_eq!(stored.len() as u64, processed.size);
+    assert_eq!(processed.thumbnails.len(), 1);
+    let thumbnail = &processed.thumbnails[0];
+    assert_eq!((thumbnail.width, thumbnail.height), (16, 12));
+    assert!(store.get_blob(&thumbnail.key).await.unwrap().is_some());
+}
+
+async fn update(store: &Arc<Memory>, update: MediaUpdate) -> Result<Attachment, HttpError> {
+    let auth = Authenticated {
+        account: "alice".to_string(),
+    };
+    let path = axum::extract::Path("1".to_string());
+    let state = axum::extract::State(store.clone());
+    let Json(view) = media::update_media(auth, path, state, Json(update)).await?;
+    let stored = store.get_media("1").await.unwrap().unwrap();
+    assert_eq!(view.description, stored.name);
+    Ok(stored)
+}
+
+#[tokio::test]
+async fn update_without_description_keeps_it() {
+    let store = Memory::new();
+    store
+        .put_media(&attachment("1", MediaState::Ready))
+        .await
+        .unwrap();
+    let updated = update(
+        &store,
+        MediaUpdate {
+            description: None,
+            focus: Some([0.5, -0.25]),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated.name.as_deref(), Some("A cat"));
+    let focus = updated.focal_point.unwrap();
+    assert_eq!((focus.x, focus.y), (0.5, -0.25));
+}
+
+#[tokio::test]
+async fn update_replaces_and_clears_description() {
+    let store = Memory::new();
+    store
+        .put_media(&attachment("1", MediaState::Ready))
+        .await
+        .unwrap();
+    let description = Some("A black cat".to_string());
+    let updated = update(
+        &store,
+        MediaUpdate {
+            description,
+            focus: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated.name.as_deref(), Some("A black cat"));
+    let description = Some(String::new());
+    let updated = update(
+        &store,
+        MediaUpdate {
+            description,
+            focus: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated.name, None);
+}
+
+#[tokio::test]
+async fn update_refuses_focus_outside_the_image() {
+    let store = Memory::new();
+    store
+        .put_media(&attachment("1", MediaState::Ready))
+        .await
+        .unwrap();
+    let result = update(
+        &store,
+        MediaUpdate {
+            description: Some("A dog".to_string()),
+            focus: Some([1.5, 0.0]),
+        },
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|e| e.status),
+        Some(http::StatusCode::UNPROCESSABLE_ENTITY)
+    );
+    let stored = store.get_media("1").await.unwrap().unwrap();
+    assert_eq!(stored.name.as_deref(), Some("A cat"));
+}
+
+#[tokio::test]
+async fn update_of_another_account_is_not_found() {
+    let store = Memory::new();
+    let mut others = attachment("1", MediaState::Ready);
+    others.account = "bob".to_string();
+    store.put_media(&others).await.unwrap();
+    let result = update(
+        &store,
+        MediaUpdate {
+            description: Some("Mine".to_string()),
+            focus: None,
+        },
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|e| e.status),
+        Some(http::StatusCode::NOT_FOUND)
+    );
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{local, url, Memory};
+use ekika::{
+    fetch::ActorStore,
+    follow::FollowStore,
+    migration,
+    model::{actor::RemoteActor, follow::FollowState},
+};
+use serde_json::json;
+
+const BOB: &str = "https://ekika.example/users/bob";
+const ORIGIN: &str = "https://old.example/users/carol";
+const TARGET: &str = "https://new.example/users/carol";
+
+/// Serve the target actor, listing `aliases` in `alsoKnownAs`.
+fn serve_target(store: &Memory, aliases: &[&str]) {
+    store.documents.lock().unwrap().insert(
+        url(TARGET),
+        json!({
+            "id": TARGET,
+            "type": "Person",
+            "inbox": format!("{TARGET}/inbox"),
+            "alsoKnownAs": aliases,
+        }),
+    );
+}
+
+fn move_activity() -> serde_json::Value {
+    json!({
+        "id": "https://old.example/moves/1",
+        "type": "Move",
+        "actor": ORIGIN,
+        "object": ORIGIN,
+        "target": TARGET,
+    })
+}
+
+async fn handle_move(store: &Memory, signer: &RemoteActor, activity: serde_json::Value) {
+    migration::handle_move(store, &local(), signer, &activity)
+        .await
+        .unwrap();
+}
+
+async fn follow_state(store: &Memory, followee: &str) -> Option<FollowState> {
+    let follow = store.get_follow(&url(BOB), &url(followee)).await.unwrap();
+    follow.map(|follow| follow.state)
+}
+
+#[tokio::test]
+async fn verified_moves_repoint_local_followers() {
+    let store = Memory::new();
+    let origin = store.remote_actor(ORIGIN);
+    store.follow(BOB, ORIGIN, FollowState::Accepted);
+    serve_target(&store, &[ORIGIN]);
+    handle_move(&store, &origin, move_activity()).await;
+    assert_eq!(follow_state(&store, ORIGIN).await, None);
+    assert_eq!(
+        follow_state(&store, TARGET).await,
+        Some(FollowState::Pending)
+    );
+    let origin = store.get_actor(&url(ORIGIN)).await.unwrap().unwrap();
+    assert_eq!(origin.moved_to, Some(url(TARGET)));
+    let kinds = store
+        .delivered_activities()
+        .into_iter()
+        .map(|activity| activity["type"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["Undo", "Follow"]);
+}
+
+#[tokio::test]
+async fn moves_to_targets_without_the_alias_are_ignored() {
+    let store = Memory::new();
+    let origin = store.remote_actor(ORIGIN);
+    store.follow(BOB, ORIGIN, FollowState::Accepted);
+    serve_target(&store, &[]);
+    handle_move(&store, &origin, move_activity()).await;
+    assert_eq!(
+        follow_state(&store, ORIGIN).await,
+        Some(FollowState::Accepted)
+    );
+    assert_eq!(follow_state(&store, TARGET).await, None);
+    let origin = store.get_actor(&url(ORIGIN)).await.unwrap().unwrap();
+    assert_eq!(origin.moved_to, None);
+    assert!(store.deliveries.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn moves_of_other_actors_are_ignored() {
+    let store = Memory::new();
+    let signer = store.remote_actor("https://old.example/users/mallory");
+    store.follow(BOB, ORIGIN, FollowState::Accepted);
+    serve_target(&store, &[ORIGIN]);
+    handle_move(&store, &signer, move_activity()).await;
+    assert_eq!(
+        follow_state(&store, ORIGIN).await,
+        Some(FollowState::Accepted)
+    );
+    assert!(store.fetched.lock().unwrap().is_empty());
+}
@@ -0,0 +1,322 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Form,
+};
+use axum_helper::{headers::ProxyInfo, HttpError};
+use common::Memory;
+use ekika::{
+    auth::{self, TokenStore},
+    model::{
+        oauth::{Application, AuthorizationCode, Session},
+        token::Token,
+    },
+    oauth::{self, AuthorizeParams, FormOrJson, LoginForm, TokenRequest},
+};
+
+fn status<T>(result: Result<T, HttpError>) -> http::StatusCode {
+    match result {
+        Ok(_) => panic!("succeeded"),
+        Err(e) => e.status,
+    }
+}
+
+const REDIRECT_URI: &str = "https://client.example/callback";
+
+/// Store with the application `app`, whose secret is `secret`, and the session `cookie` of
+/// alice.
+fn store() -> Arc<Memory> {
+    let store = Memory::new();
+    for id in ["app", "other"] {
+        store.applications.lock().unwrap().insert(
+            id.to_string(),
+            Application {
+                id: id.to_string(),
+                secret: auth::digest("secret"),
+                name: id.to_string(),
+                website: None,
+                redirect_uris: vec![REDIRECT_URI.to_string()],
+                scopes: vec!["read".to_string(), "write".to_string()],
+                created: chrono::Utc::now(),
+            },
+        );
+    }
+    store.sessions.lock().unwrap().insert(
+        auth::digest("cookie"),
+        Session {
+            id: auth::digest("cookie"),
+            account: "alice".to_string(),
+            csrf: "csrf".to_string(),
+            expires: chrono::Utc::now() + chrono::Duration::hours(1),
+        },
+    );
+    store
+}
+
+fn proxy_info() -> ProxyInfo {
+    ProxyInfo {
+        host_proxied: false,
+        proto_proxied: false,
+        host: "localhost".to_string(),
+        proto: "http".to_string(),
+    }
+}
+
+fn params(csrf: Option<&str>) -> AuthorizeParams {
+    AuthorizeParams {
+        response_type: "code".to_string(),
+        client_id: "app".to_string(),
+        redirect_uri: REDIRECT_URI.to_string(),
+        scope: Some("read".to_string()),
+        state: None,
+        code_challenge: None,
+        code_challenge_method: None,
+        csrf: csrf.map(str::to_string),
+        decision: Some("approve".to_string()),
+    }
+}
+
+fn cookie(value: &str) -> http::HeaderMap {
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::COOKIE,
+        format!("theme=dark; ekika_session={value}")
+            .parse()
+            .unwrap(),
+    );
+    headers
+}
+
+fn grant(store: &Memory, code: &str) {
+    store.codes.lock().unwrap().insert(
+        auth::digest(code),
+        AuthorizationCode {
+            id: auth::digest(code),
+            client: "app".to_string(),
+            account: "alice".to_string(),
+            redirect_uri: REDIRECT_URI.to_string(),
+            scopes: vec!["read".to_string()],
+            challenge: None,
+            expires: chrono::Utc::now() + chrono::Duration::minutes(1),
+        },
+    );
+}
+
+fn exchange(client: &str, code: &str, redirect_uri: &str) -> FormOrJson<TokenRequest> {
+    FormOrJson(TokenRequest {
+        grant_type: "authorization_code".to_string(),
+        code: Some(code.to_string()),
+        redirect_uri: Some(redirect_uri.to_string()),
+        client_id: Some(client.to_string()),
+        client_secret: Some("secret".to_string()),
+        code_verifier: None,
+        scope: None,
+    })
+}
+
+#[test]
+fn routes_declare_known_scopes() {
+    for (i, (method, route, scope)) in oauth::ROUTE_SCOPES.iter().enumerate() {
+        assert!(oauth::SCOPES.contains(scope), "{method} {route}: {scope}");
+        assert!(
+            !oauth::ROUTE_SCOPES[..i]
+                .iter()
+                .any(|(m, r, _)| m == method && r == route),
+            "{method} {route} is declared twice"
+        );
+    }
+    assert_eq!(
+        oauth::route_scope(&http::Method::GET, "/api/v1/lists/:id/accounts"),
+        Some("read:lists")
+    );
+    assert_eq!(
+        oauth::route_scope(&http::Method::HEAD, "/api/v1/timelines/list/:id"),
+        Some("read:lists")
+    );
+    assert_eq!(
+        oauth::route_scope(&http::Method::DELETE, "/api/v1/lists/:id/accounts"),
+        Some("write:lists")
+    );
+    assert_eq!(
+        oauth::route_scope(&http::Method::GET, "/api/v1/unknown"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn code_is_kept_for_a_wrong_redirect_uri() {
+    let store = store();
+    grant(&store, "code");
+    let result = oauth::token(
+        State(store.clone()),
+        http::HeaderMap::new(),
+        exchange("app", "code", "https://attacker.example/"),
+    )
+    .await;
+    assert_eq!(status(result), http::StatusCode::BAD_REQUEST);
+    assert!(store
+        .codes
+        .lock()
+        .unwrap()
+        .contains_key(&auth::digest("code")));
+
+    oauth::token(
+        State(store.clone()),
+        http::HeaderMap::new(),
+        exchange("app", "code", REDIRECT_URI),
+    )
+    .await
+    .unwrap();
+    assert!(store.codes.lock().unwrap().is_empty());
+    let result = oauth::token(
+        State(store.clone()),
+        http::HeaderMap::new(),
+        exchange("app", "code", REDIRECT_URI),
+    )
+    .await;
+    assert_eq!(status(result), http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn code_is_kept_for_another_client() {
+    let store = store();
+    grant(&store, "code");
+    let result = oauth::token(
+        State(store.clone()),
+        http::HeaderMap::new(),
+        exchange("other", "code", REDIRECT_URI),
+    )
+    .await;
+    assert_eq!(status(result), http::StatusCode::BAD_REQUEST);
+    assert!(store
+        .codes
+        .lock()
+        .unwrap()
+        .contains_key(&auth::digest("code")));
+    assert!(store.tokens.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn authorize_asks_to_sign_in() {
+    let store = store();
+    let uri: http::Uri = "/oauth/authorize?response_type=code&client_id=app"
+        .parse()
+        .unwrap();
+    let response = oauth::authorize(
+        Query(params(None)),
+        State(store.clone()),
+        proxy_info(),
+        uri.clone(),
+        http::HeaderMap::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    let location = response.headers()[http::header::LOCATION].to_str().unwrap();
+    assert_eq!(
+        location,
+        "http://localhost/oauth/login?next=http%3A%2F%2Flocalhost%2Foauth%2Fauthorize%3Fresponse_type%3Dcode%26client_id%3Dapp"
+    );
+
+    let response = oauth::authorize(
+        Query(params(None)),
+        State(store.clone()),
+        proxy_info(),
+        uri,
+        cookie("cookie"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn approve_needs_the_session_and_its_csrf_value() {
+    let store = store();
+    for (headers, csrf) in [
+        (http::HeaderMap::new(), Some("csrf")),
+        (cookie("other"), Some("csrf")),
+        (cookie("cookie"), None),
+        (cookie("cookie"), Some("wrong")),
+    ] {
+        let result = oauth::approve(State(store.clone()), headers, Form(params(csrf))).await;
+        assert_eq!(status(result), http::StatusCode::FORBIDDEN);
+    }
+    assert!(store.codes.lock().unwrap().is_empty());
+
+    let response = oauth::approve(
+        State(store.clone()),
+        cookie("cookie"),
+        Form(params(Some("csrf"))),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    let location = response.headers()[http::header::LOCATION].to_str().unwrap();
+    assert!(location.starts_with("https://client.example/callback?code="));
+    let codes = store.codes.lock().unwrap();
+    let code = codes.values().next().unwrap();
+    assert_eq!(code.account, "alice");
+    assert_eq!(code.scopes, ["read"]);
+}
+
+#[tokio::test]
+async fn login_starts_a_session() {
+    let store = store();
+    for (id, scopes) in [("full", None), ("scoped", Some(vec!["read".to_string()]))] {
+        store
+            .put_token(&Token {
+                id: auth::digest(id),
+                account: Some("bob".to_string()),
+                client: None,
+                scopes,
+                created: None,
+            })
+            .await
+            .unwrap();
+    }
+    let next = "http://localhost/oauth/authorize?client_id=app";
+    let form = |token: &str, next: &str| {
+        Form(LoginForm {
+            next: next.to_string(),
+            token: token.to_string(),
+        })
+    };
+
+    let result = oauth::login(
+        State(store.clone()),
+        proxy_info(),
+        form("full", "https://attacker.example/oauth/authorize"),
+    )
+    .await;
+    assert_eq!(status(result), http::StatusCode::BAD_REQUEST);
+    for token in ["scoped", "unknown"] {
+        let response = oauth::login(State(store.clone()), proxy_info(), form(token, next))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(store.sessions.lock().unwrap().len(), 1);
+
+    let response = oauth::login(State(store.clone()), proxy_info(), form("full", next))
+        .await
+        .unwrap()
+        .into_response();
+    assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[http::header::LOCATION], next);
+    let set_cookie = response.headers()[http::header::SET_COOKIE]
+        .to_str()
+        .unwrap();
+    assert!(set_cookie.contains("; Path=/oauth/; "));
+    assert!(set_cookie.contains("HttpOnly"));
+    let secret = set_cookie
+        .strip_prefix("ekika_session=")
+        .and_then(|cookie| cookie.split(';').next())
+        .unwrap();
+    let session = store.sessions.lock().unwrap()[&auth::digest(secret)].clone();
+    assert_eq!(session.account, "bob");
+}
@@ -0,0 +1,130 @@
+use std::io::Cursor;
+
+use ekika::processing::{self, strip_gif, strip_webp};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    DynamicImage, ExtendedColorType, ImageFormat, RgbaImage,
+};
+
+/// Two frame animation which loops forever.
+fn gif() -> Vec<u8> {
+    let mut body = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut body);
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        encoder
+            .encode(&[255; 4 * 4 * 4], 4, 4, ExtendedColorType::Rgba8)
+            .unwrap();
+        encoder
+            .encode(&[0; 4 * 4 * 4], 4, 4, ExtendedColorType::Rgba8)
+            .unwrap();
+    }
+    body
+}
+
+/// `gif` with a comment and an XMP application extension before its trailer.
+fn gif_with_metadata() -> Vec<u8> {
+    let mut body = gif();
+    let trailer = body.pop().unwrap();
+    body.extend_from_slice(&[0x21, 0xfe, 5]);
+    body.extend_from_slice(b"alice");
+    body.push(0);
+    body.extend_from_slice(&[0x21, 0xff, 11]);
+    body.extend_from_slice(b"XMP DataXMP");
+    body.push(8);
+    body.extend_from_slice(b"<x:xmpm>");
+    body.push(0);
+    body.push(trailer);
+    body
+}
+
+fn contains(body: &[u8], needle: &[u8]) -> bool {
+    body.windows(needle.len()).any(|window| window == needle)
+}
+
+fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = fourcc.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// Extended WebP file of a 4x4 image with an odd sized EXIF chunk.
+fn webp_with_exif() -> Vec<u8> {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, [10, 20, 30, 255].into()));
+    let mut simple = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut simple), ImageFormat::WebP)
+        .unwrap();
+    // The image chunk of the simple file, after the RIFF header.
+    let image_chunk = &simple[12..];
+    let mut vp8x = vec![0x08 | 0x10, 0, 0, 0];
+    vp8x.extend_from_slice(&[3, 0, 0, 3, 0, 0]);
+    let mut chunks = chunk(b"VP8X", &vp8x);
+    chunks.extend_from_slice(image_chunk);
+    chunks.extend(chunk(b"EXIF", b"MM\0*alice"));
+    let mut body = b"RIFF".to_vec();
+    body.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    body.extend_from_slice(b"WEBP");
+    body.extend(chunks);
+    body
+}
+
+#[test]
+fn gif_metadata_is_stripped() {
+    let body = gif_with_metadata();
+    let stripped = strip_gif(&body).unwrap();
+    assert!(!contains(&stripped, b"alice"));
+    assert!(!contains(&stripped, b"XMP Data"));
+    // The loop count and both frames are kept as they were.
+    assert!(contains(&stripped, b"NETSCAPE2.0"));
+    assert_eq!(stripped, gif());
+    assert_eq!(strip_gif(&stripped).unwrap(), stripped);
+}
+
+#[test]
+fn truncated_gif_is_rejected() {
+    let body = gif();
+    assert!(strip_gif(&body[..body.len() - 8]).is_err());
+    assert!(strip_gif(&body[..8]).is_err());
+}
+
+#[test]
+fn webp_metadata_is_stripped() {
+    let body = webp_with_exif();
+    let stripped = strip_webp(&body).unwrap();
+    assert!(!contains(&stripped, b"EXIF"));
+    assert!(!contains(&stripped, b"alice"));
+    // The EXIF flag is cleared and the alpha flag kept.
+    assert_eq!(stripped[20], 0x10);
+    assert_eq!(
+        u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize,
+        stripped.len() - 8
+    );
+    let image = image::load_from_memory_with_format(&stripped, ImageFormat::WebP).unwrap();
+    assert_eq!((image.width(), image.height()), (4, 4));
+    assert!(strip_webp(b"RIFF\0\0\0\0WAVE").is_err());
+}
+
+#[test]
+fn webp_is_not_reencoded() {
+    let body = webp_with_exif();
+    let processed = processing::process("image/webp", body.clone(), &[2]).unwrap();
+    assert_eq!(processed.original.media_type, "image/webp");
+    assert_eq!(processed.original.body, strip_webp(&body).unwrap());
+    assert_eq!(
+        (processed.original.width, processed.original.height),
+        (4, 4)
+    );
+    assert_eq!(processed.thumbnails.len(), 1);
+}
+
+#[test]
+fn gif_keeps_its_frames() {
+    let processed = processing::process("image/gif", gif_with_metadata(), &[]).unwrap();
+    assert_eq!(processed.original.media_type, "image/gif");
+    assert_eq!(processed.original.body, gif());
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use axum_helper::HttpError;
+use ekika::{
+    model::account::ProfileField,
+    profile::{verify_fields, Page, PageFetcher, MAX_PAGE_SIZE, MAX_REDIRECTS},
+};
+use serde_json::json;
+
+const ACTOR: &str = "https://social.example/users/alice";
+const PROFILE: &str = "https://social.example/@alice";
+
+/// Serves canned pages and records every request.
+#[derive(Default)]
+struct Pages {
+    pages: HashMap<url::Url, Page>,
+    requested: std::sync::Mutex<Vec<url::Url>>,
+}
+
+impl Pages {
+    fn with(mut self, url: &str, page: Page) -> Self {
+        self.pages.insert(url.parse().unwrap(), page);
+        self
+    }
+
+    fn requested(&self) -> Vec<url::Url> {
+        self.requested.lock().unwrap().clone()
+    }
+}
+
+impl PageFetcher for Pages {
+    async fn fetch_page(&self, url: &url::Url, _limit: u64) -> Result<Page, HttpError> {
+        self.requested.lock().unwrap().push(url.clone());
+        self.pages.get(url).cloned().ok_or_else(|| {
+            HttpError::new_json(
+                &json!({"ok": false, "msg": "not found"}),
+                http::StatusCode::BAD_GATEWAY,
+            )
+        })
+    }
+}
+
+fn field(value: &str) -> ProfileField {
+    ProfileField {
+        name: "Website".to_string(),
+        value: value.to_string(),
+        verified: None,
+    }
+}
+
+fn html(href: &str) -> Page {
+    Page::Html(format!(r#"<html><a rel="me" href="{href}">me</a></html>"#))
+}
+
+async fn verified(pages: &Pages, value: &str) -> bool {
+    let profiles = [ACTOR.parse().unwrap(), PROFILE.parse().unwrap()];
+    let fields = verify_fields(pages, &profiles, &[], vec![field(value)]).await;
+    fields[0].verified.is_some()
+}
+
+#[tokio::test]
+async fn page_linking_back_is_verified() {
+    let pages = Pages::default().with("https://alice.example/", html(PROFILE));
+    assert!(verified(&pages, "https://alice.example/").await);
+}
+
+#[tokio::test]
+async fn page_linking_to_actor_is_verified() {
+    let pages = Pages::default().with("https://alice.example/", html(ACTOR));
+    assert!(verified(&pages, "https://alice.example/").await);
+}
+
+#[tokio::test]
+async fn page_linking_elsewhere_is_not_verified() {
+    let pages = Pages::default().with(
+        "https://alice.example/",
+        html("https://social.example/@bob"),
+    );
+    assert!(!verified(&pages, "https://alice.example/").await);
+}
+
+#[tokio::test]
+async fn plain_text_is_not_fetched() {
+    let pages = Pages::default();
+    assert!(!verified(&pages, "alice.example").await);
+    assert!(pages.requested().is_empty());
+}
+
+#[tokio::test]
+async fn previous_verification_is_kept() {
+    let pages = Pages::default().with("https://alice.example/", html(PROFILE));
+    let mut previous = field("https://alice.example/");
+    let since = chrono::Utc::now() - chrono::Duration::days(30);
+    previous.verified = Some(since);
+    let fields = verify_fields(
+        &pages,
+        &[PROFILE.parse().unwrap()],
+        &[previous],
+        vec![field("https://alice.example/")],
+    )
+    .await;
+    assert_eq!(fields[0].verified, Some(since));
+}
+
+#[tokio::test]
+async fn redirect_is_followed() {
+    let pages = Pages::default()
+        .with(
+            "https://alice.example/",
+            Page::Redirect("https://www.alice.example/about".parse().unwrap()),
+        )
+        // Relative links resolve against the page the redirect ended at.
+        .with("https://www.alice.example/about", html("/@alice"));
+    let fields = verify_fields(
+        &pages,
+        &["https://www.alice.example/@alice".parse().unwrap()],
+        &[],
+        vec![field("https://alice.example/")],
+    )
+    .await;
+    assert!(fields[0].verified.is_some());
+}
+
+#[tokio::test]
+async fn redirect_to_internal_address_is_not_fetched() {
+    let pages = Pages::default()
+        .with(
+            "https://alice.example/",
+            Page::Redirect("http://169.254.169.254/latest/meta-data/".parse().unwrap()),
+        )
+        .with("http://169.254.169.254/latest/meta-data/", html(PROFILE));
+    assert!(!verified(&pages, "https://alice.example/").await);
+    assert_eq!(pages.requested().len(), 1);
+}
+
+#[tokio::test]
+async fn internal_link_is_not_fetched() {
+    let pages = Pages::default().with("http://localhost:8080/", html(PROFILE));
+    assert!(!verified(&pages, "http://localhost:8080/").await);
+    assert!(pages.requested().is_empty());
+}
+
+#[tokio::test]
+async fn redirect_loop_is_cut_off() {
+    let pages = Pages::default().with(
+        "https://alice.example/",
+        Page::Redirect("https://alice.example/".parse().unwrap()),
+    );
+    assert!(!verified(&pages, "https://alice.example/").await);
+    assert_eq!(pages.requested().len(), MAX_REDIRECTS + 1);
+}
+
+#[tokio::test]
+async fn oversized_page_is_not_verified() {
+    let padding = " ".repeat(MAX_PAGE_SIZE as usize);
+    let pages = Pages::default().with(
+        "https://alice.example/",
+        Page::Html(format!(r#"<a rel="me" href="{PROFILE}">me</a>{padding}"#)),
+    );
+    assert!(!verified(&pages, "https://alice.example/").await);
+}
+
+#[tokio::test]
+async fn failed_fetch_is_not_verified() {
+    let pages = Pages::default();
+    assert!(!verified(&pages, "https://alice.example/").await);
+}
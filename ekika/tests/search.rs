@@ -0,0 +1,219 @@
+use ekika::{
+    model::object::StoredObject,
+    search::{self, DocumentKind, Embedded, SearchDocument, SearchIndex, SearchQuery},
+    visibility::Visibility,
+};
+use serde_json::json;
+
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+fn url(s: &str) -> url::Url {
+    s.parse().unwrap()
+}
+
+/// Post of alice, addressed by `to` and `cc`.
+fn post(id: &str, content: &str, to: &[&str], cc: &[&str]) -> StoredObject {
+    StoredObject::new(
+        json!({
+            "id": format!("https://example.com/objects/{id}"),
+            "type": "Note",
+            "attributedTo": "https://example.com/users/alice",
+            "content": content,
+            "to": to,
+            "cc": cc,
+            "published": "2024-05-01T00:00:00Z",
+            "tag": [{"type": "Hashtag", "name": "#Rust"}],
+        }),
+        &url("https://example.com/users/alice/followers"),
+    )
+    .unwrap()
+}
+
+fn query(text: &str, viewer: Option<&str>, following: &[&str]) -> SearchQuery {
+    SearchQuery {
+        text: text.to_string(),
+        kind: None,
+        viewer: viewer.map(url),
+        following: following.iter().map(|actor| url(actor)).collect(),
+        limit: 20,
+        offset: 0,
+    }
+}
+
+async fn ids(index: &Embedded, query: &SearchQuery) -> Vec<String> {
+    index
+        .search(query)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|hit| hit.id)
+        .collect()
+}
+
+/// Ids of the posts with "hello" `viewer` finds, without the common prefix.
+async fn visible(index: &Embedded, viewer: Option<&str>, following: &[&str]) -> Vec<String> {
+    let mut found = ids(index, &query("hello", viewer, following))
+        .await
+        .into_iter()
+        .map(|id| {
+            id.trim_start_matches("https://example.com/objects/")
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
+
+fn account(id: &str, text: &str) -> SearchDocument {
+    SearchDocument {
+        id: id.to_string(),
+        kind: DocumentKind::Account,
+        text: text.to_string(),
+        author: None,
+        visibility: None,
+        readers: Vec::new(),
+        published: None,
+    }
+}
+
+#[test]
+fn words_are_lowercase() {
+    assert_eq!(
+        search::tokens("Hello, World_1! Café-au-lait"),
+        ["hello", "world_1", "café", "au", "lait"]
+    );
+    assert!(search::tokens(" ... ").is_empty());
+}
+
+#[test]
+fn chinese_and_japanese_are_split_into_pairs() {
+    assert_eq!(
+        search::tokens("東京タワーに行った"),
+        ["東京", "京タ", "タワ", "ワー", "ーに", "に行", "行っ", "った"]
+    );
+    assert_eq!(search::tokens("猫"), ["猫"]);
+    assert_eq!(search::tokens("Rust入門"), ["rust", "入門"]);
+    // Korean is written with spaces and keeps its words.
+    assert_eq!(search::tokens("서울 여행"), ["서울", "여행"]);
+}
+
+#[tokio::test]
+async fn words_match_the_start_of_words() {
+    let index = Embedded::default();
+    index
+        .index(&account("@alice", "alice\nAlice Liddell"))
+        .await
+        .unwrap();
+    index
+        .index(&account("@alicia", "alicia\nAlicia"))
+        .await
+        .unwrap();
+    index.index(&account("@bob", "bob\nBob")).await.unwrap();
+
+    let mut found = ids(&index, &query("ALI", None, &[])).await;
+    found.sort();
+    assert_eq!(found, ["@alice", "@alicia"]);
+    // Every word has to match.
+    assert_eq!(ids(&index, &query("ali lidd", None, &[])).await, ["@alice"]);
+    // Exact matches come first.
+    index.index(&account("@zed", "zed\nali")).await.unwrap();
+    assert_eq!(ids(&index, &query("ali", None, &[])).await[0], "@zed");
+    assert_eq!(ids(&index, &query("alicia", None, &[])).await, ["@alicia"]);
+    assert!(ids(&index, &query("lice", None, &[])).await.is_empty());
+    assert!(ids(&index, &query("  ", None, &[])).await.is_empty());
+
+    let mut page = query("ali", None, &[]);
+    page.limit = 2;
+    page.offset = 1;
+    assert_eq!(index.search(&page).await.unwrap().len(), 2);
+    page.offset = 2;
+    assert_eq!(index.search(&page).await.unwrap().len(), 1);
+    page.kind = Some(DocumentKind::Hashtag);
+    page.offset = 0;
+    assert!(index.search(&page).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reindexing_replaces_the_text() {
+    let index = Embedded::default();
+    index
+        .index(&account("@alice", "alice\nWonderland"))
+        .await
+        .unwrap();
+    index
+        .index(&account("@alice", "alice\nLooking glass"))
+        .await
+        .unwrap();
+    assert!(ids(&index, &query("wonderland", None, &[]))
+        .await
+        .is_empty());
+    assert_eq!(ids(&index, &query("glass", None, &[])).await, ["@alice"]);
+
+    index.remove("@alice").await.unwrap();
+    assert!(ids(&index, &query("alice", None, &[])).await.is_empty());
+    index.remove("@alice").await.unwrap();
+}
+
+#[tokio::test]
+async fn posts_and_hashtags_are_indexed() {
+    let index = Embedded::default();
+    let object = post("1", "<p>東京タワーに<b>行った</b></p>", &[PUBLIC], &[]);
+    search::index_object(&index, &object).await;
+
+    let id = "https://example.com/objects/1";
+    assert_eq!(ids(&index, &query("タワー", None, &[])).await, [id]);
+    assert_eq!(ids(&index, &query("東京", None, &[])).await, [id]);
+    assert_eq!(ids(&index, &query("行った", None, &[])).await, [id]);
+    assert!(ids(&index, &query("大阪", None, &[])).await.is_empty());
+    assert_eq!(
+        ids(&index, &query("rust", None, &[])).await,
+        ["#rust".to_string()]
+    );
+
+    search::remove_object(&index, &object.id).await;
+    assert!(ids(&index, &query("東京", None, &[])).await.is_empty());
+}
+
+#[tokio::test]
+async fn posts_are_found_by_who_may_see_them() {
+    let alice = "https://example.com/users/alice";
+    let followers = "https://example.com/users/alice/followers";
+    let bob = "https://example.net/users/bob";
+    let carol = "https://example.net/users/carol";
+    let index = Embedded::default();
+    let posts = [
+        post("public", "hello public", &[PUBLIC], &[followers]),
+        post("unlisted", "hello unlisted", &[followers], &[PUBLIC]),
+        post("followers", "hello followers", &[followers], &[]),
+        post("direct", "hello direct", &[bob], &[]),
+    ];
+    assert_eq!(
+        posts.each_ref().map(|post| post.visibility),
+        [
+            Visibility::Public,
+            Visibility::Unlisted,
+            Visibility::FollowersOnly,
+            Visibility::Direct,
+        ]
+    );
+    for post in &posts {
+        search::index_object(&index, post).await;
+    }
+    assert_eq!(visible(&index, None, &[]).await, ["public"]);
+    assert_eq!(visible(&index, Some(carol), &[]).await, ["public"]);
+    // Followers see unlisted and followers-only posts, but not posts sent to others.
+    assert_eq!(
+        visible(&index, Some(carol), &[alice]).await,
+        ["followers", "public", "unlisted"]
+    );
+    assert_eq!(visible(&index, Some(bob), &[]).await, ["direct", "public"]);
+    assert_eq!(
+        visible(&index, Some(alice), &[]).await,
+        ["direct", "followers", "public", "unlisted"]
+    );
+    // Hashtags of posts which aren't public stay out of the index.
+    assert_eq!(
+        ids(&index, &query("rust", None, &[])).await,
+        ["#rust".to_string()]
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{url, Memory};
+use ekika::{
+    ap,
+    model::{follow::FollowState, object::StoredObject},
+    visibility::{self, Viewer, Visibility},
+};
+use serde_json::json;
+
+const ALICE: &str = "https://ekika.example/users/alice";
+const BOB: &str = "https://ekika.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+const DAVE: &str = "https://remote.example/users/dave";
+
+fn followers() -> url::Url {
+    url(&format!("{ALICE}/followers"))
+}
+
+fn note(to: &[&str], cc: &[&str]) -> StoredObject {
+    let note = json!({
+        "id": "https://ekika.example/objects/1",
+        "type": "Note",
+        "attributedTo": ALICE,
+        "to": to,
+        "cc": cc,
+        "content": "hello",
+    });
+    StoredObject::new(note, &followers()).unwrap()
+}
+
+async fn can_view(store: &Memory, object: &StoredObject, viewer: Option<&str>) -> bool {
+    let viewer = match viewer {
+        Some(actor) => Viewer::Actor(url(actor)),
+        None => Viewer::Anonymous,
+    };
+    visibility::can_view(store, object, &viewer).await.unwrap()
+}
+
+#[tokio::test]
+async fn public_and_unlisted_objects_are_visible_to_anyone() {
+    let store = Memory::new();
+    let followers = followers();
+    let public = note(&[ap::PUBLIC], &[followers.as_str()]);
+    let unlisted = note(&[followers.as_str()], &[ap::PUBLIC]);
+    assert_eq!(public.visibility, Visibility::Public);
+    assert_eq!(unlisted.visibility, Visibility::Unlisted);
+    for object in [&public, &unlisted] {
+        assert!(can_view(&store, object, None).await);
+        assert!(can_view(&store, object, Some(CAROL)).await);
+    }
+}
+
+#[tokio::test]
+async fn followers_only_objects_are_visible_to_accepted_followers() {
+    let store = Memory::new();
+    store.follow(BOB, ALICE, FollowState::Accepted);
+    store.follow(CAROL, ALICE, FollowState::Pending);
+    let object = note(&[followers().as_str()], &[]);
+    assert_eq!(object.visibility, Visibility::FollowersOnly);
+    assert!(can_view(&store, &object, Some(ALICE)).await);
+    assert!(can_view(&store, &object, Some(BOB)).await);
+    assert!(!can_view(&store, &object, Some(CAROL)).await);
+    assert!(!can_view(&store, &object, Some(DAVE)).await);
+    assert!(!can_view(&store, &object, None).await);
+}
+
+#[tokio::test]
+async fn direct_objects_are_visible_to_addressees_only() {
+    let store = Memory::new();
+    store.follow(BOB, ALICE, FollowState::Accepted);
+    let object = note(&[CAROL], &[]);
+    assert_eq!(object.visibility, Visibility::Direct);
+    assert!(can_view(&store, &object, Some(ALICE)).await);
+    assert!(can_view(&store, &object, Some(CAROL)).await);
+    assert!(!can_view(&store, &object, Some(BOB)).await);
+    assert!(!can_view(&store, &object, None).await);
+}
+
+#[tokio::test]
+async fn delivery_expands_accepted_followers() {
+    let store = Memory::new();
+    store.follow(BOB, ALICE, FollowState::Accepted);
+    store.follow(CAROL, ALICE, FollowState::Pending);
+    let object = note(&[ap::PUBLIC, ALICE], &[followers().as_str(), DAVE]);
+    let mut recipients = visibility::delivery_recipients(store.as_ref(), &object, &followers())
+        .await
+        .unwrap()
+        .into_iter()
+        .collect::<Vec<_>>();
+    recipients.sort();
+    assert_eq!(recipients, [url(BOB), url(DAVE)]);
+}
+
+#[tokio::test]
+async fn delivery_leaves_followers_out_unless_addressed() {
+    let store = Memory::new();
+    store.follow(BOB, ALICE, FollowState::Accepted);
+    let object = note(&[CAROL], &[]);
+    let recipients = visibility::delivery_recipients(store.as_ref(), &object, &followers())
+        .await
+        .unwrap();
+    assert_eq!(recipients.into_iter().collect::<Vec<_>>(), [url(CAROL)]);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum_helper::HttpError;
+use common::{proxy_info, url, Memory};
+use ekika::webfinger::{self, WebfingerResponse};
+
+async fn lookup(store: &Arc<Memory>, resource: &str) -> Result<WebfingerResponse, HttpError> {
+    let uri: http::Uri = format!("/.well-known/webfinger?resource={resource}")
+        .parse()
+        .unwrap();
+    let query = Query::try_from_uri(&uri).unwrap();
+    let axum::Json(response) = webfinger::webfinger(
+        query,
+        State(store.clone()),
+        proxy_info(),
+        http::Method::GET,
+        uri,
+        http::HeaderMap::new(),
+    )
+    .await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn accounts_are_found_unsigned_in_open_mode() {
+    let store = Memory::new();
+    store.account("alice");
+    let response = lookup(&store, "acct:alice@ekika.example").await.unwrap();
+    assert!(response
+        .aliases
+        .contains(&url("https://ekika.example/users/alice")));
+    let error = lookup(&store, "acct:bob@ekika.example").await.unwrap_err();
+    assert_eq!(error.status, http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unsigned_lookups_are_refused_in_allowlist_mode() {
+    let store = Memory::new();
+    store.allowlist(&["allowed.example"]);
+    store.account("alice");
+    let error = lookup(&store, "acct:alice@ekika.example")
+        .await
+        .unwrap_err();
+    assert_eq!(error.status, http::StatusCode::FORBIDDEN);
+}